@@ -0,0 +1,76 @@
+//! Continued fraction expansions, like `[1; 2, 2, 2, …]` for `√2`.
+
+use crate::rational::Rational;
+
+/// The terms of the expansion of `x`, at most `max` of them. Also returns whether there
+/// would have been more.
+pub fn of_float(mut x: f64, max: usize) -> (Vec<i128>, bool) {
+    let mut terms = Vec::new();
+    while terms.len() < max {
+        let a = x.floor();
+        terms.push(a as i128);
+        let rest = x - a;
+        // anything smaller is lost in rounding errors
+        if rest < 1e-9 {
+            return (terms, false);
+        }
+        x = 1.0 / rest;
+    }
+    (terms, x.is_finite())
+}
+
+/// Like [`of_float`], but without rounding errors, so rational numbers have finite expansions.
+pub fn of_rational(r: Rational, max: usize) -> (Vec<i128>, bool) {
+    let (mut num, mut den) = r.parts();
+    let mut terms = Vec::new();
+    while den != 0 {
+        if terms.len() == max {
+            return (terms, true);
+        }
+        let a = num.div_euclid(den);
+        terms.push(a);
+        (num, den) = (den, num - a * den);
+    }
+    (terms, false)
+}
+
+/// Formats the terms like `[1; 2, 2]`, ending with `…` when `truncated`.
+pub fn format(terms: &[i128], truncated: bool) -> String {
+    let mut res = String::from("[");
+    for (i, a) in terms.iter().enumerate() {
+        res += &match i {
+            0 => a.to_string(),
+            1 => format!("; {a}"),
+            _ => format!(", {a}"),
+        };
+    }
+    if truncated {
+        res += if terms.len() == 1 { "; …" } else { ", …" };
+    }
+    res + "]"
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn floats() {
+        let (terms, truncated) = of_float(2f64.sqrt(), 5);
+        assert_eq!(format(&terms, truncated), "[1; 2, 2, 2, 2, …]");
+        let (terms, truncated) = of_float(std::f64::consts::PI, 4);
+        assert_eq!(format(&terms, truncated), "[3; 7, 15, 1, …]");
+        let (terms, truncated) = of_float(-0.75, 10);
+        assert_eq!(format(&terms, truncated), "[-1; 4]");
+        let (terms, truncated) = of_float(7.0, 10);
+        assert_eq!(format(&terms, truncated), "[7]");
+    }
+
+    #[test]
+    fn rationals() {
+        let (terms, truncated) = of_rational(Rational::new(415, 93).unwrap(), 10);
+        assert_eq!(format(&terms, truncated), "[4; 2, 6, 7]");
+        let (terms, truncated) = of_rational(Rational::new(415, 93).unwrap(), 1);
+        assert_eq!(format(&terms, truncated), "[4; …]");
+    }
+}
@@ -0,0 +1,411 @@
+//! Finds mistakes in an expression before evaluating it, including in branches of `if` that
+//! would not be evaluated.
+
+use std::fmt;
+
+use crate::context::Context;
+use crate::currency;
+use crate::functions::{self, Eval, Takes};
+use crate::i18n::{Key, Text};
+use crate::repr::*;
+use crate::uncertainty::PM;
+
+/// What an expression evaluates to. At runtime booleans are still represented as 1 and 0,
+/// durations as seconds, lengths as tenths of a millimetre, speeds as lengths per second and
+/// money in the base currency of the rates.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Type {
+    Number,
+    Boolean,
+    Duration,
+    Length,
+    Speed,
+    Money,
+    /// of numbers
+    List,
+    Text,
+    /// the result of `fft`, which can only be shown
+    ComplexList,
+}
+
+impl Type {
+    /// Whether this is a number with a unit.
+    fn is_quantity(self) -> bool {
+        matches!(self, Type::Duration | Type::Length | Type::Speed | Type::Money)
+    }
+
+    /// The type of a value that a built-in function was called with or returned.
+    pub(crate) fn of(value: &Value) -> Type {
+        match value {
+            Value::Boolean(_) => Type::Boolean,
+            Value::Duration(_) => Type::Duration,
+            Value::Length(..) => Type::Length,
+            Value::Speed(_) => Type::Speed,
+            Value::Money(..) => Type::Money,
+            Value::List(_) => Type::List,
+            Value::Text(_) => Type::Text,
+            Value::ComplexList(_) => Type::ComplexList,
+            Value::Number(_) | Value::Measurement(_) | Value::Rational(_) | Value::Precise(_) => Type::Number,
+        }
+    }
+
+    /// Whether this is one number, which operators and variables take.
+    pub fn is_scalar(self) -> bool {
+        matches!(self, Type::Number | Type::Boolean) || self.is_quantity()
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Type::Number => "number",
+            Type::Boolean => "boolean",
+            Type::Duration => "duration",
+            Type::Length => "length",
+            Type::Speed => "speed",
+            Type::Money => "money",
+            Type::List => "list",
+            Type::Text => "text",
+            Type::ComplexList => "complex list",
+        })
+    }
+}
+
+/// The error of a function like `x -> x ^ 2` that is not passed to a built-in function.
+pub(crate) const STRAY_LAMBDA: &str = "functions like x -> x ^ 2 can only be passed to functions like map";
+
+pub(crate) fn mismatch(op: Operator, a: Type, b: Type) -> String {
+    match op {
+        Operator::Add => format!("cannot add a {b} to a {a}"),
+        Operator::Sub => format!("cannot subtract a {b} from a {a}"),
+        Operator::Mul => format!("cannot multiply a {a} by a {b}"),
+        Operator::Div => format!("cannot divide a {a} by a {b}"),
+        Operator::Pow => format!("cannot raise a {a} to a {b}"),
+        _ => format!("cannot compare a {a} with a {b}"),
+    }
+}
+
+/// The names that are bound where a node is checked, like the parameters of the lambdas around
+/// it, and the user functions whose bodies are being checked for what they return.
+#[derive(Default, Clone)]
+struct Bound<'a> {
+    locals: Vec<(&'a str, Type)>,
+    calls: Vec<&'a str>,
+}
+
+impl Expr {
+    /// Checks the types of all operands, and that every called function exists and gets the
+    /// right number of arguments.
+    pub(crate) fn check(&self, ctx: &Context) -> Result<Type, EvalError> {
+        self.check_node(self.root(), ctx, &Bound::default())
+    }
+
+    /// Checks the subexpression at `id`.
+    pub(crate) fn check_at(&self, id: NodeId, ctx: &Context) -> Result<Type, EvalError> {
+        self.check_node(id, ctx, &Bound::default())
+    }
+
+    fn check_node<'a>(&'a self, id: NodeId, ctx: &'a Context, bound: &Bound<'a>) -> Result<Type, EvalError> {
+        let error = |message: Text| EvalError {
+            message,
+            span: self.span(id),
+            backtrace: Vec::new(),
+        };
+        let number = |id: NodeId, what: &str| match self.check_node(id, ctx, bound)? {
+            Type::Number => Ok(()),
+            t => Err(error(format!("{what} takes numbers, not a {t}").into())),
+        };
+        Ok(match *self.node(id) {
+            Node::Float(_) => Type::Number,
+            Node::Var(ref name) => match bound.locals.iter().rfind(|(n, _)| n == name) {
+                Some(&(_, t)) => t,
+                None if ctx.list_var(name).is_some() => Type::List,
+                None => Type::Number,
+            },
+            Node::Bool(_) => Type::Boolean,
+            Node::Text(_) => Type::Text,
+            // variables are numbers
+            Node::Assign(_, a) => match self.check_node(a, ctx, bound)? {
+                t if t.is_scalar() => Type::Number,
+                t => return Err(error(format!("only numbers can be assigned inside expressions, not a {t}").into())),
+            },
+            Node::Unary(UnaryOperator::Neg, a) => match self.check_node(a, ctx, bound)? {
+                t if t == Type::Boolean || !t.is_scalar() => return Err(error(format!("cannot negate a {t}").into())),
+                t => t,
+            },
+            Node::Op(op, a, b) => {
+                use Type::*;
+                let (ta, tb) = (self.check_node(a, ctx, bound)?, self.check_node(b, ctx, bound)?);
+                let scalars = ta.is_scalar() && tb.is_scalar();
+                let res = match (op, ta, tb) {
+                    _ if !scalars => None,
+                    (Operator::Add | Operator::Sub, _, _) if ta == tb && ta != Boolean => Some(ta),
+                    (Operator::Mul, q, Number) | (Operator::Mul, Number, q) if q.is_quantity() => Some(q),
+                    (Operator::Div, q, Number) if q.is_quantity() => Some(q),
+                    (Operator::Div, Length, Duration) => Some(Speed),
+                    (Operator::Div, Length, Speed) => Some(Duration),
+                    (Operator::Mul, Speed, Duration) | (Operator::Mul, Duration, Speed) => Some(Length),
+                    (Operator::Div, q, _) if q.is_quantity() && ta == tb => Some(Number),
+                    (Operator::Eq | Operator::Ne, _, _) if ta == tb => Some(Boolean),
+                    (Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge, _, _) if ta == tb && ta != Boolean => {
+                        Some(Boolean)
+                    }
+                    (Operator::Mul | Operator::Div | Operator::Pow, Number, Number) => Some(Number),
+                    _ => None,
+                };
+                res.ok_or_else(|| error(mismatch(op, ta, tb).into()))?
+            }
+            Node::List(ref elements) => {
+                for &e in elements {
+                    match self.check_node(e, ctx, bound)? {
+                        Type::Number => {}
+                        t => return Err(self.error_at(e, format!("lists can only have numbers, not a {t}"))),
+                    }
+                }
+                Type::List
+            }
+            Node::Range(from, to) => {
+                for e in [from, to] {
+                    match self.check_node(e, ctx, bound)? {
+                        Type::Number => {}
+                        t => return Err(self.error_at(e, format!("ranges can only have numbers, not a {t}"))),
+                    }
+                }
+                Type::List
+            }
+            Node::Lambda(..) => {
+                return Err(error(STRAY_LAMBDA.into()));
+            }
+            Node::Call(ref name, ref args) if name == PM => {
+                if args.len() != 2 {
+                    return Err(error(functions::arity(PM, "2".into(), args.len())));
+                }
+                for &a in args {
+                    number(a, name)?;
+                }
+                Type::Number
+            }
+            Node::Call(ref name, ref args) if ctx.func(name).is_some() => {
+                let f = ctx.func(name).unwrap();
+                if args.len() != f.params.len() {
+                    return Err(error(functions::arity(name, f.params.len().to_string().into(), args.len())));
+                }
+                let mut params = Vec::with_capacity(args.len());
+                for (param, &a) in f.params.iter().zip(args) {
+                    match self.check_node(a, ctx, bound)? {
+                        t @ (Type::Number | Type::List) => params.push((param.as_str(), t)),
+                        t => return Err(error(format!("{name} takes numbers, not a {t}").into())),
+                    }
+                }
+                // what the body returns, unless it is being checked already or fails, which
+                // evaluating it reports
+                if bound.calls.contains(&name.as_str()) {
+                    return Ok(Type::Number);
+                }
+                let mut calls = bound.calls.clone();
+                calls.push(name);
+                let body = Bound { locals: params, calls };
+                f.body.check_node(f.body.root(), ctx, &body).unwrap_or(Type::Number)
+            }
+            Node::Call(ref name, ref args) if currency::is_code(name) => {
+                if args.len() != 1 {
+                    return Err(error(functions::arity(name, "1".into(), args.len())));
+                }
+                number(args[0], name)?;
+                ctx.rates().to_base(name, 1.0).map_err(|message| error(message.into()))?;
+                Type::Money
+            }
+            Node::Call(ref name, ref args) => {
+                let unknown = || error(Text::new(Key::UnknownFunction, vec![name.into()]));
+                let f = functions::lookup(name).ok_or_else(unknown)?;
+                if !ctx.is_loaded(f.module) {
+                    return Err(error(functions::not_loaded(name, f.module)));
+                }
+                f.check_arity(args.len()).map_err(error)?;
+                if let Eval::Values { takes, gives, .. } = f.eval {
+                    for (&a, &takes) in args.iter().zip(takes) {
+                        self.check_argument(a, name, takes, ctx, bound)?;
+                    }
+                    return Ok(gives);
+                }
+                if name == "if" {
+                    // the condition may be a number too, like in spreadsheets
+                    match self.check_node(args[0], ctx, bound)? {
+                        t if t.is_scalar() => {}
+                        t => return Err(self.error_at(args[0], format!("if takes a boolean condition, not a {t}"))),
+                    }
+                    let (t, e) = (self.check_node(args[1], ctx, bound)?, self.check_node(args[2], ctx, bound)?);
+                    if t != e {
+                        return Err(error(format!("the branches of if are a {t} and a {e}").into()));
+                    }
+                    return Ok(t);
+                }
+                if name == "assert" {
+                    match self.check_node(args[0], ctx, bound)? {
+                        t if t.is_scalar() => {}
+                        t => return Err(error(format!("assert takes a boolean condition, not a {t}").into())),
+                    }
+                    return Ok(Type::Boolean);
+                }
+                if name == "assert_eq" {
+                    // quantities are compared in their units, like 1 h and 60 min
+                    let (a, b) = (self.check_node(args[0], ctx, bound)?, self.check_node(args[1], ctx, bound)?);
+                    if a != b || !a.is_scalar() {
+                        return Err(error(format!("assert_eq compares a {a} and a {b}").into()));
+                    }
+                    if let Some(&tolerance) = args.get(2) {
+                        number(tolerance, name)?;
+                    }
+                    return Ok(Type::Boolean);
+                }
+                for &a in args {
+                    match self.check_node(a, ctx, bound)? {
+                        Type::Number => {}
+                        // the numbers of lists are passed as arguments of their own
+                        Type::List if f.max_args == usize::MAX => {}
+                        Type::List => {
+                            let message = format!("only functions taking any number of arguments, like sum, take lists: {name}");
+                            return Err(error(message.into()));
+                        }
+                        t => return Err(error(format!("{name} takes numbers, not a {t}").into())),
+                    }
+                }
+                if functions::DURATIONS.contains(&name.as_str()) {
+                    Type::Duration
+                } else if functions::LENGTHS.contains(&name.as_str()) {
+                    Type::Length
+                } else {
+                    Type::Number
+                }
+            }
+        })
+    }
+
+    /// Checks the argument `a` of the built-in function `name`, which takes more than numbers.
+    fn check_argument<'a>(
+        &'a self,
+        a: NodeId,
+        name: &str,
+        takes: Takes,
+        ctx: &'a Context,
+        bound: &Bound<'a>,
+    ) -> Result<(), EvalError> {
+        let (params, returns, like) = match (takes, self.node(a)) {
+            (Takes::Value(t), _) => {
+                return match self.check_node(a, ctx, bound)? {
+                    found if found == t => Ok(()),
+                    found => {
+                        let message = self.unknown(a, ctx, bound);
+                        let message = message.unwrap_or_else(|| format!("{name} takes a {t}, not a {found}").into());
+                        Err(self.error_at(a, message))
+                    }
+                };
+            }
+            (Takes::Function(params, returns, like), _) => (params, returns, like),
+            (Takes::Polynomial(like), Node::Lambda(..)) => (1, Type::Number, like),
+            (Takes::Polynomial(like), _) => {
+                return match self.check_node(a, ctx, bound)? {
+                    Type::List => Ok(()),
+                    t => {
+                        let message = self.unknown(a, ctx, bound);
+                        let message = message
+                            .unwrap_or_else(|| format!("{name} takes a list or a function like {like}, not a {t}").into());
+                        Err(self.error_at(a, message))
+                    }
+                };
+            }
+        };
+        let Node::Lambda(ref names, body) = *self.node(a) else {
+            let t = self.check_node(a, ctx, bound)?;
+            return Err(self.error_at(a, format!("{name} takes a function like {like}, not a {t}")));
+        };
+        if params != usize::MAX && names.len() != params {
+            let n = names.len();
+            return Err(self.error_at(a, format!("{name} takes a function like {like}, got one with {n} parameters")));
+        }
+        let mut inner = bound.clone();
+        inner.locals.extend(names.iter().map(|n| (n.as_str(), Type::Number)));
+        match self.check_node(body, ctx, &inner)? {
+            t if t == returns => Ok(()),
+            t => Err(self.error_at(body, format!("the function of {name} must return a {returns}, not a {t}"))),
+        }
+    }
+
+    /// Why the variable at `id` has no value, if it is one that has none, which is the mistake
+    /// rather than its type.
+    fn unknown(&self, id: NodeId, ctx: &Context, bound: &Bound) -> Option<Text> {
+        match self.node(id) {
+            Node::Var(name) if !bound.locals.iter().any(|(n, _)| n == name) => ctx.lookup(name).err(),
+            _ => None,
+        }
+    }
+
+    fn error_at(&self, id: NodeId, message: impl Into<Text>) -> EvalError {
+        EvalError {
+            message: message.into(),
+            span: self.span(id),
+            backtrace: Vec::new(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    fn check(input: &str) -> Result<Type, (String, Option<Span>)> {
+        let e = parse_line(input).unwrap();
+        e.check(&Context::default()).map_err(|e| (e.message.into(), e.span))
+    }
+
+    #[test]
+    fn types() {
+        assert_eq!(check("1 + x * 2"), Ok(Type::Number));
+        assert_eq!(check("1 + 2 < 4"), Ok(Type::Boolean));
+        assert_eq!(check("(1 < 2) == (3 > 4)"), Ok(Type::Boolean));
+        assert_eq!(check("if(x, 1 < 2, 3 < 4)"), Ok(Type::Boolean));
+        assert_eq!(check("if(x < 1, 1, 2) + 1"), Ok(Type::Number));
+        assert_eq!(check("-hours(1) * 2 - minutes(x)"), Ok(Type::Duration));
+        assert_eq!(check("hours(1) / minutes(1)"), Ok(Type::Number));
+        assert_eq!(check("hours(1) < minutes(1)"), Ok(Type::Boolean));
+        assert_eq!(check("feet(1) * 3 + inches(2)"), Ok(Type::Length));
+        assert_eq!(check("kilometers(5) / hours(1)"), Ok(Type::Speed));
+        assert_eq!(check("meters(1) / seconds(1) * minutes(2)"), Ok(Type::Length));
+        assert_eq!(check("miles(1) / (meters(3) / seconds(1))"), Ok(Type::Duration));
+        assert_eq!(check("assert(x)"), Ok(Type::Boolean));
+        assert_eq!(check("USD(100) * 2 + EUR(x)"), Ok(Type::Money));
+        assert_eq!(check("USD(1) / EUR(1)"), Ok(Type::Number));
+        assert_eq!(check("assert_eq(hours(1), minutes(60))"), Ok(Type::Boolean));
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            check("3 + (1 < 2)"),
+            Err(("cannot add a boolean to a number".into(), Some(Span { start: 0, end: 11 })))
+        );
+        assert_eq!(check("-(1 < 2)").map_err(|e| e.0), Err("cannot negate a boolean".into()));
+        assert_eq!(check("(1 < 2) < 3").map_err(|e| e.0), Err("cannot compare a boolean with a number".into()));
+        assert_eq!(check("1 == (1 == 1)").map_err(|e| e.0), Err("cannot compare a number with a boolean".into()));
+        assert_eq!(check("sqrt(1 < 2)").map_err(|e| e.0), Err("sqrt takes numbers, not a boolean".into()));
+        assert_eq!(check("if(1, 2, 3 < 4)").map_err(|e| e.0), Err("the branches of if are a number and a boolean".into()));
+        // even in a branch that would not be evaluated
+        assert_eq!(
+            check("if(1, 2, sqrt(1, 2))"),
+            Err(("sqrt takes 1 arguments, got 2".into(), Some(Span { start: 9, end: 19 })))
+        );
+        assert_eq!(check("if(1, 2, foo(3))").map_err(|e| e.0), Err("unknown function: foo".into()));
+        assert_eq!(check("hours(1) + 1").map_err(|e| e.0), Err("cannot add a number to a duration".into()));
+        assert_eq!(check("hours(1) * hours(1)").map_err(|e| e.0), Err("cannot multiply a duration by a duration".into()));
+        assert_eq!(check("feet(1) + hours(1)").map_err(|e| e.0), Err("cannot add a duration to a length".into()));
+        assert_eq!(check("sqrt(hours(1))").map_err(|e| e.0), Err("sqrt takes numbers, not a duration".into()));
+        assert_eq!(check("XYZ(1)").map_err(|e| e.0), Err("unknown currency: XYZ".into()));
+        assert_eq!(check("USD(1) + hours(1)").map_err(|e| e.0), Err("cannot add a duration to a money".into()));
+        assert_eq!(
+            check("assert_eq(hours(1), 1)").map_err(|e| e.0),
+            Err("assert_eq compares a duration and a number".into())
+        );
+    }
+}
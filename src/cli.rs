@@ -1,34 +1,1680 @@
+use std::collections::VecDeque;
 use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 
-use colored::Colorize;
+use colored::{ColoredString, Colorize};
 
-use crate::parser;
+use crate::cfrac;
+use crate::check::Type;
+use crate::context::{self, Context};
+use crate::currency::{self, Rates};
+use crate::equiv::{self, Verdict};
+use crate::functions::{self, Function, Module, FUNCTIONS};
+use crate::generate::Generator;
+use crate::history::{self, HISTORY_LIMIT};
+use crate::i18n::{Key, Language, Text};
+use crate::identify::identify;
+use crate::ieee;
+use crate::json::Json;
+use crate::lint::Lint;
+use crate::lsp;
+use crate::number::{
+    self, ErrorBound, FixedWidth, IntType, Overflow, DECIMAL_DIGITS_LIMIT, DIGITS_LIMIT, DIGIT_CONSTANTS,
+    PRECISION_UNAVAILABLE,
+};
+use crate::parser::{self, Syntax};
+use crate::progress::{self, Spinner};
+use crate::repr::{EvalError, Expr, Node, Span, Statement, UserFunction, Value, STACK_PER_LEVEL};
+use crate::rpn::Stack;
+use crate::server;
+use crate::stats::{self, Summary};
+use crate::stream;
+use crate::theme::{Role, Theme, THEMES};
+use crate::tutorial::LESSONS;
 
-pub fn run_cli(stdin: &mut impl BufRead, stdout: &mut impl Write) -> Result<(), std::io::Error> {
+const PROMPT: &str = "> ";
+/// shown while reading the rest of an expression with unclosed brackets
+const CONTINUATION_PROMPT: &str = "... ";
+const WATCH_INTERVAL: Duration = Duration::from_millis(300);
+/// Errors nested deeper than this get a backtrace, since the underline is enough to find
+/// shallower ones.
+const BACKTRACE_DEPTH: usize = 4;
+/// How many enclosing subexpressions of an error are shown.
+const BACKTRACE_LIMIT: usize = 10;
+/// Terminals put these around pasted text once bracketed paste is turned on.
+const PASTE_START: &str = "\x1b[200~";
+const PASTE_END: &str = "\x1b[201~";
+/// How many bins histograms have, unless `--bins` says otherwise.
+pub const HIST_BINS: usize = 10;
+
+#[derive(Debug, Clone)]
+pub struct Options {
+    pub syntax: Syntax,
+    /// Whether to print a prompt before reading each line, which is only useful when
+    /// someone is typing the input.
+    pub prompt: bool,
+    /// The prompt, where `{n}` is replaced with the number of the input and `{mode}` with
+    /// `infix` or `rpn`.
+    pub prompt_format: String,
+    pub color: bool,
+    /// Whether `$NAME` reads environment variables, which servers may not want to expose.
+    pub env: bool,
+    /// modules of built-in functions to load in addition to the default ones
+    pub modules: Vec<Module>,
+    /// Whether integer results are also shown in hexadecimal and binary.
+    pub bases: bool,
+    /// The number of bits that negative integers are shown with in two's complement with
+    /// `bases`, instead of with a sign.
+    pub twos_complement: Option<u32>,
+    /// Whether results like `1.5707963267948966` are annotated with `≈ π/2`.
+    pub identify: bool,
+    /// Whether results like `1 / 6` are shown exactly as `0.1(6)`.
+    pub repeating: bool,
+    /// Whether results are shown with their numbers, like `[3] 42`, which `out[3]` and `in[3]`
+    /// refer to.
+    pub numbering: bool,
+    /// Whether results are annotated with an estimate of how far rounding errors may have
+    /// taken them from the exact result.
+    pub roundoff: bool,
+    /// How many terms of continued fractions `:cfrac` shows.
+    pub cfrac_terms: usize,
+    /// How deep calls of user functions can be nested, like when they call themselves.
+    pub recursion_limit: usize,
+    /// Whether expressions are evaluated with decimal floating point numbers, which is set with
+    /// `:mode decimal`.
+    pub decimal: bool,
+    /// The number of bits to evaluate expressions with instead of using `f64`, which needs the
+    /// `mpfr` feature.
+    pub precision: Option<u32>,
+    /// The integer type that expressions are evaluated with instead, which is set with `:int`.
+    pub int: Option<FixedWidth>,
+    /// Whether to warn about mistakes that are not errors, like comparing fractional numbers
+    /// with `==`.
+    pub warnings: bool,
+    /// The language of error messages and REPL text.
+    pub language: Language,
+    /// Whether numbers like `1,234.56` are read with the separators of `language`, which is set
+    /// with `:grouping`.
+    pub grouping: bool,
+    /// The file that the REPL history is loaded from and saved to, if any.
+    pub history_file: Option<PathBuf>,
+    pub theme: &'static Theme,
+    /// Whether to show a spinner on stderr while evaluating takes long, which only makes sense
+    /// when stderr is a terminal.
+    pub progress: bool,
+    /// Whether to ask the terminal to mark pasted text, so that several pasted lines are run
+    /// together instead of each after a prompt.
+    pub bracketed_paste: bool,
+    /// Whether errors and warnings of `-e`, scripts and `--filter` are written to stderr as one
+    /// JSON object per line, for tools that run the calculator.
+    pub json_errors: bool,
+    /// Whether the REPL prints exactly one line for each input, with its result, its error or
+    /// nothing, and no echoed input, warnings or underlines.
+    pub plain: bool,
+    /// The exchange rates of currencies.
+    pub rates: Rates,
+    /// The file that the rates were read from, where `:rates update` saves them.
+    pub rates_file: Option<PathBuf>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            syntax: Syntax::default(),
+            prompt: true,
+            prompt_format: PROMPT.to_string(),
+            color: true,
+            env: true,
+            modules: Vec::new(),
+            bases: false,
+            twos_complement: None,
+            identify: false,
+            repeating: false,
+            numbering: false,
+            roundoff: false,
+            cfrac_terms: 10,
+            recursion_limit: context::RECURSION_LIMIT,
+            decimal: false,
+            precision: None,
+            int: None,
+            warnings: true,
+            language: Language::default(),
+            grouping: false,
+            history_file: None,
+            theme: &THEMES[0],
+            progress: false,
+            bracketed_paste: false,
+            json_errors: false,
+            plain: false,
+            rates: Rates::default(),
+            rates_file: None,
+        }
+    }
+}
+
+impl Options {
+    /// Makes the output only results, for programs to read, like with `--plain`.
+    pub fn make_plain(&mut self) {
+        self.plain = true;
+        self.color = false;
+        self.prompt = false;
+        self.progress = false;
+        self.bracketed_paste = false;
+    }
+
+    fn context(&self) -> Context {
+        let mut ctx = Context::new(self.env);
+        for &m in &self.modules {
+            ctx.load(m);
+        }
+        ctx.set_rates(self.rates.clone());
+        ctx.set_recursion_limit(self.recursion_limit);
+        ctx
+    }
+
+    /// Formats a result like `42`, or `42 (0x2A, 0b101010)` with `bases`. Negative integers are
+    /// like `-5 (-0x5, -0b101)`, or `-5 (0xFB, 0b11111011)` in 8-bit two's complement.
+    fn format(&self, value: &Value) -> String {
+        let &Value::Number(res) = value else {
+            return value.to_string();
+        };
+        if let Some(known) = self.identify.then(|| identify(res)).flatten() {
+            return format!("{value} ≈ {known}");
+        }
+        // larger numbers are not exact integers anymore
+        if !self.bases || res.fract() != 0.0 || res.abs() > 2f64.powi(53) {
+            return value.to_string();
+        }
+        let n = res as i128;
+        if let Some(bits) = self.twos_complement.filter(|&bits| n < 0 && n >= -(1 << (bits - 1))) {
+            let pattern = n.rem_euclid(1 << bits);
+            return format!("{value} (0x{pattern:X}, 0b{pattern:b})");
+        }
+        let sign = if res < 0.0 { "-" } else { "" };
+        let n = res.abs() as u64;
+        format!("{value} ({sign}0x{n:X}, {sign}0b{n:b})")
+    }
+
+    /// Formats a result, colored by its kind.
+    fn show(&self, value: &Value) -> ColoredString {
+        let role = match value {
+            Value::Boolean(_) => Role::Boolean,
+            _ => Role::Result,
+        };
+        self.paint(&self.format(value), role)
+    }
+
+    /// Colors the parts of an expression that is shown again, like after history expansion.
+    fn highlight(&self, input: &str) -> String {
+        if self.color {
+            self.theme.highlight(input)
+        } else {
+            input.to_string()
+        }
+    }
+
+    /// Translates and colors an error message.
+    fn error(&self, message: impl Into<Text>) -> ColoredString {
+        self.paint(&self.language.show(&message.into()), Role::Error)
+    }
+
+    fn paint(&self, s: &str, role: Role) -> ColoredString {
+        if self.color {
+            self.theme.paint(s, role)
+        } else {
+            s.normal()
+        }
+    }
+}
+
+/// Runs inputs until the end of `stdin`, and returns whether they all ran without errors, for
+/// the exit status when a script is piped in.
+pub fn run_cli(
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    options: &Options,
+) -> Result<bool, std::io::Error> {
     let mut line = String::new();
+    // changed by bc `scale=` statements
+    let mut options = options.clone();
+    let mut ctx = options.context();
+    // set in `:mode rpn`
+    let mut rpn = None;
+    let mut history = match &options.history_file {
+        Some(path) => history::load(path, HISTORY_LIMIT),
+        None => Vec::new(),
+    };
+    let mut n = 0;
+    // the inputs of a paste that are left to run
+    let mut pasted = VecDeque::new();
+    let mut failed = false;
+    if options.bracketed_paste {
+        write!(stdout, "\x1b[?2004h")?;
+    }
+
+    loop {
+        n += 1;
+        let mode = mode_name(&options, &rpn);
+        let prompt = options
+            .prompt_format
+            .replace("{n}", &n.to_string())
+            .replace("{mode}", mode);
+        let from_paste = !pasted.is_empty();
+        if options.prompt && !from_paste {
+            write!(stdout, "{}", options.paint(&prompt, Role::Prompt))?;
+            stdout.flush().unwrap();
+        }
+
+        let mut input = match pasted.pop_front() {
+            Some(input) => input,
+            None => {
+                line.clear();
+                let bytes_read = stdin.read_line(&mut line).unwrap();
+                if bytes_read == 0 {
+                    // EOF
+                    if options.bracketed_paste {
+                        write!(stdout, "\x1b[?2004l")?;
+                    }
+                    return Ok(!failed);
+                }
+                line.strip_suffix('\n').unwrap_or_else(|| &line).to_string()
+            }
+        };
+        if let Some(first) = input.strip_prefix(PASTE_START) {
+            pasted.extend(read_paste(first, stdin));
+            n -= 1;
+            continue;
+        }
+        // piped input has no one to finish the expression, so its lines are all complete
+        while options.prompt && !from_paste && !input.starts_with(':') && parser::is_unclosed(&input) {
+            write!(stdout, "{CONTINUATION_PROMPT}")?;
+            stdout.flush().unwrap();
+            let mut more = String::new();
+            if stdin.read_line(&mut more).unwrap() == 0 {
+                break;
+            }
+            input.push(' ');
+            input.push_str(more.strip_suffix('\n').unwrap_or(&more));
+        }
+        if let Some(close) = (!from_paste && !input.starts_with(':')).then(|| block_opened(&input)).flatten() {
+            let mut body = Vec::new();
+            loop {
+                if options.prompt {
+                    write!(stdout, "{CONTINUATION_PROMPT}")?;
+                    stdout.flush().unwrap();
+                }
+                let mut more = String::new();
+                if stdin.read_line(&mut more).unwrap() == 0 {
+                    break;
+                }
+                let more = more.trim();
+                match block_closed(more, close) {
+                    Some(last) => {
+                        body.push(last.to_string());
+                        break;
+                    }
+                    None => body.push(more.to_string()),
+                }
+            }
+            input = join_block(&input, &body);
+        }
+        let expanded = expand_history(&input, &history).and_then(|expanded| {
+            let line = expanded.as_deref().unwrap_or(&input);
+            let expanded = expand_inputs(line, &ctx)?.or(expanded);
+            let line = expanded.as_deref().unwrap_or(&input);
+            let native = options.syntax == Syntax::Native && rpn.is_none();
+            Ok(native.then(|| apply_to_previous(line)).flatten().or(expanded))
+        });
+        let line = match expanded {
+            Ok(Some(expanded)) => {
+                if !options.plain {
+                    writeln!(stdout, "{}", options.highlight(&expanded))?;
+                }
+                expanded
+            }
+            Ok(None) => input,
+            Err(err) => {
+                writeln!(stdout, "{}", options.error(&err))?;
+                failed = true;
+                continue;
+            }
+        };
+        if !line.trim().is_empty() {
+            if let Some(path) = &options.history_file {
+                // the session goes on without saving if the file can not be written
+                let _ = history::append(path, &line);
+            }
+            history.push(line.clone());
+        }
+        let line = line.as_str();
+
+        if let Some(command) = line.strip_prefix(':') {
+            run_command(command, stdout, &mut options, &mut ctx, &mut rpn, &history)?;
+            continue;
+        }
+
+        if let Some(stack) = &mut rpn {
+            match stack.run(line, &ctx) {
+                Ok(()) => {
+                    let values: Vec<_> = stack.values().iter().map(|v| v.to_string()).collect();
+                    writeln!(stdout, "{}", options.paint(&values.join(" "), Role::Result))?;
+                }
+                Err(err) => {
+                    writeln!(stdout, "{}", options.error(&err))?;
+                    failed = true;
+                }
+            }
+            continue;
+        }
+
+        let mut warnings = Vec::new();
+        let spinner = options.progress.then(|| Spinner::start(progress::DELAY, std::io::stderr()));
+        let res = run_line(line, &mut ctx, &mut options, &mut warnings);
+        drop(spinner);
+        for warning in warnings.into_iter().filter(|_| !options.plain) {
+            let warning = options.language.show(&Text::new(Key::Warning, vec![warning.into()]));
+            writeln!(stdout, "{}", options.paint(&warning, Role::Warning))?;
+        }
+        match res {
+            Ok(Some(res)) if options.numbering && !options.plain => {
+                let number = format!("[{}]", ctx.last_output().unwrap_or(0));
+                writeln!(stdout, "{} {}", options.paint(&number, Role::Prompt), options.show(&res))?
+            }
+            Ok(Some(res)) => writeln!(stdout, "{}", options.show(&res))?,
+            // so that the output has as many lines as the input
+            Ok(None) if options.plain => writeln!(stdout)?,
+            Ok(None) => {}
+            Err(err) if options.plain => {
+                writeln!(stdout, "{}", options.error(&err.message))?;
+                failed = true;
+            }
+            Err(err) => {
+                failed = true;
+                if let Some(span) = err.span {
+                    // the input is not shown on the screen when it is piped, and pasted input
+                    // is shown before all of its results
+                    let indent = if options.prompt && !from_paste {
+                        prompt.chars().count()
+                    } else {
+                        writeln!(stdout, "{line}")?;
+                        0
+                    };
+                    writeln!(stdout, "{}", options.error(underline(line, span, indent)))?;
+                }
+                writeln!(stdout, "{}", options.error(&err.message))?;
+                for frame in backtrace(line, &err).iter().flat_map(|b| b.lines()) {
+                    writeln!(stdout, "{}", options.paint(frame, Role::Error))?;
+                }
+            }
+        }
+    }
+}
+
+/// The word that closes the block that `do` or `{` opens at the end of `line`, which lets the
+/// body of a function go on over several lines.
+fn block_opened(line: &str) -> Option<&'static str> {
+    let line = line.trim_end();
+    if line.ends_with('{') {
+        Some("}")
+    } else if line.strip_suffix("do").is_some_and(|rest| rest.ends_with([' ', '=', '('])) {
+        Some("end")
+    } else {
+        None
+    }
+}
+
+/// The last line of a block without the word that closes it, if `line` closes it.
+fn block_closed<'a>(line: &'a str, close: &str) -> Option<&'a str> {
+    let rest = line.strip_suffix(close)?;
+    // not like `x + legend`
+    (close == "}" || rest.is_empty() || rest.ends_with(' ')).then(|| rest.trim_end())
+}
+
+/// The line that opened a block with the lines of its body, as one line where the block is in
+/// parentheses.
+fn join_block(first: &str, body: &[String]) -> String {
+    let first = first.trim_end();
+    let head = first.strip_suffix('{').or_else(|| first.strip_suffix("do")).unwrap_or(first);
+    let body: Vec<&str> = body.iter().map(|l| l.as_str()).filter(|l| !l.is_empty()).collect();
+    format!("{}({})", head, body.join(" "))
+}
+
+/// Reads the rest of a paste that starts with `first`, and splits it into inputs: one for each
+/// line, or all lines joined when some of them leave brackets open.
+fn read_paste(first: &str, stdin: &mut impl BufRead) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = first.to_string();
+    loop {
+        if let Some(end) = line.find(PASTE_END) {
+            line.truncate(end);
+            lines.push(line);
+            break;
+        }
+        lines.push(line);
+        line = String::new();
+        if stdin.read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        if line.ends_with('\n') {
+            line.pop();
+        }
+    }
+    lines.retain(|l| !l.trim().is_empty());
+    if lines.iter().any(|l| l.matches('(').count() != l.matches(')').count()) {
+        vec![lines.join(" ")]
+    } else {
+        lines
+    }
+}
+
+/// Replaces `!!` with the previous input line and `!N` with the `N`th one, counting from 1.
+/// Returns `None` when there is nothing to replace.
+fn expand_history(line: &str, history: &[String]) -> Result<Option<String>, String> {
+    let mut res = String::new();
+    let mut rest = line;
+    let mut expanded = false;
+    while let Some(i) = rest.find('!') {
+        res.push_str(&rest[..i]);
+        let after = &rest[i + 1..];
+        if let Some(after) = after.strip_prefix('!') {
+            res.push_str(history.last().ok_or("there is no previous input")?);
+            expanded = true;
+            rest = after;
+        } else {
+            let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+            if digits == 0 {
+                // like in `!=`
+                res.push('!');
+            } else {
+                let n: usize = after[..digits].parse().map_err(|_| "invalid history number")?;
+                let entry = n.checked_sub(1).and_then(|i| history.get(i));
+                res.push_str(entry.ok_or_else(|| format!("no such history entry: !{n}"))?);
+                expanded = true;
+            }
+            rest = &after[digits..];
+        }
+    }
+    if !expanded {
+        return Ok(None);
+    }
+    res.push_str(rest);
+    Ok(Some(res))
+}
+
+/// Replaces `in[N]` with the input that gave the result numbered `N`, in parentheses so that
+/// it is run again as a whole. Returns `None` when there is nothing to replace.
+fn expand_inputs(line: &str, ctx: &Context) -> Result<Option<String>, String> {
+    let mut res = String::new();
+    let mut rest = line;
+    let mut expanded = false;
+    while let Some(i) = rest.find("in[") {
+        let word = rest[..i].chars().next_back().is_some_and(parser::is_name_continue);
+        let after = &rest[i + 3..];
+        let digits = after.find(|c: char| !c.is_ascii_digit()).unwrap_or(after.len());
+        res.push_str(&rest[..i]);
+        // like `min[` or `in[x]`, which are not references
+        if word || digits == 0 || !after[digits..].starts_with(']') {
+            res.push_str("in[");
+            rest = after;
+            continue;
+        }
+        let n: usize = after[..digits].parse().map_err(|_| "invalid output number")?;
+        let (input, _) = ctx.output(n).map_err(|err| err.replace("out[", "in["))?;
+        res.push_str(&format!("({input})"));
+        expanded = true;
+        rest = &after[digits + 1..];
+    }
+    if !expanded {
+        return Ok(None);
+    }
+    res.push_str(rest);
+    Ok(Some(res))
+}
+
+/// Rewrites a line that starts with an operator, like `* 2`, into `ans * 2`, which applies it
+/// to the previous result like desk calculators do. A `-` only continues when a space follows,
+/// since `-5` is a number. Percentages like `+ 10%` are of the previous result.
+fn apply_to_previous(line: &str) -> Option<String> {
+    let line = line.trim();
+    let op = ["+", "- ", "*", "/", "^"].into_iter().find(|op| line.starts_with(op))?.trim();
+    let rest = line[op.len()..].trim();
+    if rest.is_empty() {
+        return None;
+    }
+    let percent = rest.strip_suffix('%').map(str::trim).filter(|p| p.parse::<f64>().is_ok());
+    Some(match (op, percent) {
+        ("+" | "-", Some(p)) => format!("ans {op} ans * ({p} / 100)"),
+        ("*" | "/", Some(p)) => format!("ans {op} ({p} / 100)"),
+        _ => format!("ans {op} {rest}"),
+    })
+}
+
+/// `infix`, `rpn` or `decimal`.
+fn mode_name(options: &Options, rpn: &Option<Stack>) -> &'static str {
+    match (rpn, options.decimal) {
+        (Some(_), _) => "rpn",
+        (None, true) => "decimal",
+        (None, false) => "infix",
+    }
+}
+
+fn definition(name: &str, f: &UserFunction) -> String {
+    let memo = if f.memo { "memo " } else { "" };
+    format!("{memo}{name}({}) = {}", f.params.join(", "), f.body)
+}
+
+/// Parses one line of input with the syntax and number grouping of `options`.
+fn parse_input(line: &str, options: &Options) -> Result<Statement, Text> {
+    let source = match options.grouping {
+        true => parser::ungroup(line, options.language.grouping()),
+        false => line.into(),
+    };
+    parser::parse_statement(&source, options.syntax)
+}
+
+/// Runs one line of input, returning the result of an expression or `None` for statements.
+/// Adds any warnings about the line to `warnings` when it runs without errors.
+fn run_line(
+    line: &str,
+    ctx: &mut Context,
+    options: &mut Options,
+    warnings: &mut Vec<String>,
+) -> Result<Option<Value>, EvalError> {
+    if let Syntax::Bc { ref mut scale } = options.syntax {
+        if let Some(s) = parser::parse_scale_assignment(line) {
+            *scale = s;
+            return Ok(None);
+        }
+    }
+    let statement = parse_input(line, options).map_err(|message| EvalError {
+        message,
+        span: None,
+        backtrace: Vec::new(),
+    })?;
+    // before assignments change the variables
+    let found = match statement {
+        // they are about rounding errors of binary floating point numbers
+        Statement::Expr(_) if options.decimal => Vec::new(),
+        _ if options.warnings => statement.warnings(ctx),
+        _ => Vec::new(),
+    };
+    let res = run_statement(&statement, ctx, options)?;
+    // lists are not numbered, since out[n] is a number
+    if res.as_ref().is_some_and(|res| !matches!(res, Value::List(_) | Value::ComplexList(_))) {
+        ctx.add_output(line);
+    }
+    warnings.extend(found);
+    Ok(res)
+}
+
+/// Runs a statement with the numbers that the options choose.
+fn run_statement(statement: &Statement, ctx: &mut Context, options: &Options) -> Result<Option<Value>, EvalError> {
+    // the expression sees the variables as they were before it assigns any
+    let alternative = match statement {
+        Statement::Expr(e) => evaluate_alternative(e, ctx, options),
+        _ => None,
+    };
+    let res = match options.decimal {
+        true => ctx.run_decimal(statement)?,
+        false => ctx.run(statement)?,
+    };
+    match alternative {
+        Some(alternative) => alternative.map(Some),
+        None => Ok(res),
+    }
+}
+
+/// The value of the expression `e` with the numbers that the options choose instead of f64, if
+/// they do, for when it is a plain number. Decimals are not, since assignments keep them.
+fn evaluate_alternative(e: &Expr, ctx: &Context, options: &Options) -> Option<Result<Value, EvalError>> {
+    if e.check(ctx).ok().filter(|_| !e.has_uncertainty())? != Type::Number {
+        return None;
+    }
+    if let Some(fixed) = options.int {
+        return Some(e.evaluate_with(&fixed, ctx).and_then(|n| match n.0 {
+            Some(n) => Ok(Value::Precise(n.to_string())),
+            None => Err(EvalError {
+                message: "division by zero".into(),
+                span: None,
+                backtrace: Vec::new(),
+            }),
+        }));
+    }
+    if let Some(bits) = options.precision {
+        return Some(number::evaluate_precise(e, ctx, bits).map(Value::Precise));
+    }
+    if options.repeating {
+        if let Some(r) = e.evaluate_exact(ctx).filter(|r| r.is_repeating()) {
+            return Some(Ok(Value::Rational(r)));
+        }
+    }
+    if options.roundoff {
+        return Some(e.evaluate_with(&ErrorBound, ctx).map(|x| {
+            let estimate = match x.relative_error() {
+                Some(0.0) => "exact".to_string(),
+                Some(relative) => format!("relative error ≈ {relative:.1e}"),
+                None => format!("error ≈ {:.1e}", x.error),
+            };
+            Value::Precise(format!("{} ({estimate})", Value::Number(x.value)))
+        }));
+    }
+    None
+}
+
+fn run_command(
+    command: &str,
+    stdout: &mut impl Write,
+    options: &mut Options,
+    ctx: &mut Context,
+    rpn: &mut Option<Stack>,
+    history: &[String],
+) -> Result<(), std::io::Error> {
+    let (name, arg) = command.split_once(' ').unwrap_or((command, ""));
+    match name {
+        "dot" => match parser::parse_line_with(arg, options.syntax) {
+            Ok(e) => write!(stdout, "{}", e.to_dot())?,
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "mathml" => match parser::parse_line_with(arg, options.syntax) {
+            Ok(e) => match e.evaluate_in(ctx) {
+                Ok(res) => writeln!(stdout, "{}", e.to_mathml_equation(res))?,
+                Err(_) => writeln!(stdout, "{}", e.to_mathml())?,
+            },
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "lint" => match lint_line(arg, options.syntax) {
+            Ok(lints) if lints.is_empty() => writeln!(stdout, "{}", options.language.text(Key::NoProblems))?,
+            Ok(lints) => {
+                for lint in lints {
+                    writeln!(stdout, "{}", options.highlight(arg))?;
+                    if let Some(span) = lint.span {
+                        writeln!(stdout, "{}", options.paint(&underline(arg, span, 0), Role::Warning))?;
+                    }
+                    writeln!(stdout, "{}", options.paint(&lint.message, Role::Warning))?;
+                }
+            }
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "profile" => match parser::parse_line_with(arg, options.syntax) {
+            Ok(e) => {
+                ctx.start_profile();
+                let res = ctx.run(&Statement::Expr(e.clone()));
+                let profile = ctx.finish_profile();
+                match res {
+                    Ok(Some(res)) => writeln!(stdout, "{}", options.show(&res))?,
+                    Ok(None) => {}
+                    Err(err) => writeln!(stdout, "{}", options.error(&err.message))?,
+                }
+                writeln!(stdout)?;
+                write!(stdout, "{}", table(&["function", "calls", "time", "share"], &profile.flat(&e, ctx)))?;
+                writeln!(stdout)?;
+                write!(stdout, "{}", table(&["time", "share", "count", "expression"], &profile.tree(&e, ctx)))?;
+            }
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "explain" => match parser::parse_line_with(arg, options.syntax) {
+            Ok(e) => writeln!(stdout, "{}", e.parenthesized())?,
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "cfrac" => match parser::parse_line_with(arg, options.syntax) {
+            Ok(e) => {
+                let (terms, truncated) = match e.evaluate_exact(ctx) {
+                    Some(r) => cfrac::of_rational(r, options.cfrac_terms),
+                    None => match e.evaluate_in(ctx) {
+                        Ok(x) => cfrac::of_float(x, options.cfrac_terms),
+                        Err(err) => return writeln!(stdout, "{}", options.error(&err.message)),
+                    },
+                };
+                writeln!(stdout, "{}", cfrac::format(&terms, truncated))?;
+            }
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "bits" => match parser::parse_line_with(arg, options.syntax).map(|e| e.evaluate_in(ctx)) {
+            Ok(Ok(x)) => {
+                write!(stdout, "{}", table(&["field", "bits", "hex", "value"], &ieee::fields(x)))?;
+                writeln!(stdout, "exactly {}", ieee::exact(x))?;
+            }
+            Ok(Err(err)) => writeln!(stdout, "{}", options.error(&err.message))?,
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "hist" => match hist_values(arg.trim(), options, ctx) {
+            Ok(values) if values.is_empty() => writeln!(stdout, "{}", options.error("the list is empty"))?,
+            Ok(values) => write!(stdout, "{}", stats::histogram(&stats::bins(&values, HIST_BINS)))?,
+            Err(err) => writeln!(stdout, "{}", options.error(&err))?,
+        },
+        "doc" if arg.trim().is_empty() => {
+            let rows: Vec<_> = FUNCTIONS
+                .iter()
+                .map(|f| vec![f.signature(), f.module.name().into(), f.domain.into(), example(f, ctx)])
+                .collect();
+            write!(stdout, "{}", table(&["function", "module", "domain", "example"], &rows))?;
+        }
+        "doc" => match functions::lookup(arg.trim()) {
+            Some(f) => writeln!(stdout, "{}", doc(f, ctx))?,
+            None => {
+                let message = format!("unknown built-in function: {}", arg.trim());
+                writeln!(stdout, "{}", options.error(&message))?;
+            }
+        },
+        "undo" => match ctx.undo() {
+            Some(name) => match (ctx.var(&name), ctx.func(&name)) {
+                (Some(value), _) => writeln!(stdout, "{name} = {value}")?,
+                (None, Some(f)) => writeln!(stdout, "{}", definition(&name, f))?,
+                (None, None) => writeln!(stdout, "{name} is undefined")?,
+            },
+            None => writeln!(stdout, "{}", options.error(Text::new(Key::NothingToUndo, Vec::new())))?,
+        },
+        "vars" => {
+            let rows: Vec<_> = ctx.vars().map(|(name, value)| vec![name.into(), value.to_string()]).collect();
+            if !rows.is_empty() {
+                write!(stdout, "{}", table(&["name", "value"], &rows))?;
+            }
+        }
+        "funcs" => {
+            for (name, f) in ctx.funcs() {
+                writeln!(stdout, "{}", definition(name, f))?;
+            }
+        }
+        "ns" => {
+            for (ns, names) in ctx.namespaces() {
+                writeln!(stdout, "{ns}: {}", names.join(", "))?;
+            }
+        }
+        "load" if arg.trim().is_empty() => {
+            let rows: Vec<_> = Module::ALL
+                .into_iter()
+                .map(|m| vec![m.name().into(), if ctx.is_loaded(m) { "loaded" } else { "not loaded" }.into()])
+                .collect();
+            write!(stdout, "{}", table(&["module", "status"], &rows))?;
+        }
+        "load" | "unload" => match Module::from_name(arg.trim()) {
+            Some(m) if name == "load" => ctx.load(m),
+            Some(m) => {
+                if let Err(err) = ctx.unload(m) {
+                    writeln!(stdout, "{}", options.error(&err))?;
+                }
+            }
+            None => {
+                let message = Text::new(Key::UnknownModule, vec![arg.trim().into()]);
+                writeln!(stdout, "{}", options.error(message))?;
+            }
+        },
+        "mode" => match arg.trim() {
+            "" => writeln!(stdout, "{}", mode_name(options, rpn))?,
+            "infix" => (*rpn, options.decimal) = (None, false),
+            "rpn" => (*rpn, options.decimal) = (Some(Stack::default()), false),
+            "decimal" => (*rpn, options.decimal) = (None, true),
+            mode => writeln!(stdout, "{}", options.error(Text::new(Key::UnknownMode, vec![mode.into()])))?,
+        },
+        "lang" => match arg.trim() {
+            "" => writeln!(stdout, "{}", options.language.code())?,
+            code => match Language::from_code(code) {
+                Some(language) => options.language = language,
+                None => writeln!(stdout, "{}", options.error(Text::new(Key::UnknownLanguage, vec![code.into()])))?,
+            },
+        },
+        "theme" => match arg.trim() {
+            "" => writeln!(stdout, "{}", options.theme.name)?,
+            name => match Theme::from_name(name) {
+                Some(theme) => options.theme = theme,
+                None => {
+                    let names: Vec<_> = THEMES.iter().map(|t| t.name).collect();
+                    let message = format!("unknown theme: {name}, expected one of {}", names.join(", "));
+                    writeln!(stdout, "{}", options.error(&message))?;
+                }
+            },
+        },
+        "rates" => match arg.trim() {
+            "" => {
+                let rates = ctx.rates();
+                writeln!(stdout, "1 {} on {} is worth", rates.base, rates.date)?;
+                let rows: Vec<_> =
+                    rates.rates.iter().map(|(code, rate)| vec![code.clone(), rate.to_string()]).collect();
+                write!(stdout, "{}", table(&["currency", "rate"], &rows))?;
+                if let Some(warning) = rates.stale(currency::today()) {
+                    writeln!(stdout, "{}", options.paint(&format!("warning: {warning}"), Role::Warning))?;
+                }
+            }
+            "update" => {
+                if let Err(err) = update_rates(options, ctx) {
+                    writeln!(stdout, "{}", options.error(&err))?;
+                }
+            }
+            _ => writeln!(stdout, "{}", options.error("expected :rates or :rates update"))?,
+        },
+        "bases" => match arg.trim() {
+            "" => match (options.bases, options.twos_complement) {
+                (true, Some(bits)) => writeln!(stdout, "on, with {bits}-bit two's complement")?,
+                (true, None) => writeln!(stdout, "on")?,
+                (false, _) => writeln!(stdout, "off")?,
+            },
+            "on" => (options.bases, options.twos_complement) = (true, None),
+            "off" => options.bases = false,
+            bits @ ("8" | "16" | "32" | "64") => (options.bases, options.twos_complement) = (true, bits.parse().ok()),
+            _ => writeln!(stdout, "{}", options.error("expected :bases on, :bases 8|16|32|64 or :bases off"))?,
+        },
+        "grouping" => match arg.trim() {
+            "" => writeln!(stdout, "{}", if options.grouping { "on" } else { "off" })?,
+            "on" => options.grouping = true,
+            "off" => options.grouping = false,
+            _ => writeln!(stdout, "{}", options.error("expected :grouping on or :grouping off"))?,
+        },
+        "identify" => match arg.trim() {
+            "" => writeln!(stdout, "{}", if options.identify { "on" } else { "off" })?,
+            "on" => options.identify = true,
+            "off" => options.identify = false,
+            _ => writeln!(stdout, "{}", options.error("expected :identify on or :identify off"))?,
+        },
+        "repeating" => match arg.trim() {
+            "" => writeln!(stdout, "{}", if options.repeating { "on" } else { "off" })?,
+            "on" => options.repeating = true,
+            "off" => options.repeating = false,
+            _ => writeln!(stdout, "{}", options.error("expected :repeating on or :repeating off"))?,
+        },
+        "numbering" => match arg.trim() {
+            "" => writeln!(stdout, "{}", if options.numbering { "on" } else { "off" })?,
+            "on" => options.numbering = true,
+            "off" => options.numbering = false,
+            _ => writeln!(stdout, "{}", options.error("expected :numbering on or :numbering off"))?,
+        },
+        "roundoff" => match arg.trim() {
+            "" => writeln!(stdout, "{}", if options.roundoff { "on" } else { "off" })?,
+            "on" => options.roundoff = true,
+            "off" => options.roundoff = false,
+            _ => writeln!(stdout, "{}", options.error("expected :roundoff on or :roundoff off"))?,
+        },
+        "int" => match arg.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => match options.int {
+                Some(FixedWidth { int, overflow }) => writeln!(stdout, "{} {}", int.name(), overflow.name())?,
+                None => writeln!(stdout, "off")?,
+            },
+            ["off"] => options.int = None,
+            [name, ref overflow @ ..] => match (IntType::from_name(name), overflow) {
+                (Some(int), [] | ["wrap"]) => options.int = Some(FixedWidth { int, overflow: Overflow::Wrap }),
+                (Some(int), ["saturate"]) => options.int = Some(FixedWidth { int, overflow: Overflow::Saturate }),
+                _ => {
+                    let expected = "expected :int u8|u16|u32|i32|i64 [wrap|saturate] or :int off";
+                    writeln!(stdout, "{}", options.error(expected))?
+                }
+            },
+        },
+        "precision" => match arg.split_whitespace().collect::<Vec<_>>()[..] {
+            [] => match options.precision {
+                Some(bits) => writeln!(stdout, "{bits} bits")?,
+                None => writeln!(stdout, "53 bits (f64)")?,
+            },
+            ["off"] => options.precision = None,
+            ["bits", _] if !cfg!(feature = "mpfr") => {
+                writeln!(stdout, "{}", options.error(PRECISION_UNAVAILABLE))?
+            }
+            ["bits", bits] => match bits.parse() {
+                Ok(bits) if bits >= 2 => options.precision = Some(bits),
+                _ => writeln!(stdout, "{}", options.error("expected at least 2 bits"))?,
+            },
+            _ => writeln!(stdout, "{}", options.error("expected :precision bits N or :precision off"))?,
+        },
+        "digits" => match arg.split_whitespace().collect::<Vec<_>>()[..] {
+            [name, decimals] if DIGIT_CONSTANTS.contains(&name) => match decimals.parse() {
+                Ok(decimals @ 1..=DIGITS_LIMIT) if cfg!(feature = "mpfr") || decimals <= DECIMAL_DIGITS_LIMIT => {
+                    number::write_digits(name, decimals, stdout)?
+                }
+                Ok(1..=DIGITS_LIMIT) => writeln!(stdout, "{}", options.error(PRECISION_UNAVAILABLE))?,
+                _ => writeln!(stdout, "{}", options.error(format!("expected 1 to {DIGITS_LIMIT} digits")))?,
+            },
+            _ => writeln!(stdout, "{}", options.error("expected :digits pi, e, sqrt2 or ln2 and a number of digits"))?,
+        },
+        "warnings" => match arg.trim() {
+            "" => writeln!(stdout, "{}", if options.warnings { "on" } else { "off" })?,
+            "on" => options.warnings = true,
+            "off" => options.warnings = false,
+            _ => writeln!(stdout, "{}", options.error("expected :warnings on or :warnings off"))?,
+        },
+        "history" => {
+            for (i, line) in history.iter().enumerate() {
+                writeln!(stdout, "{:>4}  {line}", i + 1)?;
+            }
+        }
+        "unset" => {
+            if !ctx.unset(arg.trim()) {
+                let message = format!("unknown variable or function: {}", arg.trim());
+                writeln!(stdout, "{}", options.error(&message))?;
+            }
+        }
+        _ => {
+            let message = Text::new(Key::UnknownCommand, vec![format!(":{name}").into()]);
+            writeln!(stdout, "{}", options.error(message))?
+        }
+    }
+    Ok(())
+}
+
+/// Fetches the current exchange rates, and saves them where they were read from.
+#[cfg(feature = "online-rates")]
+fn update_rates(options: &mut Options, ctx: &mut Context) -> Result<(), String> {
+    let rates = currency::fetch()?;
+    if let Some(path) = options.rates_file.clone().or_else(currency::default_path) {
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|err| format!("{}: {err}", dir.display()))?;
+        }
+        std::fs::write(&path, rates.to_toml()).map_err(|err| format!("{}: {err}", path.display()))?;
+    }
+    ctx.set_rates(rates.clone());
+    options.rates = rates;
+    Ok(())
+}
+
+#[cfg(not(feature = "online-rates"))]
+fn update_rates(_: &mut Options, _: &mut Context) -> Result<(), String> {
+    Err("fetching rates needs the online-rates feature, so edit the rates file instead".into())
+}
+
+/// One line about a built-in function, like `sqrt(x) for x >= 0, e.g. sqrt(2) = 1.4142135623730951`.
+fn doc(f: &Function, ctx: &Context) -> String {
+    let mut line = f.signature();
+    if !f.domain.is_empty() {
+        line += &format!(" for {}", f.domain);
+    }
+    line += &format!(", e.g. {}", example(f, ctx));
+    if !ctx.is_loaded(f.module) {
+        line += &format!(" (in the {} module)", f.module.name());
+    }
+    line
+}
 
+/// The example of a built-in function with its result, like `sqrt(2) = 1.4142135623730951`.
+fn example(f: &Function, ctx: &Context) -> String {
+    // the example works even if the module of the function is not loaded
+    let mut ctx = ctx.clone();
+    ctx.load(f.module);
+    // shown like results, so that lengths are in feet and inches rather than their unit
+    match parser::parse_line(f.example).map(|e| ctx.run(&Statement::Expr(e))) {
+        Ok(Ok(Some(value))) => format!("{} = {value}", f.example),
+        _ => f.example.to_string(),
+    }
+}
+
+/// The numbers of a list variable or list expression for `:hist`.
+fn hist_values(arg: &str, options: &Options, ctx: &mut Context) -> Result<Vec<f64>, String> {
+    if let Some(values) = ctx.list_var(arg) {
+        return Ok(values.to_vec());
+    }
+    let expected = || format!("expected a list like v or [x ^ 2 for x in 1..10], got {arg}");
+    let e = parser::parse_line_with(arg, options.syntax).map_err(|_| expected())?;
+    match ctx.run(&Statement::Expr(e)).map_err(|err| err.message)? {
+        Some(Value::List(values)) => Ok(values),
+        _ => Err(expected()),
+    }
+}
+
+/// Lines with the cells of each column aligned, and the headers on the first line.
+fn table(headers: &[&str], rows: &[Vec<String>]) -> String {
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let headers = headers.iter().map(|h| h.to_string()).collect();
+    let mut res = String::new();
+    for row in std::iter::once(&headers).chain(rows) {
+        let mut line = String::new();
+        for (cell, width) in row.iter().zip(&widths) {
+            line += &format!("{cell:width$}  ");
+        }
+        res += line.trim_end();
+        res.push('\n');
+    }
+    res
+}
+
+/// The subexpressions that enclose the one where evaluating `line` failed, innermost first,
+/// as a table. Only errors in user functions, which can not be underlined, and deeply nested
+/// ones have a backtrace.
+fn backtrace(line: &str, err: &EvalError) -> Option<String> {
+    let in_function = err.backtrace.iter().any(|frame| frame.function.is_some());
+    if !in_function && err.backtrace.len() <= BACKTRACE_DEPTH {
+        return None;
+    }
+    let mut rows: Vec<_> = err
+        .backtrace
+        .iter()
+        .take(BACKTRACE_LIMIT)
+        .map(|frame| {
+            let location = match (&frame.function, frame.span) {
+                (Some(f), _) => format!("in {f}"),
+                (None, Some(span)) => format!("column {}", line[..span.start].chars().count() + 1),
+                (None, None) => String::new(),
+            };
+            vec![frame.expr.clone(), location]
+        })
+        .collect();
+    if err.backtrace.len() > BACKTRACE_LIMIT {
+        rows.push(vec![format!("and {} more", err.backtrace.len() - BACKTRACE_LIMIT)]);
+    }
+    Some(table(&["while evaluating", "at"], &rows))
+}
+
+/// Runs `lines` in order in one context, like the arguments of `-e` flags. Only the result
+/// of the last line is printed, unless `each` is set. Stops at the first error, and returns
+/// whether there was none.
+pub fn run_lines(
+    lines: &[String],
+    each: bool,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let mut options = options.clone();
+    let mut ctx = options.context();
+    for (i, line) in lines.iter().enumerate() {
+        let mut warnings = Vec::new();
+        let res = run_line(line, &mut ctx, &mut options, &mut warnings);
+        for warning in warnings {
+            match options.json_errors {
+                true => writeln!(stderr, "{}", json_error("warning", &warning, Some(i + 1), None))?,
+                false => writeln!(stderr, "warning: {warning}")?,
+            }
+        }
+        match res {
+            Ok(Some(res)) if each || i == lines.len() - 1 => writeln!(stdout, "{}", options.format(&res))?,
+            Ok(_) => {}
+            Err(err) if options.json_errors => {
+                let span = err.span.map(|span| (line.as_str(), span));
+                writeln!(stderr, "{}", json_error(error_code(line, &options), &err.message, Some(i + 1), span))?;
+                return Ok(false);
+            }
+            Err(err) => {
+                writeln!(stderr, "error: {err}")?;
+                for frame in backtrace(line, &err).iter().flat_map(|b| b.lines()) {
+                    writeln!(stderr, "  {frame}")?;
+                }
+                return Ok(false);
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Runs the lines of a script in one context, and prints the result of each expression.
+/// Empty lines and comments starting with `#` are skipped. Returns whether every line ran
+/// without errors.
+pub fn run_script(
+    source: &str,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let mut options = options.clone();
+    let mut ctx = options.context();
+    let mut failed = false;
+    for (n, line) in source.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut warnings = Vec::new();
+        let res = run_line(line, &mut ctx, &mut options, &mut warnings);
+        for warning in warnings {
+            match options.json_errors {
+                true => writeln!(stderr, "{}", json_error("warning", &warning, Some(n + 1), None))?,
+                false => writeln!(stderr, "warning: line {}: {warning}", n + 1)?,
+            }
+        }
+        match res {
+            Ok(Some(res)) => writeln!(stdout, "{}", options.format(&res))?,
+            Ok(None) => {}
+            Err(err) if options.json_errors => {
+                failed = true;
+                let span = err.span.map(|span| (line, span));
+                writeln!(stderr, "{}", json_error(error_code(line, &options), &err.message, Some(n + 1), span))?;
+            }
+            Err(err) => {
+                failed = true;
+                writeln!(stderr, "error: line {}: {err}", n + 1)?;
+                for frame in backtrace(line, &err).iter().flat_map(|b| b.lines()) {
+                    writeln!(stderr, "  {frame}")?;
+                }
+            }
+        }
+    }
+    Ok(!failed)
+}
+
+/// Whether the statement on `line` calls `assert` or `assert_eq`.
+fn is_assertion(line: &str, syntax: Syntax) -> bool {
+    let Ok(Statement::Expr(e) | Statement::Assign(_, e)) = parser::parse_statement(line, syntax) else {
+        return false;
+    };
+    e.nodes().iter().any(|n| matches!(n, Node::Call(name, _) if name == "assert" || name == "assert_eq"))
+}
+
+/// Runs a script like [`run_script`] without printing results, and reports the lines that fail,
+/// which are the failed assertions and any other errors. Returns whether every line passed.
+pub fn run_test(
+    source: &str,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let mut options = options.clone();
+    let mut ctx = options.context();
+    let (mut passed, mut failed) = (0, 0);
+    for (n, line) in source.lines().enumerate() {
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
+        }
+        let mut warnings = Vec::new();
+        let res = run_line(line, &mut ctx, &mut options, &mut warnings);
+        for warning in warnings {
+            writeln!(stderr, "warning: line {}: {warning}", n + 1)?;
+        }
+        match res {
+            Ok(_) if is_assertion(line, options.syntax) => passed += 1,
+            Ok(_) => {}
+            Err(err) => {
+                failed += 1;
+                writeln!(stderr, "failed: line {}: {}: {err}", n + 1, line.trim())?;
+                for frame in backtrace(line, &err).iter().flat_map(|b| b.lines()) {
+                    writeln!(stderr, "  {frame}")?;
+                }
+            }
+        }
+    }
+    writeln!(stdout, "{passed} passed, {failed} failed")?;
+    Ok(failed == 0)
+}
+
+/// Walks through the lessons of [`tutorial::LESSONS`], running each input like the REPL and
+/// going on to the next task once the input does the current one. `:hint` shows an input that
+/// does it, `:skip` skips it and `:quit` ends the tutorial.
+pub fn run_tutorial(
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    options: &Options,
+) -> Result<(), std::io::Error> {
+    let mut options = options.clone();
+    let mut ctx = options.context();
+    let count = LESSONS.len();
+    for (i, lesson) in LESSONS.iter().enumerate() {
+        writeln!(stdout, "Lesson {} of {count}: {}", i + 1, lesson.title)?;
+        for task in lesson.tasks {
+            writeln!(stdout, "{}", task.text)?;
+            loop {
+                if options.prompt {
+                    write!(stdout, "{}", options.paint(PROMPT, Role::Prompt))?;
+                    stdout.flush()?;
+                }
+                let mut line = String::new();
+                if stdin.read_line(&mut line)? == 0 {
+                    return Ok(());
+                }
+                let line = line.trim();
+                match line {
+                    "" => continue,
+                    ":hint" => {
+                        writeln!(stdout, "try: {}", options.highlight(task.hint))?;
+                        continue;
+                    }
+                    ":skip" => break,
+                    ":quit" => return Ok(()),
+                    _ => {}
+                }
+                let mut warnings = Vec::new();
+                let res = run_line(line, &mut ctx, &mut options, &mut warnings);
+                for warning in warnings {
+                    let warning = options.language.show(&Text::new(Key::Warning, vec![warning.into()]));
+                    writeln!(stdout, "{}", options.paint(&warning, Role::Warning))?;
+                }
+                let res = match res {
+                    Ok(res) => res,
+                    Err(err) => {
+                        writeln!(stdout, "{}", options.error(&err.message))?;
+                        continue;
+                    }
+                };
+                if let Some(res) = &res {
+                    writeln!(stdout, "{}", options.show(res))?;
+                }
+                if task.is_done(res.as_ref(), &ctx) {
+                    writeln!(stdout, "Correct!")?;
+                    break;
+                }
+                writeln!(stdout, "Not quite, try again or type :hint")?;
+            }
+        }
+        writeln!(stdout)?;
+    }
+    writeln!(stdout, "That was the last lesson, well done!")
+}
+
+/// Runs the script at `path` again every time it is modified. Only returns on errors.
+pub fn watch(
+    path: &Path,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    let mut last_modified = None;
     loop {
-        write!(stdout, "> ")?;
-        stdout.flush().unwrap();
+        // editors may briefly remove the file while saving it
+        if let Ok(modified) = std::fs::metadata(path).and_then(|m| m.modified()) {
+            if last_modified != Some(modified) {
+                last_modified = Some(modified);
+                let source = std::fs::read_to_string(path)?;
+                writeln!(stdout, "--- {} ---", path.display())?;
+                run_script(&source, options, stdout, stderr)?;
+                stdout.flush()?;
+            }
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Evaluates one JSON expression tree per line, see [`crate::json`] for the format.
+pub fn run_from_ast(
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    for line in stdin.lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let res = Json::parse(&line)
+            .and_then(|json| Expr::from_json(&json))
+            .and_then(|e| e.evaluate().map_err(|err| err.message.into()));
+        match res {
+            Ok(res) => writeln!(stdout, "{res}")?,
+            Err(err) => writeln!(stderr, "error: {err}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Prints the JSON expression tree of each input line.
+pub fn run_to_ast(
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    for line in stdin.lines() {
+        match parser::parse_line(&line?) {
+            Ok(e) => writeln!(stdout, "{}", e.to_json())?,
+            Err(err) => writeln!(stderr, "error: {err}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Evaluates the whole input as one expression, which is parsed as it is read, so that huge
+/// generated expressions are never held as a string.
+pub fn run_stream(
+    input: &mut impl BufRead,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    let mut ctx = options.context();
+    let res = stream::parse_reader(input).and_then(|e| {
+        // checking recurses into the tree, and a long sum is as deep as it is long
+        let stack = (2 << 20) + e.depth() * STACK_PER_LEVEL;
+        std::thread::scope(|scope| {
+            let thread = std::thread::Builder::new().stack_size(stack).spawn_scoped(scope, || {
+                ctx.run(&Statement::Expr(e)).map_err(|err| err.message.into())
+            });
+            thread.map_err(|err| err.to_string())?.join().map_err(|_| "the evaluation crashed".to_string())?
+        })
+    });
+    match res {
+        Ok(Some(res)) => writeln!(stdout, "{}", options.format(&res)),
+        Ok(None) => Ok(()),
+        Err(err) => writeln!(stderr, "error: {err}"),
+    }
+}
+
+/// Prints each input line with canonical spacing and only the parentheses that are needed,
+/// like `1 + 2 * 3` for `1+2 *(3 )`. Empty lines and comments starting with `#` are kept.
+/// Returns whether every line could be parsed.
+pub fn run_fmt(
+    input: &mut impl BufRead,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let mut parsed = true;
+    for (n, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            writeln!(stdout, "{line}")?;
+            continue;
+        }
+        match parser::parse_statement(&line, options.syntax) {
+            Ok(Statement::Expr(e)) => writeln!(stdout, "{e}")?,
+            Ok(Statement::Assign(name, e)) => writeln!(stdout, "{name} = {e}")?,
+            Ok(Statement::Define(name, f)) => writeln!(stdout, "{}", definition(&name, &f))?,
+            Ok(Statement::Memory(_)) => writeln!(stdout, "{}", line.trim())?,
+            Err(err) => {
+                writeln!(stderr, "error: line {}: {err}", n + 1)?;
+                parsed = false;
+            }
+        }
+    }
+    Ok(parsed)
+}
+
+/// Prints whether two expressions are equivalent, and whether that is certain because they
+/// simplify to the same polynomial, or values of their variables where they differ.
+pub fn run_equiv(
+    left: &str,
+    right: &str,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    let parse = |line| parser::parse_line_with(line, options.syntax);
+    let (left, right) = match (parse(left), parse(right)) {
+        (Ok(left), Ok(right)) => (left, right),
+        (Err(err), _) | (_, Err(err)) => return writeln!(stderr, "error: {err}"),
+    };
+    match equiv::equivalent(&left, &right, &options.context()) {
+        Ok(Verdict::Same) => writeln!(stdout, "equivalent"),
+        Ok(Verdict::Simplified(simplified)) => writeln!(stdout, "equivalent, since both are {simplified}"),
+        Ok(Verdict::Agree(1)) => writeln!(stdout, "equal"),
+        Ok(Verdict::Agree(n)) => writeln!(stdout, "equivalent, as far as {n} random tests can tell"),
+        Ok(Verdict::Differ { assignment, left, right }) => {
+            let assignment: Vec<_> = assignment.iter().map(|(name, v)| format!("{name} = {v}")).collect();
+            if assignment.is_empty() {
+                writeln!(stdout, "not equivalent: {left} and {right}")
+            } else {
+                let assignment = assignment.join(", ");
+                writeln!(stdout, "not equivalent: with {assignment} they are {left} and {right}")
+            }
+        }
+        Err(err) => writeln!(stderr, "error: {err}"),
+    }
+}
 
-        line.clear();
-        let bytes_read = stdin.read_line(&mut line).unwrap();
-        if bytes_read == 0 {
-            // EOF
-            return Ok(());
+/// Prints the count, min, max, mean, median, standard deviation and percentiles of the numbers
+/// in the input, which are separated by whitespace.
+pub fn run_stats(
+    input: &mut impl BufRead,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    let values = match stats::parse(&text) {
+        Ok(values) => values,
+        Err(err) => return writeln!(stderr, "error: {err}"),
+    };
+    let Some(summary) = Summary::new(&values) else {
+        return writeln!(stderr, "error: there are no numbers to summarize");
+    };
+    let rows = summary.rows();
+    let width = rows.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+    for (name, x) in rows {
+        writeln!(stdout, "{name:<width$}  {}", Value::Number(x))?;
+    }
+    Ok(())
+}
+
+/// Prints a histogram of the numbers in the input, which are separated by whitespace, with
+/// `bins` bins of equal width.
+pub fn run_hist(
+    bins: usize,
+    input: &mut impl BufRead,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    let mut text = String::new();
+    input.read_to_string(&mut text)?;
+    match stats::parse(&text) {
+        Ok(values) if values.is_empty() => writeln!(stderr, "error: there are no numbers to draw"),
+        Ok(values) => write!(stdout, "{}", stats::histogram(&stats::bins(&values, bins))),
+        Err(err) => writeln!(stderr, "error: {err}"),
+    }
+}
+
+/// Converts the quantity to the unit, like `90 min` to `hours`, and prints only the number.
+/// Returns whether the quantity could be converted.
+pub fn run_convert(
+    quantity: &str,
+    unit: &str,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let unit = unit.trim();
+    if !parser::is_unit(unit) {
+        let expected = "a time like h or s, a length like ft or km, a speed like m/s, or a currency like EUR";
+        writeln!(stderr, "error: unknown unit: {unit}, expected {expected}")?;
+        return Ok(false);
+    }
+    let e = match parser::parse_line_with(&format!("({quantity}) to {unit}"), Syntax::Native) {
+        Ok(e) => e,
+        Err(err) => {
+            writeln!(stderr, "error: {err}")?;
+            return Ok(false);
+        }
+    };
+    match options.context().run(&Statement::Expr(e)) {
+        Ok(Some(res @ (Value::Number(_) | Value::Money(..)))) => {
+            writeln!(stdout, "{res}")?;
+            Ok(true)
+        }
+        // like a length in hours, which is a speed
+        Ok(_) => {
+            writeln!(stderr, "error: cannot convert {quantity} to {unit}")?;
+            Ok(false)
+        }
+        Err(err) => {
+            writeln!(stderr, "error: {}", err.message)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Prints `count` random expressions, each followed by a tab and its value when `values` is set.
+pub fn run_generate(
+    generator: &mut Generator,
+    count: usize,
+    values: bool,
+    stdout: &mut impl Write,
+) -> Result<(), std::io::Error> {
+    for _ in 0..count {
+        let e = generator.expression();
+        match (values, e.evaluate()) {
+            (true, Ok(value)) => writeln!(stdout, "{e}\t{value}")?,
+            (true, Err(err)) => writeln!(stdout, "{e}\terror: {err}")?,
+            (false, _) => writeln!(stdout, "{e}")?,
+        }
+    }
+    Ok(())
+}
+
+/// Serves editors as a language server over stdin and stdout.
+pub fn run_lsp(
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    options: &Options,
+) -> Result<(), std::io::Error> {
+    lsp::run(stdin, stdout, options.context())
+}
+
+/// Evaluates for WebSocket clients on `addr`, until the process is stopped.
+pub fn run_serve(addr: &str, options: &Options, stderr: &mut impl Write) -> Result<(), std::io::Error> {
+    server::serve(addr, &options.context(), stderr)
+}
+
+/// Lints a statement in the given syntax.
+fn lint_line(line: &str, syntax: Syntax) -> Result<Vec<Lint>, String> {
+    Ok(match parser::parse_statement(line, syntax)? {
+        Statement::Expr(e) | Statement::Assign(_, e) => e.lint(line),
+        Statement::Define(_, f) => f.body.lint(line),
+        Statement::Memory(_) => Vec::new(),
+    })
+}
+
+/// Prints what could be improved in each input line, like `x * 1`, with the line and column
+/// where it is. Returns whether there was nothing to report, and every line could be parsed.
+pub fn run_lint(
+    input: &mut impl BufRead,
+    options: &Options,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let mut clean = true;
+    for (n, line) in input.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() || line.trim_start().starts_with('#') {
+            continue;
         }
-        let line = line.strip_suffix('\n').unwrap_or_else(|| &line);
+        match lint_line(&line, options.syntax) {
+            Ok(lints) => {
+                for lint in lints {
+                    let column = lint.span.map_or(1, |s| line[..s.start].chars().count() + 1);
+                    writeln!(stdout, "line {}, column {column}: {}", n + 1, lint.message)?;
+                    clean = false;
+                }
+            }
+            Err(err) => {
+                writeln!(stderr, "error: line {}: {err}", n + 1)?;
+                clean = false;
+            }
+        }
+    }
+    Ok(clean)
+}
 
-        match parser::parse_line(line) {
-            Ok(v) => match v.evaluate() {
-                Ok(res) => writeln!(stdout, "{}", res.to_string().green())?,
-                Err(err) => writeln!(stdout, "{}", err.red())?,
+/// Evaluates `expression` for each line of delimited fields, and prints the line with the
+/// result appended as a new field. The fields are bound to the variables `c1`, `c2`, ...
+/// Without a delimiter, fields are separated by whitespace. An expression like
+/// `format(c1 * 2, "0.2f")` formats the results. Each line is printed as soon as it is read,
+/// so that the input can be endless like `tail -f`. Plain arithmetic on the fields is compiled
+/// once, and anything else runs like a line of the REPL. Returns whether every line got a result.
+pub fn run_filter(
+    expression: &str,
+    delimiter: Option<char>,
+    options: &Options,
+    stdin: &mut impl BufRead,
+    stdout: &mut impl Write,
+    stderr: &mut impl Write,
+) -> Result<bool, std::io::Error> {
+    let report = |stderr: &mut dyn Write, code, message: &str, line: Option<usize>| {
+        match options.json_errors {
+            true => writeln!(stderr, "{}", json_error(code, message, line, None))?,
+            false => match line {
+                Some(n) => writeln!(stderr, "error: line {n}: {message}")?,
+                None => writeln!(stderr, "error: {message}")?,
             },
-            Err(err) => writeln!(stdout, "{}", err.red())?,
         }
+        Ok(false)
+    };
+    let e = match parser::parse_line_with(expression, options.syntax) {
+        Ok(e) => e,
+        Err(err) => return report(stderr, "syntax", &err, None),
+    };
+    let mut ctx = options.context();
+    // the fields that are used, and their indices
+    let (mut vars, mut columns) = (Vec::new(), Vec::new());
+    // only arithmetic on the fields in `f64` is compiled
+    let mut plain = options.int.is_none() && !options.decimal && options.precision.is_none();
+    plain = plain && !options.repeating && !options.roundoff && !e.has_uncertainty();
+    for node in e.nodes() {
+        let Node::Var(var) = node else { continue };
+        match var.strip_prefix('c').and_then(|i| i.parse::<usize>().ok()) {
+            Some(_) if vars.contains(&var.as_str()) => {}
+            Some(i) if i >= 1 => {
+                vars.push(var.as_str());
+                columns.push(i - 1);
+            }
+            // like `$HOME`, which is the same for every line
+            _ => {
+                if let Err(err) = ctx.lookup(var) {
+                    return report(stderr, "eval", &err, None);
+                }
+                plain = false;
+            }
+        }
+    }
+    let batch = match e.check(&ctx) {
+        Ok(Type::Number) if plain => e.compile_batch(&vars).ok(),
+        _ => None,
+    };
+    let statement = Statement::Expr(e.clone());
+
+    let mut succeeded = true;
+    for (n, line) in stdin.lines().enumerate() {
+        let line = line?;
+        let fields: Vec<&str> = match delimiter {
+            Some(d) => line.split(d).collect(),
+            None => line.split_whitespace().collect(),
+        };
+        let row = columns.iter().map(|&i| {
+            let field = fields.get(i).ok_or_else(|| format!("missing field c{}", i + 1))?;
+            field.trim().parse().map_err(|_| format!("field c{} is not a number: {field:?}", i + 1))
+        });
+        let res = row.collect::<Result<Vec<f64>, String>>().map_err(|err| ("input", err)).and_then(|row| {
+            let res = match &batch {
+                Some(batch) => batch.run(&row).map(|res| Value::Number(res).to_string()),
+                None => {
+                    for (&var, x) in vars.iter().zip(row) {
+                        let field = Statement::Assign(var.to_string(), Expr::from(x));
+                        run_statement(&field, &mut ctx, options).map_err(|err| ("eval", err.message.into()))?;
+                    }
+                    let res = run_statement(&statement, &mut ctx, options).map_err(|err| err.message.into());
+                    res.map(|res| res.map_or_else(String::new, |res| res.to_string()))
+                }
+            };
+            res.map_err(|err| ("eval", err))
+        });
+        match res {
+            Ok(res) => writeln!(stdout, "{line}{}{res}", delimiter.unwrap_or(' '))?,
+            Err((code, err)) => {
+                report(stderr, code, &err, Some(n + 1))?;
+                succeeded = false;
+            }
+        }
+    }
+    Ok(succeeded)
+}
+
+/// Whether the error of `line` is a `syntax` error or an `eval` error.
+fn error_code(line: &str, options: &Options) -> &'static str {
+    match parse_input(line, options) {
+        Ok(_) => "eval",
+        Err(_) => "syntax",
     }
 }
 
+/// An error for `--json-errors`, like `{"code":"eval","message":"unknown variable: y","line":2,
+/// "span":{"start":4,"end":5}}`, where `line` counts from 1 and the span counts characters
+/// of the line from 0.
+fn json_error(code: &str, message: &str, line: Option<usize>, span: Option<(&str, Span)>) -> Json {
+    let number = |n: usize| Json::Number(n as f64);
+    let span = match span {
+        Some((line, span)) => Json::Object(vec![
+            ("start".into(), number(line[..span.start].chars().count())),
+            ("end".into(), number(line[..span.end].chars().count())),
+        ]),
+        None => Json::Null,
+    };
+    Json::Object(vec![
+        ("code".into(), Json::String(code.into())),
+        ("message".into(), Json::String(message.into())),
+        ("line".into(), line.map_or(Json::Null, number)),
+        ("span".into(), span),
+    ])
+}
+
+/// Marks the given part of the input line, which is printed `indent` columns to the right.
+fn underline(line: &str, span: Span, indent: usize) -> String {
+    let indent = indent + line[..span.start].chars().count();
+    let width = line[span.start..span.end].chars().count().max(1);
+    format!("{}{}", " ".repeat(indent), "^".repeat(width))
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::BufReader;
@@ -39,6 +1685,12 @@ mod tests {
         pub const RESET: &str = "\u{1b}[0m";
         pub const FG_RED: &str = "\u{1b}[31m";
         pub const FG_GREEN: &str = "\u{1b}[32m";
+        pub const FG_YELLOW: &str = "\u{1b}[33m";
+        pub const FG_CYAN: &str = "\u{1b}[36m";
+        pub const BOLD: &str = "\u{1b}[1;";
+        pub const FG_BRIGHT_WHITE: &str = "97m";
+        pub const FG_BRIGHT_RED: &str = "91m";
+        pub const FG_BRIGHT_CYAN: &str = "96m";
     }
 
     #[test]
@@ -54,7 +1706,26 @@ mod tests {
         ];
 
         let mut output = Vec::new();
-        run_cli(&mut BufReader::new(input.as_bytes()), &mut output).unwrap();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_booleans() {
+        let input = "1 < 2\nfalse\nif(true, 3, 4)";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_CYAN, "true", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_CYAN, "false", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
 
         assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
     }
@@ -78,7 +1749,7 @@ mod tests {
         ];
 
         let mut output = Vec::new();
-        run_cli(&mut BufReader::new(input.as_bytes()), &mut output).unwrap();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
 
         assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
     }
@@ -96,30 +1767,1547 @@ mod tests {
         ];
 
         let mut output = Vec::new();
-        run_cli(&mut BufReader::new(input.as_bytes()), &mut output).unwrap();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
 
         assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
     }
 
     #[test]
-    fn cli_multiple_prompts() {
-        let input = "1 + 2\n3 * 4";
+    fn cli_eval_error() {
+        let input = "1 + foo * 2";
         let expected_output = [
             // initial prompt
             "> ",
-            // answer (with color)
-            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
-            // next prompt
-            "> ",
-            // answer (with color)
-            &[ansi::FG_GREEN, "12", ansi::RESET, "\n"].concat(),
+            // location of the error (with color)
+            &[ansi::FG_RED, "      ^^^", ansi::RESET, "\n"].concat(),
+            // error message (with color)
+            &[ansi::FG_RED, "unknown variable: foo", ansi::RESET, "\n"].concat(),
             // next prompt
             "> ",
         ];
 
         let mut output = Vec::new();
-        run_cli(&mut BufReader::new(input.as_bytes()), &mut output).unwrap();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
 
         assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
     }
+
+    #[test]
+    fn cli_piped() {
+        let input = "1 + 2\n1 + foo * 2";
+        let expected_output = ["3\n", "1 + foo * 2\n", "    ^^^\n", "unknown variable: foo\n"];
+
+        let options = Options {
+            prompt: false,
+            color: false,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_commands() {
+        let input = ":dot 1\n:nope";
+        let expected_output = [
+            "> ",
+            "digraph expr {\n    node [shape=box];\n    n0 [label=\"1\"];\n}\n",
+            "> ",
+            &[ansi::FG_RED, "unknown command: :nope", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_multiple_prompts() {
+        let input = "1 + 2\n3 * 4";
+        let expected_output = [
+            // initial prompt
+            "> ",
+            // answer (with color)
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            // next prompt
+            "> ",
+            // answer (with color)
+            &[ansi::FG_GREEN, "12", ansi::RESET, "\n"].concat(),
+            // next prompt
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_excel() {
+        let input = "=ROUND(SUM(1,2,3)/7, 2)\n=IF(2<>2, 1, 0)";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "0.86", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "0", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let options = Options {
+            syntax: Syntax::Excel,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_bc() {
+        let input = "7 / 2\nscale=2\n7 / 3\nscale";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            // assignments print nothing
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "2.33", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "2", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let options = Options {
+            syntax: Syntax::Bc { scale: 0 },
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_assignments() {
+        let input = "x = 2\nx * 3";
+        let expected_output = ["> ", "> ", &[ansi::FG_GREEN, "6", ansi::RESET, "\n"].concat(), "> "];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_undo() {
+        let input = "x = 1\nx = 2\n:undo\n:undo\n:undo";
+        let expected_output = [
+            "> ",
+            "> ",
+            "> ",
+            "x = 1\n",
+            "> ",
+            "x is undefined\n",
+            "> ",
+            &[ansi::FG_RED, "nothing to undo", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_symbols() {
+        let input = "b = 2\na = 1\nf(x, y) = x*(y + a)\n:vars\n:funcs\n:unset a\n:unset a\n:vars\n:undo";
+        let expected_output = [
+            "> ",
+            "> ",
+            "> ",
+            "> ",
+            "name  value\na     1\nb     2\n",
+            "> ",
+            "f(x, y) = x * (y + a)\n",
+            "> ",
+            "> ",
+            &[ansi::FG_RED, "unknown variable or function: a", ansi::RESET, "\n"].concat(),
+            "> ",
+            "name  value\nb     2\n",
+            "> ",
+            "a = 1\n",
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_modules() {
+        let input = ":load\n:load bits\nbor(1, 2)\n:unload trig\n:unload core\n:load foo";
+        let expected_output = [
+            "> ",
+            "module   status\ncore     loaded\ntrig     loaded\nstats    loaded\n",
+            "finance  not loaded\nbits     not loaded\n",
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_RED, "the core module can not be unloaded", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "unknown module: foo", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+
+        let options = Options {
+            modules: vec![Module::Finance],
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&["pmt(0, 4, 100)".into()], false, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(String::from_utf8(output), Ok("-25\n".into()));
+    }
+
+    #[test]
+    fn cli_rpn() {
+        let input = ":mode rpn\n:mode\n1 2\n+ 4 *\nswap\n:mode infix\n1 + 2";
+        let expected_output = [
+            "> ",
+            "> ",
+            "rpn\n",
+            "> ",
+            &[ansi::FG_GREEN, "1 2", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "12", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "swap needs 2 values, but the stack has 1", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn cli_decimal() {
+        let input = ":mode decimal\n:mode\n0.1 + 0.2\n0.1 + 0.2 == 0.3\n1 / 3\n\
+                     x = 0.1 + 0.2\nx == 0.3\ny = 1 / 3\ny - 1 / 3\n:mode infix\n0.1 + 0.2\nx = 0.1 + 0.2\n:mode decimal\nx == 0.3";
+        let expected_output = [
+            "> ",
+            "> ",
+            "decimal\n",
+            "> ",
+            &[ansi::FG_GREEN, "0.3", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_CYAN, "true", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "0.3333333333333333333333333333333333", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_CYAN, "true", ansi::RESET, "\n"].concat(),
+            // the exact decimal is kept, not the closest f64
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "0", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "0.30000000000000004", ansi::RESET, "\n"].concat(),
+            // assigned with f64
+            "> ",
+            "> ",
+            "> ",
+            &[ansi::FG_CYAN, "false", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn history_expansion() {
+        let history = ["1 + 2".to_string(), "x = 3".to_string()];
+        assert_eq!(expand_history("1 != 2", &history), Ok(None));
+        assert_eq!(expand_history("!!", &history), Ok(Some("x = 3".into())));
+        assert_eq!(expand_history("(!1) * 2 != !!", &history), Ok(Some("(1 + 2) * 2 != x = 3".into())));
+        assert_eq!(expand_history("!3", &history), Err("no such history entry: !3".into()));
+        assert_eq!(expand_history("!0", &history), Err("no such history entry: !0".into()));
+        assert_eq!(expand_history("!!", &[]), Err("there is no previous input".into()));
+    }
+
+    #[test]
+    fn cli_numbered_results() {
+        let input = ":numbering on\n6 * 7\nx = 2\nout[1] / x\nin[2] + in[1]\nin[9]\nmin[1]";
+        let expected_output = [
+            "> ",
+            "> ",
+            &["[1] ", ansi::FG_GREEN, "42", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &["[2] ", ansi::FG_GREEN, "21", ansi::RESET, "\n"].concat(),
+            "> ",
+            "(out[1] / x) + (6 * 7)\n",
+            &["[3] ", ansi::FG_GREEN, "63", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "there is no in[9]", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "could not parse the end of the input, namely: \"[1]\"", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn applying_to_previous() {
+        for (input, output) in [
+            ("* 2", Some("ans * 2")),
+            ("  / (1 + x) ", Some("ans / (1 + x)")),
+            ("- 3", Some("ans - 3")),
+            ("-3", None),
+            ("+ 10%", Some("ans + ans * (10 / 100)")),
+            ("- 2.5 %", Some("ans - ans * (2.5 / 100)")),
+            ("/ 50%", Some("ans / (50 / 100)")),
+            ("+", None),
+            ("1 + 2", None),
+        ] {
+            assert_eq!(apply_to_previous(input).as_deref(), output, "input was {input:?}");
+        }
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn cli_apply_to_previous() {
+        let input = "40\n+ 2\n* 10%\n-1";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "40", ansi::RESET, "\n"].concat(),
+            "> ",
+            "ans + 2\n",
+            &[ansi::FG_GREEN, "42", ansi::RESET, "\n"].concat(),
+            "> ",
+            "ans * (10 / 100)\n",
+            &[ansi::FG_GREEN, "4.2", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "-1", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_history() {
+        let input = "1 + 2\n!!\n!1 * 2\n!9\n:history";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            "> ",
+            "1 + 2\n",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            "> ",
+            "1 + 2 * 2\n",
+            &[ansi::FG_GREEN, "5", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "no such history entry: !9", ansi::RESET, "\n"].concat(),
+            "> ",
+            "   1  1 + 2\n   2  1 + 2\n   3  1 + 2 * 2\n   4  :history\n",
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_blocks() {
+        let input = [
+            "f(x) = do\n  x ^ 2\n\n  + 1\nend\nf(2)\n",
+            "g(x) = {\n  x *\n  2 }\ng(3)\n",
+            "legend = 1\nh(x) = do\nx + legend end\nh(1)\n",
+        ]
+        .concat();
+        let options = Options {
+            prompt: false,
+            color: false,
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok("5\n6\n2\n".into()));
+        assert_eq!(join_block("f(x) = do", &["x ^ 2".into(), "".into(), "+ 1".into()]), "f(x) = (x ^ 2 + 1)");
+        assert_eq!(block_opened("f(x) = undo"), None);
+        assert_eq!(block_closed("x + legend", "end"), None);
+    }
+
+    #[test]
+    fn cli_plain() {
+        let input = "x = 2\n!!\n1 / 0 == 0.1 + 0.2\nx * y\n:numbering on\nx + 1\n";
+        let mut options = Options {
+            numbering: true,
+            ..Options::default()
+        };
+        options.make_plain();
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok("\n\nfalse\nunknown variable: y\n3\n".into()));
+    }
+
+    #[test]
+    fn cli_persistent_history() {
+        let dir = std::env::temp_dir().join(format!("calculator-cli-history-{}", std::process::id()));
+        let options = Options {
+            prompt: false,
+            color: false,
+            history_file: Some(dir.join("history")),
+            ..Options::default()
+        };
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new("1 + 2\n\n".as_bytes()), &mut output, &options).unwrap();
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new("!! * 2\n:history".as_bytes()), &mut output, &options).unwrap();
+        assert_eq!(
+            String::from_utf8(output),
+            Ok("1 + 2 * 2\n5\n   1  1 + 2\n   2  1 + 2 * 2\n   3  :history\n".into())
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cli_paste() {
+        let input = "\x1b[200~1 + 2\nx\n3 * 4\x1b[201~\n\x1b[200~max(1,\n  2)\n\x1b[201~\n5";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+            "x\n",
+            &[ansi::FG_RED, "^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "unknown variable: x", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_GREEN, "12", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "2", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "5", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+
+        let options = Options {
+            bracketed_paste: true,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new("".as_bytes()), &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output), Ok("\x1b[?2004h> \x1b[?2004l".into()));
+    }
+
+    #[test]
+    fn cli_bases() {
+        let input = "42\n:bases on\n42\n-5\n0.5\n:bases\n:bases off\n42";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "42", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "42 (0x2A, 0b101010)", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "-5 (-0x5, -0b101)", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "0.5", ansi::RESET, "\n"].concat(),
+            "> ",
+            "on\n",
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "42", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_twos_complement() {
+        let input = ":bases 32\n-1\n:bases\n5\n:bases 8\n-128\n-129";
+        let expected_output = [
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "-1 (0xFFFFFFFF, 0b11111111111111111111111111111111)", ansi::RESET, "\n"].concat(),
+            "> ",
+            "on, with 32-bit two's complement\n",
+            "> ",
+            &[ansi::FG_GREEN, "5 (0x5, 0b101)", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "-128 (0x80, 0b10000000)", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "-129 (-0x81, -0b10000001)", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn identify_results() {
+        let options = Options {
+            identify: true,
+            ..Options::default()
+        };
+        let lines = ["pi() / 2".into(), "1 / 3".into(), "0.25 + 2".into(), "sqrt(7)".into()];
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&lines, true, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(
+            String::from_utf8(output),
+            Ok("1.5707963267948966 ≈ π/2\n0.3333333333333333 ≈ 1/3\n2.25 ≈ 9/4\n2.6457513110645907\n".into())
+        );
+    }
+
+    #[test]
+    fn grouped_numbers() {
+        let options = Options {
+            grouping: true,
+            language: Language::Swedish,
+            ..Options::default()
+        };
+        let lines = ["1 234,5 * 2".into(), "max(1,5)".into(), "1 000 / 0,5".into()];
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&lines, true, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(String::from_utf8(output), Ok("2469\n5\n2000\n".into()));
+    }
+
+    #[test]
+    fn recursion_limit() {
+        let options = Options {
+            recursion_limit: 5,
+            ..Options::default()
+        };
+        let lines = ["f(n) = if(n == 0, 0, 1 + f(n - 1))".into(), "f(4)".into(), "f(5)".into()];
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&lines, true, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(String::from_utf8(output), Ok("4\n".into()));
+        let errors = String::from_utf8(errors).unwrap();
+        assert!(errors.starts_with("error: recursion limit exceeded in f(0)\n  while evaluating"), "{errors}");
+        // long backtraces are cut
+        assert!(errors.ends_with("  and 6 more\n"), "{errors}");
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn repeating_decimals() {
+        let options = Options {
+            repeating: true,
+            ..Options::default()
+        };
+        let lines = ["x = 1 / 4".into(), "x".into(), "x / 3 - 1".into(), "sqrt(2)".into()];
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&lines, true, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(String::from_utf8(output), Ok("0.25\n-0.91(6)\n1.4142135623730951\n".into()));
+    }
+
+    #[test]
+    fn cli_int() {
+        let input = ":int\n:int u8\n:int\n200 + 100\n:int i32 saturate\n2 ^ 40\n7 / 0\n:int i16\n:int off\n7 / 2";
+        let expected_output = [
+            "> ",
+            "off\n",
+            "> ",
+            "> ",
+            "u8 wrap\n",
+            "> ",
+            &[ansi::FG_GREEN, "44", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "2147483647", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "division by zero", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "expected :int u8|u16|u32|i32|i64 [wrap|saturate] or :int off", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "3.5", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn roundoff_estimates() {
+        let options = Options {
+            roundoff: true,
+            ..Options::default()
+        };
+        let lines = [
+            "2 ^ 10 + 1".into(),
+            "0.1 + 0.2".into(),
+            "(1 + 0.000000000000001) - 1".into(),
+            "0.3 - 0.1 * 3".into(),
+            "1 < 2".into(),
+        ];
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&lines, true, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            [
+                "1025 (exact)\n",
+                "0.30000000000000004 (relative error ≈ 2.2e-16)\n",
+                "0.0000000000000011102230246251565 (relative error ≈ 1.0e-1)\n",
+                "-0.00000000000000005551115123125783 (relative error ≈ 1.8e0)\n",
+                "true\n",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn cli_explain() {
+        let input = ":explain 1+2*3^2\n:explain -2 ^ 2 / 4 < 1\n:explain 1 +";
+        let expected_output = [
+            "> ",
+            "(1 + (2 * (3 ^ 2)))\n",
+            "> ",
+            "(((-(2 ^ 2)) / 4) < 1)\n",
+            "> ",
+            &[ansi::FG_RED, "invalid term: \"\"", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_cfrac() {
+        let input = ":cfrac sqrt(2)\n:cfrac 415 / 93\n:cfrac y";
+        let expected_output = [
+            "> ",
+            "[1; 2, 2, 2, …]\n",
+            "> ",
+            "[4; 2, 6, 7]\n",
+            "> ",
+            &[ansi::FG_RED, "unknown variable: y", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let options = Options {
+            cfrac_terms: 4,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_lists() {
+        let input = "[x ^ 2 for x in 1..4]\nv = [1, -2]\n[2 * x for x in v if x < 0]\n[x for x in 1..3 if x ^ 2]\n\
+                     range(0, 1, 0.25)\nsum(1..4)\nsum(v) * 3\nmap(v, x -> x * 10)";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "[1, 4, 9, 16]", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "[-4]", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "                      ^^^^^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "the function of filter must return a boolean, not a number", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "[0, 0.25, 0.5, 0.75]", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "10", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "-3", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "[10, -20]", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_format() {
+        let input = "format(2 / 3, \"0.3f\")\nformat(2 / 3 * 3, \"0.0f\")\nformat(-255, “hex”)\nformat(1 < 2, \"bin\")";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_GREEN, "0.667", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "2", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "-0xFF", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "         ^^^^^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "format takes a number, not a boolean", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_fft() {
+        let input = "v = [1, 2, 3, 4]\nfft(v)\nifft([4, 0, 0, 0])\nfft(map(v, x -> x ^ 2))";
+        let expected_output = [
+            "> ",
+            "> ",
+            &[ansi::FG_GREEN, "[10, -2 + 2i, -2, -2 - 2i]", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "[1, 1, 1, 1]", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_GREEN, "[30, -8 + 12i, -10, -8 - 12i]", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn cli_bits() {
+        let input = ":bits 0.1\n:bits -1 / 0\n:bits y\nbits(0.1)";
+        let expected_output = [
+            "> ",
+            "field     bits                                                  hex              value\n",
+            "sign      0                                                     0x0              +\n",
+            "exponent  01111111011                                           0x3FB            2^-4\n",
+            "mantissa  1001100110011001100110011001100110011001100110011010  0x999999999999A  ",
+            "1.600000000000000088817841970012523233890533447265625\n",
+            "exactly 0.1000000000000000055511151231257827021181583404541015625\n",
+            "> ",
+            "field     bits                                                  hex              value\n",
+            "sign      1                                                     0x1              -\n",
+            "exponent  11111111111                                           0x7FF            infinity\n",
+            "mantissa  0000000000000000000000000000000000000000000000000000  0x0000000000000  1\n",
+            "exactly -inf\n",
+            "> ",
+            &[ansi::FG_RED, "unknown variable: y", ansi::RESET, "\n"].concat(),
+            "> ",
+            ansi::FG_GREEN,
+            "sign 0 (+), exponent 01111111011 = 0x3FB (2^-4), ",
+            "mantissa 1001100110011001100110011001100110011001100110011010 = 0x999999999999A ",
+            "(1.600000000000000088817841970012523233890533447265625)",
+            ansi::RESET,
+            "\n> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn custom_prompt() {
+        let input = "1 + y\n:mode rpn\n1";
+        let expected_output = [
+            "[1 infix] ",
+            // the prompt is wider than the default one
+            "              ^\n",
+            "unknown variable: y\n",
+            "[2 infix] ",
+            "[3 rpn] ",
+            "1\n",
+            "[4 rpn] ",
+        ];
+
+        let options = Options {
+            prompt_format: "[{n} {mode}] ".into(),
+            color: false,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(not(feature = "mpfr"))]
+    #[test]
+    fn cli_precision() {
+        let input = ":precision\n:precision bits 1000\n:precision bytes 3";
+        let expected_output = [
+            "> ",
+            "53 bits (f64)\n",
+            "> ",
+            &[ansi::FG_RED, PRECISION_UNAVAILABLE, ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "expected :precision bits N or :precision off", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(feature = "mpfr")]
+    #[test]
+    fn cli_precision() {
+        let input = ":precision bits 200\n:precision\nsqrt(2)\n:precision off\nsqrt(2)";
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.contains("200 bits\n"));
+        assert!(output.contains("1.41421356237309504880168872420969807"));
+        assert!(output.contains(&[ansi::FG_GREEN, "1.4142135623730951", ansi::RESET].concat()));
+    }
+
+    #[test]
+    fn cli_digits() {
+        let input = ":digits tau 10\n:digits pi 0\n:digits e";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_RED, "expected :digits pi, e, sqrt2 or ln2 and a number of digits", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "expected 1 to 1000000 digits", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "expected :digits pi, e, sqrt2 or ln2 and a number of digits", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(feature = "mpfr")]
+    #[test]
+    fn cli_digits_of_pi() {
+        let input = ":digits pi 100";
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let pi = "3.14159265358979323846264338327950288419716939937510582097494459230781640628620899862803482534211706";
+        assert!(output.contains(&[pi, "79\n"].concat()));
+    }
+
+    #[cfg(not(feature = "mpfr"))]
+    #[test]
+    fn cli_digits_without_mpfr() {
+        let input = ":digits pi 30\n:digits pi 31";
+        let expected_output = [
+            "> ",
+            "3.141592653589793238462643383279\n",
+            "> ",
+            &[ansi::FG_RED, PRECISION_UNAVAILABLE, ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn cli_warnings() {
+        let input = "0.1 + 0.2 == 0.3\n:warnings off\n0.1 + 0.2 == 0.3";
+        let warning = "warning: comparing fractional numbers with == is unreliable because of rounding errors";
+        let expected_output = [
+            "> ",
+            &[ansi::FG_YELLOW, warning, ansi::RESET, "\n"].concat(),
+            &[ansi::FG_CYAN, "false", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_CYAN, "false", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn continuation() {
+        let input = "max(1,\n  (2 +\n3))\n(1";
+        let expected_output = [
+            "> ",
+            "... ",
+            "... ",
+            &[ansi::FG_GREEN, "5", ansi::RESET, "\n"].concat(),
+            "> ",
+            "... ",
+            &[ansi::FG_RED, "unclosed ( at column 1", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+
+        // without a prompt nobody is typing, and brackets in strings do not count
+        let options = Options {
+            prompt: false,
+            ..Options::default()
+        };
+        let input = "(1\nformat(2, \"(\")\n3";
+        let unknown = "unknown format: \"(\", expected one like \"0.3f\", \"0.3e\", \"hex\", \"oct\" or \"bin\"";
+        let expected_output = [
+            [ansi::FG_RED, "unclosed ( at column 1", ansi::RESET, "\n"].concat(),
+            "format(2, \"(\")\n".to_string(),
+            [ansi::FG_RED, "^^^^^^^^^^^^^^", ansi::RESET, "\n"].concat(),
+            [ansi::FG_RED, unknown, ansi::RESET, "\n"].concat(),
+            [ansi::FG_GREEN, "3", ansi::RESET, "\n"].concat(),
+        ];
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn currencies() {
+        let options = Options {
+            rates: Rates::parse("base = \"EUR\"\ndate = \"2025-01-02\"\n[rates]\nUSD = 1.25").unwrap(),
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_lines(&["100 USD to EUR".into()], false, &options, &mut output, &mut errors).unwrap();
+        assert_eq!(String::from_utf8(output), Ok("80.00 EUR\n".into()));
+        let errors = String::from_utf8(errors).unwrap();
+        assert!(errors.starts_with("warning: the currency rates are from 2025-01-02, "), "{errors}");
+
+        let input = ":rates\n:rates nope\n";
+        let mut output = Vec::new();
+        let options = Options { color: false, ..options };
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let expected = "> 1 EUR on 2025-01-02 is worth\ncurrency  rate\nUSD       1.25\nwarning: ";
+        assert!(output.starts_with(expected), "{output}");
+        assert!(output.ends_with("> expected :rates or :rates update\n> "), "{output}");
+    }
+
+    #[test]
+    fn lines() {
+        let run = |lines: &[&str], each| {
+            let lines: Vec<String> = lines.iter().map(|l| l.to_string()).collect();
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            let succeeded = run_lines(&lines, each, &Options::default(), &mut output, &mut errors).unwrap();
+            let errors = String::from_utf8(errors).unwrap();
+            // the exit status, which warnings do not change
+            assert_eq!(succeeded, !errors.contains("error: "), "{lines:?}");
+            (String::from_utf8(output).unwrap(), errors)
+        };
+
+        assert_eq!(run(&["x=3", "x^2"], false), ("9\n".into(), "".into()));
+        assert_eq!(run(&["x=3", "x * 2±0.5"], false), ("6 ± 1.5\n".into(), "".into()));
+        assert_eq!(run(&["1", "x = 3", "x + 1"], true), ("1\n4\n".into(), "".into()));
+        assert_eq!(
+            run(&["1", "y", "2"], true),
+            ("1\n".into(), "error: unknown variable: y\n".into())
+        );
+        assert_eq!(
+            run(&["f(x, y) = x", "f(1, 2)"], false),
+            ("1\n".into(), "warning: the parameter y of f is never used\n".into())
+        );
+    }
+
+    #[test]
+    fn script() {
+        let source = "# radius\nr = 2\n\n3.14 * r ^ 2\nr / q\n  # done\nr";
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        assert!(!run_script(source, &Options::default(), &mut output, &mut errors).unwrap());
+
+        assert_eq!(String::from_utf8(output), Ok("12.56\n2\n".into()));
+        assert_eq!(String::from_utf8(errors), Ok("error: line 5: unknown variable: q\n".into()));
+    }
+
+    #[test]
+    fn exit_status() {
+        let script = |source: &str| run_script(source, &Options::default(), &mut Vec::new(), &mut Vec::new()).unwrap();
+        assert!(script("x = 2\n# twice\nx * 2"));
+        assert!(!script("x = 2\ny * 2\nx"));
+        assert!(!script("1 +"));
+
+        let piped = |input: &str| {
+            let options = Options::default();
+            run_cli(&mut BufReader::new(input.as_bytes()), &mut Vec::new(), &options).unwrap()
+        };
+        assert!(piped("x = 2\nx * 2\n:vars\n"));
+        assert!(!piped("x = 2\ny * 2\nx\n"));
+        assert!(!piped(":mode rpn\n1 +\n"));
+    }
+
+    #[test]
+    fn script_backtrace() {
+        let source = "f(x) = 2 * assert(x > 1)\n1 + max(2, f(1))";
+        let mut errors = Vec::new();
+        run_script(source, &Options::default(), &mut Vec::new(), &mut errors).unwrap();
+        assert_eq!(
+            String::from_utf8(errors).unwrap(),
+            [
+                "error: line 2: in f: assertion failed\n",
+                "  while evaluating   at\n",
+                "  assert(x > 1)      in f\n",
+                "  2 * assert(x > 1)  in f\n",
+                "  f(1)               column 12\n",
+                "  max(2, f(1))       column 5\n",
+                "  1 + max(2, f(1))   column 1\n",
+            ]
+            .concat()
+        );
+    }
+
+    #[test]
+    fn test_script() {
+        let run = |source| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            let passed = run_test(source, &Options::default(), &mut output, &mut errors).unwrap();
+            (passed, String::from_utf8(output).unwrap(), String::from_utf8(errors).unwrap())
+        };
+
+        let source = "f(x) = 2 * x\nassert(f(2) == 4)\nassert_eq(f(0.1), 0.2, 0.000001)\n# comment\nf(3)";
+        assert_eq!(run(source), (true, "2 passed, 0 failed\n".into(), "".into()));
+
+        let source = "f(x) = 2 * x\nassert_eq(f(2), 5)\nassert(f(1) > 1)\ng(1)";
+        assert_eq!(
+            run(source),
+            (
+                false,
+                "1 passed, 2 failed\n".into(),
+                [
+                    "failed: line 2: assert_eq(f(2), 5): assertion failed: 4 != 5\n",
+                    "failed: line 4: g(1): unknown function: g\n",
+                ]
+                .concat()
+            )
+        );
+    }
+
+    #[test]
+    fn tutorial() {
+        let input = "7 + 5\n:hint\n7 + 5 * 3\n\n:skip\n2 ^ 10\n:quit\n1";
+        let options = Options {
+            prompt: false,
+            color: false,
+            ..Options::default()
+        };
+        let mut output = Vec::new();
+        run_tutorial(&mut BufReader::new(input.as_bytes()), &mut output, &options).unwrap();
+        let expected_output = [
+            "Lesson 1 of 3: Operators\n",
+            "Compute 7 plus 5 times 3.\n",
+            "12\n",
+            "Not quite, try again or type :hint\n",
+            "try: 7 + 5 * 3\n",
+            "22\n",
+            "Correct!\n",
+            "Now add 7 and 5 first, and then multiply by 3.\n",
+            "Raise 2 to the power of 10.\n",
+            "1024\n",
+            "Correct!\n",
+            "Divide 1 by 8.\n",
+        ];
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn from_ast() {
+        let input = r#"{"op": "add", "lhs": 1, "rhs": {"op": "neg", "arg": 2.5}}
+{"op": "add", "lhs": 1}
+{"var": "x"}"#;
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_from_ast(&mut BufReader::new(input.as_bytes()), &mut output, &mut errors).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok("-1.5\n".into()));
+        assert_eq!(
+            String::from_utf8(errors),
+            Ok([
+                "error: missing field \"rhs\" in {\"op\":\"add\",\"lhs\":1}\n",
+                "error: unknown variable: x\n"
+            ]
+            .concat())
+        );
+    }
+
+    #[test]
+    fn stream() {
+        let run = |input: String| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            run_stream(&mut BufReader::new(input.as_bytes()), &Options::default(), &mut output, &mut errors).unwrap();
+            (String::from_utf8(output).unwrap(), String::from_utf8(errors).unwrap())
+        };
+        // deeper than evaluating on the main thread would go
+        assert_eq!(run("1 + ".repeat(50_000) + "1\n"), ("50001\n".into(), "".into()));
+        assert_eq!(run("(1 +\n 2) * 3 < 10".into()), ("true\n".into(), "".into()));
+        assert_eq!(run("1 +".into()), ("".into(), "error: the input ended where a term was expected\n".into()));
+    }
+
+    #[test]
+    fn filter() {
+        let run_with = |options: &Options, expression, delimiter, input: &str| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            let mut stdin = BufReader::new(input.as_bytes());
+            let succeeded = run_filter(expression, delimiter, options, &mut stdin, &mut output, &mut errors).unwrap();
+            let errors = String::from_utf8(errors).unwrap();
+            // the exit status
+            assert_eq!(succeeded, errors.is_empty(), "{expression}");
+            (String::from_utf8(output).unwrap(), errors)
+        };
+        let run = |expression, delimiter, input: &str| run_with(&Options::default(), expression, delimiter, input);
+
+        assert_eq!(
+            run("c3 * 1.5", None, "a  1 2\nb 3 4\n"),
+            ("a  1 2 3\nb 3 4 6\n".into(), "".into())
+        );
+        assert_eq!(
+            run("c1 - c2", Some(','), "5, 3\n5\n1,x\n"),
+            (
+                "5, 3,2\n".into(),
+                "error: line 2: missing field c2\nerror: line 3: field c2 is not a number: \"x\"\n".into()
+            )
+        );
+        assert_eq!(run("2 + 2", None, "a\n"), ("a 4\n".into(), "".into()));
+        assert_eq!(run("c0 + x", None, "1\n"), ("".into(), "error: unknown variable: c0\n".into()));
+        assert_eq!(
+            run("format(c1 / 3, \"0.2f\")", Some(','), "1\n2\n"),
+            ("1,0.33\n2,0.67\n".into(), "".into())
+        );
+        assert_eq!(
+            run("format(c1, \"hex\")", None, "0.5\n"),
+            ("".into(), "error: line 1: hex takes integers up to 2^53, got 0.5\n".into())
+        );
+
+        // what is not plain arithmetic on the fields runs like a line of the REPL
+        assert_eq!(run("c1 > 2", None, "1\n3\n"), ("1 false\n3 true\n".into(), "".into()));
+        std::env::set_var("CALCULATOR_TEST_FILTER", "10");
+        assert_eq!(run("c1 * $CALCULATOR_TEST_FILTER", None, "2\n"), ("2 20\n".into(), "".into()));
+        let decimal = Options {
+            decimal: true,
+            ..Options::default()
+        };
+        assert_eq!(run_with(&decimal, "c1 + 0.2", None, "0.1\n"), ("0.1 0.3\n".into(), "".into()));
+        assert_eq!(run("1 / c1 + 1 ± 0.1", None, "2\n"), ("2 1.5 ± 0.1\n".into(), "".into()));
+
+        // each line is printed before the next one is read
+        struct Endless(bool);
+        impl std::io::Read for Endless {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                if std::mem::replace(&mut self.0, true) {
+                    return Err(std::io::Error::other("no more input yet"));
+                }
+                buf[..2].copy_from_slice(b"4\n");
+                Ok(2)
+            }
+        }
+        let mut output = Vec::new();
+        let mut stdin = BufReader::new(Endless(false));
+        let res = run_filter("c1 / 2", None, &Options::default(), &mut stdin, &mut output, &mut Vec::new());
+        assert!(res.is_err());
+        assert_eq!(String::from_utf8(output).unwrap(), "4 2\n");
+    }
+
+    #[test]
+    fn json_errors() {
+        let options = Options {
+            json_errors: true,
+            ..Options::default()
+        };
+        let mut errors = Vec::new();
+        let source = "f(x, y) = x\n1 +\nf(2, 3) * é\n";
+        run_script(source, &options, &mut Vec::new(), &mut errors).unwrap();
+        let expected = [
+            r#"{"code":"warning","message":"the parameter y of f is never used","line":1,"span":null}"#,
+            r#"{"code":"syntax","message":"invalid term: \"\"","line":2,"span":null}"#,
+            r#"{"code":"eval","message":"unknown variable: é","line":3,"span":{"start":10,"end":11}}"#,
+        ];
+        assert_eq!(String::from_utf8(errors).unwrap(), expected.join("\n") + "\n");
+
+        let mut errors = Vec::new();
+        let mut stdin = BufReader::new("1\nx\n".as_bytes());
+        run_filter("1 / c1", None, &options, &mut stdin, &mut Vec::new(), &mut errors).unwrap();
+        let expected = r#"{"code":"input","message":"field c1 is not a number: \"x\"","line":2,"span":null}"#;
+        assert_eq!(String::from_utf8(errors).unwrap(), format!("{expected}\n"));
+    }
+
+    #[test]
+    fn fmt() {
+        let input = "1+2 *(3 )\n\n# area\nf(r)=3.14*r^2\nx=-(2^2)\n(1+\nM+";
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let parsed = run_fmt(&mut BufReader::new(input.as_bytes()), &Options::default(), &mut output, &mut errors);
+        assert!(!parsed.unwrap());
+
+        assert_eq!(
+            String::from_utf8(output),
+            Ok("1 + 2 * 3\n\n# area\nf(r) = 3.14 * r ^ 2\nx = -2 ^ 2\nM+\n".into())
+        );
+        assert_eq!(String::from_utf8(errors), Ok("error: line 6: unclosed ( at column 1\n".into()));
+    }
+
+    #[test]
+    fn fmt_status() {
+        let run = |input: &str| run_fmt(&mut input.as_bytes(), &Options::default(), &mut Vec::new(), &mut Vec::new());
+        assert!(run("1+2\n# fine\n\nx=3").unwrap());
+        assert!(!run("1+2\n2 *").unwrap());
+    }
+
+    #[test]
+    fn lint() {
+        let input = "1 * (x ^ 2)\nf(x) = x / 0\n\n2 +";
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        let clean = run_lint(&mut BufReader::new(input.as_bytes()), &Options::default(), &mut output, &mut errors);
+        assert!(!clean.unwrap());
+
+        assert_eq!(
+            String::from_utf8(output),
+            Ok([
+                "line 1, column 5: these parentheses are not needed\n",
+                "line 1, column 1: multiplying by 1 does nothing\n",
+                "line 2, column 8: this divides by 0\n",
+            ]
+            .concat())
+        );
+        assert_eq!(String::from_utf8(errors), Ok("error: line 4: invalid term: \"\"\n".into()));
+    }
+
+    #[test]
+    fn lint_status() {
+        let run = |input: &str, syntax| {
+            let options = Options { syntax, ..Options::default() };
+            let mut output = Vec::new();
+            let clean = run_lint(&mut input.as_bytes(), &options, &mut output, &mut Vec::new()).unwrap();
+            (clean, String::from_utf8(output).unwrap())
+        };
+        assert_eq!(run("1 + 2 * x\n# fine", Syntax::Native), (true, "".into()));
+        assert_eq!(run("(x) + 1", Syntax::Native), (false, "line 1, column 1: these parentheses are not needed\n".into()));
+        // <> is only an operator in spreadsheets
+        assert_eq!(run("=IF(1 <> x, 1 * x, 2)", Syntax::Excel), (false, "line 1, column 13: multiplying by 1 does nothing\n".into()));
+        assert!(!run("=IF(1 <> x, 1 * x, 2)", Syntax::Native).0);
+    }
+
+    #[test]
+    fn cli_lang() {
+        let input = ":lang sv\n:lang\nx\nmax()\n:undo\n:lang xx\n:lang de\n:nope\nf(x) = x + y\nf(1)\n1)";
+        let expected_output = [
+            "> ",
+            "> ",
+            "sv\n",
+            "> ",
+            &[ansi::FG_RED, "  ^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "okänd variabel: x", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "  ^^^^^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "max tar minst 1 argument, fick 0", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "inget att ångra", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "okänt språk: xx", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_RED, "unbekannter Befehl: :nope", ansi::RESET, "\n"].concat(),
+            "> ",
+            "> ",
+            &[ansi::FG_RED, "  ^^^^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "in f: unbekannte Variable: y", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "while evaluating  at", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "y                 in f", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "x + y             in f", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_RED, "f(1)              column 1", ansi::RESET, "\n"].concat(),
+            "> ",
+            &[ansi::FG_RED, "unerwartete ) in Spalte 2", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn cli_theme() {
+        let input = ":theme monochrome\n1 + 2\nx\n:theme high-contrast\n:theme\n1 < 2\n:theme nope";
+        let expected_output = [
+            "> ",
+            "> ",
+            "3\n",
+            "> ",
+            "  ^\n",
+            "unknown variable: x\n",
+            "> ",
+            &[ansi::BOLD, ansi::FG_BRIGHT_WHITE, "> ", ansi::RESET].concat(),
+            "high-contrast\n",
+            &[ansi::BOLD, ansi::FG_BRIGHT_WHITE, "> ", ansi::RESET].concat(),
+            &[ansi::BOLD, ansi::FG_BRIGHT_CYAN, "true", ansi::RESET, "\n"].concat(),
+            &[ansi::BOLD, ansi::FG_BRIGHT_WHITE, "> ", ansi::RESET].concat(),
+            &[ansi::BOLD, ansi::FG_BRIGHT_RED].concat(),
+            "unknown theme: nope, expected one of default, high-contrast, monochrome",
+            &[ansi::RESET, "\n"].concat(),
+            &[ansi::BOLD, ansi::FG_BRIGHT_WHITE, "> ", ansi::RESET].concat(),
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn aligned_table() {
+        let rows = [vec!["x".into(), "1".into()], vec!["total".into(), "-2.5".into()], vec!["y".into(), "".into()]];
+        assert_eq!(table(&["name", "value"], &rows), "name   value\nx      1\ntotal  -2.5\ny\n");
+        assert_eq!(table(&["a", "b"], &[]), "a  b\n");
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn cli_doc() {
+        let input = ":doc sqrt\n:doc pmt\n:doc pi\n:doc nope";
+        let expected_output = [
+            "> ",
+            "sqrt(x) for x >= 0, e.g. sqrt(2) = 1.4142135623730951\n",
+            "> ",
+            "pmt(rate, periods, present, future = 0) for periods != 0, e.g. pmt(0.01, 12, 10000) = -888.4878867834169 \
+             (in the finance module)\n",
+            "> ",
+            "pi(), e.g. pi() = 3.141592653589793\n",
+            "> ",
+            &[ansi::FG_RED, "unknown built-in function: nope", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(":doc".as_bytes()), &mut output, &Options::default()).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), FUNCTIONS.len() + 2);
+        let sqrt = output.lines().find(|line| line.starts_with("sqrt(x) ")).unwrap();
+        assert_eq!(sqrt.split("  ").filter(|cell| !cell.is_empty()).count(), 4);
+        assert!(sqrt.ends_with("  sqrt(2) = 1.4142135623730951"));
+    }
+
+    #[test]
+    fn cli_lint() {
+        let input = ":lint 1 + x\n:lint x == x";
+        let expected_output = [
+            "> ",
+            "no problems found\n",
+            "> ",
+            "x == x\n",
+            &[ansi::FG_YELLOW, "^^^^^^", ansi::RESET, "\n"].concat(),
+            &[ansi::FG_YELLOW, "this comparison is always true", ansi::RESET, "\n"].concat(),
+            "> ",
+        ];
+
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok(expected_output.concat()));
+    }
+
+    #[test]
+    fn equiv() {
+        let run = |left, right| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            run_equiv(left, right, &Options::default(), &mut output, &mut errors).unwrap();
+            (String::from_utf8(output).unwrap(), String::from_utf8(errors).unwrap())
+        };
+
+        assert_eq!(
+            run("a*(b+c)", "a*b + a*c"),
+            ("equivalent, since both are a * b + a * c\n".into(), "".into())
+        );
+        assert_eq!(
+            run("sin(x)^2 + cos(x)^2", "1"),
+            ("equivalent, as far as 200 random tests can tell\n".into(), "".into())
+        );
+        assert_eq!(run("(1 + x)", "1+x"), ("equivalent\n".into(), "".into()));
+        assert_eq!(run("2 ^ 10", "1024"), ("equal\n".into(), "".into()));
+        #[cfg(not(feature = "f32"))]
+        assert_eq!(run("1 / 3", "0.33"), ("not equivalent: 0.3333333333333333 and 0.33\n".into(), "".into()));
+        assert!(run("x + 1", "x").0.starts_with("not equivalent: with x = "));
+        assert_eq!(run("x +", "x"), ("".into(), "error: invalid term: \"\"\n".into()));
+    }
+
+    #[cfg(not(feature = "f32"))]
+    #[test]
+    fn stats() {
+        let run = |input: &str| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            run_stats(&mut BufReader::new(input.as_bytes()), &mut output, &mut errors).unwrap();
+            (String::from_utf8(output).unwrap(), String::from_utf8(errors).unwrap())
+        };
+
+        let expected = [
+            "count   4", "min     1", "max     10", "mean    4.5", "median  3.5", "stddev  3.872983346207417",
+            "p5      1.3", "p25     2.5", "p75     5.5", "p90     8.200000000000001", "p95     9.099999999999998",
+            "p99     9.819999999999999",
+        ];
+        assert_eq!(run("3 1\n10\n  4\n"), (expected.map(|row| format!("{row}\n")).concat(), "".into()));
+        assert_eq!(run("1 x"), ("".into(), "error: not a number: x\n".into()));
+        assert_eq!(run("\n"), ("".into(), "error: there are no numbers to summarize\n".into()));
+    }
+
+    #[test]
+    fn hist() {
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_hist(2, &mut BufReader::new("1 2\n3 3.5 4".as_bytes()), &mut output, &mut errors).unwrap();
+        let expected = format!("[1, 2.5)  {:<40}  2\n[2.5, 4]  {}  3\n", "#".repeat(27), "#".repeat(40));
+        assert_eq!(String::from_utf8(output), Ok(expected));
+        assert_eq!(String::from_utf8(errors), Ok("".into()));
+    }
+
+    #[test]
+    fn cli_hist() {
+        let input = "v = [1, 1, 2]\n:hist v\n:hist [x for x in 1..3]\n:hist 1 + 2\n:hist filter(v, x -> x > 5)";
+        let mut output = Vec::new();
+        run_cli(&mut BufReader::new(input.as_bytes()), &mut output, &Options::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        let rows: Vec<Vec<&str>> = output.lines().map(|line| line.split_whitespace().collect()).collect();
+        assert!(rows.contains(&vec!["[1.9,", "2]", &"#".repeat(20), "1"]), "{output}");
+        assert!(rows.contains(&vec!["[1.2,", "1.4)", "0"]), "{output}");
+        assert!(output.contains("expected a list like v or [x ^ 2 for x in 1..10], got 1 + 2"));
+        assert!(output.contains("the list is empty"));
+    }
+
+    #[test]
+    fn convert() {
+        let options = Options {
+            rates: Rates::parse("base = \"EUR\"\ndate = \"2026-10-01\"\n[rates]\nUSD = 1.25").unwrap(),
+            ..Options::default()
+        };
+        let run = |quantity, unit| {
+            let mut output = Vec::new();
+            let mut errors = Vec::new();
+            let converted = run_convert(quantity, unit, &options, &mut output, &mut errors).unwrap();
+            let errors = String::from_utf8(errors).unwrap();
+            // the exit status
+            assert_eq!(converted, errors.is_empty(), "{quantity} to {unit}");
+            (String::from_utf8(output).unwrap(), errors)
+        };
+
+        assert_eq!(run("90 min", "hours"), ("1.5\n".into(), "".into()));
+        assert_eq!(run("5' 6\"", "in"), ("66\n".into(), "".into()));
+        assert_eq!(run("1:30 + 30 min", "h"), ("2\n".into(), "".into()));
+        assert_eq!(run("100 USD", " EUR "), ("80.00 EUR\n".into(), "".into()));
+        assert_eq!(run("10 EUR", "USD"), ("12.50 USD\n".into(), "".into()));
+        assert_eq!(run("90 km/h", "m/s"), ("25\n".into(), "".into()));
+        assert_eq!(run("6 ft", "m"), ("1.8288\n".into(), "".into()));
+        assert_eq!(run("3 m/s * 2 min", "km"), ("0.36\n".into(), "".into()));
+        let expected = "a time like h or s, a length like ft or km, a speed like m/s, or a currency like EUR";
+        assert_eq!(run("5 km", "furlong"), ("".into(), format!("error: unknown unit: furlong, expected {expected}\n")));
+        assert_eq!(run("5 km", "h"), ("".into(), "error: cannot convert 5 km to h\n".into()));
+        assert_eq!(run("1 h", "km"), ("".into(), "error: cannot divide a duration by a length\n".into()));
+        assert!(run("5 min +", "s").1.starts_with("error: "));
+    }
+
+    #[test]
+    fn generate() {
+        let mut output = Vec::new();
+        run_generate(&mut Generator::new(7, 3), 5, true, &mut output).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert_eq!(output.lines().count(), 5);
+        for line in output.lines() {
+            let (e, value) = line.split_once('\t').unwrap();
+            let e = parser::parse_line(e).unwrap();
+            assert_eq!(e.evaluate().unwrap().to_string(), value);
+        }
+    }
+
+    #[test]
+    fn to_ast() {
+        let input = "x * 2\n1 +";
+
+        let mut output = Vec::new();
+        let mut errors = Vec::new();
+        run_to_ast(&mut BufReader::new(input.as_bytes()), &mut output, &mut errors).unwrap();
+
+        assert_eq!(String::from_utf8(output), Ok("{\"op\":\"mul\",\"lhs\":{\"var\":\"x\"},\"rhs\":2}\n".into()));
+        assert_eq!(String::from_utf8(errors), Ok("error: invalid term: \"\"\n".into()));
+    }
 }
@@ -0,0 +1,1126 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use crate::check::Type;
+use crate::currency::{self, Rates};
+use crate::decimal::Decimal;
+use crate::functions::{self, Module, INCH, METER};
+use crate::i18n::{Key, Text};
+use crate::number::{Backend, Decimal128, NATIVE};
+use crate::profile::Profile;
+use crate::repr::{EvalError, Expr, MemoryKey, Node, NodeId, Operator, Statement, UserFunction, Value};
+use crate::uncertainty::Uncertain;
+
+/// How many changes can be undone.
+const UNDO_LIMIT: usize = 100;
+
+/// How many results are kept for `out[n]`.
+const OUTPUT_LIMIT: usize = 1000;
+
+/// How deep calls of user functions can be nested by default, which is far from overflowing the
+/// stack of the thread that evaluates.
+pub const RECURSION_LIMIT: usize = 1000;
+
+/// How many results of `memo` functions are cached before the cache is cleared.
+const MEMO_LIMIT: usize = 100_000;
+
+/// An error about all of `e`.
+fn error(e: &Expr, message: impl Into<Text>) -> EvalError {
+    EvalError {
+        message: message.into(),
+        span: e.span(e.root()),
+        backtrace: Vec::new(),
+    }
+}
+
+/// The length `x` that the subexpression `id` of `e` evaluated to, in the first unit of length
+/// in it, like the metres of `2 m + 30 cm`, or in feet and inches if that unit is imperial.
+pub(crate) fn length(e: &Expr, id: NodeId, x: f64) -> Value {
+    let (mut first, mut stack) = (None, vec![id]);
+    while let Some(id) = stack.pop() {
+        match *e.node(id) {
+            Node::Call(ref name, _) if functions::LENGTHS.contains(&name.as_str()) => {
+                first = Some(name.as_str());
+                break;
+            }
+            // the left operand first
+            Node::Call(_, ref args) | Node::List(ref args) => stack.extend(args.iter().rev()),
+            Node::Op(_, a, b) | Node::Range(a, b) => stack.extend([b, a]),
+            Node::Unary(_, a) | Node::Assign(_, a) | Node::Lambda(_, a) => stack.push(a),
+            Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) => {}
+        }
+    }
+    match functions::METRIC.into_iter().find(|&(name, _, _)| Some(name) == first) {
+        Some((_, symbol, length)) => Value::Length(x / length, Some(symbol)),
+        None => Value::Length(x / INCH, None),
+    }
+}
+
+/// A change of a variable or function, with its previous value.
+#[derive(Debug, Clone)]
+enum Change {
+    Var(String, Option<f64>),
+    Func(String, Option<UserFunction>),
+    List(String, Option<Vec<f64>>),
+}
+
+/// Supplies the values of variables that a [`Context`] does not have, for applications that
+/// embed the calculator and keep their values elsewhere, like in a database or a spreadsheet.
+pub trait VariableResolver: Send + Sync {
+    /// The current value of `name`, or `None` if it is unknown here too.
+    fn resolve(&self, name: &str) -> Option<f64>;
+}
+
+impl<F: Fn(&str) -> Option<f64> + Send + Sync> VariableResolver for F {
+    fn resolve(&self, name: &str) -> Option<f64> {
+        self(name)
+    }
+}
+
+/// Shared between clones of the context, like the sessions of `serve`.
+#[derive(Clone)]
+struct Resolver(Arc<dyn VariableResolver>);
+
+impl fmt::Debug for Resolver {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Resolver")
+    }
+}
+
+/// The results of `memo` functions by the backend that computed them, their name and the bits
+/// of their arguments. Evaluating only borrows the context, so the cache is behind a lock.
+#[derive(Debug, Default)]
+struct Memos(Mutex<HashMap<MemoKey, f64>>);
+
+type MemoKey = (&'static str, String, Vec<u64>);
+
+impl Clone for Memos {
+    fn clone(&self) -> Self {
+        Memos(Mutex::new(self.0.lock().unwrap().clone()))
+    }
+}
+
+/// The user functions that are being called, innermost last. Clones are not in any calls.
+#[derive(Debug, Default)]
+struct Calls(Mutex<Vec<String>>);
+
+impl Clone for Calls {
+    fn clone(&self) -> Self {
+        Calls::default()
+    }
+}
+
+/// The profile of `:profile`, which is only recorded while `profiling` is set on the context.
+/// Clones are not profiled.
+#[derive(Debug, Default)]
+struct Profiler(Mutex<Profile>);
+
+impl Clone for Profiler {
+    fn clone(&self) -> Self {
+        Profiler::default()
+    }
+}
+
+/// A call of a user function, which ends when this is dropped.
+pub struct Call<'a> {
+    ctx: &'a Context,
+    /// whether the function was already being called
+    pub recursive: bool,
+}
+
+impl Drop for Call<'_> {
+    fn drop(&mut self) {
+        self.ctx.calls.0.lock().unwrap().pop();
+    }
+}
+
+/// The state shared by all lines of a session.
+#[derive(Debug, Clone)]
+pub struct Context {
+    vars: BTreeMap<String, f64>,
+    /// the exact values of variables that were assigned decimals, which are only used while the
+    /// variables still have the closest `f64` to them
+    decimals: BTreeMap<String, Decimal>,
+    funcs: BTreeMap<String, UserFunction>,
+    lists: BTreeMap<String, Vec<f64>>,
+    /// whether `$NAME` reads the environment variable `NAME`
+    env: bool,
+    /// the result of the most recent expression
+    last: Option<f64>,
+    memory: f64,
+    /// most recent last
+    undo: Vec<Change>,
+    /// the recent results with their numbers and the input that gave them, oldest first
+    outputs: Vec<(usize, String, f64)>,
+    /// how many results have been numbered
+    numbered: usize,
+    /// the modules of built-in functions that can be called
+    modules: Vec<Module>,
+    /// for converting currencies
+    rates: Rates,
+    /// asked for variables that are not in `vars`
+    resolver: Option<Resolver>,
+    /// cleared by every change, since the functions may use variables and other functions
+    memos: Memos,
+    calls: Calls,
+    /// how deep calls of user functions can be nested
+    recursion_limit: usize,
+    /// whether evaluating records the time of each node in `profiler`, which is not locked
+    /// otherwise
+    profiling: bool,
+    profiler: Profiler,
+}
+
+impl Default for Context {
+    fn default() -> Self {
+        Context {
+            vars: BTreeMap::new(),
+            decimals: BTreeMap::new(),
+            funcs: BTreeMap::new(),
+            lists: BTreeMap::new(),
+            env: false,
+            last: None,
+            memory: 0.0,
+            undo: Vec::new(),
+            outputs: Vec::new(),
+            numbered: 0,
+            modules: Module::ALL.into_iter().filter(|m| m.loaded_by_default()).collect(),
+            rates: Rates::default(),
+            resolver: None,
+            memos: Memos::default(),
+            calls: Calls::default(),
+            recursion_limit: RECURSION_LIMIT,
+            profiling: false,
+            profiler: Profiler::default(),
+        }
+    }
+}
+
+impl Context {
+    pub fn new(env: bool) -> Self {
+        Context {
+            env,
+            ..Context::default()
+        }
+    }
+
+    pub fn var(&self, name: &str) -> Option<f64> {
+        self.vars.get(name).copied()
+    }
+
+    /// The exact decimal of the variable `name`, if it was assigned one and still has it.
+    pub(crate) fn decimal_var(&self, name: &str) -> Option<Decimal> {
+        let d = self.decimals.get(name)?;
+        (self.var(name) == Some(d.to_f64())).then_some(*d)
+    }
+
+    /// The numbers of the list variable `name`, unless a number variable has the same name.
+    pub fn list_var(&self, name: &str) -> Option<&[f64]> {
+        self.lists.get(name).filter(|_| self.var(name).is_none()).map(Vec::as_slice)
+    }
+
+    pub fn vars(&self) -> impl Iterator<Item = (&str, f64)> {
+        self.vars.iter().map(|(k, &v)| (k.as_str(), v))
+    }
+
+    pub fn func(&self, name: &str) -> Option<&UserFunction> {
+        self.funcs.get(name)
+    }
+
+    pub fn funcs(&self) -> impl Iterator<Item = (&str, &UserFunction)> {
+        self.funcs.iter().map(|(k, f)| (k.as_str(), f))
+    }
+
+    /// Like [`Context::var`], but also reads environment variables, asks the resolver and
+    /// explains failures. `ans` is the previous result, unless it is assigned.
+    pub fn lookup(&self, name: &str) -> Result<f64, Text> {
+        if name == "ans" && self.var(name).is_none() {
+            return self.last.ok_or_else(|| "there is no previous result for ans".into());
+        }
+        if let Some(n) = name.strip_prefix("out[").and_then(|n| n.strip_suffix(']')) {
+            let n = n.parse().map_err(|_| format!("invalid output number: {name}"))?;
+            return Ok(self.output(n).map(|(_, value)| value)?);
+        }
+        let Some(key) = name.strip_prefix('$') else {
+            let resolved = || self.resolver.as_ref()?.0.resolve(name);
+            if self.var(name).is_none() && self.lists.contains_key(name) {
+                return Err(format!("{name} is a list, not a number").into());
+            }
+            let unknown = || Text::new(Key::UnknownVariable, vec![name.into()]);
+            return self.var(name).or_else(resolved).ok_or_else(unknown);
+        };
+        if !self.env {
+            return Err(format!("environment variables are disabled: {name}").into());
+        }
+        let value = std::env::var(key).map_err(|_| format!("environment variable not set: {key}"))?;
+        let value = value.trim().parse().map_err(|_| format!("environment variable is not a number: {key}={value}"))?;
+        Ok(value)
+    }
+
+    /// Lets `resolver` supply the variables that are not assigned. Its values are asked for
+    /// every time they are used, and never stored in the context.
+    pub fn set_resolver(&mut self, resolver: impl VariableResolver + 'static) {
+        self.resolver = Some(Resolver(Arc::new(resolver)));
+        self.forget_memos();
+    }
+
+    pub fn rates(&self) -> &Rates {
+        &self.rates
+    }
+
+    pub fn set_rates(&mut self, rates: Rates) {
+        self.rates = rates;
+        self.forget_memos();
+    }
+
+    /// The cached result of the `memo` function `name` for `args`, computed by the backend with
+    /// the [`memo_key`](crate::number::Backend::memo_key) `backend`.
+    pub fn memoized(&self, backend: &'static str, name: &str, args: &[f64]) -> Option<f64> {
+        let key: MemoKey = (backend, name.to_string(), args.iter().map(|x| x.to_bits()).collect());
+        self.memos.0.lock().unwrap().get(&key).copied()
+    }
+
+    pub fn memoize(&self, backend: &'static str, name: &str, args: Vec<f64>, res: f64) {
+        let mut memos = self.memos.0.lock().unwrap();
+        if memos.len() >= MEMO_LIMIT {
+            memos.clear();
+        }
+        memos.insert((backend, name.to_string(), args.into_iter().map(f64::to_bits).collect()), res);
+    }
+
+    pub fn set_recursion_limit(&mut self, limit: usize) {
+        self.recursion_limit = limit;
+    }
+
+    /// Starts a call of the user function `name` with `args`, unless calls are nested as deep
+    /// as the recursion limit allows.
+    pub fn enter(&self, name: &str, args: &[f64]) -> Result<Call<'_>, Text> {
+        let mut calls = self.calls.0.lock().unwrap();
+        if calls.len() >= self.recursion_limit {
+            let args: Vec<String> = args.iter().map(|&x| Value::Number(x).to_string()).collect();
+            let call = format!("{name}({})", args.join(", "));
+            return Err(Text::new(Key::RecursionLimit, vec![call.into()]));
+        }
+        let recursive = calls.iter().any(|f| f == name);
+        calls.push(name.to_string());
+        Ok(Call { ctx: self, recursive })
+    }
+
+    /// Starts recording the time that evaluating each node takes.
+    pub fn start_profile(&mut self) {
+        self.profiling = true;
+        *self.profiler.0.get_mut().unwrap() = Profile::default();
+    }
+
+    /// Stops recording, and returns what was recorded.
+    pub fn finish_profile(&mut self) -> Profile {
+        self.profiling = false;
+        std::mem::take(self.profiler.0.get_mut().unwrap())
+    }
+
+    pub fn is_profiling(&self) -> bool {
+        self.profiling
+    }
+
+    /// Starts timing a node, for [`Context::leave_node`].
+    pub fn enter_node(&self) -> Instant {
+        self.profiler.0.lock().unwrap().enter();
+        Instant::now()
+    }
+
+    /// Records the time of the node `id` of the innermost user function being called.
+    pub fn leave_node(&self, id: NodeId, start: Instant) {
+        let elapsed = start.elapsed();
+        let calls = self.calls.0.lock().unwrap();
+        let function = calls.last().cloned();
+        let nested = function.as_ref().is_some_and(|f| calls.iter().filter(|&c| c == f).count() > 1);
+        drop(calls);
+        self.profiler.0.lock().unwrap().leave(function, id, elapsed, nested);
+    }
+
+    fn forget_memos(&mut self) {
+        self.memos.0.get_mut().unwrap().clear();
+    }
+
+    pub fn is_loaded(&self, module: Module) -> bool {
+        self.modules.contains(&module)
+    }
+
+    pub fn load(&mut self, module: Module) {
+        if !self.is_loaded(module) {
+            self.modules.push(module);
+        }
+    }
+
+    /// The core module can not be unloaded.
+    pub fn unload(&mut self, module: Module) -> Result<(), Text> {
+        if module == Module::Core {
+            return Err(Text::new(Key::UnloadCore, Vec::new()));
+        }
+        self.modules.retain(|&m| m != module);
+        Ok(())
+    }
+
+    /// Numbers the result of the most recent expression, which came from `input`, so that it
+    /// can be used as `out[n]`. Returns the number.
+    pub fn add_output(&mut self, input: &str) -> Option<usize> {
+        let value = self.last?;
+        if self.outputs.len() == OUTPUT_LIMIT {
+            self.outputs.remove(0);
+        }
+        self.numbered += 1;
+        self.outputs.push((self.numbered, input.to_string(), value));
+        Some(self.numbered)
+    }
+
+    /// The number of the most recent result.
+    pub fn last_output(&self) -> Option<usize> {
+        self.outputs.last().map(|(n, ..)| *n)
+    }
+
+    /// The input and the result numbered `n`.
+    pub fn output(&self, n: usize) -> Result<(&str, f64), String> {
+        match self.outputs.iter().find(|(m, ..)| *m == n) {
+            Some((_, input, value)) => Ok((input, *value)),
+            None if n >= 1 && n <= self.numbered => Err(format!("out[{n}] is too old to be kept")),
+            None => Err(format!("there is no out[{n}]")),
+        }
+    }
+
+    fn record(&mut self, change: Change) {
+        self.forget_memos();
+        if self.undo.len() == UNDO_LIMIT {
+            self.undo.remove(0);
+        }
+        self.undo.push(change);
+    }
+
+    /// Reverts the most recent assignment, definition or [`Context::unset`]. Returns the name
+    /// of the restored variable or function.
+    pub fn undo(&mut self) -> Option<String> {
+        self.forget_memos();
+        Some(match self.undo.pop()? {
+            Change::Var(name, previous) => {
+                match previous {
+                    Some(value) => self.vars.insert(name.clone(), value),
+                    None => self.vars.remove(&name),
+                };
+                name
+            }
+            Change::Func(name, previous) => {
+                match previous {
+                    Some(f) => self.funcs.insert(name.clone(), f),
+                    None => self.funcs.remove(&name),
+                };
+                name
+            }
+            Change::List(name, previous) => {
+                match previous {
+                    Some(values) => self.lists.insert(name.clone(), values),
+                    None => self.lists.remove(&name),
+                };
+                name
+            }
+        })
+    }
+
+    /// Removes the variable `name`, or if there is none, the function `name`. Returns
+    /// whether anything was removed.
+    pub fn unset(&mut self, name: &str) -> bool {
+        if let Some(value) = self.vars.remove(name) {
+            self.record(Change::Var(name.to_string(), Some(value)));
+        } else if let Some(f) = self.funcs.remove(name) {
+            self.record(Change::Func(name.to_string(), Some(f)));
+        } else if let Some(values) = self.lists.remove(name) {
+            self.record(Change::List(name.to_string(), Some(values)));
+        } else {
+            return false;
+        }
+        true
+    }
+
+    /// The namespaces of qualified names like `geom.area`, with the names declared directly
+    /// in each of them.
+    pub fn namespaces(&self) -> BTreeMap<&str, Vec<&str>> {
+        let mut namespaces = BTreeMap::<_, Vec<_>>::new();
+        for name in self.vars.keys().chain(self.funcs.keys()) {
+            if let Some((ns, local)) = name.rsplit_once('.') {
+                namespaces.entry(ns).or_default().push(local);
+            }
+        }
+        for names in namespaces.values_mut() {
+            names.sort();
+            names.dedup();
+        }
+        namespaces
+    }
+
+    /// Makes the assignments that evaluating an expression made, in the order it made them.
+    fn assign(&mut self, assigned: Vec<(String, f64)>) {
+        for (name, value) in assigned {
+            let previous = self.vars.insert(name.clone(), value);
+            self.record(Change::Var(name, previous));
+        }
+    }
+
+    /// Evaluates `e`, and makes the assignments inside it if that succeeds. The result is a
+    /// number, or a list or text.
+    fn evaluate_assigning(&mut self, e: &Expr) -> Result<Value, EvalError> {
+        let (res, assigned) = e.evaluate_items(e.root(), self, &NATIVE, Vec::new())?;
+        self.assign(assigned.into_iter().map(|(name, value)| (name, NATIVE.to_f64(&value))).collect());
+        Ok(res.into_value(&NATIVE))
+    }
+
+    /// The currency that `e` converts money to, like the `EUR` of `10 USD to EUR`, which divides
+    /// by one euro and so is the amount in euros.
+    fn exchange<'e>(&self, e: &'e Expr) -> Option<&'e str> {
+        let Node::Op(Operator::Div, money, one) = *e.node(e.root()) else { return None };
+        let Node::Call(ref code, ref args) = *e.node(one) else { return None };
+        let one = matches!(args[..], [x] if *e.node(x) == Node::Float(1.0));
+        let money = e.check_at(money, self).is_ok_and(|t| t == Type::Money);
+        (currency::is_code(code) && one && money).then_some(code)
+    }
+
+    /// Like [`Context::run`], but evaluates numbers and truth values with decimals, and keeps the
+    /// exact decimals that are assigned to variables, so that `x = 0.1 + 0.2` makes `x == 0.3`.
+    pub fn run_decimal(&mut self, statement: &Statement) -> Result<Option<Value>, EvalError> {
+        let e = match statement {
+            Statement::Expr(e) | Statement::Assign(_, e) if !e.has_uncertainty() => e,
+            _ => return self.run(statement),
+        };
+        let t = e.check(self)?;
+        // amounts of money keep being rounded to cents
+        if t != Type::Number && t != Type::Boolean || self.exchange(e).is_some() {
+            return self.run(statement);
+        }
+        let (res, mut assigned) = e.evaluate_assigning_with(&Decimal128, self)?;
+        if let Statement::Assign(name, _) = statement {
+            assigned.push((name.clone(), res));
+        }
+        self.decimals.extend(assigned.iter().cloned());
+        self.assign(assigned.into_iter().map(|(name, d)| (name, d.to_f64())).collect());
+        if let Statement::Assign(..) = statement {
+            return Ok(None);
+        }
+        self.last = Some(res.to_f64());
+        Ok(Some(match t {
+            Type::Boolean => Value::Boolean(res.to_f64() != 0.0),
+            _ => Value::Precise(res.to_string()),
+        }))
+    }
+
+    /// Evaluates an expression, or runs a statement. Only expressions and `MR` have results.
+    pub fn run(&mut self, statement: &Statement) -> Result<Option<Value>, EvalError> {
+        let res = match statement {
+            Statement::Expr(e) if e.has_uncertainty() => {
+                e.check(self)?;
+                let (m, assigned) = e.evaluate_assigning_with(&Uncertain, self)?;
+                let mut exact = Vec::with_capacity(assigned.len());
+                for (name, value) in assigned {
+                    if value.error != 0.0 {
+                        return Err(EvalError {
+                            message: format!("variables can only be assigned exact numbers, not {value}: {name}")
+                                .into(),
+                            span: None,
+                            backtrace: Vec::new(),
+                        });
+                    }
+                    exact.push((name, value.value));
+                }
+                self.assign(exact);
+                self.last = Some(m.value);
+                return Ok(Some(Value::Measurement(m)));
+            }
+            Statement::Expr(e) => {
+                let t = e.check(self)?;
+                let res = match self.evaluate_assigning(e)? {
+                    Value::Number(res) => res,
+                    // lists and text are no previous result for ans
+                    value => return Ok(Some(value)),
+                };
+                self.last = Some(res);
+                return Ok(Some(match t {
+                    Type::Number => match self.exchange(e) {
+                        Some(code) => Value::Money(res, code.to_string()),
+                        None => Value::Number(res),
+                    },
+                    Type::Boolean => Value::Boolean(res != 0.0),
+                    Type::Duration => Value::Duration(res),
+                    Type::Length => length(e, e.root(), res),
+                    Type::Speed => Value::Speed(res / METER),
+                    Type::Money => Value::Money(res, self.rates.base.clone()),
+                    Type::List | Type::Text | Type::ComplexList => Value::Number(res),
+                }));
+            }
+            Statement::Assign(name, e) => {
+                e.check(self)?;
+                // after the assignments inside, so that `a = b = 5` sets `b` before `a`
+                match self.evaluate_assigning(e)? {
+                    Value::Number(value) => self.assign(vec![(name.clone(), value)]),
+                    Value::List(values) => {
+                        // the list is hidden by a number with the same name
+                        if let Some(previous) = self.vars.remove(name) {
+                            self.record(Change::Var(name.clone(), Some(previous)));
+                        }
+                        let previous = self.lists.insert(name.clone(), values);
+                        self.record(Change::List(name.clone(), previous));
+                    }
+                    value => {
+                        let message = format!("only numbers and lists can be assigned, not a {}", Type::of(&value));
+                        return Err(error(e, message));
+                    }
+                }
+                return Ok(None);
+            }
+            Statement::Define(name, f) => {
+                let previous = self.funcs.insert(name.clone(), f.clone());
+                self.record(Change::Func(name.clone(), previous));
+                return Ok(None);
+            }
+            Statement::Memory(MemoryKey::Recall) => self.memory,
+            Statement::Memory(MemoryKey::Clear) => {
+                self.memory = 0.0;
+                return Ok(None);
+            }
+            Statement::Memory(key) => {
+                let last = self.last.ok_or_else(|| EvalError {
+                    message: Text::new(Key::NoMemory, Vec::new()),
+                    span: None,
+                    backtrace: Vec::new(),
+                })?;
+                if *key == MemoryKey::Add {
+                    self.memory += last;
+                } else {
+                    self.memory -= last;
+                }
+                return Ok(None);
+            }
+        };
+        self.last = Some(res);
+        Ok(Some(Value::Number(res)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::number::{Backend, NATIVE};
+    use crate::parser::{parse_statement, Syntax};
+
+    use super::*;
+
+    fn run(ctx: &mut Context, line: &str) -> Result<Option<f64>, String> {
+        let statement = parse_statement(line, Syntax::Native)?;
+        let res = ctx.run(&statement).map_err(|e| e.message)?;
+        Ok(res.map(|v| match v {
+            Value::Number(n) => n,
+            Value::Boolean(b) => b as u8 as f64,
+            Value::Measurement(m) => m.value,
+            Value::Duration(seconds) => seconds,
+            Value::Length(length, _) => length,
+            Value::Speed(v) => v,
+            Value::Money(amount, _) => amount,
+            Value::Rational(_) | Value::Precise(_) | Value::List(_) | Value::Text(_) | Value::ComplexList(_) => {
+                unreachable!()
+            }
+        }))
+    }
+
+    #[test]
+    fn assignments() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "x = 3"), Ok(None));
+        assert_eq!(run(&mut ctx, "x ^ 2"), Ok(Some(9.0)));
+        assert_eq!(run(&mut ctx, "x = x + 1"), Ok(None));
+        assert_eq!(ctx.var("x"), Some(4.0));
+        assert_eq!(run(&mut ctx, "y = z"), Err("unknown variable: z".into()));
+        assert_eq!(ctx.var("y"), None);
+    }
+
+    #[test]
+    fn nsolve() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "a = 2"), Ok(None));
+        let mut solve = |line: &str| {
+            let statement = parse_statement(line, Syntax::Native).unwrap();
+            match ctx.run(&statement) {
+                Ok(Some(Value::List(values))) => Ok(values),
+                Ok(res) => panic!("{line} gave {res:?}"),
+                Err(e) => Err(e.message),
+            }
+        };
+        let x = solve("nsolve([x + y = 3, x * y = 2], [x, y], [0, 3])").unwrap();
+        assert!((x[0] - 1.0).abs() < 1e-12 && (x[1] - 2.0).abs() < 1e-12, "{x:?}");
+        let x = solve("nsolve([exp(t) - 2], [t], [0])").unwrap();
+        assert!((x[0] - 2f64.ln()).abs() < 1e-12, "{x:?}");
+        let stuck = "nsolve got stuck at x = 1, y = 1, where the Jacobian is singular, so try other guesses";
+        assert_eq!(solve("nsolve([x + y = 3, x * y = 2], [x, y], [1, 1])"), Err(stuck.into()));
+        assert!(solve("nsolve([x ^ 2 = -1], [x], [0.5])").unwrap_err().starts_with("nsolve did not converge"));
+        let counts = "nsolve needs as many equations as variables, got 1 and 2";
+        assert_eq!(solve("nsolve([x = 1], [x, y], [0, 0])"), Err(counts.into()));
+        let guesses = "nsolve needs a guess for each variable, got 2 and 1";
+        assert_eq!(solve("nsolve([x = 1], [x], 1..2)"), Err(guesses.into()));
+        let sides = "cannot subtract a boolean from a number";
+        assert_eq!(solve("nsolve([x = (x < 2)], [x], [0])"), Err(sides.into()));
+        assert_eq!(solve("nsolve([x = y], [x], [0])"), Err("unknown variable: y".into()));
+        let x = solve("nsolve([x ^ 2 = a], [x], [1])").unwrap();
+        assert!((x[0] - 2f64.sqrt()).abs() < 1e-12, "{x:?}");
+    }
+
+    #[test]
+    fn roots() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "a = 3"), Ok(None));
+        let mut roots = |line: &str| {
+            let statement = parse_statement(line, Syntax::Native).unwrap();
+            match ctx.run(&statement) {
+                Ok(Some(Value::List(values))) => Ok(values),
+                Ok(res) => panic!("{line} gave {res:?}"),
+                Err(e) => Err(e.message),
+            }
+        };
+        assert_eq!(roots("roots([1, 0, -2])"), Ok(vec![-2f64.sqrt(), 2f64.sqrt()]));
+        assert_eq!(roots("roots(x ^ 2 - 2, x)"), Ok(vec![-2f64.sqrt(), 2f64.sqrt()]));
+        assert_eq!(roots("roots(x * (x - a), x)"), Ok(vec![0.0, 3.0]));
+        assert_eq!(roots("roots(a * t ^ 2 + 3, t)"), Ok(vec![]));
+        assert_eq!(roots("roots(reverse([-6, 1]))"), Ok(vec![6.0]));
+        assert_eq!(roots("roots(0 * x, x)"), Err("every number is a root of 0".into()));
+        let message = "roots needs a polynomial in x, with only +, -, *, / by numbers and whole powers";
+        assert_eq!(roots("roots(exp(x) - 1, x)"), Err(message.into()));
+    }
+
+    #[test]
+    fn factor() {
+        let mut ctx = Context::default();
+        let mut factor = |line: &str| {
+            let statement = parse_statement(line, Syntax::Native).unwrap();
+            match ctx.run(&statement) {
+                Ok(Some(Value::List(values))) => Ok(values),
+                Ok(res) => panic!("{line} gave {res:?}"),
+                Err(e) => Err(e.message.to_string()),
+            }
+        };
+        assert_eq!(factor("factor(360)"), Ok(vec![2.0, 2.0, 2.0, 3.0, 3.0, 5.0]));
+        #[cfg(not(feature = "f32"))]
+        assert_eq!(factor("factor(2 ^ 53 - 1)"), Ok(vec![6361.0, 69431.0, 20394401.0]));
+        assert_eq!(factor("factor(1)"), Ok(vec![]));
+        let message = "factor takes whole numbers from 1 to 2^53, got ";
+        assert_eq!(factor("factor(0)"), Err(format!("{message}0")));
+        assert_eq!(factor("factor(2.5)"), Err(format!("{message}2.5")));
+        #[cfg(not(feature = "f32"))]
+        assert_eq!(factor("factor(2 ^ 54)"), Err(format!("{message}18014398509481984")));
+    }
+
+    #[test]
+    fn lists() {
+        let mut ctx = Context::default();
+        let mut list = |line: &str| {
+            let statement = parse_statement(line, Syntax::Native).unwrap();
+            match ctx.run(&statement) {
+                Ok(Some(Value::List(values))) => Ok(values),
+                Ok(res) => panic!("{line} gave {res:?}"),
+                Err(e) => Err(e.message),
+            }
+        };
+        assert_eq!(list("[x ^ 2 for x in 1..5]"), Ok(vec![1.0, 4.0, 9.0, 16.0, 25.0]));
+        assert_eq!(list("[1, 2 * 3, -4]"), Ok(vec![1.0, 6.0, -4.0]));
+        assert_eq!(list("[y for y in 0.5..3 if y != 1.5]"), Ok(vec![0.5, 2.5]));
+        assert_eq!(list("[x + 1 for x in [x * 10 for x in 1..3]]"), Ok(vec![11.0, 21.0, 31.0]));
+        assert_eq!(list("[x for x in 5..1]"), Ok(vec![]));
+        assert_eq!(list("[x for x in 1..10 ^ 9 if x == 3]"), Err("ranges can have at most 1000000 numbers".into()));
+        let body = "the function of map must return a number, not a boolean";
+        assert_eq!(list("[x < 2 for x in 1..3]"), Err(body.into()));
+        let condition = "the function of filter must return a boolean, not a number";
+        assert_eq!(list("[x for x in 1..3 if x]"), Err(condition.into()));
+        assert_eq!(list("[x for x in v]"), Err("unknown variable: v".into()));
+        assert_eq!(list("[x for x in 1..3 if y > 0]"), Err("unknown variable: y".into()));
+        assert_eq!(list("2..4.5"), Ok(vec![2.0, 3.0, 4.0]));
+        assert_eq!(list("range(0, 3)"), Ok(vec![0.0, 1.0, 2.0]));
+        assert_eq!(list("range(0, 1, 0.25)"), Ok(vec![0.0, 0.25, 0.5, 0.75]));
+        assert_eq!(list("range(1, -1, -0.5)"), Ok(vec![1.0, 0.5, 0.0, -0.5]));
+        assert_eq!(list("range(0, 1, -1)"), Ok(vec![]));
+        assert_eq!(list("[x * 2 for x in range(1, 2, 0.5)]"), Ok(vec![2.0, 3.0]));
+        let step = "the step of a range must be a nonzero number, got 0";
+        assert_eq!(list("range(0, 1, 1 - 1)"), Err(step.into()));
+        assert_eq!(list("range(0, 2, 10 ^ -6)"), Err("ranges can have at most 1000000 numbers".into()));
+        assert_eq!(list("map(1..3, x -> x ^ 2)"), Ok(vec![1.0, 4.0, 9.0]));
+        assert_eq!(list("filter([3, -1, 0.5], x -> x > 0)"), Ok(vec![3.0, 0.5]));
+        assert_eq!(list("map(filter(1..6, n -> mod(n, 2) == 0), n -> n / 2)"), Ok(vec![1.0, 2.0, 3.0]));
+        assert_eq!(list("[y + 1 for y in map(1..2, x -> x * 10)]"), Ok(vec![11.0, 21.0]));
+        let map = "map takes a function like x -> x ^ 2, got one with 2 parameters";
+        assert_eq!(list("map(1..3, (a, b) -> a)"), Err(map.into()));
+        let filter = "the function of filter must return a boolean, not a number";
+        assert_eq!(list("filter(1..3, x -> x)"), Err(filter.into()));
+        assert_eq!(list("map(1..3, x -> y)"), Err("unknown variable: y".into()));
+        assert_eq!(list("sort([3, -1, 2, -1])"), Ok(vec![-1.0, -1.0, 2.0, 3.0]));
+        assert_eq!(list("reverse(sort([3, -1, 2]))"), Ok(vec![3.0, 2.0, -1.0]));
+        assert_eq!(list("unique([3, 1, 3, 0, -0, 1])"), Ok(vec![3.0, 1.0, 0.0]));
+        assert_eq!(list("cumsum(1..4)"), Ok(vec![1.0, 3.0, 6.0, 10.0]));
+        assert_eq!(list("[x for x in cumsum([]) ]"), Ok(vec![]));
+        assert_eq!(list("odesolve((x, y) -> 2 * x, 1, 0..2)"), Ok(vec![1.0, 2.0, 5.0]));
+        let ode = "odesolve takes a function like (x, y) -> x * y, got one with 1 parameters";
+        assert_eq!(list("odesolve(x -> x, 1, 0..2)"), Err(ode.into()));
+
+        assert_eq!(run(&mut ctx, "v = [3, -1, 4 - 5 * 2, 0.5]"), Ok(None));
+        let statement = parse_statement("[x for x in v if x > 0]", Syntax::Native).unwrap();
+        assert_eq!(ctx.run(&statement), Ok(Some(Value::List(vec![3.0, 0.5]))));
+        // the variable is only bound in the list
+        assert_eq!(run(&mut ctx, "x"), Err("unknown variable: x".into()));
+        assert_eq!(run(&mut ctx, "v + 1"), Err("cannot add a number to a list".into()));
+        assert_eq!(run(&mut ctx, "1 + sum(sort(v))"), Ok(Some(-2.5)));
+        assert_eq!(ctx.undo(), Some("v".into()));
+        assert_eq!(run(&mut ctx, "v + 1"), Err("unknown variable: v".into()));
+
+        assert_eq!(run(&mut ctx, "sum(1..100)"), Ok(Some(5050.0)));
+        assert_eq!(run(&mut ctx, "avg([x ^ 2 for x in range(0, 4)])"), Ok(Some(3.5)));
+        assert_eq!(run(&mut ctx, "v = range(1, 4)"), Ok(None));
+        assert_eq!(run(&mut ctx, "sum(v) / 2"), Ok(Some(3.0)));
+        assert_eq!(run(&mut ctx, "max(v, 10) + min(0, v)"), Ok(Some(10.0)));
+        assert_eq!(run(&mut ctx, "len(v) * 2"), Ok(Some(6.0)));
+        assert_eq!(run(&mut ctx, "len(unique([1, 1]))"), Ok(Some(1.0)));
+        assert_eq!(run(&mut ctx, "len(5..1)"), Ok(Some(0.0)));
+        assert_eq!(run(&mut ctx, "f(v) = v * 2"), Ok(None));
+        assert_eq!(run(&mut ctx, "f(5)"), Ok(Some(10.0)));
+        assert_eq!(run(&mut ctx, "sum(5..1)"), Err("sum takes at least 1 arguments, got 0".into()));
+        let only = "only functions taking any number of arguments, like sum, take lists";
+        assert_eq!(run(&mut ctx, "sqrt(1..4)"), Err(format!("{only}: sqrt")));
+        assert_eq!(run(&mut ctx, "f(1..4)"), Err("in f: cannot multiply a list by a number".into()));
+        assert_eq!(run(&mut ctx, "mean(l) = sum(l) / len(l)"), Ok(None));
+        assert_eq!(run(&mut ctx, "x = [4, 1, 7]"), Ok(None));
+        assert_eq!(run(&mut ctx, "mean(sort(x)) + mean(1..3)"), Ok(Some(6.0)));
+        assert_eq!(run(&mut ctx, "g(1..4)"), Err("unknown function: g".into()));
+
+        assert_eq!(run(&mut ctx, "reduce(1..4, 1, (a, b) -> a * b)"), Ok(Some(24.0)));
+        assert_eq!(run(&mut ctx, "reduce([], 5, (a, b) -> a + b)"), Ok(Some(5.0)));
+        assert_eq!(run(&mut ctx, "reduce(v, 0, (biggest, x) -> max(biggest, x))"), Ok(Some(3.0)));
+        let reduce = "reduce takes a function like (a, b) -> a + b, got one with 1 parameters";
+        assert_eq!(run(&mut ctx, "reduce(v, 0, x -> x)"), Err(reduce.into()));
+    }
+
+    #[test]
+    fn resolver() {
+        let mut ctx = Context::default();
+        ctx.set_resolver(|name: &str| (name == "sensor").then_some(21.5));
+        assert_eq!(run(&mut ctx, "sensor * 2"), Ok(Some(43.0)));
+        assert_eq!(run(&mut ctx, "other"), Err("unknown variable: other".into()));
+        // assigned variables come first, and resolved ones are not stored
+        assert_eq!(run(&mut ctx, "sensor = 1"), Ok(None));
+        assert_eq!(run(&mut ctx, "sensor"), Ok(Some(1.0)));
+        ctx.unset("sensor");
+        assert_eq!(ctx.var("sensor"), None);
+        assert_eq!(run(&mut ctx, "sensor"), Ok(Some(21.5)));
+    }
+
+    #[test]
+    fn previous_result() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "ans"), Err("there is no previous result for ans".into()));
+        run(&mut ctx, "6 * 7").unwrap();
+        assert_eq!(run(&mut ctx, "ans + 1"), Ok(Some(43.0)));
+        assert_eq!(run(&mut ctx, "ans + 1"), Ok(Some(44.0)));
+        run(&mut ctx, "ans = 1").unwrap();
+        assert_eq!(run(&mut ctx, "ans + 1"), Ok(Some(2.0)));
+    }
+
+    #[test]
+    fn outputs() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "out[1]"), Err("there is no out[1]".into()));
+        run(&mut ctx, "6 * 7").unwrap();
+        assert_eq!(ctx.add_output("6 * 7"), Some(1));
+        run(&mut ctx, "out[1] + 1").unwrap();
+        assert_eq!(ctx.add_output("out[1] + 1"), Some(2));
+        assert_eq!(ctx.output(2), Ok(("out[1] + 1", 43.0)));
+        for _ in 0..OUTPUT_LIMIT {
+            ctx.add_output("43");
+        }
+        assert_eq!(run(&mut ctx, "out[2] + out[1002]"), Err("out[2] is too old to be kept".into()));
+        assert_eq!(run(&mut ctx, "out[3] + out[1002]"), Ok(Some(86.0)));
+    }
+
+    #[test]
+    fn chained_assignments() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "y = (x = 3) * 2"), Ok(None));
+        assert_eq!((ctx.var("x"), ctx.var("y")), (Some(3.0), Some(6.0)));
+        assert_eq!(run(&mut ctx, "a = b = 5"), Ok(None));
+        assert_eq!((ctx.var("a"), ctx.var("b")), (Some(5.0), Some(5.0)));
+        assert_eq!(run(&mut ctx, "(x = x + 1) + x"), Ok(Some(8.0)));
+        assert_eq!(ctx.var("x"), Some(4.0));
+        // nothing is assigned when the expression is wrong
+        assert_eq!(run(&mut ctx, "(z = 1) + true"), Err("cannot add a boolean to a number".into()));
+        assert_eq!(ctx.var("z"), None);
+        // or when evaluating it fails
+        assert_eq!(
+            run(&mut ctx, "(z = 1) + rounded((x = 0), 0.5)"),
+            Err("rounded takes integers, got 0.5".into())
+        );
+        assert_eq!((ctx.var("x"), ctx.var("z")), (Some(4.0), None));
+
+        // in the order they are evaluated
+        run(&mut ctx, "x = 1").unwrap();
+        assert_eq!(run(&mut ctx, "(x = x + 1) + (x = x * 10)"), Ok(Some(22.0)));
+        assert_eq!(ctx.var("x"), Some(20.0));
+        // only in the branch that is taken
+        assert_eq!(run(&mut ctx, "if(0, (x = 5), (w = 6))"), Ok(Some(6.0)));
+        assert_eq!((ctx.var("x"), ctx.var("w")), (Some(20.0), Some(6.0)));
+    }
+
+    #[test]
+    fn functions() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "k = 10"), Ok(None));
+        assert_eq!(run(&mut ctx, "f(x, y) = k * x + y"), Ok(None));
+        assert_eq!(run(&mut ctx, "g(x) = f(x, x) - sqrt(x)"), Ok(None));
+        assert_eq!(run(&mut ctx, "g(4)"), Ok(Some(42.0)));
+        assert_eq!(run(&mut ctx, "x = 1"), Ok(None));
+        // parameters are not visible in called functions
+        assert_eq!(run(&mut ctx, "h(k) = f(0, 0) + k"), Ok(None));
+        assert_eq!(run(&mut ctx, "h(5) + x"), Ok(Some(6.0)));
+
+        assert_eq!(run(&mut ctx, "f(1)"), Err("f takes 2 arguments, got 1".into()));
+        assert_eq!(run(&mut ctx, "e() = y"), Ok(None));
+        assert_eq!(run(&mut ctx, "e()"), Err("in e: unknown variable: y".into()));
+        assert_eq!(run(&mut ctx, "f(x, y) = g(x)"), Ok(None));
+        assert_eq!(
+            run(&mut ctx, "f(1, 2)"),
+            Err("recursion limit exceeded in f(1, 1)".into())
+        );
+    }
+
+    #[test]
+    fn namespaces() {
+        let mut ctx = Context::default();
+        run(&mut ctx, "geom.area(r) = pi * r ^ 2").unwrap();
+        run(&mut ctx, "geom.scale = 2").unwrap();
+        run(&mut ctx, "geom.solid.volume(r) = 4 * r ^ 3").unwrap();
+        run(&mut ctx, "area = 1").unwrap();
+        run(&mut ctx, "phys.g = 9.81").unwrap();
+        assert_eq!(run(&mut ctx, "geom.area(geom.scale) / pi + area"), Ok(Some(5.0)));
+        assert_eq!(
+            ctx.namespaces().into_iter().collect::<Vec<_>>(),
+            [
+                ("geom", vec!["area", "scale"]),
+                ("geom.solid", vec!["volume"]),
+                ("phys", vec!["g"]),
+            ]
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let mut ctx = Context::default();
+        run(&mut ctx, "fact(n) = if(n <= 1, 1, n * fact(n - 1))").unwrap();
+        assert_eq!(run(&mut ctx, "fact(10)"), Ok(Some(3628800.0)));
+        run(&mut ctx, "memo fib(n) = if(n < 2, n, fib(n - 1) + fib(n - 2))").unwrap();
+        #[cfg(not(feature = "f32"))]
+        assert_eq!(run(&mut ctx, "fib(80)"), Ok(Some(23416728348467685.0)));
+        run(&mut ctx, "down(n) = down(n - 1)").unwrap();
+        assert_eq!(run(&mut ctx, "down(10)"), Err("recursion limit exceeded in down(-990)".into()));
+        // the calls that failed are over
+        assert_eq!(run(&mut ctx, "fact(5)"), Ok(Some(120.0)));
+        run(&mut ctx, "even(n) = if(n == 0, 1, 1 - even(n - 1))").unwrap();
+        assert_eq!(run(&mut ctx, "even(999)"), Ok(Some(0.0)));
+        ctx.set_recursion_limit(10);
+        assert_eq!(run(&mut ctx, "fact(11)"), Err("recursion limit exceeded in fact(1)".into()));
+        run(&mut ctx, "f(n) = 1 / assert(n > 0) + f(n - 1)").unwrap();
+        assert_eq!(run(&mut ctx, "f(3)"), Err("in f: assertion failed".into()));
+        // calls are not nested on the native stack
+        ctx.set_recursion_limit(usize::MAX);
+        assert_eq!(run(&mut ctx, "even(200000)"), Ok(Some(1.0)));
+        assert_eq!(run(&mut ctx, "even(3) + (1±0.1) * even(2)"), Ok(Some(1.0)));
+    }
+
+    #[test]
+    fn memo_functions() {
+        let mut ctx = Context::default();
+        run(&mut ctx, "a = 2").unwrap();
+        run(&mut ctx, "memo f(x, y) = a * x + y").unwrap();
+        assert_eq!(run(&mut ctx, "f(3, 1) + f(3, 1)"), Ok(Some(14.0)));
+        let key = NATIVE.memo_key().unwrap();
+        assert_eq!(ctx.memoized(key, "f", &[3.0, 1.0]), Some(7.0));
+        assert_eq!(ctx.memoized(key, "f", &[1.0, 3.0]), None);
+        // the cached results may have used the old value
+        run(&mut ctx, "a = 10").unwrap();
+        assert_eq!(ctx.memoized(key, "f", &[3.0, 1.0]), None);
+        assert_eq!(run(&mut ctx, "f(3, 1)"), Ok(Some(31.0)));
+        run(&mut ctx, "g(x) = x").unwrap();
+        run(&mut ctx, "g(1)").unwrap();
+        assert_eq!(ctx.memoized(key, "g", &[1.0]), None);
+    }
+
+    #[test]
+    #[cfg(feature = "f32")]
+    fn memo_functions_f32() {
+        let mut ctx = Context::default();
+        run(&mut ctx, "memo fib(n) = if(n < 2, n, fib(n - 1) + fib(n - 2))").unwrap();
+        assert_eq!(run(&mut ctx, "fib(30)"), Ok(Some(832040.0)));
+        assert_eq!(ctx.memoized("f32", "fib", &[30.0]), Some(832040.0));
+        // results of f64 are not mixed up with the ones of f32
+        assert_eq!(ctx.memoized("f64", "fib", &[30.0]), None);
+    }
+
+    #[test]
+    fn undo() {
+        let mut ctx = Context::default();
+        assert_eq!(ctx.undo(), None);
+        run(&mut ctx, "x = 1").unwrap();
+        run(&mut ctx, "x = 2").unwrap();
+        run(&mut ctx, "y = x").unwrap();
+        assert_eq!(ctx.undo(), Some("y".into()));
+        assert_eq!(ctx.var("y"), None);
+        assert_eq!(ctx.undo(), Some("x".into()));
+        assert_eq!(ctx.var("x"), Some(1.0));
+        assert_eq!(ctx.undo(), Some("x".into()));
+        assert_eq!(ctx.var("x"), None);
+        assert_eq!(ctx.undo(), None);
+
+        run(&mut ctx, "f(x) = x").unwrap();
+        run(&mut ctx, "f(x) = 2 * x").unwrap();
+        assert!(ctx.unset("f"));
+        assert_eq!(run(&mut ctx, "f(1)"), Err("unknown function: f".into()));
+        assert_eq!(ctx.undo(), Some("f".into()));
+        assert_eq!(run(&mut ctx, "f(1)"), Ok(Some(2.0)));
+        assert_eq!(ctx.undo(), Some("f".into()));
+        assert_eq!(run(&mut ctx, "f(1)"), Ok(Some(1.0)));
+
+        for i in 0..UNDO_LIMIT + 5 {
+            run(&mut ctx, &format!("x = {i}")).unwrap();
+        }
+        while ctx.undo().is_some() {}
+        assert_eq!(ctx.var("x"), Some(4.0));
+    }
+
+    #[test]
+    fn memory() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "MR"), Ok(Some(0.0)));
+        assert_eq!(run(&mut ctx, "MC"), Ok(None));
+        assert_eq!(run(&mut ctx, "x = 1"), Ok(None));
+        assert_eq!(run(&mut ctx, "M+"), Ok(None));
+        assert_eq!(run(&mut ctx, "2 * 3"), Ok(Some(6.0)));
+        assert_eq!(run(&mut ctx, "M+"), Ok(None));
+        assert_eq!(run(&mut ctx, "M+"), Ok(None));
+        assert_eq!(run(&mut ctx, "1"), Ok(Some(1.0)));
+        assert_eq!(run(&mut ctx, "M-"), Ok(None));
+        assert_eq!(run(&mut ctx, "MR"), Ok(Some(11.0)));
+        assert_eq!(run(&mut ctx, "MC"), Ok(None));
+        assert_eq!(run(&mut ctx, "MR"), Ok(Some(0.0)));
+
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "M-"), Err("there is no result to add to the memory yet".into()));
+    }
+
+    #[test]
+    fn values() {
+        let mut ctx = Context::default();
+        let statement = parse_statement("1 < 2 == true", Syntax::Native).unwrap();
+        assert_eq!(ctx.run(&statement), Ok(Some(Value::Boolean(true))));
+        let statement = parse_statement("if(false, 1, 2)", Syntax::Native).unwrap();
+        assert_eq!(ctx.run(&statement), Ok(Some(Value::Number(2.0))));
+
+        for (input, output) in [
+            ("1:30 + 0:45", "2:15"),
+            ("90 min", "1:30"),
+            ("90 min to hours", "1.5"),
+            ("(2:00 - 0:30:15) * 2", "2:59:30"),
+            ("-1:30 / 4", "-0:22:30"),
+            ("5.5 s", "0:00:05.5"),
+            ("5' 10\" + 2 in", "6' 0\""),
+            ("6 ft 2 in / 4", "1' 6 1/2\""),
+            ("(5' 10 1/2\" - 6') * 2", "-0' 3\""),
+            ("0.375\"", "0' 3/8\""),
+            ("6 ft to in", "72"),
+            ("1 ft / 4 in", "3"),
+            // in the unit they were made in, unless it is imperial
+            ("2 m", "2 m"),
+            ("2 m + 30 cm", "2.3 m"),
+            ("1.5 km * 2", "3 km"),
+            ("3 m/s * 2 min", "360 m"),
+            ("2 ft + 1 m", "5' 3 3/8\""),
+            ("1 mi / 1000", "5' 3 3/8\""),
+            ("3.048 m to ft", "10"),
+            ("100 m / 8 s", "12.5 m/s"),
+            ("60 km/h * 90 min to km", "90"),
+            ("1 mi to km", "1.609344"),
+            ("90 min/hours(1)", "1.5"),
+        ] {
+            let statement = parse_statement(input, Syntax::Native).unwrap();
+            let res = ctx.run(&statement).unwrap().unwrap();
+            assert_eq!(res.to_string(), output, "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn currencies() {
+        let mut ctx = Context::default();
+        ctx.set_rates(Rates::parse("base = \"EUR\"\ndate = \"2026-10-01\"\n[rates]\nUSD = 1.25\nSEK = 10").unwrap());
+        for (input, output) in [
+            ("100 USD", "80.00 EUR"),
+            ("100 USD to EUR", "80.00 EUR"),
+            ("(100 SEK + 2 EUR) to USD", "15.00 USD"),
+            ("100 SEK to USD", "12.50 USD"),
+            ("10 EUR / 4", "2.50 EUR"),
+            ("10 EUR / 3", "3.33… EUR"),
+            // what `to SEK` is parsed as
+            ("1 USD / 1 SEK", "8.00 SEK"),
+            ("1 USD / 2 SEK", "4"),
+        ] {
+            let statement = parse_statement(input, Syntax::Native).unwrap();
+            let res = ctx.run(&statement).unwrap().unwrap();
+            assert_eq!(res.to_string(), output, "input was {input:?}");
+        }
+        assert_eq!(run(&mut ctx, "1 GBP"), Err("unknown currency: GBP".into()));
+        assert_eq!(run(&mut ctx, "1 USD + 1"), Err("cannot add a number to a money".into()));
+    }
+
+    #[test]
+    fn checked_before_evaluation() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "x = if(1, 2, max())"), Err("max takes at least 1 arguments, got 0".into()));
+        assert_eq!(run(&mut ctx, "(1 < 2) * 3"), Err("cannot multiply a boolean by a number".into()));
+        assert_eq!(ctx.var("x"), None);
+    }
+
+    #[test]
+    fn modules() {
+        let mut ctx = Context::default();
+        assert_eq!(run(&mut ctx, "median(3, 1, 2)"), Ok(Some(2.0)));
+        assert_eq!(
+            run(&mut ctx, "band(6, 3)"),
+            Err("band is in the bits module, which is not loaded".into())
+        );
+        ctx.load(Module::Bits);
+        assert_eq!(run(&mut ctx, "band(6, 3)"), Ok(Some(2.0)));
+        assert_eq!(ctx.unload(Module::Trig), Ok(()));
+        assert!(run(&mut ctx, "sin(0)").is_err());
+        // user functions take precedence over unloaded built-ins
+        assert_eq!(run(&mut ctx, "sin(x) = x"), Ok(None));
+        assert_eq!(run(&mut ctx, "sin(2)"), Ok(Some(2.0)));
+        assert!(ctx.unload(Module::Core).is_err());
+        assert_eq!(run(&mut ctx, "sqrt(4)"), Ok(Some(2.0)));
+    }
+
+    #[test]
+    fn environment() {
+        std::env::set_var("CALCULATOR_TEST_WIDTH", " 80 ");
+        std::env::set_var("CALCULATOR_TEST_NAME", "abc");
+        let mut ctx = Context::new(true);
+        assert_eq!(run(&mut ctx, "$CALCULATOR_TEST_WIDTH / 2"), Ok(Some(40.0)));
+        assert_eq!(
+            run(&mut ctx, "$CALCULATOR_TEST_NAME"),
+            Err("environment variable is not a number: CALCULATOR_TEST_NAME=abc".into())
+        );
+        assert_eq!(
+            run(&mut ctx, "$CALCULATOR_TEST_UNSET"),
+            Err("environment variable not set: CALCULATOR_TEST_UNSET".into())
+        );
+
+        let mut ctx = Context::new(false);
+        assert_eq!(
+            run(&mut ctx, "$CALCULATOR_TEST_WIDTH"),
+            Err("environment variables are disabled: $CALCULATOR_TEST_WIDTH".into())
+        );
+    }
+}
@@ -0,0 +1,251 @@
+//! Currencies like `100 USD`, which are converted with exchange rates from a TOML file like
+//!
+//! ```toml
+//! base = "EUR"
+//! date = "2026-10-15"
+//!
+//! [rates]
+//! USD = 1.08
+//! SEK = 11.2
+//! ```
+//!
+//! where each rate is how much of the currency one unit of the base is worth. Without a file
+//! the built-in rates are used, which are surely out of date.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// How many days old the rates can be before a warning is shown.
+pub const STALE_AFTER: i64 = 7;
+
+/// Rates for when there is no file, which are only roughly right.
+const BUILT_IN: &str = r#"base = "EUR"
+date = "2025-01-02"
+
+[rates]
+AUD = 1.67
+CAD = 1.49
+CHF = 0.94
+CNY = 7.56
+DKK = 7.46
+GBP = 0.83
+JPY = 162.0
+NOK = 11.8
+SEK = 11.5
+USD = 1.03
+"#;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rates {
+    /// the currency that the others are relative to, and that amounts are counted in
+    pub base: String,
+    /// the day the rates are from, like `2026-10-15`
+    pub date: String,
+    /// how much of each currency one unit of the base is worth
+    pub rates: BTreeMap<String, f64>,
+}
+
+impl Default for Rates {
+    fn default() -> Self {
+        Rates::parse(BUILT_IN).unwrap()
+    }
+}
+
+/// Whether `name` looks like a currency code, like `USD`.
+pub fn is_code(name: &str) -> bool {
+    name.len() == 3 && name.bytes().all(|b| b.is_ascii_uppercase())
+}
+
+impl Rates {
+    /// Reads the subset of TOML that rate files use: strings and numbers, and a `[rates]` table.
+    pub fn parse(source: &str) -> Result<Rates, String> {
+        let (mut base, mut date, mut rates) = (None, None, BTreeMap::new());
+        let mut in_rates = false;
+        for (n, line) in source.lines().enumerate() {
+            let error = |message: &str| format!("line {}: {message}", n + 1);
+            let line = match line.split_once('#') {
+                // no string in a rate file has a `#`
+                Some((line, _)) => line.trim(),
+                None => line.trim(),
+            };
+            if line.is_empty() {
+                continue;
+            }
+            if let Some(table) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+                if table.trim() != "rates" {
+                    return Err(error(&format!("unknown table: {}", table.trim())));
+                }
+                in_rates = true;
+                continue;
+            }
+            let (key, value) = line.split_once('=').ok_or_else(|| error("expected key = value"))?;
+            let (key, value) = (key.trim(), value.trim());
+            if in_rates {
+                if !is_code(key) {
+                    return Err(error(&format!("not a currency code: {key}")));
+                }
+                let rate: f64 = value.parse().map_err(|_| error(&format!("not a number: {value}")))?;
+                if !(rate.is_finite() && rate > 0.0) {
+                    return Err(error(&format!("the rate of {key} is not positive")));
+                }
+                rates.insert(key.to_string(), rate);
+            } else {
+                let string = value.strip_prefix('"').and_then(|v| v.strip_suffix('"'));
+                let string = string.ok_or_else(|| error(&format!("{key} takes a string")))?.to_string();
+                match key {
+                    "base" => base = Some(string),
+                    "date" => date = Some(string),
+                    _ => return Err(error(&format!("unknown key: {key}"))),
+                }
+            }
+        }
+        let date = date.ok_or("missing date")?;
+        if days(&date).is_none() {
+            return Err(format!("the date is not like 2026-10-15: {date}"));
+        }
+        Ok(Rates {
+            base: base.ok_or("missing base")?,
+            date,
+            rates,
+        })
+    }
+
+    #[cfg_attr(not(feature = "online-rates"), allow(dead_code))]
+    pub fn to_toml(&self) -> String {
+        let mut res = format!("base = \"{}\"\ndate = \"{}\"\n\n[rates]\n", self.base, self.date);
+        for (code, rate) in &self.rates {
+            res += &format!("{code} = {rate}\n");
+        }
+        res
+    }
+
+    /// Converts an amount of the currency `code` to the base currency.
+    pub fn to_base(&self, code: &str, amount: f64) -> Result<f64, String> {
+        if code == self.base {
+            return Ok(amount);
+        }
+        match self.rates.get(code) {
+            Some(rate) => Ok(amount / rate),
+            None => Err(format!("unknown currency: {code}")),
+        }
+    }
+
+    /// A warning if the rates are older than [`STALE_AFTER`] days on the day `today`, which
+    /// is counted in days since 1970.
+    pub fn stale(&self, today: i64) -> Option<String> {
+        let age = today - days(&self.date)?;
+        (age > STALE_AFTER).then(|| {
+            format!("the currency rates are from {}, {age} days ago, so conversions may be off", self.date)
+        })
+    }
+}
+
+/// The number of days since 1970-01-01 of a date like `2026-10-15`.
+fn days(date: &str) -> Option<i64> {
+    let mut parts = date.splitn(3, '-').map(|p| p.parse::<i64>().ok());
+    let (y, m, d) = (parts.next()??, parts.next()??, parts.next()??);
+    if !(1..=12).contains(&m) || !(1..=31).contains(&d) {
+        return None;
+    }
+    // counted from March, so that the leap day is last
+    let y = if m <= 2 { y - 1 } else { y };
+    let era = y.div_euclid(400);
+    let year_of_era = y - era * 400;
+    let day_of_year = (153 * ((m + 9) % 12) + 2) / 5 + d - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    Some(era * 146097 + day_of_era - 719468)
+}
+
+/// Today in days since 1970.
+pub fn today() -> i64 {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH);
+    now.map_or(0, |d| d.as_secs() as i64 / 86400)
+}
+
+/// Where the rates are read from by default, which is `~/.config/calc/rates.toml` unless
+/// `XDG_CONFIG_HOME` says otherwise.
+pub fn default_path() -> Option<PathBuf> {
+    let config = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("calc/rates.toml"))
+}
+
+pub fn load(path: &Path) -> Result<Rates, String> {
+    let source = std::fs::read_to_string(path).map_err(|err| format!("{}: {err}", path.display()))?;
+    Rates::parse(&source).map_err(|err| format!("{}: {err}", path.display()))
+}
+
+/// Downloads the reference rates of the European Central Bank from frankfurter.app.
+#[cfg(feature = "online-rates")]
+pub fn fetch() -> Result<Rates, String> {
+    use crate::json::Json;
+
+    // curl is on most systems, and saves a TLS implementation for a rarely used feature
+    let output = std::process::Command::new("curl")
+        .args(["--silent", "--show-error", "--fail", "https://api.frankfurter.app/latest"])
+        .output()
+        .map_err(|err| format!("could not run curl: {err}"))?;
+    if !output.status.success() {
+        return Err(format!("could not fetch the rates: {}", String::from_utf8_lossy(&output.stderr).trim()));
+    }
+    let json = Json::parse(&String::from_utf8_lossy(&output.stdout))?;
+    let (Some(Json::String(base)), Some(Json::String(date)), Some(Json::Object(fields))) =
+        (json.get("base"), json.get("date"), json.get("rates"))
+    else {
+        return Err(format!("unexpected rates: {json}"));
+    };
+    let mut rates = BTreeMap::new();
+    for (code, rate) in fields {
+        if let Json::Number(rate) = rate {
+            rates.insert(code.clone(), *rate);
+        }
+    }
+    Ok(Rates {
+        base: base.clone(),
+        date: date.clone(),
+        rates,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        let rates = Rates::parse("# from the bank\nbase = \"EUR\"\ndate = \"2026-10-15\"\n\n[rates]\nUSD = 1.25 # high\n")
+            .unwrap();
+        assert_eq!(rates.base, "EUR");
+        assert_eq!(rates.to_base("USD", 100.0), Ok(80.0));
+        assert_eq!(rates.to_base("EUR", 100.0), Ok(100.0));
+        assert_eq!(rates.to_base("XYZ", 1.0), Err("unknown currency: XYZ".into()));
+        assert_eq!(Rates::parse(&rates.to_toml()), Ok(rates));
+
+        assert_eq!(Rates::parse("base = \"EUR\""), Err("missing date".into()));
+        assert_eq!(Rates::parse("[rates]\nusd = 1"), Err("line 2: not a currency code: usd".into()));
+        assert_eq!(Rates::parse("[rates]\nUSD = -1"), Err("line 2: the rate of USD is not positive".into()));
+        assert_eq!(Rates::parse("base = EUR"), Err("line 1: base takes a string".into()));
+        assert!(Rates::parse(BUILT_IN).is_ok());
+    }
+
+    #[test]
+    fn stale() {
+        assert_eq!(days("1970-01-01"), Some(0));
+        assert_eq!(days("2000-03-01"), Some(11017));
+        assert_eq!(days("2026-13-01"), None);
+
+        let rates = Rates {
+            date: "2026-10-01".into(),
+            ..Rates::default()
+        };
+        let day = days("2026-10-01").unwrap();
+        assert_eq!(rates.stale(day + STALE_AFTER), None);
+        assert_eq!(
+            rates.stale(day + 10),
+            Some("the currency rates are from 2026-10-01, 10 days ago, so conversions may be off".into())
+        );
+    }
+}
@@ -0,0 +1,505 @@
+//! Decimal floating point numbers like IEEE 754 decimal128, so that `0.1 + 0.2` is exactly
+//! `0.3` and amounts of money are not rounded to binary fractions.
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// The number of significant digits that are kept.
+const DIGITS: usize = 34;
+/// The smallest coefficient with more than [`DIGITS`] digits.
+const LIMIT: u128 = 10u128.pow(DIGITS as u32);
+/// The range of exponents of decimal128. Larger numbers are infinite and smaller ones zero.
+const MAX_EXPONENT: i32 = 6111;
+const MIN_EXPONENT: i32 = -6176;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Decimal {
+    /// `coefficient * 10^exponent`, where the coefficient has no trailing zeros
+    Finite { coefficient: i128, exponent: i32 },
+    Infinite { negative: bool },
+    NaN,
+}
+
+/// How to round to a number of decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rounding {
+    Floor,
+    Ceil,
+    Trunc,
+    /// like 2.5 to 3 and -2.5 to -3
+    HalfAwayFromZero,
+}
+
+/// An unsigned integer that is wide enough for the product of two coefficients.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord)]
+struct Wide {
+    hi: u128,
+    lo: u128,
+}
+
+impl Wide {
+    fn new(x: u128) -> Self {
+        Wide { hi: 0, lo: x }
+    }
+
+    fn mul(a: u128, b: u128) -> Self {
+        const MASK: u128 = u64::MAX as u128;
+        let (a1, a0) = (a >> 64, a & MASK);
+        let (b1, b0) = (b >> 64, b & MASK);
+        let (p00, p01, p10, p11) = (a0 * b0, a0 * b1, a1 * b0, a1 * b1);
+        let mid = (p00 >> 64) + (p01 & MASK) + (p10 & MASK);
+        Wide {
+            hi: p11 + (p01 >> 64) + (p10 >> 64) + (mid >> 64),
+            lo: (p00 & MASK) | (mid << 64),
+        }
+    }
+
+    fn add(self, x: u128) -> Self {
+        let (lo, carry) = self.lo.overflowing_add(x);
+        Wide {
+            hi: self.hi + carry as u128,
+            lo,
+        }
+    }
+
+    /// `self - x`, which must not be negative.
+    fn sub(self, x: u128) -> Self {
+        let (lo, borrow) = self.lo.overflowing_sub(x);
+        Wide {
+            hi: self.hi - borrow as u128,
+            lo,
+        }
+    }
+
+    /// Divides by `d`, which is less than 2^127, returning the quotient and remainder.
+    fn div_rem(self, d: u128) -> (Self, u128) {
+        let (mut q, mut r) = (Wide::default(), 0u128);
+        for i in (0..256).rev() {
+            let bit = if i >= 128 { self.hi >> (i - 128) } else { self.lo >> i } & 1;
+            r = (r << 1) | bit;
+            q = Wide {
+                hi: (q.hi << 1) | (q.lo >> 127),
+                lo: q.lo << 1,
+            };
+            if r >= d {
+                r -= d;
+                q.lo |= 1;
+            }
+        }
+        (q, r)
+    }
+}
+
+fn pow10(n: usize) -> u128 {
+    10u128.pow(n as u32)
+}
+
+fn digits(x: u128) -> usize {
+    x.checked_ilog10().map_or(1, |d| d as usize + 1)
+}
+
+impl Decimal {
+    pub const ZERO: Decimal = Decimal::Finite {
+        coefficient: 0,
+        exponent: 0,
+    };
+
+    pub fn from_bool(b: bool) -> Self {
+        Decimal::Finite {
+            coefficient: b as i128,
+            exponent: 0,
+        }
+    }
+
+    /// The decimal that is printed for `f`, so `0.1` is exactly one tenth.
+    pub fn from_f64(f: f64) -> Self {
+        if f.is_nan() {
+            return Decimal::NaN;
+        }
+        if f.is_infinite() {
+            return Decimal::Infinite { negative: f < 0.0 };
+        }
+        let s = f.to_string();
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s.as_str()),
+        };
+        let (whole, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let digits = format!("{whole}{fraction}");
+        let significant = digits.trim_end_matches('0');
+        let exponent = (digits.len() - significant.len()) as i32 - fraction.len() as i32;
+        // there are at most 17 significant digits, but maybe many leading zeros
+        let coefficient: u128 = significant.trim_start_matches('0').parse().unwrap_or(0);
+        Decimal::round(negative, Wide::new(coefficient), exponent, false)
+    }
+
+    pub fn to_f64(self) -> f64 {
+        match self {
+            Decimal::Finite { coefficient, exponent } => format!("{coefficient}e{exponent}").parse().unwrap(),
+            Decimal::Infinite { negative } => if negative { f64::NEG_INFINITY } else { f64::INFINITY },
+            Decimal::NaN => f64::NAN,
+        }
+    }
+
+    /// Rounds `magnitude * 10^exponent` to [`DIGITS`] significant digits, half to even.
+    /// `sticky` tells whether something nonzero was already cut off after the magnitude.
+    fn round(negative: bool, mut magnitude: Wide, mut exponent: i32, mut sticky: bool) -> Self {
+        let mut last = 0;
+        while magnitude >= Wide::new(LIMIT) {
+            sticky |= last != 0;
+            (magnitude, last) = magnitude.div_rem(10);
+            exponent += 1;
+        }
+        let mut coefficient = magnitude.lo;
+        if last > 5 || (last == 5 && (sticky || coefficient % 2 == 1)) {
+            coefficient += 1;
+            if coefficient == LIMIT {
+                coefficient /= 10;
+                exponent += 1;
+            }
+        }
+        Decimal::finite(negative, coefficient, exponent)
+    }
+
+    /// Removes trailing zeros, and handles exponents out of range.
+    fn finite(negative: bool, mut coefficient: u128, mut exponent: i32) -> Self {
+        if coefficient == 0 {
+            return Decimal::ZERO;
+        }
+        while coefficient.is_multiple_of(10) {
+            coefficient /= 10;
+            exponent += 1;
+        }
+        if exponent > MAX_EXPONENT {
+            return Decimal::Infinite { negative };
+        }
+        if exponent < MIN_EXPONENT {
+            return Decimal::ZERO;
+        }
+        let coefficient = coefficient as i128;
+        Decimal::Finite {
+            coefficient: if negative { -coefficient } else { coefficient },
+            exponent,
+        }
+    }
+
+    /// The sign, and the coefficient scaled up to [`DIGITS`] digits with its exponent.
+    fn widened(coefficient: i128, exponent: i32) -> (bool, u128, i32) {
+        let c = coefficient.unsigned_abs();
+        let shift = DIGITS - digits(c);
+        (coefficient < 0, c * pow10(shift), exponent - shift as i32)
+    }
+
+    pub fn add(self, other: Self) -> Self {
+        use Decimal::*;
+        match (self, other) {
+            (NaN, _) | (_, NaN) => NaN,
+            (Infinite { negative: a }, Infinite { negative: b }) if a != b => NaN,
+            (Infinite { .. }, _) => self,
+            (_, Infinite { .. }) => other,
+            (Finite { coefficient: 0, .. }, _) => other,
+            (_, Finite { coefficient: 0, .. }) => self,
+            (Finite { coefficient: a, exponent: ea }, Finite { coefficient: b, exponent: eb }) => {
+                let (a, b) = (Decimal::widened(a, ea), Decimal::widened(b, eb));
+                // `x` has the larger exponent
+                let ((nx, x, ex), (ny, y, ey)) = if a.2 >= b.2 { (a, b) } else { (b, a) };
+                let shift = (ex - ey) as usize;
+                if shift > DIGITS + 1 {
+                    // `y` is less than a hundredth of the last digit of `x`
+                    return Decimal::round(nx, Wide::new(x), ex, true);
+                }
+                let x = Wide::mul(x, pow10(shift));
+                if nx == ny {
+                    Decimal::round(nx, x.add(y), ey, false)
+                } else if x >= Wide::new(y) {
+                    Decimal::round(nx, x.sub(y), ey, false)
+                } else {
+                    // only when the exponents are equal, so nothing was shifted
+                    Decimal::round(ny, Wide::new(y - x.lo), ey, false)
+                }
+            }
+        }
+    }
+
+    pub fn neg(self) -> Self {
+        match self {
+            Decimal::Finite { coefficient, exponent } => Decimal::Finite {
+                coefficient: -coefficient,
+                exponent,
+            },
+            Decimal::Infinite { negative } => Decimal::Infinite { negative: !negative },
+            Decimal::NaN => Decimal::NaN,
+        }
+    }
+
+    fn is_negative(self) -> bool {
+        match self {
+            Decimal::Finite { coefficient, .. } => coefficient < 0,
+            Decimal::Infinite { negative } => negative,
+            Decimal::NaN => false,
+        }
+    }
+
+    fn is_zero(self) -> bool {
+        self == Decimal::ZERO
+    }
+
+    pub fn mul(self, other: Self) -> Self {
+        use Decimal::*;
+        let negative = self.is_negative() != other.is_negative();
+        match (self, other) {
+            (NaN, _) | (_, NaN) => NaN,
+            (Infinite { .. }, x) | (x, Infinite { .. }) if x.is_zero() => NaN,
+            (Infinite { .. }, _) | (_, Infinite { .. }) => Infinite { negative },
+            (Finite { coefficient: a, exponent: ea }, Finite { coefficient: b, exponent: eb }) => {
+                Decimal::round(negative, Wide::mul(a.unsigned_abs(), b.unsigned_abs()), ea + eb, false)
+            }
+        }
+    }
+
+    pub fn div(self, other: Self) -> Self {
+        use Decimal::*;
+        let negative = self.is_negative() != other.is_negative();
+        match (self, other) {
+            (NaN, _) | (_, NaN) | (Infinite { .. }, Infinite { .. }) => NaN,
+            (Infinite { .. }, _) => Infinite { negative },
+            (_, Infinite { .. }) => Decimal::ZERO,
+            (x, y) if y.is_zero() => if x.is_zero() { NaN } else { Infinite { negative } },
+            (x, _) if x.is_zero() => Decimal::ZERO,
+            (Finite { coefficient: a, exponent: ea }, Finite { coefficient: b, exponent: eb }) => {
+                // enough digits for the quotient to be rounded correctly
+                let (_, a, ea) = Decimal::widened(a, ea);
+                let (q, r) = Wide::mul(a, pow10(DIGITS + 1)).div_rem(b.unsigned_abs());
+                Decimal::round(negative, q, ea - DIGITS as i32 - 1 - eb, r != 0)
+            }
+        }
+    }
+
+    /// Raises to an integer power, or returns `None` for other exponents.
+    pub fn powi(self, exponent: Self) -> Option<Self> {
+        let Decimal::Finite { coefficient, exponent: e } = exponent else {
+            return None;
+        };
+        if e < 0 {
+            return None;
+        }
+        let n = coefficient.unsigned_abs().checked_mul(10u128.checked_pow(e as u32)?)?;
+        let n = u32::try_from(n).ok()?;
+        let (mut base, mut n, mut res) = (self, n, Decimal::from_bool(true));
+        while n > 0 {
+            if n % 2 == 1 {
+                res = res.mul(base);
+            }
+            base = base.mul(base);
+            n /= 2;
+        }
+        Some(if coefficient < 0 { Decimal::from_bool(true).div(res) } else { res })
+    }
+
+    /// Rounds to `places` decimal places, or to tens and hundreds when negative.
+    pub fn to_places(self, places: i32, rounding: Rounding) -> Self {
+        let Decimal::Finite { coefficient, exponent } = self else {
+            return self;
+        };
+        let cut = -places - exponent;
+        if cut <= 0 {
+            return self;
+        }
+        let (q, r) = match cut {
+            // everything is cut off, and it is less than half of what is left
+            39.. => (0, coefficient.signum()),
+            _ => (coefficient / pow10(cut as usize) as i128, coefficient % pow10(cut as usize) as i128),
+        };
+        let half = cut < 39 && 2 * r.unsigned_abs() >= pow10(cut as usize);
+        let q = match rounding {
+            Rounding::Floor if r < 0 => q - 1,
+            Rounding::Ceil if r > 0 => q + 1,
+            Rounding::HalfAwayFromZero if half => q + coefficient.signum(),
+            _ => q,
+        };
+        Decimal::finite(q < 0, q.unsigned_abs(), -places)
+    }
+
+    pub fn abs(self) -> Self {
+        if self.is_negative() { self.neg() } else { self }
+    }
+
+    /// One of the constants `pi`, `e`, `sqrt2` and `ln2`, computed from series. The last few
+    /// of the 34 digits may be off from the rounding of the terms.
+    pub fn constant(name: &str) -> Option<Self> {
+        let int = |n: u32| Decimal::from_f64(n as f64);
+        // atan(1 / n), from its Taylor series
+        let atan_inverse = |n: u32| {
+            let (mut sum, mut power) = (Decimal::ZERO, int(1).div(int(n)));
+            for k in 0..40 {
+                let term = power.div(int(2 * k + 1));
+                sum = if k % 2 == 0 { sum.add(term) } else { sum.add(term.neg()) };
+                power = power.div(int(n * n));
+            }
+            sum
+        };
+        Some(match name {
+            // Machin's formula
+            "pi" => int(16).mul(atan_inverse(5)).add(int(4).mul(atan_inverse(239)).neg()),
+            "e" => {
+                let (mut sum, mut term) = (int(1), int(1));
+                for k in 1..40 {
+                    term = term.div(int(k));
+                    sum = sum.add(term);
+                }
+                sum
+            }
+            "sqrt2" => {
+                let mut x = Decimal::from_f64(std::f64::consts::SQRT_2);
+                // every Newton step doubles the correct digits
+                for _ in 0..3 {
+                    x = x.add(int(2).div(x)).div(int(2));
+                }
+                x
+            }
+            // the sum of 1 / (k 2^k)
+            "ln2" => {
+                let (mut sum, mut power) = (Decimal::ZERO, int(1));
+                for k in 1..120 {
+                    power = power.div(int(2));
+                    sum = sum.add(power.div(int(k)));
+                }
+                sum
+            }
+            _ => return None,
+        })
+    }
+
+    /// The digits of `self` truncated to `places` decimal places, with trailing zeros.
+    pub fn to_fixed(self, places: usize) -> String {
+        let Decimal::Finite { coefficient, exponent } = self.to_places(places as i32, Rounding::Trunc) else {
+            return self.to_string();
+        };
+        let sign = if coefficient < 0 { "-" } else { "" };
+        let digits = format!("{}{}", coefficient.unsigned_abs(), "0".repeat((exponent + places as i32) as usize));
+        let digits = format!("{digits:0>width$}", width = places + 1);
+        let (whole, fraction) = digits.split_at(digits.len() - places);
+        format!("{sign}{whole}.{fraction}")
+    }
+}
+
+impl PartialOrd for Decimal {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        use Decimal::*;
+        match (*self, *other) {
+            (NaN, _) | (_, NaN) => None,
+            (Infinite { negative: a }, Infinite { negative: b }) => Some(b.cmp(&a)),
+            (Infinite { negative }, _) => Some(if negative { Ordering::Less } else { Ordering::Greater }),
+            (_, Infinite { negative }) => Some(if negative { Ordering::Greater } else { Ordering::Less }),
+            (Finite { coefficient: a, exponent: ea }, Finite { coefficient: b, exponent: eb }) => {
+                if (a < 0) != (b < 0) || a == 0 || b == 0 {
+                    return Some(a.cmp(&b));
+                }
+                let ((negative, a, ea), (_, b, eb)) = (Decimal::widened(a, ea), Decimal::widened(b, eb));
+                let magnitude = ea.cmp(&eb).then(a.cmp(&b));
+                Some(if negative { magnitude.reverse() } else { magnitude })
+            }
+        }
+    }
+}
+
+/// Writes the number without an exponent unless it is very large or small, like `f64` does
+/// for numbers of reasonable size.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (coefficient, exponent) = match *self {
+            Decimal::Finite { coefficient, exponent } => (coefficient, exponent),
+            Decimal::Infinite { negative } => return f.write_str(if negative { "-inf" } else { "inf" }),
+            Decimal::NaN => return f.write_str("NaN"),
+        };
+        let sign = if coefficient < 0 { "-" } else { "" };
+        let digits = coefficient.unsigned_abs().to_string();
+        // where the decimal point goes in the digits
+        let point = digits.len() as i32 + exponent;
+        match point {
+            _ if exponent >= 0 && point <= 40 => write!(f, "{sign}{digits}{}", "0".repeat(exponent as usize)),
+            1..=40 => {
+                let (whole, fraction) = digits.split_at(point as usize);
+                write!(f, "{sign}{whole}.{fraction}")
+            }
+            -9..=0 => write!(f, "{sign}0.{}{digits}", "0".repeat(-point as usize)),
+            _ => {
+                let (first, rest) = digits.split_at(1);
+                let rest = if rest.is_empty() { String::new() } else { format!(".{rest}") };
+                write!(f, "{sign}{first}{rest}e{}", point - 1)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn d(f: f64) -> Decimal {
+        Decimal::from_f64(f)
+    }
+
+    #[test]
+    fn exact_decimals() {
+        assert_eq!(d(0.1).add(d(0.2)), d(0.3));
+        assert_eq!(d(1.1).mul(d(1.1)).to_string(), "1.21");
+        assert_eq!(d(0.3).add(d(-0.1).neg().neg()).to_string(), "0.2");
+        assert_eq!(d(100.0).to_string(), "100");
+        assert_eq!(d(-0.005).to_string(), "-0.005");
+        assert_eq!(d(1e300).to_string(), "1e300");
+        assert_eq!(d(2.5).to_f64(), 2.5);
+    }
+
+    #[test]
+    fn rounding() {
+        assert_eq!(d(1.0).div(d(3.0)).to_string(), format!("0.{}", "3".repeat(34)));
+        assert_eq!(d(2.0).div(d(3.0)).to_string(), format!("0.{}7", "6".repeat(33)));
+        // the 0.1 is beyond the 34 digits
+        let big = d(1e33).add(d(0.1));
+        assert_eq!(big, d(1e33));
+        assert_eq!(d(1.0).add(d(1e-30)).to_string(), format!("1.{}1", "0".repeat(29)));
+        assert_eq!(d(1.0).div(d(0.0)), Decimal::Infinite { negative: false });
+        assert_eq!(d(0.0).div(d(0.0)), Decimal::NaN);
+    }
+
+    #[test]
+    fn powers() {
+        assert_eq!(d(1.5).powi(d(2.0)), Some(d(2.25)));
+        assert_eq!(d(2.0).powi(d(-2.0)), Some(d(0.25)));
+        assert_eq!(d(10.0).powi(d(7000.0)), Some(Decimal::Infinite { negative: false }));
+        assert_eq!(d(2.0).powi(d(0.5)), None);
+    }
+
+    #[test]
+    fn places() {
+        assert_eq!(d(2.675).to_places(2, Rounding::HalfAwayFromZero), d(2.68));
+        assert_eq!(d(-2.5).to_places(0, Rounding::HalfAwayFromZero), d(-3.0));
+        assert_eq!(d(-2.375).to_places(2, Rounding::Trunc), d(-2.37));
+        assert_eq!(d(-2.1).to_places(0, Rounding::Floor), d(-3.0));
+        assert_eq!(d(2.1).to_places(0, Rounding::Ceil), d(3.0));
+        assert_eq!(d(1234.0).to_places(-2, Rounding::HalfAwayFromZero), d(1200.0));
+    }
+
+    #[test]
+    fn constants() {
+        let digits = |name| Decimal::constant(name).unwrap().to_fixed(30);
+        assert_eq!(digits("pi"), "3.141592653589793238462643383279");
+        assert_eq!(digits("e"), "2.718281828459045235360287471352");
+        assert_eq!(digits("sqrt2"), "1.414213562373095048801688724209");
+        assert_eq!(digits("ln2"), "0.693147180559945309417232121458");
+        assert_eq!(Decimal::constant("tau"), None);
+        assert_eq!(d(0.05).to_fixed(3), "0.050");
+        assert_eq!(d(-12.5).to_fixed(2), "-12.50");
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(d(0.1) < d(0.2));
+        assert!(d(-0.2) < d(-0.1));
+        assert!(d(-1.0) < d(0.0));
+        assert!(d(100.0) > d(99.99));
+        assert!(Decimal::Infinite { negative: true } < d(-1e300));
+        assert_eq!(Decimal::NaN.partial_cmp(&d(1.0)), None);
+    }
+}
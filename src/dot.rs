@@ -0,0 +1,79 @@
+use std::fmt::Write;
+
+use crate::repr::*;
+
+impl Expr {
+    /// Renders the tree as a Graphviz graph, e.g. for `dot -Tsvg`.
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph expr {\n    node [shape=box];\n");
+        self.dot_node(self.root(), &mut out);
+        out.push_str("}\n");
+        out
+    }
+
+    fn dot_node(&self, id: NodeId, out: &mut String) {
+        let n = id.index();
+        let label = match *self.node(id) {
+            Node::Float(f) => f.to_string(),
+            Node::Bool(b) => b.to_string(),
+            Node::Var(ref name) => name.clone(),
+            Node::Unary(UnaryOperator::Neg, _) => "-".to_string(),
+            Node::Op(op, _, _) => op.symbol().to_string(),
+            Node::Call(ref name, _) => format!("{name}()"),
+            Node::Assign(ref name, _) => format!("{name} ="),
+            Node::List(_) => "[]".to_string(),
+            Node::Range(..) => "..".to_string(),
+            Node::Text(ref text) => format!("{text:?}"),
+            Node::Lambda(ref params, _) => format!("({}) ->", params.join(", ")),
+        };
+        writeln!(out, "    n{n} [label={label:?}];").unwrap();
+        match *self.node(id) {
+            Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) => {}
+            Node::Unary(_, a) | Node::Assign(_, a) | Node::Lambda(_, a) => {
+                self.dot_node(a, out);
+                writeln!(out, "    n{n} -> n{};", a.index()).unwrap();
+            }
+            Node::Op(_, a, b) | Node::Range(a, b) => {
+                self.dot_node(a, out);
+                self.dot_node(b, out);
+                writeln!(out, "    n{n} -> n{};", a.index()).unwrap();
+                writeln!(out, "    n{n} -> n{};", b.index()).unwrap();
+            }
+            Node::Call(_, ref args) | Node::List(ref args) => {
+                for &a in args {
+                    self.dot_node(a, out);
+                }
+                for &a in args {
+                    writeln!(out, "    n{n} -> n{};", a.index()).unwrap();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    #[test]
+    fn to_dot() {
+        assert_eq!(
+            parse_line("1 + x * -y").unwrap().to_dot(),
+            r#"digraph expr {
+    node [shape=box];
+    n5 [label="+"];
+    n0 [label="1"];
+    n4 [label="*"];
+    n1 [label="x"];
+    n3 [label="-"];
+    n2 [label="y"];
+    n3 -> n2;
+    n4 -> n1;
+    n4 -> n3;
+    n5 -> n0;
+    n5 -> n4;
+}
+"#
+        );
+    }
+}
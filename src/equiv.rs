@@ -0,0 +1,343 @@
+//! Decides whether two expressions are equivalent, like `a * (b + c)` and `a * b + a * c`:
+//! certainly if they simplify to the same polynomial, and otherwise as far as comparing them
+//! for random values of their variables can tell.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::context::Context;
+use crate::random::Random;
+use crate::repr::*;
+
+/// How many random assignments of the variables are tried.
+const TRIALS: usize = 200;
+
+/// How many terms a simplified expression can have, so that `(a + b) ^ 30` is not expanded.
+const TERM_LIMIT: usize = 1000;
+
+/// The highest whole power that is expanded, like the 2 of `(x + 1) ^ 2`.
+const POWER_LIMIT: f64 = 32.0;
+
+#[derive(Debug, PartialEq)]
+pub enum Verdict {
+    /// they are written the same, apart from spacing and parentheses
+    Same,
+    /// they simplify to the same polynomial, which is this
+    Simplified(String),
+    /// they have the same value for this many assignments of the variables
+    Agree(usize),
+    /// they have different values when the variables are `assignment`
+    Differ {
+        assignment: Vec<(String, f64)>,
+        left: f64,
+        right: f64,
+    },
+}
+
+/// How far apart relative to their size results may be and still count as equal.
+#[cfg(not(feature = "f32"))]
+const TOLERANCE: f64 = 1e-9;
+#[cfg(feature = "f32")]
+const TOLERANCE: f64 = 1e-4;
+
+/// Whether `a` and `b` are equal within rounding errors. Both being NaN counts, since that
+/// means that both are undefined.
+fn close(a: f64, b: f64) -> bool {
+    (a.is_nan() && b.is_nan()) || a == b || (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.0)
+}
+
+/// The variables that `ctx` does not define, in alphabetical order.
+fn free_vars(e: &Expr, ctx: &Context, vars: &mut Vec<String>) {
+    for node in e.nodes() {
+        if let Node::Var(name) = node {
+            if !name.starts_with('$') && ctx.var(name).is_none() && !vars.contains(name) {
+                vars.push(name.clone());
+            }
+        }
+    }
+    vars.sort();
+}
+
+/// Compares the expressions for random values of their free variables, between -10 and 10
+/// with at most three decimals.
+pub fn equivalent(a: &Expr, b: &Expr, ctx: &Context) -> Result<Verdict, EvalError> {
+    if a.to_string() == b.to_string() {
+        return Ok(Verdict::Same);
+    }
+    let mut vars = Vec::new();
+    free_vars(a, ctx, &mut vars);
+    free_vars(b, ctx, &mut vars);
+    if !vars.is_empty() {
+        let simplified = (simplify(a, a.root(), ctx, &vars), simplify(b, b.root(), ctx, &vars));
+        if let (Some(left), Some(right)) = simplified {
+            if left.same(&right) {
+                return Ok(Verdict::Simplified(left.to_string()));
+            }
+        }
+    }
+    let trials = if vars.is_empty() { 1 } else { TRIALS };
+
+    let mut random = Random::new(0);
+    for _ in 0..trials {
+        let mut ctx = ctx.clone();
+        let assignment: Vec<(String, f64)> = vars
+            .iter()
+            .map(|name| (name.clone(), ((random.unit() * 20.0 - 10.0) * 1000.0).round() / 1000.0))
+            .collect();
+        for (name, value) in &assignment {
+            ctx.run(&Statement::Assign(name.clone(), Expr::from(*value)))?;
+        }
+        let (left, right) = (a.evaluate_in(&ctx)?, b.evaluate_in(&ctx)?);
+        if !close(left, right) {
+            return Ok(Verdict::Differ { assignment, left, right });
+        }
+    }
+    Ok(Verdict::Agree(trials))
+}
+
+/// A product of factors with their whole powers, like `x ^ 2 * sin(y)`. The factors are the
+/// free variables, and the parts that are not simplified further, like `sin(y)`, in order.
+type Monomial = Vec<(String, i32)>;
+
+/// A sum of products with their coefficients, with the like terms collected.
+#[derive(Debug, Clone, Default)]
+struct Polynomial(BTreeMap<Monomial, f64>);
+
+impl Polynomial {
+    fn constant(c: f64) -> Polynomial {
+        Polynomial::term(Vec::new(), c)
+    }
+
+    fn factor(name: String, power: i32) -> Polynomial {
+        Polynomial::term(vec![(name, power)], 1.0)
+    }
+
+    fn term(monomial: Monomial, c: f64) -> Polynomial {
+        let mut terms = BTreeMap::new();
+        if c != 0.0 {
+            terms.insert(monomial, c);
+        }
+        Polynomial(terms)
+    }
+
+    /// The number that this is, if it has no factors.
+    fn as_constant(&self) -> Option<f64> {
+        match self.0.iter().next() {
+            None => Some(0.0),
+            Some((monomial, &c)) if monomial.is_empty() && self.0.len() == 1 => Some(c),
+            Some(_) => None,
+        }
+    }
+
+    /// `self + sign * other`.
+    fn add(mut self, other: &Polynomial, sign: f64) -> Polynomial {
+        for (monomial, &c) in &other.0 {
+            let sum = self.0.get(monomial).copied().unwrap_or(0.0) + sign * c;
+            if sum == 0.0 {
+                self.0.remove(monomial);
+            } else {
+                self.0.insert(monomial.clone(), sum);
+            }
+        }
+        self
+    }
+
+    fn mul(&self, other: &Polynomial) -> Option<Polynomial> {
+        if self.0.len() * other.0.len() > TERM_LIMIT {
+            return None;
+        }
+        let mut product = Polynomial::default();
+        for (a, &c) in &self.0 {
+            for (b, &d) in &other.0 {
+                let mut factors: BTreeMap<String, i32> = a.iter().cloned().collect();
+                for (name, power) in b {
+                    *factors.entry(name.clone()).or_insert(0) += power;
+                }
+                // x * x ^ -1 is 1
+                let monomial = factors.into_iter().filter(|&(_, power)| power != 0).collect();
+                product = product.add(&Polynomial::term(monomial, c * d), 1.0);
+            }
+        }
+        Some(product)
+    }
+
+    /// `self ^ n`. Negative powers only simplify for one term, like `2 * x` to `0.5 * x ^ -1`.
+    fn pow(&self, n: i32) -> Option<Polynomial> {
+        if n >= 0 {
+            return (0..n).try_fold(Polynomial::constant(1.0), |p, _| p.mul(self));
+        }
+        let [(monomial, c)] = &self.0.iter().collect::<Vec<_>>()[..] else {
+            return None;
+        };
+        let inverse = monomial.iter().map(|(name, power)| (name.clone(), -power)).collect();
+        Polynomial::term(inverse, 1.0 / *c).pow(-n)
+    }
+
+    /// Whether the polynomials have the same terms, with coefficients that are equal within
+    /// rounding errors.
+    fn same(&self, other: &Polynomial) -> bool {
+        self.0.len() == other.0.len()
+            && self.0.iter().zip(&other.0).all(|((a, &c), (b, &d))| a == b && close(c, d))
+    }
+}
+
+/// Like `1 + 2 * x + x ^ 2`.
+impl fmt::Display for Polynomial {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.is_empty() {
+            return f.write_str("0");
+        }
+        for (i, (monomial, &c)) in self.0.iter().enumerate() {
+            let (sign, c) = match (i, c < 0.0) {
+                (0, true) => ("-", -c),
+                (0, false) => ("", c),
+                (_, true) => (" - ", -c),
+                (_, false) => (" + ", c),
+            };
+            f.write_str(sign)?;
+            if monomial.is_empty() {
+                write!(f, "{}", Value::Number(c))?;
+                continue;
+            }
+            if c != 1.0 {
+                write!(f, "{} * ", Value::Number(c))?;
+            }
+            for (j, (name, power)) in monomial.iter().enumerate() {
+                let separator = if j == 0 { "" } else { " * " };
+                match power {
+                    1 => write!(f, "{separator}{name}")?,
+                    _ => write!(f, "{separator}{name} ^ {power}")?,
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The subexpression `id` of `e` as a polynomial in the free variables `vars`, with the parts
+/// without them evaluated. Only arithmetic simplifies, and other parts like `sin(x + 0)` are
+/// factors, with their arguments simplified. `None` if it has parts that can not be factors,
+/// like comparisons.
+fn simplify(e: &Expr, id: NodeId, ctx: &Context, vars: &[String]) -> Option<Polynomial> {
+    // children come before their parents, so one pass finds where the variables are used
+    let mut uses = Vec::with_capacity(e.nodes().len());
+    for node in e.nodes() {
+        let used = match node {
+            Node::Var(name) => vars.contains(name),
+            Node::Float(_) | Node::Bool(_) | Node::Text(_) => false,
+            &Node::Unary(_, a) | &Node::Assign(_, a) | &Node::Lambda(_, a) => uses[a.index()],
+            &Node::Op(_, a, b) | &Node::Range(a, b) => uses[a.index()] || uses[b.index()],
+            Node::Call(_, args) | Node::List(args) => args.iter().any(|a| uses[a.index()]),
+        };
+        uses.push(used);
+    }
+    Simplifier { e, ctx, uses }.simplify(id)
+}
+
+struct Simplifier<'a> {
+    e: &'a Expr,
+    ctx: &'a Context,
+    /// whether each node uses a free variable
+    uses: Vec<bool>,
+}
+
+impl Simplifier<'_> {
+    fn simplify(&self, id: NodeId) -> Option<Polynomial> {
+        if !self.uses[id.index()] {
+            // like `2 * 3` or `sqrt(a)` of a variable that is defined
+            return match (self.e.check_at(id, self.ctx), self.e.evaluate_at(id, self.ctx)) {
+                (Ok(t), Ok(x)) if t.is_scalar() && x.is_finite() => Some(Polynomial::constant(x)),
+                _ => None,
+            };
+        }
+        let factor = |name: String| Some(Polynomial::factor(name, 1));
+        match *self.e.node(id) {
+            Node::Var(ref name) => factor(name.clone()),
+            Node::Unary(UnaryOperator::Neg, a) => Some(Polynomial::default().add(&self.simplify(a)?, -1.0)),
+            Node::Op(Operator::Add, a, b) => Some(self.simplify(a)?.add(&self.simplify(b)?, 1.0)),
+            Node::Op(Operator::Sub, a, b) => Some(self.simplify(a)?.add(&self.simplify(b)?, -1.0)),
+            Node::Op(Operator::Mul, a, b) => self.simplify(a)?.mul(&self.simplify(b)?),
+            Node::Op(Operator::Div, a, b) => {
+                let (a, b) = (self.simplify(a)?, self.simplify(b)?);
+                if b.as_constant() == Some(0.0) {
+                    return None;
+                }
+                match b.pow(-1) {
+                    Some(inverse) => a.mul(&inverse),
+                    // like `1 / (x + 1)`
+                    None => a.mul(&Polynomial::factor(parenthesized(&b), -1)),
+                }
+            }
+            Node::Op(Operator::Pow, a, b) => {
+                let (base, exponent) = (self.simplify(a)?, self.simplify(b)?);
+                match exponent.as_constant() {
+                    Some(n) if n.fract() == 0.0 && n.abs() <= POWER_LIMIT => {
+                        if let Some(power) = base.pow(n as i32) {
+                            return Some(power);
+                        }
+                    }
+                    _ => {}
+                }
+                factor(format!("{} ^ {}", parenthesized(&base), parenthesized(&exponent)))
+            }
+            Node::Call(ref name, ref args) => {
+                let args = args.iter().map(|&a| Some(self.simplify(a)?.to_string()));
+                let args: Vec<String> = args.collect::<Option<_>>()?;
+                factor(format!("{name}({})", args.join(", ")))
+            }
+            Node::Op(..)
+            | Node::Float(_)
+            | Node::Bool(_)
+            | Node::Assign(..)
+            | Node::List(_)
+            | Node::Range(..)
+            | Node::Text(_)
+            | Node::Lambda(..) => None,
+        }
+    }
+}
+
+/// The polynomial as a factor, in parentheses unless it is one already.
+fn parenthesized(p: &Polynomial) -> String {
+    let s = p.to_string();
+    match s.contains(' ') {
+        true => format!("({s})"),
+        false => s,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::number::native;
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    fn equiv(a: &str, b: &str) -> Verdict {
+        equivalent(&parse_line(a).unwrap(), &parse_line(b).unwrap(), &Context::default()).unwrap()
+    }
+
+    #[test]
+    fn equivalent_expressions() {
+        assert_eq!(equiv("(a*b)+c", "a * b + c"), Verdict::Same);
+        assert_eq!(equiv("a*(b+c)", "a*b + a*c"), Verdict::Simplified("a * b + a * c".into()));
+        let square = "1 + 2 * x + x ^ 2".to_string();
+        assert_eq!(equiv("(x + 1) ^ 2", "x^2 + 2*x + 1"), Verdict::Simplified(square));
+        assert_eq!(equiv("x / 2 - (3 - 1) * y", "0.5 * x - y * 2"), Verdict::Simplified("0.5 * x - 2 * y".into()));
+        assert_eq!(equiv("sqrt(x + x) / x", "x ^ -1 * sqrt(2 * x)"), Verdict::Simplified("sqrt(2 * x) * x ^ -1".into()));
+        assert_eq!(equiv("sin(x)^2 + cos(x)^2", "1"), Verdict::Agree(TRIALS));
+        assert_eq!(equiv("0.1 + 0.2", "0.3"), Verdict::Agree(1));
+    }
+
+    #[test]
+    fn counterexamples() {
+        let Verdict::Differ { assignment, left, right } = equiv("a - b", "b - a") else {
+            panic!("a - b and b - a are not equivalent");
+        };
+        let (a, b) = (assignment[0].1, assignment[1].1);
+        assert_eq!(assignment.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        assert_eq!((left, right), (native(native(a) - native(b)), native(native(b) - native(a))));
+        assert!(matches!(equiv("sqrt(x ^ 2)", "x"), Verdict::Differ { .. }));
+        assert!(matches!(equiv("(x + 1) / (x + 1)", "x / x + 1"), Verdict::Differ { .. }));
+    }
+}
@@ -0,0 +1,621 @@
+//! Evaluating expressions with any [`Backend`].
+//!
+//! Calls of user functions may nest as deep as the recursion limit allows, so this evaluates
+//! with explicit stacks instead of recursion: a stack of scopes, one for each body of a user
+//! function being called, with the work left in each, and a stack of the values computed so far.
+
+use std::cell::RefCell;
+use std::time::Instant;
+
+use crate::check::{mismatch, Type, STRAY_LAMBDA};
+use crate::context::{Call, Context};
+use crate::currency;
+use crate::functions::{self, Args, Eval, Lambdas, Takes};
+use crate::i18n::{Key, Text};
+use crate::number::{Backend, F64};
+use crate::poly;
+use crate::repr::*;
+use crate::uncertainty::PM;
+
+/// Work left in evaluating an expression. The tasks of a scope are done last first.
+enum Task {
+    Eval(NodeId),
+    /// finishes the node with the values of its operands, which are on top of the value stack,
+    /// and the time that it started at if profiling
+    Apply(NodeId, Option<Instant>),
+    /// evaluates the branch of the `if` node that the condition on the value stack chooses
+    Branch(NodeId),
+}
+
+/// A value on the value stack: a number of the backend, or a list or text, which are the same
+/// with every backend.
+#[derive(Debug, Clone)]
+pub(crate) enum Item<N> {
+    Num(N),
+    Value(Value),
+}
+
+impl<N> Item<N> {
+    /// The number, or the type of what this is instead.
+    fn number(self) -> Result<N, Type> {
+        match self {
+            Item::Num(n) => Ok(n),
+            Item::Value(v) => Err(Type::of(&v)),
+        }
+    }
+
+    fn into_f64<B: Backend<Num = N>>(self, backend: &B) -> Item<f64> {
+        match self {
+            Item::Num(n) => Item::Num(backend.to_f64(&n)),
+            Item::Value(v) => Item::Value(v),
+        }
+    }
+
+    pub(crate) fn into_value<B: Backend<Num = N>>(self, backend: &B) -> Value {
+        match self {
+            Item::Num(n) => Value::Number(backend.to_f64(&n)),
+            Item::Value(v) => v,
+        }
+    }
+}
+
+/// The evaluation of an expression, which is the body of a user function, unless it is the
+/// expression being evaluated.
+struct Scope<'a, N> {
+    expr: &'a Expr,
+    /// the parameters of the user function
+    locals: Vec<(&'a str, Item<N>)>,
+    tasks: Vec<Task>,
+    call: Option<UserCall<'a>>,
+}
+
+/// A call of a user function whose body is being evaluated.
+struct UserCall<'a> {
+    name: &'a str,
+    /// the node of the call, in the scope below
+    id: NodeId,
+    start: Option<Instant>,
+    /// the arguments, for caching the result of `memo` functions under
+    args: Vec<f64>,
+    memo: Option<&'static str>,
+    call: Call<'a>,
+}
+
+/// The variables assigned by an expression, in the order they were assigned.
+pub(crate) type Assignments<N> = Vec<(String, N)>;
+
+/// The value of an expression, and the variables that it assigns.
+pub(crate) type Evaluated<N> = (Item<N>, Assignments<N>);
+
+/// An error at the node `id` of the innermost scope, which started at `start`.
+struct Failure {
+    id: NodeId,
+    start: Option<Instant>,
+    error: EvalError,
+}
+
+impl Expr {
+    /// Evaluates the subexpression at `id`. `locals` are bound like parameters are. Errors get
+    /// the subexpressions that were being evaluated as their backtrace.
+    pub(crate) fn evaluate_node<'a, B: Backend>(
+        &'a self,
+        id: NodeId,
+        ctx: &'a Context,
+        backend: &B,
+        locals: &[(&'a str, B::Num)],
+    ) -> Result<B::Num, EvalError> {
+        self.evaluate_assigning(id, ctx, backend, locals).map(|(res, _)| res)
+    }
+
+    /// Like [`Expr::evaluate_node`], but also returns the assignments like `x = 1` that were
+    /// evaluated, for the caller to make. The rest of the expression sees the assigned values.
+    pub(crate) fn evaluate_assigning<'a, B: Backend>(
+        &'a self,
+        id: NodeId,
+        ctx: &'a Context,
+        backend: &B,
+        locals: &[(&'a str, B::Num)],
+    ) -> Result<(B::Num, Assignments<B::Num>), EvalError> {
+        let locals = locals.iter().map(|(name, n)| (*name, Item::Num(n.clone()))).collect();
+        let (res, assigned) = self.evaluate_items(id, ctx, backend, locals)?;
+        let res = res.number().map_err(|t| EvalError {
+            message: format!("expected a number, not a {t}").into(),
+            span: self.span(id),
+            backtrace: Vec::new(),
+        })?;
+        Ok((res, assigned))
+    }
+
+    /// Like [`Expr::evaluate_assigning`], but the result may also be a list or text.
+    pub(crate) fn evaluate_items<'a, B: Backend>(
+        &'a self,
+        id: NodeId,
+        ctx: &'a Context,
+        backend: &B,
+        locals: Vec<(&'a str, Item<B::Num>)>,
+    ) -> Result<Evaluated<B::Num>, EvalError> {
+        let mut scopes = vec![Scope {
+            expr: self,
+            locals,
+            tasks: vec![Task::Eval(id)],
+            call: None,
+        }];
+        let mut values = Vec::new();
+        let mut assigned = Vec::new();
+        while let Some(scope) = scopes.last_mut() {
+            let Some(task) = scope.tasks.pop() else {
+                let Some(call) = scopes.pop().and_then(|scope| scope.call) else { break };
+                // only numbers are cached
+                let res = match values.last().unwrap() {
+                    Item::Num(n) => Some(backend.to_f64(n)),
+                    Item::Value(_) => None,
+                };
+                let memo = call.memo.zip(res);
+                drop(call.call);
+                if let Some((key, res)) = memo {
+                    ctx.memoize(key, call.name, call.args, res);
+                }
+                if let Some(start) = call.start {
+                    ctx.leave_node(call.id, start);
+                }
+                continue;
+            };
+            let res = match task {
+                Task::Eval(id) => {
+                    let start = ctx.is_profiling().then(|| ctx.enter_node());
+                    scope.eval(id, start, ctx, backend, &mut values, &assigned).map_err(|error| Failure { id, start, error })
+                }
+                Task::Apply(id, start) => Scope::apply(&mut scopes, id, start, ctx, backend, &mut values, &mut assigned)
+                    .map_err(|error| Failure { id, start, error }),
+                Task::Branch(id) => scope.branch(id, backend, &mut values).map_err(|error| Failure { id, start: None, error }),
+            };
+            if let Err(failure) = res {
+                return Err(unwind(scopes, failure, ctx));
+            }
+        }
+        Ok((values.pop().unwrap(), assigned))
+    }
+}
+
+impl<'a, N: Clone> Scope<'a, N> {
+    /// Starts evaluating the node `id`, or evaluates it if it has no operands.
+    fn eval<B: Backend<Num = N>>(
+        &mut self,
+        id: NodeId,
+        start: Option<Instant>,
+        ctx: &Context,
+        backend: &B,
+        values: &mut Vec<Item<N>>,
+        assigned: &Assignments<N>,
+    ) -> Result<(), EvalError> {
+        let error = |message: Text| EvalError {
+            message,
+            span: self.expr.span(id),
+            backtrace: Vec::new(),
+        };
+        let value = match *self.expr.node(id) {
+            Node::Float(f) => Item::Num(backend.number(f)),
+            Node::Bool(b) => Item::Num(backend.number(b as u8 as f64)),
+            Node::Text(ref text) => Item::Value(Value::Text(text.clone())),
+            // variables assigned earlier in the expression have their new values
+            Node::Var(ref name) => match self.locals.iter().find(|(n, _)| n == name) {
+                Some((_, v)) => v.clone(),
+                None => match assigned.iter().rfind(|(n, _)| n == name) {
+                    Some((_, v)) => Item::Num(v.clone()),
+                    None => match (backend.var(ctx, name), ctx.list_var(name)) {
+                        (Some(v), _) => Item::Num(v),
+                        (None, Some(list)) => Item::Value(Value::List(list.to_vec())),
+                        (None, None) => Item::Num(backend.number(ctx.lookup(name).map_err(error)?)),
+                    },
+                },
+            },
+            Node::Lambda(..) => return Err(error(STRAY_LAMBDA.into())),
+            _ => return self.push_operands(id, start, ctx).map_err(error),
+        };
+        self.finish(id, start, value, ctx, values);
+        Ok(())
+    }
+
+    /// Adds the tasks of evaluating the operands of the node `id` and then the node, after
+    /// checking that it can be. Only the branch of `if` that the condition chooses is evaluated,
+    /// and the functions passed to built-in functions only when they call them.
+    fn push_operands(&mut self, id: NodeId, start: Option<Instant>, ctx: &Context) -> Result<(), Text> {
+        let operands = match *self.expr.node(id) {
+            Node::Unary(_, a) | Node::Assign(_, a) => vec![a],
+            Node::Op(_, a, b) | Node::Range(a, b) => vec![a, b],
+            Node::List(ref elements) => elements.clone(),
+            Node::Call(ref name, ref args) if ctx.func(name).is_some() => {
+                let f = ctx.func(name).unwrap();
+                if args.len() != f.params.len() {
+                    return Err(functions::arity(name, f.params.len().to_string().into(), args.len()));
+                }
+                args.clone()
+            }
+            Node::Call(ref name, ref args) if currency::is_code(name) && args.len() == 1 => args.clone(),
+            Node::Call(ref name, ref args) if name == PM => {
+                if args.len() != 2 {
+                    return Err(functions::arity(PM, "2".into(), args.len()));
+                }
+                args.clone()
+            }
+            Node::Call(ref name, ref args) => {
+                let f = functions::lookup(name).ok_or_else(|| Text::new(Key::UnknownFunction, vec![name.into()]))?;
+                if !ctx.is_loaded(f.module) {
+                    return Err(functions::not_loaded(name, f.module));
+                }
+                match f.eval {
+                    Eval::Values { takes, .. } => {
+                        f.check_arity(args.len())?;
+                        for (&a, &takes) in args.iter().zip(takes) {
+                            self.check_function(name, a, takes)?;
+                        }
+                        args.iter().copied().filter(|&a| !self.is_lambda(a)).collect()
+                    }
+                    // the lists among the arguments count as their numbers, which are only known
+                    // after evaluating them
+                    Eval::Numbers(_) if f.max_args == usize::MAX => args.clone(),
+                    Eval::Numbers(_) => {
+                        f.check_arity(args.len())?;
+                        if name == "if" {
+                            self.tasks.push(Task::Apply(id, start));
+                            self.tasks.push(Task::Branch(id));
+                            self.tasks.push(Task::Eval(args[0]));
+                            return Ok(());
+                        }
+                        args.clone()
+                    }
+                }
+            }
+            Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) | Node::Lambda(..) => vec![],
+        };
+        self.tasks.push(Task::Apply(id, start));
+        self.tasks.extend(operands.into_iter().rev().map(Task::Eval));
+        Ok(())
+    }
+
+    fn is_lambda(&self, id: NodeId) -> bool {
+        matches!(self.expr.node(id), Node::Lambda(..))
+    }
+
+    /// Checks that the argument `a` of the built-in function `name` is a function exactly when
+    /// it takes one, with as many parameters as it calls it with.
+    fn check_function(&self, name: &str, a: NodeId, takes: Takes) -> Result<(), String> {
+        let Node::Lambda(ref params, _) = *self.expr.node(a) else {
+            return match takes {
+                Takes::Function(_, _, like) => Err(format!("{name} takes a function like {like}")),
+                _ => Ok(()),
+            };
+        };
+        let (n, like) = match takes {
+            Takes::Value(t) => return Err(format!("{name} takes a {t}, not a function")),
+            Takes::Function(n, _, like) => (n, like),
+            Takes::Polynomial(like) => (1, like),
+        };
+        if n != usize::MAX && params.len() != n {
+            return Err(format!("{name} takes a function like {like}, got one with {} parameters", params.len()));
+        }
+        Ok(())
+    }
+
+    /// Evaluates the branch of the `if` node `id` that the condition on the value stack chooses.
+    fn branch<B: Backend<Num = N>>(&mut self, id: NodeId, backend: &B, values: &mut Vec<Item<N>>) -> Result<(), EvalError> {
+        let Node::Call(_, ref args) = *self.expr.node(id) else { unreachable!() };
+        let condition = values.pop().unwrap().number().map_err(|t| EvalError {
+            message: format!("if takes a boolean condition, not a {t}").into(),
+            span: self.expr.span(args[0]),
+            backtrace: Vec::new(),
+        })?;
+        let branch = if backend.to_f64(&condition) != 0.0 { args[1] } else { args[2] };
+        self.tasks.push(Task::Eval(branch));
+        Ok(())
+    }
+
+    /// Finishes the node `id` with `value`.
+    fn finish(&self, id: NodeId, start: Option<Instant>, value: Item<N>, ctx: &Context, values: &mut Vec<Item<N>>) {
+        values.push(value);
+        if let Some(start) = start {
+            ctx.leave_node(id, start);
+        }
+    }
+
+    /// Finishes the node `id` of the innermost scope with the values of its operands, or starts
+    /// evaluating the body of the user function that it calls.
+    fn apply<B: Backend<Num = N>>(
+        scopes: &mut Vec<Scope<'a, N>>,
+        id: NodeId,
+        start: Option<Instant>,
+        ctx: &'a Context,
+        backend: &B,
+        values: &mut Vec<Item<N>>,
+        assigned: &mut Assignments<N>,
+    ) -> Result<(), EvalError> {
+        let scope = scopes.last_mut().unwrap();
+        let expr = scope.expr;
+        let error = |message: Text| EvalError {
+            message,
+            span: expr.span(id),
+            backtrace: Vec::new(),
+        };
+        let res = match *expr.node(id) {
+            Node::Unary(op, _) => match values.pop().unwrap().number() {
+                Ok(a) => Item::Num(backend.unary(op, &a)),
+                Err(t) => return Err(error(format!("cannot negate a {t}").into())),
+            },
+            Node::Op(op, _, _) => {
+                let b = values.pop().unwrap().number();
+                let a = values.pop().unwrap().number();
+                match (a, b) {
+                    (Ok(a), Ok(b)) => Item::Num(backend.op(op, &a, &b)),
+                    (a, b) => {
+                        let (a, b) = (a.err().unwrap_or(Type::Number), b.err().unwrap_or(Type::Number));
+                        return Err(error(mismatch(op, a, b).into()));
+                    }
+                }
+            }
+            Node::Assign(ref name, _) => match values.pop().unwrap().number() {
+                Ok(value) => {
+                    assigned.push((name.clone(), value.clone()));
+                    Item::Num(value)
+                }
+                Err(t) => {
+                    return Err(error(format!("only numbers can be assigned inside expressions, not a {t}").into()));
+                }
+            },
+            Node::List(ref elements) => {
+                let items = values.split_off(values.len() - elements.len());
+                let mut list = Vec::with_capacity(items.len());
+                for item in items {
+                    let x = item.number().map_err(|t| error(format!("lists can only have numbers, not a {t}").into()))?;
+                    list.push(backend.to_f64(&x));
+                }
+                Item::Value(Value::List(list))
+            }
+            Node::Range(..) => {
+                let to = values.pop().unwrap().number();
+                let from = values.pop().unwrap().number();
+                let (from, to) = match (from, to) {
+                    (Ok(from), Ok(to)) => (backend.to_f64(&from), backend.to_f64(&to)),
+                    (Err(t), _) | (_, Err(t)) => {
+                        return Err(error(format!("ranges can only have numbers, not a {t}").into()));
+                    }
+                };
+                // with to, unlike `range`
+                let list = functions::progression(from, 1.0, (to - from).floor() + 1.0);
+                let list = list.map_err(|message| error(message.into()))?;
+                Item::Value(Value::List(list))
+            }
+            Node::Call(ref name, ref args) if ctx.func(name).is_some() => {
+                let f = ctx.func(name).unwrap();
+                let scope_values = values.split_off(values.len() - args.len());
+                let numbers: Option<Vec<f64>> = scope_values
+                    .iter()
+                    .map(|v| match v {
+                        Item::Num(n) => Some(backend.to_f64(n)),
+                        Item::Value(_) => None,
+                    })
+                    .collect();
+                // other backends have more than the cached f64 results, and calls with lists
+                // are not cached
+                let memo = backend.memo_key().filter(|_| f.memo && numbers.is_some());
+                let args = numbers.unwrap_or_default();
+                if let Some(res) = memo.and_then(|key| ctx.memoized(key, name, &args)) {
+                    Item::Num(backend.number(res))
+                } else {
+                    let call = ctx.enter(name, &args).map_err(error)?;
+                    let locals = f.params.iter().map(String::as_str).zip(scope_values).collect();
+                    scopes.push(Scope {
+                        expr: &f.body,
+                        locals,
+                        tasks: vec![Task::Eval(f.body.root())],
+                        call: Some(UserCall {
+                            name,
+                            id,
+                            start,
+                            args,
+                            memo,
+                            call,
+                        }),
+                    });
+                    return Ok(());
+                }
+            }
+            Node::Call(ref name, _) if currency::is_code(name) => {
+                let amount = values.pop().unwrap().number();
+                let amount = amount.map_err(|t| error(format!("{name} takes numbers, not a {t}").into()))?;
+                let factor = ctx.rates().to_base(name, 1.0).map_err(|message| error(message.into()))?;
+                Item::Num(backend.op(Operator::Mul, &amount, &backend.number(factor)))
+            }
+            Node::Call(ref name, _) if name == PM => {
+                let args = values.split_off(values.len() - 2).into_iter().map(Item::number);
+                let args = args.collect::<Result<Vec<_>, _>>();
+                let args = args.map_err(|t| error(format!("{PM} takes numbers, not a {t}").into()))?;
+                let res = backend.call(PM, &args);
+                Item::Num(res.ok_or_else(|| error("uncertainties like 1±0.1 are not supported here".into()))?)
+            }
+            // the branch that was taken has the value
+            Node::Call(ref name, _) if name == "if" => values.pop().unwrap(),
+            Node::Call(ref name, ref args) => {
+                let f = functions::lookup(name).unwrap();
+                match f.eval {
+                    Eval::Numbers(eval) => {
+                        let mut numbers = Vec::with_capacity(args.len());
+                        for item in values.split_off(values.len() - args.len()) {
+                            match item {
+                                Item::Num(n) => numbers.push(n),
+                                // the numbers of lists are arguments of their own
+                                Item::Value(Value::List(list)) if f.max_args == usize::MAX => {
+                                    numbers.extend(list.into_iter().map(|x| backend.number(x)));
+                                }
+                                Item::Value(Value::List(_)) => {
+                                    let message =
+                                        format!("only functions taking any number of arguments, like sum, take lists: {name}");
+                                    return Err(error(message.into()));
+                                }
+                                Item::Value(v) => {
+                                    return Err(error(format!("{name} takes numbers, not a {}", Type::of(&v)).into()));
+                                }
+                            }
+                        }
+                        f.check_arity(numbers.len()).map_err(error)?;
+                        match backend.call(name, &numbers) {
+                            Some(res) => Item::Num(res),
+                            None => {
+                                let numbers: Vec<f64> = numbers.iter().map(|a| backend.to_f64(a)).collect();
+                                Item::Num(backend.number(eval(&numbers).map_err(|message| error(message.into()))?))
+                            }
+                        }
+                    }
+                    Eval::Values { eval, .. } => {
+                        let n = args.iter().filter(|&&a| !scope.is_lambda(a)).count();
+                        let mut evaluated = values.split_off(values.len() - n).into_iter();
+                        let arguments = args.iter().map(|&a| match scope.is_lambda(a) {
+                            true => None,
+                            false => Some(evaluated.next().unwrap().into_value(backend)),
+                        });
+                        let arguments = arguments.collect();
+                        let bodies = Bodies {
+                            expr,
+                            args,
+                            ctx,
+                            locals: scope.visible(args, assigned).into_iter().map(|(n, x)| (n, x.into_f64(backend))).collect(),
+                            failure: RefCell::new(None),
+                        };
+                        let res = eval(&mut Args::new(f.name, arguments, &bodies));
+                        match res {
+                            Ok(Value::Number(x)) => Item::Num(backend.number(x)),
+                            Ok(value) => Item::Value(value),
+                            // the error of a function passed to it, which has a backtrace
+                            Err(message) => match bodies.failure.into_inner() {
+                                Some(failure) if failure.message == message.as_str() => return Err(failure),
+                                _ => return Err(error(message.into())),
+                            },
+                        }
+                    }
+                }
+            }
+            Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) | Node::Lambda(..) => unreachable!(),
+        };
+        scopes.last().unwrap().finish(id, start, res, ctx, values);
+        Ok(())
+    }
+
+    /// The variables of the scope that the functions among `args` use, which are the parameters
+    /// of the user function and then the variables assigned so far.
+    fn visible(&self, args: &[NodeId], assigned: &Assignments<N>) -> Vec<(&'a str, Item<N>)> {
+        let expr = self.expr;
+        let (mut used, mut stack) = (Vec::new(), args.to_vec());
+        while let Some(id) = stack.pop() {
+            match *expr.node(id) {
+                Node::Var(ref name) => used.push(name.as_str()),
+                Node::Unary(_, a) | Node::Assign(_, a) | Node::Lambda(_, a) => stack.push(a),
+                Node::Op(_, a, b) | Node::Range(a, b) => stack.extend([a, b]),
+                Node::Call(_, ref args) | Node::List(ref args) => stack.extend(args),
+                Node::Float(_) | Node::Bool(_) | Node::Text(_) => {}
+            }
+        }
+        used.sort_unstable();
+        used.dedup();
+        let locals = self.locals.iter().filter(|(name, _)| used.contains(name)).cloned();
+        let assigned = used.iter().filter_map(|&name| {
+            let (_, value) = assigned.iter().rfind(|(n, _)| n == name)?;
+            Some((name, Item::Num(value.clone())))
+        });
+        locals.chain(assigned).collect()
+    }
+}
+
+/// The functions like `x -> x ^ 2` that are passed to a built-in function, which see the
+/// variables of the scope around them. They are evaluated in `f64` like the built-in functions
+/// are, so that `nsolve` converges as well with every backend.
+struct Bodies<'a> {
+    expr: &'a Expr,
+    /// the arguments of the call
+    args: &'a [NodeId],
+    ctx: &'a Context,
+    locals: Vec<(&'a str, Item<f64>)>,
+    /// the error of the function that failed last, which the built-in function only gets the
+    /// message of
+    failure: RefCell<Option<EvalError>>,
+}
+
+impl Bodies<'_> {
+    fn lambda(&self, i: usize) -> (&[String], NodeId) {
+        let Node::Lambda(ref params, body) = *self.expr.node(self.args[i]) else { unreachable!() };
+        (params, body)
+    }
+
+    fn fail(&self, error: EvalError) -> String {
+        let message = error.message.to_string();
+        *self.failure.borrow_mut() = Some(error);
+        message
+    }
+}
+
+impl Lambdas for Bodies<'_> {
+    fn params(&self, i: usize) -> Vec<String> {
+        self.lambda(i).0.to_vec()
+    }
+
+    fn call(&self, i: usize, args: &[f64]) -> Result<Value, String> {
+        let (params, body) = self.lambda(i);
+        let params = params.iter().map(String::as_str).zip(args.iter().map(|&x| Item::Num(x)));
+        // the parameters hide the variables around
+        let locals = params.chain(self.locals.iter().cloned()).collect();
+        match self.expr.evaluate_items(body, self.ctx, &F64, locals) {
+            Ok((res, _)) => Ok(res.into_value(&F64)),
+            Err(e) => Err(self.fail(e)),
+        }
+    }
+
+    fn coefficients(&self, i: usize) -> Result<Vec<f64>, String> {
+        let (params, body) = self.lambda(i);
+        let mut constant = |id| {
+            let (res, _) = self.expr.evaluate_items(id, self.ctx, &F64, self.locals.clone())?;
+            res.number().map_err(|t| EvalError {
+                message: format!("polynomials can only have numbers, not a {t}").into(),
+                span: self.expr.span(id),
+                backtrace: Vec::new(),
+            })
+        };
+        poly::coefficients(self.expr, body, &params[0], &mut constant).map_err(|e| self.fail(e))
+    }
+}
+
+/// The error of `failure`, with the nodes that were being evaluated as its backtrace, from the
+/// one that failed out to the outermost expression. The calls of user functions say what
+/// function the error is in.
+fn unwind<N>(mut scopes: Vec<Scope<'_, N>>, failure: Failure, ctx: &Context) -> EvalError {
+    let Failure { mut id, mut start, mut error } = failure;
+    while let Some(scope) = scopes.pop() {
+        // the nodes of the scope that are still being evaluated
+        let unfinished = scope.tasks.iter().rev().filter_map(|task| match *task {
+            Task::Apply(id, start) => Some((id, start)),
+            _ => None,
+        });
+        for (id, start) in std::iter::once((id, start)).chain(unfinished) {
+            if let Some(start) = start {
+                ctx.leave_node(id, start);
+            }
+            error.backtrace.push(Frame {
+                expr: scope.expr.subexpression(id),
+                function: None,
+                span: scope.expr.span(id),
+            });
+        }
+        let Some(call) = scope.call else { break };
+        let outer = &scopes.last().unwrap().expr;
+        // the spans of the body point into the line that defined it, not the outer one
+        let backtrace = error.backtrace.into_iter().map(|frame| Frame {
+            function: frame.function.or_else(|| Some(call.name.to_string())),
+            span: None,
+            ..frame
+        });
+        error = EvalError {
+            message: in_function(call.name, error.message, call.call.recursive),
+            span: outer.span(call.id),
+            backtrace: backtrace.collect(),
+        };
+        (id, start) = (call.id, call.start);
+    }
+    error
+}
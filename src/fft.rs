@@ -0,0 +1,198 @@
+//! The discrete Fourier transform of lists, for `fft` and `ifft`.
+
+use std::f64::consts::PI;
+use std::fmt;
+
+/// The longest lists that are transformed by the definition, which rounds less than the fast
+/// transform of lengths that are not a power of two.
+const DIRECT_LIMIT: usize = 1024;
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Complex {
+    pub re: f64,
+    pub im: f64,
+}
+
+impl Complex {
+    pub fn new(re: f64, im: f64) -> Self {
+        Complex { re, im }
+    }
+
+    /// `e^(-i 2 pi k / n)`, or `e^(i 2 pi k / n)` for the inverse. The angle is first reflected
+    /// to at most an eighth of a turn, so that the symmetries hold exactly, and quarter turns
+    /// are exact where `cos(pi / 2)` is not quite 0.
+    fn turn(k: usize, n: usize, inverse: bool) -> Self {
+        let t = (k % n) as f64 / n as f64;
+        let (t, below) = if t > 0.5 { (1.0 - t, inverse) } else { (t, !inverse) };
+        let (t, negate) = if t > 0.25 { (0.5 - t, true) } else { (t, false) };
+        let (cos, sin) = if t > 0.125 {
+            let angle = 2.0 * PI * (0.25 - t);
+            (angle.sin(), angle.cos())
+        } else {
+            let angle = 2.0 * PI * t;
+            (angle.cos(), angle.sin())
+        };
+        Complex::new(if negate { -cos } else { cos }, if below { -sin } else { sin })
+    }
+
+    pub fn add(self, other: Self) -> Self {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+
+    pub fn sub(self, other: Self) -> Self {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+
+    pub fn mul(self, other: Self) -> Self {
+        Complex::new(self.re * other.re - self.im * other.im, self.re * other.im + self.im * other.re)
+    }
+
+    pub fn div(self, other: Self) -> Self {
+        let d = other.re * other.re + other.im * other.im;
+        Complex::new(self.re * other.re + self.im * other.im, self.im * other.re - self.re * other.im).scale(1.0 / d)
+    }
+
+    pub fn abs(self) -> f64 {
+        self.re.hypot(self.im)
+    }
+
+    fn conj(self) -> Self {
+        Complex::new(self.re, -self.im)
+    }
+
+    fn scale(self, factor: f64) -> Self {
+        Complex::new(self.re * factor, self.im * factor)
+    }
+}
+
+/// Prints the number like `1.5 - 2i`, without the parts that are 0.
+impl fmt::Display for Complex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.re, self.im) {
+            (re, 0.0) => write!(f, "{re}"),
+            (0.0, im) => write!(f, "{im}i"),
+            (re, im) if im < 0.0 => write!(f, "{re} - {}i", -im),
+            (re, im) => write!(f, "{re} + {im}i"),
+        }
+    }
+}
+
+/// The discrete Fourier transform of `values`, or the inverse transform, which divides by the
+/// length. Parts that are only rounding errors, compared to the largest part, are made 0 so
+/// that `fft([1, 2, 3, 4])` is exactly `[10, -2 + 2i, -2, -2 - 2i]`.
+pub fn transform(values: &[f64], inverse: bool) -> Vec<Complex> {
+    let values: Vec<Complex> = values.iter().map(|&x| Complex::new(x, 0.0)).collect();
+    let n = values.len();
+    let mut res = if n.is_power_of_two() {
+        radix2(values, inverse)
+    } else if n <= DIRECT_LIMIT {
+        direct(&values, inverse)
+    } else {
+        bluestein(&values, inverse)
+    };
+    if inverse {
+        for c in &mut res {
+            *c = c.scale(1.0 / n as f64);
+        }
+    }
+    let largest = res.iter().map(|c| c.re.abs().max(c.im.abs())).fold(0.0, f64::max);
+    let noise = largest * f64::EPSILON * (n.max(2) as f64).log2() * 4.0;
+    for c in &mut res {
+        for part in [&mut c.re, &mut c.im] {
+            if part.abs() <= noise {
+                *part = 0.0;
+            }
+        }
+    }
+    res
+}
+
+/// The Cooley-Tukey transform of a power of two numbers.
+fn radix2(values: Vec<Complex>, inverse: bool) -> Vec<Complex> {
+    let n = values.len();
+    if n <= 1 {
+        return values;
+    }
+    let (even, odd): (Vec<_>, Vec<_>) = values.chunks(2).map(|pair| (pair[0], pair[1])).unzip();
+    let (even, odd) = (radix2(even, inverse), radix2(odd, inverse));
+    let mut res = vec![Complex::new(0.0, 0.0); n];
+    for k in 0..n / 2 {
+        let twisted = Complex::turn(k, n, inverse).mul(odd[k]);
+        res[k] = even[k].add(twisted);
+        res[k + n / 2] = even[k].sub(twisted);
+    }
+    res
+}
+
+/// The transform by the definition, which takes `n^2` steps.
+fn direct(values: &[Complex], inverse: bool) -> Vec<Complex> {
+    let n = values.len();
+    (0..n)
+        .map(|k| {
+            let terms = values.iter().enumerate().map(|(j, x)| x.mul(Complex::turn(j * k % n, n, inverse)));
+            terms.fold(Complex::new(0.0, 0.0), Complex::add)
+        })
+        .collect()
+}
+
+/// The transform of any number of numbers, as a convolution with a chirp that is done with
+/// power of two transforms.
+fn bluestein(values: &[Complex], inverse: bool) -> Vec<Complex> {
+    let n = values.len();
+    // half turns of k^2 / n, where k^2 grows too large for the angle to stay exact, but k^2 mod 2n
+    // does not
+    let chirp: Vec<Complex> = (0..n).map(|k| Complex::turn((k * k) % (2 * n), 2 * n, inverse)).collect();
+    let m = (2 * n - 1).next_power_of_two();
+    let mut a = vec![Complex::new(0.0, 0.0); m];
+    for k in 0..n {
+        a[k] = values[k].mul(chirp[k]);
+    }
+    let mut b = vec![Complex::new(0.0, 0.0); m];
+    b[0] = chirp[0].conj();
+    for k in 1..n {
+        b[k] = chirp[k].conj();
+        b[m - k] = chirp[k].conj();
+    }
+    let (a, b) = (radix2(a, false), radix2(b, false));
+    let product = a.into_iter().zip(b).map(|(a, b)| a.mul(b)).collect();
+    let convolution = radix2(product, true);
+    (0..n).map(|k| convolution[k].scale(1.0 / m as f64).mul(chirp[k])).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn show(values: &[Complex]) -> Vec<String> {
+        values.iter().map(|c| c.to_string()).collect()
+    }
+
+    #[test]
+    fn transforms() {
+        assert_eq!(show(&transform(&[1.0, 2.0, 3.0, 4.0], false)), ["10", "-2 + 2i", "-2", "-2 - 2i"]);
+        assert_eq!(show(&transform(&[10.0, 0.0], true)), ["5", "5"]);
+        assert_eq!(show(&transform(&[1.0, 1.0, 1.0], false)), ["3", "0", "0"]);
+        assert_eq!(transform(&[], false), []);
+        assert_eq!(show(&transform(&[-2.5], false)), ["-2.5"]);
+        // a cosine with 2 periods in 6 samples
+        let wave: Vec<f64> = (0..6).map(|k| (2.0 * PI * 2.0 * k as f64 / 6.0).cos()).collect();
+        let spectrum = transform(&wave, false);
+        for (k, c) in spectrum.iter().enumerate() {
+            let expected = if k == 2 || k == 4 { 3.0 } else { 0.0 };
+            assert!((c.re - expected).abs() < 1e-12 && c.im.abs() < 1e-12, "{k}: {c}");
+        }
+    }
+
+    #[test]
+    fn inverse() {
+        let values = [3.0, -1.0, 4.0, 1.5, 9.0];
+        let spectrum = transform(&values, false);
+        // the inverse of a real spectrum needs the complex one, so invert with the conjugate
+        let conjugated: Vec<Complex> = spectrum.iter().map(|c| c.conj()).collect();
+        for back in [direct(&conjugated, false), bluestein(&conjugated, false)] {
+            for (x, c) in values.iter().zip(back) {
+                assert!((c.re / 5.0 - x).abs() < 1e-12 && c.im.abs() < 1e-12);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+//! The formats of `format(x, "0.3f")`, which formats one result regardless of the options.
+
+use std::fmt;
+
+/// The most decimals a format can have.
+pub const DECIMALS_LIMIT: usize = 100;
+
+/// Follows a number that is shown rounded, so that it is not taken for the stored value.
+const ROUNDED: char = '…';
+
+/// How `format` writes a number.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Spec {
+    /// `0.3f`, with that many decimals
+    Fixed(usize),
+    /// `0.3e`, like `1.235e3`
+    Scientific(usize),
+    /// `hex`, `oct` or `bin`, for integers
+    Radix(u32),
+}
+
+impl Spec {
+    pub fn parse(spec: &str) -> Result<Spec, String> {
+        let radix = match spec {
+            "hex" => Some(16),
+            "oct" => Some(8),
+            "bin" => Some(2),
+            _ => None,
+        };
+        if let Some(radix) = radix {
+            return Ok(Spec::Radix(radix));
+        }
+        let unknown = || {
+            let expected = "\"0.3f\", \"0.3e\", \"hex\", \"oct\" or \"bin\"";
+            format!("unknown format: {spec:?}, expected one like {expected}")
+        };
+        let rest = spec.strip_prefix('0').unwrap_or(spec).strip_prefix('.').ok_or_else(unknown)?;
+        let (decimals, make): (_, fn(usize) -> Spec) = match (rest.strip_suffix('f'), rest.strip_suffix('e')) {
+            (Some(decimals), _) => (decimals, Spec::Fixed),
+            (_, Some(decimals)) => (decimals, Spec::Scientific),
+            _ => return Err(unknown()),
+        };
+        if decimals.is_empty() || !decimals.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(unknown());
+        }
+        match decimals.parse() {
+            Ok(decimals) if decimals <= DECIMALS_LIMIT => Ok(make(decimals)),
+            _ => Err(format!("formats can have at most {DECIMALS_LIMIT} decimals")),
+        }
+    }
+
+    pub fn format(self, x: f64) -> Result<String, String> {
+        let n = match self {
+            Spec::Fixed(decimals) => return Ok(format!("{x:.decimals$}")),
+            Spec::Scientific(decimals) => return Ok(format!("{x:.decimals$e}")),
+            // larger numbers are not exact integers anymore
+            Spec::Radix(_) if x.fract() != 0.0 || x.abs() > 2f64.powi(53) => {
+                return Err(format!("{self} takes integers up to 2^53, got {x}"));
+            }
+            Spec::Radix(_) => x.abs() as u64,
+        };
+        let sign = if x < 0.0 { "-" } else { "" };
+        Ok(match self {
+            Spec::Radix(16) => format!("{sign}0x{n:X}"),
+            Spec::Radix(8) => format!("{sign}0o{n:o}"),
+            _ => format!("{sign}0b{n:b}"),
+        })
+    }
+}
+
+/// `shown` followed by `…` when it is not exactly `x`, like `3.33…` for 10/3 with 2 decimals,
+/// for results that are shown rounded by default. What `format` writes is left as it is, since
+/// the digits were asked for.
+pub fn mark_rounded(mut shown: String, x: f64) -> String {
+    if shown.parse() != Ok(x) {
+        shown.push(ROUNDED);
+    }
+    shown
+}
+
+/// Prints the spec like it is written in `format`, without the quotes.
+impl fmt::Display for Spec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Spec::Fixed(decimals) => write!(f, "0.{decimals}f"),
+            Spec::Scientific(decimals) => write!(f, "0.{decimals}e"),
+            Spec::Radix(16) => f.write_str("hex"),
+            Spec::Radix(8) => f.write_str("oct"),
+            Spec::Radix(_) => f.write_str("bin"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format(x: f64, spec: &str) -> Result<String, String> {
+        Spec::parse(spec)?.format(x)
+    }
+
+    #[test]
+    fn specs() {
+        assert_eq!(format(2.0 / 3.0, "0.3f"), Ok("0.667".into()));
+        assert_eq!(format(-1.5, ".0f"), Ok("-2".into()));
+        assert_eq!(format(1234.5, "0.2e"), Ok("1.23e3".into()));
+        assert_eq!(format(255.0, "hex"), Ok("0xFF".into()));
+        assert_eq!(format(-8.0, "oct"), Ok("-0o10".into()));
+        assert_eq!(format(5.0, "bin"), Ok("0b101".into()));
+        assert_eq!(format(0.5, "hex"), Err("hex takes integers up to 2^53, got 0.5".into()));
+        assert_eq!(format(1.0, "0.101f"), Err("formats can have at most 100 decimals".into()));
+        let unknown = "unknown format: \"3f\", expected one like \"0.3f\", \"0.3e\", \"hex\", \"oct\" or \"bin\"";
+        assert_eq!(format(1.0, "3f"), Err(unknown.into()));
+        assert!(format(1.0, "0.3g").is_err());
+        assert!(format(1.0, "0.f").is_err());
+        assert!(format(1.0, "0.+3f").is_err());
+        assert!(format(1.0, ".é").is_err());
+        assert_eq!(Spec::parse(".3e").map(|spec| spec.to_string()), Ok("0.3e".into()));
+    }
+
+    #[test]
+    fn rounded() {
+        assert_eq!(mark_rounded("80.00".into(), 80.0), "80.00");
+        assert_eq!(mark_rounded("0.30".into(), 0.1 + 0.2), "0.30…");
+        assert_eq!(mark_rounded("3.33".into(), 10.0 / 3.0), "3.33…");
+    }
+}
@@ -0,0 +1,1327 @@
+use std::collections::HashSet;
+use std::time::Instant;
+
+use crate::check::Type;
+use crate::fft;
+use crate::format::{Spec, DECIMALS_LIMIT};
+use crate::i18n::{Key, Text};
+use crate::ieee;
+use crate::number::IntType;
+use crate::poly;
+use crate::primes::{self, Partial};
+use crate::repr::Value;
+use crate::solve::{self, Failure};
+
+/// A group of built-in functions that can be loaded and unloaded together.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
+pub enum Module {
+    /// always loaded
+    Core,
+    Trig,
+    Stats,
+    Finance,
+    Bits,
+}
+
+impl Module {
+    pub const ALL: [Module; 5] = [Module::Core, Module::Trig, Module::Stats, Module::Finance, Module::Bits];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Module::Core => "core",
+            Module::Trig => "trig",
+            Module::Stats => "stats",
+            Module::Finance => "finance",
+            Module::Bits => "bits",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Module> {
+        Module::ALL.into_iter().find(|m| m.name() == name)
+    }
+
+    pub fn loaded_by_default(self) -> bool {
+        matches!(self, Module::Core | Module::Trig | Module::Stats)
+    }
+}
+
+/// The functions that make durations, which are counted in seconds.
+pub const DURATIONS: [&str; 4] = ["hms", "hours", "minutes", "seconds"];
+
+/// The functions that make lengths, which are counted in tenths of a millimetre, so that both
+/// inches and metres are whole numbers of them.
+pub const LENGTHS: [&str; 7] = ["feet", "inches", "miles", "meters", "kilometers", "centimeters", "millimeters"];
+
+/// An inch and a metre as lengths.
+pub const INCH: f64 = 254.0;
+pub const METER: f64 = 10000.0;
+
+/// The metric functions of [`LENGTHS`], with the symbols that lengths made by them are shown
+/// with, and how long one is. Other lengths are shown in feet and inches.
+pub const METRIC: [(&str, &str, f64); 4] = [
+    ("meters", "m", METER),
+    ("kilometers", "km", 1000.0 * METER),
+    ("centimeters", "cm", METER / 100.0),
+    ("millimeters", "mm", METER / 1000.0),
+];
+
+/// How many numbers a list or a range can have.
+pub const LIST_LIMIT: usize = 1_000_000;
+
+/// A built-in function that can be called from expressions, like `sqrt(2)`.
+pub struct Function {
+    pub module: Module,
+    pub name: &'static str,
+    pub min_args: usize,
+    /// `usize::MAX` for functions taking any number of arguments
+    pub max_args: usize,
+    /// the parameters as shown in the signature, like `x, digits = 0`
+    pub params: &'static str,
+    /// the arguments the function is defined for, or empty when it takes none
+    pub domain: &'static str,
+    /// a call that shows what the function does
+    pub example: &'static str,
+    pub eval: Eval,
+}
+
+/// How a built-in function is evaluated.
+#[derive(Clone, Copy)]
+pub enum Eval {
+    /// with its arguments as numbers, where lists are passed as their numbers
+    Numbers(fn(&[f64]) -> Result<f64, String>),
+    /// with arguments that can be lists, text and functions like `x -> x ^ 2`, which the
+    /// function calls as it needs
+    Values {
+        takes: &'static [Takes],
+        gives: Type,
+        eval: fn(&mut Args) -> Result<Value, String>,
+    },
+}
+
+/// An argument of a function evaluated with [`Eval::Values`].
+#[derive(Debug, Clone, Copy)]
+pub enum Takes {
+    Value(Type),
+    /// a function like the example with this many parameters, or `usize::MAX` for any number,
+    /// which returns the type
+    Function(usize, Type, &'static str),
+    /// a polynomial, as a list of its coefficients from the highest degree, or as a function of
+    /// one parameter like the example
+    Polynomial(&'static str),
+}
+
+/// Calls the functions passed to a built-in function, like the `x -> x ^ 2` of
+/// `map(v, x -> x ^ 2)`, by the index of their argument.
+pub trait Lambdas {
+    fn params(&self, i: usize) -> Vec<String>;
+
+    fn call(&self, i: usize, args: &[f64]) -> Result<Value, String>;
+
+    /// The coefficients of the function as a polynomial in its parameter, lowest degree first.
+    fn coefficients(&self, i: usize) -> Result<Vec<f64>, String>;
+}
+
+/// The arguments of a function evaluated with [`Eval::Values`].
+pub struct Args<'a> {
+    name: &'static str,
+    /// `None` for the functions, which `lambdas` calls
+    values: Vec<Option<Value>>,
+    lambdas: &'a dyn Lambdas,
+}
+
+impl<'a> Args<'a> {
+    pub fn new(name: &'static str, values: Vec<Option<Value>>, lambdas: &'a dyn Lambdas) -> Self {
+        Args { name, values, lambdas }
+    }
+
+    fn mismatch(&self, i: usize, t: Type) -> String {
+        let found = match &self.values[i] {
+            Some(value) => Type::of(value).to_string(),
+            None => "function".into(),
+        };
+        format!("{} takes a {t}, not a {found}", self.name)
+    }
+
+    pub fn number(&self, i: usize) -> Result<f64, String> {
+        match self.values[i] {
+            Some(Value::Number(x)) => Ok(x),
+            _ => Err(self.mismatch(i, Type::Number)),
+        }
+    }
+
+    /// The number of the optional argument `i`, or `default` if it is left out.
+    pub fn number_or(&self, i: usize, default: f64) -> Result<f64, String> {
+        if i < self.values.len() { self.number(i) } else { Ok(default) }
+    }
+
+    pub fn list(&mut self, i: usize) -> Result<Vec<f64>, String> {
+        match self.values[i].take() {
+            Some(Value::List(values)) => Ok(values),
+            other => {
+                self.values[i] = other;
+                Err(self.mismatch(i, Type::List))
+            }
+        }
+    }
+
+    pub fn text(&self, i: usize) -> Result<&str, String> {
+        match &self.values[i] {
+            Some(Value::Text(text)) => Ok(text),
+            _ => Err(self.mismatch(i, Type::Text)),
+        }
+    }
+
+    pub fn params(&self, i: usize) -> Vec<String> {
+        self.lambdas.params(i)
+    }
+
+    pub fn call(&self, i: usize, args: &[f64]) -> Result<Value, String> {
+        self.lambdas.call(i, args)
+    }
+
+    /// Calls the function `i`, which returns a number.
+    pub fn call_number(&self, i: usize, args: &[f64]) -> Result<f64, String> {
+        match self.call(i, args)? {
+            Value::Number(x) => Ok(x),
+            v => Err(format!("the function of {} must return a number, not a {}", self.name, Type::of(&v))),
+        }
+    }
+
+    /// The coefficients of the polynomial `i`, lowest degree first.
+    pub fn coefficients(&mut self, i: usize) -> Result<Vec<f64>, String> {
+        if self.values[i].is_none() {
+            return self.lambdas.coefficients(i);
+        }
+        let mut coefficients = self.list(i)?;
+        coefficients.reverse();
+        Ok(coefficients)
+    }
+}
+
+impl Function {
+    pub fn signature(&self) -> String {
+        format!("{}({})", self.name, self.params)
+    }
+
+    pub fn check_arity(&self, n: usize) -> Result<(), Text> {
+        if n < self.min_args || n > self.max_args {
+            let expected = if self.min_args == self.max_args {
+                self.min_args.to_string().into()
+            } else if self.max_args == usize::MAX {
+                Text::new(Key::ArityAtLeast, vec![self.min_args.to_string().into()])
+            } else {
+                Text::new(Key::ArityRange, vec![self.min_args.to_string().into(), self.max_args.to_string().into()])
+            };
+            return Err(arity(self.name, expected, n));
+        }
+        Ok(())
+    }
+
+    /// The parameters of the signature, or `None` for functions taking any number of arguments,
+    /// which have no names to pass arguments by.
+    pub fn parameters(&self) -> Option<Vec<Param>> {
+        if self.params.ends_with("...") {
+            return None;
+        }
+        let params = self.params.split(", ").filter(|p| !p.is_empty());
+        Some(
+            params
+                .map(|p| match p.split_once(" = ") {
+                    Some((name, default)) => Param { name, default: default.parse().ok() },
+                    None => Param { name: p, default: None },
+                })
+                .collect(),
+        )
+    }
+
+    /// Puts keyword arguments like `base = 2` in the places of their parameters after the
+    /// positional arguments `args`. Parameters that are skipped get their defaults, which
+    /// `default` makes arguments of, and those after the last argument are left out.
+    pub fn bind<T>(
+        &self,
+        mut args: Vec<T>,
+        named: Vec<(&str, T)>,
+        mut default: impl FnMut(f64) -> T,
+    ) -> Result<Vec<T>, String> {
+        let params = self.parameters().ok_or_else(|| format!("{} takes no keyword arguments", self.name))?;
+        if args.len() > params.len() {
+            self.check_arity(args.len() + named.len())?;
+        }
+        let mut slots: Vec<Option<T>> = params.iter().map(|_| None).collect();
+        for (name, arg) in named {
+            let i = params.iter().position(|p| p.name == name);
+            let i = i.ok_or_else(|| format!("{} has no parameter named {name}", self.name))?;
+            if i < args.len() || slots[i].is_some() {
+                return Err(format!("{} got {name} twice", self.name));
+            }
+            slots[i] = Some(arg);
+        }
+        let last = slots.iter().rposition(Option::is_some).map_or(0, |i| i + 1);
+        for (i, slot) in slots.into_iter().enumerate().take(last).skip(args.len()) {
+            let arg = match (slot, params[i].default) {
+                (Some(arg), _) => arg,
+                (None, Some(value)) => default(value),
+                (None, None) => return Err(format!("{} is missing {}", self.name, params[i].name)),
+            };
+            args.push(arg);
+        }
+        Ok(args)
+    }
+}
+
+/// A parameter of a built-in function, which arguments can be passed to by name.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Param {
+    pub name: &'static str,
+    /// the value when the argument is left out, or `None` when it is required
+    pub default: Option<f64>,
+}
+
+/// The error for a call of `name` with `got` arguments when it takes `expected`.
+pub fn arity(name: &str, expected: Text, got: usize) -> Text {
+    Text::new(Key::Arity, vec![name.into(), expected, got.to_string().into()])
+}
+
+/// The error for a call of the built-in function `name` from a module that is not loaded.
+pub fn not_loaded(name: &str, module: Module) -> Text {
+    Text::new(Key::ModuleNotLoaded, vec![name.into(), module.name().into()])
+}
+
+pub fn lookup(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+const fn unary(
+    module: Module,
+    name: &'static str,
+    domain: &'static str,
+    example: &'static str,
+    eval: fn(&[f64]) -> Result<f64, String>,
+) -> Function {
+    Function {
+        module,
+        name,
+        params: "x",
+        domain,
+        example,
+        min_args: 1,
+        max_args: 1,
+        eval: Eval::Numbers(eval),
+    }
+}
+
+const fn variadic(
+    module: Module,
+    name: &'static str,
+    domain: &'static str,
+    example: &'static str,
+    eval: fn(&[f64]) -> Result<f64, String>,
+) -> Function {
+    Function {
+        module,
+        name,
+        params: "x, ...",
+        domain,
+        example,
+        min_args: 1,
+        max_args: usize::MAX,
+        eval: Eval::Numbers(eval),
+    }
+}
+
+/// Functions of the bits module take two integers.
+macro_rules! bitwise {
+    ($name:literal, $example:literal, $op:expr) => {
+        Function {
+            module: Module::Bits,
+            name: $name,
+            min_args: 2,
+            max_args: 2,
+            params: "a, b",
+            domain: "integers a and b",
+            example: $example,
+            eval: Eval::Numbers(|a| {
+                let op: fn(i64, i64) -> Option<i64> = $op;
+                let (x, y) = (integer($name, a[0])?, integer($name, a[1])?);
+                op(x, y).map(|v| v as f64).ok_or_else(|| format!("{}: out of range", $name))
+            }),
+        }
+    };
+}
+
+fn integer(function: &str, x: f64) -> Result<i64, String> {
+    if x.fract() != 0.0 || x.abs() > 2f64.powi(53) {
+        return Err(format!("{function} takes integers, got {x}"));
+    }
+    Ok(x as i64)
+}
+
+/// The digits of `|n|` in `base`, lowest first. 0 has the digit 0.
+fn digits(n: i64, base: u64) -> Vec<u64> {
+    let mut n = n.unsigned_abs();
+    let mut digits = vec![n % base];
+    while n >= base {
+        n /= base;
+        digits.push(n % base);
+    }
+    digits
+}
+
+/// The primes dividing `x` with how many times they do, for the functions of the factorization.
+fn prime_powers(function: &str, x: f64) -> Result<Vec<(u64, u32)>, String> {
+    let n = integer(function, x)?;
+    if n < 1 {
+        return Err(format!("{function} takes integers of at least 1, got {x}"));
+    }
+    let factors = primes::factor(n as u64, Instant::now() + primes::TIME_LIMIT)
+        .map_err(|_| format!("{function} ran out of time factoring {x}"))?;
+    let mut powers: Vec<(u64, u32)> = Vec::new();
+    for p in factors {
+        match powers.last_mut() {
+            Some((q, k)) if *q == p => *k += 1,
+            _ => powers.push((p, 1)),
+        }
+    }
+    Ok(powers)
+}
+
+/// The modulus of `powmod` and `invmod`, which is a positive integer.
+fn modulus(function: &str, m: f64) -> Result<u64, String> {
+    match integer(function, m)? {
+        m if m >= 1 => Ok(m as u64),
+        _ => Err(format!("{function} takes a modulus of at least 1, got {m}")),
+    }
+}
+
+fn no_inverse(a: f64, m: u64) -> String {
+    format!("{a} has no inverse mod {m}, since they have a common factor")
+}
+
+/// Converts `x` to an integer type like a cast in a program does, wrapping around.
+fn convert(int: IntType, x: f64) -> Result<f64, String> {
+    if !x.is_finite() {
+        return Err(format!("{} takes finite numbers, got {x}", int.name()));
+    }
+    Ok(int.wrap(x as i128) as f64)
+}
+
+/// `x` rounded to `digits` decimals like on paper, so that `round(0.015, 2)` is `0.02` although
+/// `0.015` is stored as slightly less. [`rounded`] rounds by the stored value instead.
+fn round(x: f64, digits: f64) -> f64 {
+    let scale = 10f64.powi(digits as i32);
+    (x * scale).round() / scale
+}
+
+/// `x` rounded to `digits` decimals by its stored value, which is the number that formatting it
+/// with that many decimals shows, so that `rounded(0.015, 2)` is `0.01`. Unlike [`round`], the
+/// result never differs from what is shown for `x`.
+fn rounded(x: f64, digits: f64) -> Result<f64, String> {
+    let digits = integer("rounded", digits)?;
+    if !(0..=DECIMALS_LIMIT as i64).contains(&digits) {
+        return Err(format!("rounded takes 0 to {DECIMALS_LIMIT} digits, got {digits}"));
+    }
+    Ok(format!("{x:.*}", digits as usize).parse().unwrap())
+}
+
+fn trunc(x: f64, digits: f64) -> f64 {
+    let scale = 10f64.powi(digits as i32);
+    (x * scale).trunc() / scale
+}
+
+/// The `n` numbers `from + i * step` of a range, which are computed like this so that rounding
+/// errors do not add up.
+pub fn progression(from: f64, step: f64, n: f64) -> Result<Vec<f64>, String> {
+    if n.is_nan() || n > LIST_LIMIT as f64 {
+        return Err(format!("ranges can have at most {LIST_LIMIT} numbers"));
+    }
+    Ok((0..n.max(0.0) as usize).map(|i| from + i as f64 * step).collect())
+}
+
+/// Solves the equations that `f` returns the differences of the sides of with Newton's method,
+/// starting from `guesses`.
+fn nsolve(a: &Args, f: usize, guesses: Vec<f64>) -> Result<Vec<f64>, String> {
+    let vars = a.params(f);
+    if guesses.len() != vars.len() {
+        return Err(format!("nsolve needs a guess for each variable, got {} and {}", guesses.len(), vars.len()));
+    }
+    let sides = |x: &[f64]| match a.call(f, x)? {
+        Value::List(sides) if sides.len() == x.len() => Ok(sides.into_iter().map(|d| (d, 0.0)).collect()),
+        Value::List(sides) => {
+            let (equations, vars) = (sides.len(), x.len());
+            Err(format!("nsolve needs as many equations as variables, got {equations} and {vars}"))
+        }
+        v => Err(format!("the function of nsolve must return a list, not a {}", Type::of(&v))),
+    };
+    let at = |x: &[f64]| {
+        let values = vars.iter().zip(x).map(|(v, &x)| format!("{v} = {}", Value::Number(x)));
+        values.collect::<Vec<_>>().join(", ")
+    };
+    solve::newton(sides, guesses).map_err(|failure| match failure {
+        Failure::Eval(message) => message,
+        Failure::Singular(x) => {
+            format!("nsolve got stuck at {}, where the Jacobian is singular, so try other guesses", at(&x))
+        }
+        Failure::NoConvergence(x, off) => {
+            format!("nsolve did not converge, and got to {}, where an equation is off by {off}", at(&x))
+        }
+    })
+}
+
+pub static FUNCTIONS: &[Function] = &[
+    unary(Module::Core, "abs", "any x", "abs(-3)", |a| Ok(a[0].abs())),
+    unary(Module::Core, "sqrt", "x >= 0", "sqrt(2)", |a| Ok(a[0].sqrt())),
+    unary(Module::Core, "exp", "any x", "exp(1)", |a| Ok(a[0].exp())),
+    unary(Module::Core, "ln", "x > 0", "ln(10)", |a| Ok(a[0].ln())),
+    unary(Module::Core, "log10", "x > 0", "log10(1000)", |a| Ok(a[0].log10())),
+    Function {
+        module: Module::Core,
+        name: "log",
+        min_args: 1,
+        max_args: 2,
+        params: "x, base = 10",
+        domain: "x > 0 and base > 0",
+        example: "log(8, 2)",
+        eval: Eval::Numbers(|a| {
+            Ok(match a.get(1).copied().unwrap_or(10.0) {
+                // exact results for exact powers
+                2.0 => a[0].log2(),
+                10.0 => a[0].log10(),
+                base => a[0].ln() / base.ln(),
+            })
+        }),
+    },
+    unary(Module::Trig, "sin", "any x, in radians", "sin(pi() / 6)", |a| Ok(a[0].sin())),
+    unary(Module::Trig, "cos", "any x, in radians", "cos(pi())", |a| Ok(a[0].cos())),
+    unary(Module::Trig, "tan", "any x, in radians", "tan(pi() / 4)", |a| Ok(a[0].tan())),
+    unary(Module::Trig, "asin", "-1 <= x <= 1", "asin(1)", |a| Ok(a[0].asin())),
+    unary(Module::Trig, "acos", "-1 <= x <= 1", "acos(0)", |a| Ok(a[0].acos())),
+    unary(Module::Trig, "atan", "any x", "atan(1)", |a| Ok(a[0].atan())),
+    unary(Module::Core, "floor", "any x", "floor(-2.5)", |a| Ok(a[0].floor())),
+    unary(Module::Core, "ceil", "any x", "ceil(2.1)", |a| Ok(a[0].ceil())),
+    Function {
+        module: Module::Core,
+        name: "trunc",
+        min_args: 1,
+        max_args: 2,
+        params: "x, digits = 0",
+        domain: "any x and integer digits",
+        example: "trunc(-2.375, 2)",
+        eval: Eval::Numbers(|a| Ok(trunc(a[0], a.get(1).copied().unwrap_or(0.0)))),
+    },
+    Function {
+        module: Module::Core,
+        name: "round",
+        min_args: 1,
+        max_args: 2,
+        params: "x, digits = 0",
+        domain: "any x and integer digits",
+        example: "round(2.375, 2)",
+        eval: Eval::Numbers(|a| Ok(round(a[0], a.get(1).copied().unwrap_or(0.0)))),
+    },
+    Function {
+        module: Module::Core,
+        name: "rounded",
+        min_args: 2,
+        max_args: 2,
+        params: "x, digits",
+        domain: "any x and integer digits from 0 to 100",
+        example: "rounded(0.015, 2)",
+        eval: Eval::Numbers(|a| rounded(a[0], a[1])),
+    },
+    Function {
+        module: Module::Core,
+        name: "nextafter",
+        min_args: 1,
+        max_args: 2,
+        params: "x, toward = inf",
+        domain: "any x and toward",
+        example: "nextafter(1)",
+        eval: Eval::Numbers(|a| Ok(ieee::next_after(a[0], a.get(1).copied().unwrap_or(f64::INFINITY)))),
+    },
+    unary(Module::Core, "mantissa", "finite x", "mantissa(0.1)", |a| {
+        ieee::mantissa(a[0]).ok_or_else(|| format!("mantissa takes finite numbers, got {}", a[0]))
+    }),
+    unary(Module::Core, "exponent", "finite x != 0", "exponent(0.1)", |a| {
+        let exponent = ieee::exponent(a[0]).map(f64::from);
+        exponent.ok_or_else(|| format!("exponent takes finite nonzero numbers, got {}", a[0]))
+    }),
+    unary(Module::Core, "ulp", "any x", "ulp(1)", |a| Ok(ieee::ulp(a[0]))),
+    Function {
+        module: Module::Core,
+        name: "bits",
+        min_args: 1,
+        max_args: 1,
+        params: "x",
+        domain: "any x",
+        example: "bits(0.1)",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::Number)],
+            gives: Type::Text,
+            eval: |a| Ok(Value::Text(ieee::describe(a.number(0)?))),
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "ldexp",
+        min_args: 2,
+        max_args: 2,
+        params: "m, e",
+        domain: "any m and integer e",
+        example: "ldexp(1.6, -4)",
+        eval: Eval::Numbers(|a| Ok(ieee::ldexp(a[0], integer("ldexp", a[1])?))),
+    },
+    Function {
+        module: Module::Core,
+        name: "pow",
+        min_args: 2,
+        max_args: 2,
+        params: "x, y",
+        domain: "any x and y",
+        example: "pow(2, 10)",
+        eval: Eval::Numbers(|a| Ok(a[0].powf(a[1]))),
+    },
+    Function {
+        module: Module::Core,
+        name: "mod",
+        min_args: 2,
+        max_args: 2,
+        params: "x, y",
+        domain: "y != 0",
+        example: "mod(-7, 3)",
+        // the result has the sign of the divisor, like in spreadsheets
+        eval: Eval::Numbers(|a| Ok(a[0] - a[1] * (a[0] / a[1]).floor())),
+    },
+    Function {
+        module: Module::Core,
+        name: "powmod",
+        min_args: 3,
+        max_args: 3,
+        params: "a, b, m",
+        domain: "integers a and b, and m >= 1, where a has an inverse if b < 0",
+        example: "powmod(3, 200, 1000003)",
+        eval: Eval::Numbers(|a| {
+            let (x, b, m) = (integer("powmod", a[0])?, integer("powmod", a[1])?, modulus("powmod", a[2])?);
+            let x = x.rem_euclid(m as i64) as u64;
+            let base = if b < 0 { primes::inv_mod(x, m).ok_or_else(|| no_inverse(a[0], m))? } else { x };
+            Ok(primes::pow_mod(base, b.unsigned_abs(), m) as f64)
+        }),
+    },
+    Function {
+        module: Module::Core,
+        name: "invmod",
+        min_args: 2,
+        max_args: 2,
+        params: "a, m",
+        domain: "integers a and m >= 1 without common factors",
+        example: "invmod(3, 7)",
+        eval: Eval::Numbers(|a| {
+            let (x, m) = (integer("invmod", a[0])?, modulus("invmod", a[1])?);
+            let inverse = primes::inv_mod(x.rem_euclid(m as i64) as u64, m);
+            inverse.map(|x| x as f64).ok_or_else(|| no_inverse(a[0], m))
+        }),
+    },
+    unary(Module::Core, "digitsum", "integers x", "digitsum(1234)", |a| {
+        Ok(digits(integer("digitsum", a[0])?, 10).iter().sum::<u64>() as f64)
+    }),
+    unary(Module::Core, "digitalroot", "integers x", "digitalroot(1234)", |a| {
+        // repeating the digit sum leaves the remainder mod 9, but 9 instead of 0
+        let n = integer("digitalroot", a[0])?.unsigned_abs();
+        Ok(if n == 0 { 0.0 } else { (1 + (n - 1) % 9) as f64 })
+    }),
+    Function {
+        module: Module::Core,
+        name: "numdigits",
+        min_args: 1,
+        max_args: 2,
+        params: "n, base = 10",
+        domain: "integers n, and base from 2 to 36",
+        example: "numdigits(255, 16)",
+        eval: Eval::Numbers(|a| {
+            let n = integer("numdigits", a[0])?;
+            let base = match a.get(1).map(|&b| integer("numdigits", b)).transpose()? {
+                None => 10,
+                Some(base @ 2..=36) => base as u64,
+                Some(base) => return Err(format!("numdigits takes a base from 2 to 36, got {base}")),
+            };
+            Ok(digits(n, base).len() as f64)
+        }),
+    },
+    unary(Module::Core, "reversedigits", "integers x", "reversedigits(1230)", |a| {
+        let n = integer("reversedigits", a[0])?;
+        // the lowest digit comes first, so it becomes the highest
+        let reversed = digits(n, 10).iter().try_fold(0u64, |acc, &d| acc.checked_mul(10)?.checked_add(d));
+        match reversed {
+            Some(r) if r <= 1 << 53 => Ok(r as f64 * (n.signum() as f64)),
+            _ => Err("reversedigits: out of range".into()),
+        }
+    }),
+    unary(Module::Core, "phi", "integers x >= 1", "phi(36)", |a| {
+        // n times (1 - 1/p) for each prime p, in integers
+        let powers = prime_powers("phi", a[0])?;
+        Ok(powers.iter().map(|&(p, k)| (p - 1) * p.pow(k - 1)).product::<u64>() as f64)
+    }),
+    unary(Module::Core, "ndiv", "integers x >= 1", "ndiv(36)", |a| {
+        Ok(prime_powers("ndiv", a[0])?.iter().map(|&(_, k)| k as u64 + 1).product::<u64>() as f64)
+    }),
+    unary(Module::Core, "sigma", "integers x >= 1", "sigma(36)", |a| {
+        let powers = prime_powers("sigma", a[0])?;
+        Ok(powers.iter().map(|&(p, k)| (0..=k).map(|i| (p as f64).powi(i as i32)).sum::<f64>()).product())
+    }),
+    unary(Module::Core, "mu", "integers x >= 1", "mu(30)", |a| {
+        let powers = prime_powers("mu", a[0])?;
+        // 0 unless no prime divides it twice, and then the sign of how many primes there are
+        if powers.iter().any(|&(_, k)| k > 1) {
+            Ok(0.0)
+        } else {
+            Ok(if powers.len() % 2 == 0 { 1.0 } else { -1.0 })
+        }
+    }),
+    variadic(Module::Core, "min", "any numbers", "min(3, -1, 2)", |a| {
+        Ok(a.iter().copied().fold(f64::INFINITY, f64::min))
+    }),
+    variadic(Module::Core, "max", "any numbers", "max(3, -1, 2)", |a| {
+        Ok(a.iter().copied().fold(f64::NEG_INFINITY, f64::max))
+    }),
+    variadic(Module::Stats, "sum", "any numbers", "sum(1, 2, 3.5)", |a| Ok(a.iter().sum())),
+    variadic(Module::Stats, "avg", "any numbers", "avg(1, 2, 6)", |a| Ok(a.iter().sum::<f64>() / a.len() as f64)),
+    Function {
+        // mostly for lists, like `len(v)`
+        module: Module::Stats,
+        name: "len",
+        min_args: 0,
+        max_args: usize::MAX,
+        params: "x, ...",
+        domain: "any numbers",
+        example: "len(3, 1, 4)",
+        eval: Eval::Numbers(|a| Ok(a.len() as f64)),
+    },
+    Function {
+        module: Module::Core,
+        name: "pi",
+        min_args: 0,
+        max_args: 0,
+        params: "",
+        domain: "",
+        example: "pi()",
+        eval: Eval::Numbers(|_| Ok(std::f64::consts::PI)),
+    },
+    Function {
+        // only the chosen branch is evaluated by the tree evaluator
+        module: Module::Core,
+        name: "if",
+        min_args: 3,
+        max_args: 3,
+        params: "condition, then, else",
+        domain: "any numbers, where 0 is false",
+        example: "if(1 < 2, 10, 20)",
+        eval: Eval::Numbers(|a| Ok(if a[0] != 0.0 { a[1] } else { a[2] })),
+    },
+    // assertions are true when they hold, and errors otherwise, for testing scripts
+    Function {
+        module: Module::Core,
+        name: "assert",
+        min_args: 1,
+        max_args: 1,
+        params: "condition",
+        domain: "any condition, where 0 is false",
+        example: "assert(1 < 2)",
+        eval: Eval::Numbers(|a| if a[0] != 0.0 { Ok(1.0) } else { Err("assertion failed".into()) }),
+    },
+    Function {
+        module: Module::Core,
+        name: "assert_eq",
+        min_args: 2,
+        max_args: 3,
+        params: "a, b, tolerance = 0",
+        domain: "tolerance >= 0",
+        example: "assert_eq(0.1 + 0.2, 0.3, 0.000001)",
+        eval: Eval::Numbers(|a| match a.get(2) {
+            _ if a[0] == a[1] => Ok(1.0),
+            Some(&tol) if (a[0] - a[1]).abs() <= tol => Ok(1.0),
+            Some(tol) => Err(format!("assertion failed: {} is not within {tol} of {}", a[0], a[1])),
+            None => Err(format!("assertion failed: {} != {}", a[0], a[1])),
+        }),
+    },
+    Function {
+        module: Module::Core,
+        name: "hms",
+        min_args: 2,
+        max_args: 3,
+        params: "hours, minutes, seconds = 0",
+        domain: "any numbers",
+        example: "hms(1, 30)",
+        eval: Eval::Numbers(|a| Ok(a[0] * 3600.0 + a[1] * 60.0 + a.get(2).copied().unwrap_or(0.0))),
+    },
+    unary(Module::Core, "hours", "any x", "hours(1.5)", |a| Ok(a[0] * 3600.0)),
+    unary(Module::Core, "minutes", "any x", "minutes(1.5)", |a| Ok(a[0] * 60.0)),
+    unary(Module::Core, "seconds", "any x", "seconds(90)", |a| Ok(a[0])),
+    unary(Module::Core, "feet", "any x", "feet(6)", |a| Ok(a[0] * 12.0 * INCH)),
+    unary(Module::Core, "inches", "any x", "inches(8)", |a| Ok(a[0] * INCH)),
+    unary(Module::Core, "miles", "any x", "miles(2)", |a| Ok(a[0] * 63360.0 * INCH)),
+    unary(Module::Core, "meters", "any x", "meters(1.5)", |a| Ok(a[0] * METER)),
+    unary(Module::Core, "kilometers", "any x", "kilometers(5)", |a| Ok(a[0] * 1000.0 * METER)),
+    unary(Module::Core, "centimeters", "any x", "centimeters(30)", |a| Ok(a[0] * METER / 100.0)),
+    unary(Module::Core, "millimeters", "any x", "millimeters(30)", |a| Ok(a[0] * METER / 1000.0)),
+    Function {
+        module: Module::Stats,
+        name: "median",
+        min_args: 1,
+        max_args: usize::MAX,
+        params: "x, ...",
+        domain: "any numbers",
+        example: "median(5, 1, 3)",
+        eval: Eval::Numbers(|a| {
+            let mut a = a.to_vec();
+            a.sort_by(f64::total_cmp);
+            let n = a.len();
+            Ok(if n % 2 == 1 { a[n / 2] } else { (a[n / 2 - 1] + a[n / 2]) / 2.0 })
+        }),
+    },
+    // the sample standard deviation
+    Function {
+        module: Module::Stats,
+        name: "stdev",
+        min_args: 2,
+        max_args: usize::MAX,
+        params: "x, y, ...",
+        domain: "any numbers",
+        example: "stdev(2, 4, 4, 4, 5, 5, 7, 9)",
+        eval: Eval::Numbers(|a| {
+            let mean = a.iter().sum::<f64>() / a.len() as f64;
+            let squares: f64 = a.iter().map(|x| (x - mean).powi(2)).sum();
+            Ok((squares / (a.len() - 1) as f64).sqrt())
+        }),
+    },
+    Function {
+        module: Module::Core,
+        name: "range",
+        min_args: 2,
+        max_args: 3,
+        params: "from, to, step = 1",
+        domain: "any from and to, and step != 0",
+        example: "range(0, 1, 0.25)",
+        // unlike from..to, without to
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::Number); 3],
+            gives: Type::List,
+            eval: |a| {
+                let (from, to, step) = (a.number(0)?, a.number(1)?, a.number_or(2, 1.0)?);
+                if step == 0.0 || !step.is_finite() {
+                    return Err(format!("the step of a range must be a nonzero number, got {step}"));
+                }
+                Ok(Value::List(progression(from, step, ((to - from) / step).ceil())?))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "map",
+        min_args: 2,
+        max_args: 2,
+        params: "list, f",
+        domain: "any list, and f of one number",
+        example: "map([1, 2, 3], x -> x ^ 2)",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List), Takes::Function(1, Type::Number, "x -> x ^ 2")],
+            gives: Type::List,
+            eval: |a| {
+                let values = a.list(0)?;
+                Ok(Value::List(values.into_iter().map(|x| a.call_number(1, &[x])).collect::<Result<_, _>>()?))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "filter",
+        min_args: 2,
+        max_args: 2,
+        params: "list, f",
+        domain: "any list, and f of one number returning a boolean",
+        example: "filter([3, -1, 0.5], x -> x > 0)",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List), Takes::Function(1, Type::Boolean, "x -> x > 0")],
+            gives: Type::List,
+            eval: |a| {
+                let mut kept = Vec::new();
+                for x in a.list(0)? {
+                    if a.call_number(1, &[x])? != 0.0 {
+                        kept.push(x);
+                    }
+                }
+                Ok(Value::List(kept))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "reduce",
+        min_args: 3,
+        max_args: 3,
+        params: "list, initial, f",
+        domain: "any list and initial, and f of two numbers",
+        example: "reduce([1, 2, 3, 4], 1, (a, b) -> a * b)",
+        eval: Eval::Values {
+            takes: &[
+                Takes::Value(Type::List),
+                Takes::Value(Type::Number),
+                Takes::Function(2, Type::Number, "(a, b) -> a + b"),
+            ],
+            gives: Type::Number,
+            eval: |a| {
+                let (values, initial) = (a.list(0)?, a.number(1)?);
+                Ok(Value::Number(values.into_iter().try_fold(initial, |res, x| a.call_number(2, &[res, x]))?))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "sort",
+        min_args: 1,
+        max_args: 1,
+        params: "list",
+        domain: "any list",
+        example: "sort([3, -1, 2])",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List)],
+            gives: Type::List,
+            eval: |a| {
+                let mut values = a.list(0)?;
+                // NaN goes last
+                values.sort_by(f64::total_cmp);
+                Ok(Value::List(values))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "reverse",
+        min_args: 1,
+        max_args: 1,
+        params: "list",
+        domain: "any list",
+        example: "reverse([1, 2, 3])",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List)],
+            gives: Type::List,
+            eval: |a| {
+                let mut values = a.list(0)?;
+                values.reverse();
+                Ok(Value::List(values))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "unique",
+        min_args: 1,
+        max_args: 1,
+        params: "list",
+        domain: "any list",
+        example: "unique([3, 1, 3])",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List)],
+            gives: Type::List,
+            eval: |a| {
+                let mut values = a.list(0)?;
+                // with -0 the same as 0
+                let mut seen = HashSet::new();
+                values.retain(|&x| seen.insert(if x == 0.0 { 0 } else { x.to_bits() }));
+                Ok(Value::List(values))
+            },
+        },
+    },
+    Function {
+        // the sums of the numbers up to each number
+        module: Module::Core,
+        name: "cumsum",
+        min_args: 1,
+        max_args: 1,
+        params: "list",
+        domain: "any list",
+        example: "cumsum([1, 2, 3, 4])",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List)],
+            gives: Type::List,
+            eval: |a| {
+                let mut values = a.list(0)?;
+                let mut sum = 0.0;
+                for x in &mut values {
+                    sum += *x;
+                    *x = sum;
+                }
+                Ok(Value::List(values))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "format",
+        min_args: 2,
+        max_args: 2,
+        params: "x, spec",
+        domain: "any x, and a spec like \"0.3f\", \"e\" or \"hex\"",
+        example: "format(2 / 3, \"0.3f\")",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::Number), Takes::Value(Type::Text)],
+            gives: Type::Text,
+            eval: |a| Ok(Value::Text(Spec::parse(a.text(1)?)?.format(a.number(0)?)?)),
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "nsolve",
+        min_args: 2,
+        max_args: 2,
+        params: "f, guesses",
+        domain: "f of the variables returning a list of the equations, with a guess for each variable",
+        example: "nsolve((x, y) -> [x + y - 3, x * y - 2], [0, 3])",
+        eval: Eval::Values {
+            takes: &[
+                Takes::Function(usize::MAX, Type::List, "(x, y) -> [x + y - 3, x * y - 2]"),
+                Takes::Value(Type::List),
+            ],
+            gives: Type::List,
+            eval: |a| {
+                let guesses = a.list(1)?;
+                Ok(Value::List(nsolve(a, 0, guesses)?))
+            },
+        },
+    },
+    Function {
+        // with the Runge-Kutta method, at the numbers of xs
+        module: Module::Core,
+        name: "odesolve",
+        min_args: 3,
+        max_args: 3,
+        params: "f, y0, xs",
+        domain: "f of x and y, and any y0 and xs",
+        example: "odesolve((x, y) -> 2 * x, 1, [0, 1, 2])",
+        eval: Eval::Values {
+            takes: &[
+                Takes::Function(2, Type::Number, "(x, y) -> x * y"),
+                Takes::Value(Type::Number),
+                Takes::Value(Type::List),
+            ],
+            gives: Type::List,
+            eval: |a| {
+                let (y0, xs) = (a.number(1)?, a.list(2)?);
+                Ok(Value::List(solve::rk4(|x, y| a.call_number(0, &[x, y]), y0, &xs)?))
+            },
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "fft",
+        min_args: 1,
+        max_args: 1,
+        params: "list",
+        domain: "any list",
+        example: "fft([1, 0, -1, 0])",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List)],
+            gives: Type::ComplexList,
+            eval: |a| Ok(Value::ComplexList(fft::transform(&a.list(0)?, false))),
+        },
+    },
+    Function {
+        module: Module::Core,
+        name: "ifft",
+        min_args: 1,
+        max_args: 1,
+        params: "list",
+        domain: "any list",
+        example: "ifft([0, 2, 0, 2])",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::List)],
+            gives: Type::ComplexList,
+            eval: |a| Ok(Value::ComplexList(fft::transform(&a.list(0)?, true))),
+        },
+    },
+    Function {
+        // the real ones, from the smallest
+        module: Module::Core,
+        name: "roots",
+        min_args: 1,
+        max_args: 1,
+        params: "p",
+        domain: "the coefficients of a polynomial from the highest degree, or a polynomial like x -> x ^ 2 - 2",
+        example: "roots([1, 0, -2])",
+        eval: Eval::Values {
+            takes: &[Takes::Polynomial("x -> x ^ 2 - 2")],
+            gives: Type::List,
+            eval: |a| Ok(Value::List(poly::roots(&a.coefficients(0)?)?)),
+        },
+    },
+    Function {
+        // the primes, from the smallest and as many times as they divide n
+        module: Module::Core,
+        name: "factor",
+        min_args: 1,
+        max_args: 1,
+        params: "n",
+        domain: "whole numbers n from 1 to 2^53",
+        example: "factor(360)",
+        eval: Eval::Values {
+            takes: &[Takes::Value(Type::Number)],
+            gives: Type::List,
+            eval: |a| {
+                let n = a.number(0)?;
+                // larger numbers are not exact integers anymore
+                if n < 1.0 || n.fract() != 0.0 || n > 2f64.powi(53) {
+                    return Err(format!("factor takes whole numbers from 1 to 2^53, got {}", Value::Number(n)));
+                }
+                match primes::factor(n as u64, Instant::now() + primes::TIME_LIMIT) {
+                    Ok(factors) => Ok(Value::List(factors.into_iter().map(|p| p as f64).collect())),
+                    Err(Partial { found, left }) => {
+                        let found: Vec<String> = found.iter().map(u64::to_string).collect();
+                        let found = if found.is_empty() { "no factors".into() } else { found.join(", ") };
+                        Err(format!("factor ran out of time, and found {found} with {left} left"))
+                    }
+                }
+            },
+        },
+    },
+    // like in spreadsheets, money paid out is negative
+    Function {
+        module: Module::Finance,
+        name: "pmt",
+        min_args: 3,
+        max_args: 4,
+        params: "rate, periods, present, future = 0",
+        domain: "periods != 0",
+        example: "pmt(0.01, 12, 10000)",
+        eval: Eval::Numbers(|a| {
+            let (rate, n, pv, fv) = (a[0], a[1], a[2], a.get(3).copied().unwrap_or(0.0));
+            if rate == 0.0 {
+                return Ok(-(pv + fv) / n);
+            }
+            let growth = (1.0 + rate).powf(n);
+            Ok(-(pv * growth + fv) * rate / (growth - 1.0))
+        }),
+    },
+    Function {
+        module: Module::Finance,
+        name: "fv",
+        min_args: 3,
+        max_args: 4,
+        params: "rate, periods, payment, present = 0",
+        domain: "periods != 0",
+        example: "fv(0.01, 12, 0, -10000)",
+        eval: Eval::Numbers(|a| {
+            let (rate, n, pmt, pv) = (a[0], a[1], a[2], a.get(3).copied().unwrap_or(0.0));
+            if rate == 0.0 {
+                return Ok(-(pv + pmt * n));
+            }
+            let growth = (1.0 + rate).powf(n);
+            Ok(-(pv * growth + pmt * (growth - 1.0) / rate))
+        }),
+    },
+    Function {
+        module: Module::Finance,
+        name: "pv",
+        min_args: 3,
+        max_args: 4,
+        params: "rate, periods, payment, future = 0",
+        domain: "periods != 0",
+        example: "pv(0.01, 12, -888.49)",
+        eval: Eval::Numbers(|a| {
+            let (rate, n, pmt, fv) = (a[0], a[1], a[2], a.get(3).copied().unwrap_or(0.0));
+            if rate == 0.0 {
+                return Ok(-(fv + pmt * n));
+            }
+            let growth = (1.0 + rate).powf(n);
+            Ok(-(fv + pmt * (growth - 1.0) / rate) / growth)
+        }),
+    },
+    bitwise!("band", "band(12, 10)", |a, b| Some(a & b)),
+    bitwise!("bor", "bor(12, 10)", |a, b| Some(a | b)),
+    bitwise!("bxor", "bxor(12, 10)", |a, b| Some(a ^ b)),
+    bitwise!("shl", "shl(1, 10)", |a, b| a.checked_shl(b.try_into().ok()?)),
+    bitwise!("shr", "shr(-8, 1)", |a, b| a.checked_shr(b.try_into().ok()?)),
+    unary(Module::Bits, "u8", "any finite x", "u8(300)", |a| convert(IntType::U8, a[0])),
+    unary(Module::Bits, "u16", "any finite x", "u16(-1)", |a| convert(IntType::U16, a[0])),
+    unary(Module::Bits, "u32", "any finite x", "u32(-1)", |a| convert(IntType::U32, a[0])),
+    unary(Module::Bits, "i32", "any finite x", "i32(2 ^ 31)", |a| convert(IntType::I32, a[0])),
+    unary(Module::Bits, "i64", "any finite x", "i64(-2.5)", |a| convert(IntType::I64, a[0])),
+];
+
+#[cfg(test)]
+mod tests {
+    use crate::context::Context;
+    use crate::parser::parse_line;
+    use crate::repr::Statement;
+
+    use super::*;
+
+    fn call(name: &str, args: &[f64]) -> Result<f64, String> {
+        let f = lookup(name).unwrap();
+        f.check_arity(args.len())?;
+        let Eval::Numbers(eval) = f.eval else { panic!("{name} does not take numbers") };
+        eval(args)
+    }
+
+    #[test]
+    fn names_are_unique() {
+        for (i, f) in FUNCTIONS.iter().enumerate() {
+            assert!(FUNCTIONS[..i].iter().all(|g| g.name != f.name), "{} is defined twice", f.name);
+        }
+    }
+
+    #[test]
+    fn arity() {
+        assert_eq!(call("sqrt", &[]), Err("sqrt takes 1 arguments, got 0".into()));
+        assert_eq!(call("round", &[1.0, 2.0, 3.0]), Err("round takes 1 to 2 arguments, got 3".into()));
+        assert_eq!(call("max", &[]), Err("max takes at least 1 arguments, got 0".into()));
+        assert_eq!(call("pi", &[1.0]), Err("pi takes 0 arguments, got 1".into()));
+    }
+
+    #[test]
+    fn evaluate() {
+        assert_eq!(call("sum", &[1.0, 2.0, 3.5]), Ok(6.5));
+        assert_eq!(call("avg", &[1.0, 2.0, 6.0]), Ok(3.0));
+        assert_eq!(call("nextafter", &[1.0]), Ok(1.0000000000000002));
+        assert_eq!(call("nextafter", &[0.3, 0.0]), Ok(0.29999999999999993));
+        assert_eq!(call("mantissa", &[0.1]), Ok(1.6));
+        assert_eq!(call("exponent", &[0.1]), Ok(-4.0));
+        assert_eq!(call("exponent", &[0.0]), Err("exponent takes finite nonzero numbers, got 0".into()));
+        assert_eq!(call("ulp", &[1.0]), Ok(f64::EPSILON));
+        assert_eq!(call("ldexp", &[1.6, -4.0]), Ok(0.1));
+        assert_eq!(call("ldexp", &[1.0, 0.5]), Err("ldexp takes integers, got 0.5".into()));
+        assert_eq!(call("min", &[3.0, -1.0, 2.0]), Ok(-1.0));
+        assert_eq!(call("round", &[2.375, 2.0]), Ok(2.38));
+        assert_eq!(call("round", &[-2.5]), Ok(-3.0));
+        assert_eq!(call("trunc", &[-2.375, 2.0]), Ok(-2.37));
+        assert_eq!(call("rounded", &[2.0 / 3.0, 2.0]), Ok(0.67));
+        assert_eq!(call("rounded", &[0.015, 2.0]), Ok(0.01));
+        assert_eq!(call("round", &[0.015, 2.0]), Ok(0.02));
+        assert_eq!(call("rounded", &[1.5, 101.0]), Err("rounded takes 0 to 100 digits, got 101".into()));
+        assert_eq!(call("mod", &[-7.0, 3.0]), Ok(2.0));
+        assert_eq!(call("powmod", &[3.0, 200.0, 1000003.0]), Ok(333986.0));
+        assert_eq!(call("powmod", &[-2.0, 3.0, 5.0]), Ok(2.0));
+        assert_eq!(call("powmod", &[3.0, -1.0, 7.0]), Ok(5.0));
+        assert_eq!(call("powmod", &[2.0, 2f64.powi(53), 2f64.powi(53) - 1.0]), Ok(4.0));
+        let no_inverse = "2 has no inverse mod 4, since they have a common factor";
+        assert_eq!(call("powmod", &[2.0, -1.0, 4.0]), Err(no_inverse.into()));
+        assert_eq!(call("powmod", &[2.0, 3.0, 0.0]), Err("powmod takes a modulus of at least 1, got 0".into()));
+        assert_eq!(call("invmod", &[3.0, 7.0]), Ok(5.0));
+        assert_eq!(call("invmod", &[-3.0, 7.0]), Ok(2.0));
+        assert_eq!(call("invmod", &[0.5, 7.0]), Err("invmod takes integers, got 0.5".into()));
+    }
+
+    #[test]
+    fn digits() {
+        assert_eq!(call("digitsum", &[1234.0]), Ok(10.0));
+        assert_eq!(call("digitsum", &[-907.0]), Ok(16.0));
+        assert_eq!(call("digitalroot", &[1234.0]), Ok(1.0));
+        assert_eq!(call("digitalroot", &[99.0]), Ok(9.0));
+        assert_eq!(call("digitalroot", &[0.0]), Ok(0.0));
+        assert_eq!(call("numdigits", &[1234.0]), Ok(4.0));
+        assert_eq!(call("numdigits", &[0.0]), Ok(1.0));
+        assert_eq!(call("numdigits", &[255.0, 16.0]), Ok(2.0));
+        assert_eq!(call("numdigits", &[256.0, 2.0]), Ok(9.0));
+        assert_eq!(call("numdigits", &[-999.0, 10.0]), Ok(3.0));
+        assert_eq!(call("numdigits", &[5.0, 1.0]), Err("numdigits takes a base from 2 to 36, got 1".into()));
+        assert_eq!(call("reversedigits", &[1230.0]), Ok(321.0));
+        assert_eq!(call("reversedigits", &[-42.0]), Ok(-24.0));
+        assert_eq!(call("reversedigits", &[1e15 + 19.0]), Err("reversedigits: out of range".into()));
+        assert_eq!(call("digitsum", &[1.5]), Err("digitsum takes integers, got 1.5".into()));
+    }
+
+    #[test]
+    fn number_theory() {
+        assert_eq!(call("phi", &[36.0]), Ok(12.0));
+        assert_eq!(call("phi", &[1.0]), Ok(1.0));
+        assert_eq!(call("phi", &[97.0]), Ok(96.0));
+        assert_eq!(call("ndiv", &[36.0]), Ok(9.0));
+        assert_eq!(call("ndiv", &[1.0]), Ok(1.0));
+        assert_eq!(call("sigma", &[36.0]), Ok(91.0));
+        assert_eq!(call("sigma", &[28.0]), Ok(56.0));
+        assert_eq!(call("mu", &[30.0]), Ok(-1.0));
+        assert_eq!(call("mu", &[6.0]), Ok(1.0));
+        assert_eq!(call("mu", &[12.0]), Ok(0.0));
+        assert_eq!(call("mu", &[1.0]), Ok(1.0));
+        assert_eq!(call("phi", &[2f64.powi(53) - 1.0]), Ok(6360.0 * 69430.0 * 20394400.0));
+        assert_eq!(call("phi", &[0.0]), Err("phi takes integers of at least 1, got 0".into()));
+        assert_eq!(call("log", &[8.0, 2.0]), Ok(3.0));
+        assert_eq!(call("if", &[0.0, 1.0, 2.0]), Ok(2.0));
+        assert_eq!(call("assert", &[0.0]), Err("assertion failed".into()));
+        assert_eq!(call("assert_eq", &[0.1 + 0.2, 0.3]), Err("assertion failed: 0.30000000000000004 != 0.3".into()));
+        assert_eq!(call("assert_eq", &[1.0, 1.5, 0.1]), Err("assertion failed: 1 is not within 0.1 of 1.5".into()));
+        assert_eq!(call("hms", &[1.0, 30.0]), Ok(5400.0));
+        assert_eq!(call("minutes", &[1.5]), Ok(90.0));
+        assert_eq!(call("median", &[5.0, 1.0, 3.0]), Ok(3.0));
+        assert_eq!(call("median", &[4.0, 1.0, 3.0, 2.0]), Ok(2.5));
+        assert_eq!(call("stdev", &[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]), Ok(2.138089935299395));
+    }
+
+    #[test]
+    fn finance() {
+        let round = |r: Result<f64, String>| r.map(|x| (x * 100.0).round() / 100.0);
+        // a loan of 10000 over 12 months at 1% per month
+        assert_eq!(round(call("pmt", &[0.01, 12.0, 10000.0])), Ok(-888.49));
+        assert_eq!(round(call("pv", &[0.01, 12.0, -888.4879])), Ok(10000.0));
+        assert_eq!(round(call("fv", &[0.01, 12.0, 0.0, -10000.0])), Ok(11268.25));
+        assert_eq!(call("pmt", &[0.0, 10.0, 1000.0]), Ok(-100.0));
+    }
+
+    #[test]
+    fn bits() {
+        assert_eq!(call("band", &[12.0, 10.0]), Ok(8.0));
+        assert_eq!(call("bor", &[12.0, 10.0]), Ok(14.0));
+        assert_eq!(call("bxor", &[12.0, 10.0]), Ok(6.0));
+        assert_eq!(call("shl", &[1.0, 10.0]), Ok(1024.0));
+        assert_eq!(call("shr", &[-8.0, 1.0]), Ok(-4.0));
+        assert_eq!(call("band", &[1.5, 1.0]), Err("band takes integers, got 1.5".into()));
+        assert_eq!(call("shl", &[1.0, 64.0]), Err("shl: out of range".into()));
+        assert_eq!(call("u8", &[300.0]), Ok(44.0));
+        assert_eq!(call("u16", &[-1.0]), Ok(65535.0));
+        assert_eq!(call("i32", &[2f64.powi(31)]), Ok(-2f64.powi(31)));
+        assert_eq!(call("u32", &[-2.9]), Ok(4294967294.0));
+        assert_eq!(call("i64", &[f64::INFINITY]), Err("i64 takes finite numbers, got inf".into()));
+    }
+
+    #[test]
+    fn examples() {
+        for f in FUNCTIONS {
+            let e = parse_line(f.example).unwrap();
+            assert!(e.to_string().starts_with(&format!("{}(", f.name)), "{} has the example {}", f.name, f.example);
+            let mut ctx = Context::default();
+            ctx.load(f.module);
+            assert!(ctx.run(&Statement::Expr(e)).is_ok(), "the example of {} fails", f.name);
+        }
+        assert_eq!(lookup("round").unwrap().signature(), "round(x, digits = 0)");
+        assert_eq!(lookup("pi").unwrap().signature(), "pi()");
+    }
+
+    #[test]
+    fn parameters() {
+        for f in FUNCTIONS.iter().filter(|f| f.params.contains(" = ")) {
+            let params = f.parameters().unwrap();
+            assert!(params.iter().filter(|p| p.default.is_none()).count() == f.min_args, "{}", f.params);
+        }
+        let defaults: Vec<_> = lookup("log").unwrap().parameters().unwrap().iter().map(|p| p.default).collect();
+        assert_eq!(defaults, [None, Some(10.0)]);
+        assert_eq!(lookup("max").unwrap().parameters(), None);
+
+        let bind = |name, args: &[f64], named: &[(&str, f64)]| {
+            lookup(name).unwrap().bind(args.to_vec(), named.to_vec(), |x| x)
+        };
+        assert_eq!(bind("round", &[2.5], &[("digits", 1.0)]), Ok(vec![2.5, 1.0]));
+        assert_eq!(bind("log", &[], &[("base", 2.0), ("x", 8.0)]), Ok(vec![8.0, 2.0]));
+        assert_eq!(bind("hms", &[1.0], &[("seconds", 30.0), ("minutes", 2.0)]), Ok(vec![1.0, 2.0, 30.0]));
+        assert_eq!(bind("pmt", &[0.05, 10.0, 1000.0], &[("future", 5.0)]), Ok(vec![0.05, 10.0, 1000.0, 5.0]));
+        assert_eq!(bind("nextafter", &[1.0], &[("toward", 2.0)]), Ok(vec![1.0, 2.0]));
+        assert_eq!(bind("round", &[2.5], &[("x", 1.0)]), Err("round got x twice".into()));
+        assert_eq!(bind("round", &[2.5], &[("base", 1.0)]), Err("round has no parameter named base".into()));
+        assert_eq!(bind("hms", &[1.0], &[("seconds", 30.0)]), Err("hms is missing minutes".into()));
+        assert_eq!(bind("max", &[1.0], &[("x", 2.0)]), Err("max takes no keyword arguments".into()));
+        assert_eq!(bind("round", &[1.0, 2.0, 3.0], &[("x", 2.0)]), Err("round takes 1 to 2 arguments, got 4".into()));
+    }
+
+    #[test]
+    fn default_modules() {
+        assert!(FUNCTIONS.iter().any(|f| !f.module.loaded_by_default()));
+        assert_eq!(lookup("sin").map(|f| f.module), Some(Module::Trig));
+        assert_eq!(Module::from_name("finance"), Some(Module::Finance));
+    }
+}
@@ -0,0 +1,108 @@
+//! Random expressions, for testing other calculators against this one and for benchmarks.
+
+use crate::random::Random;
+use crate::repr::*;
+
+/// Functions that are defined for most arguments, and that most calculators have.
+const FUNCTIONS: [&str; 6] = ["abs", "floor", "ceil", "sqrt", "sin", "cos"];
+
+const OPERATORS: [Operator; 4] = [Operator::Add, Operator::Sub, Operator::Mul, Operator::Div];
+
+pub struct Generator {
+    random: Random,
+    /// the most nested that the expressions get
+    depth: u32,
+}
+
+impl Generator {
+    pub fn new(seed: u64, depth: u32) -> Self {
+        Generator {
+            random: Random::new(seed),
+            depth,
+        }
+    }
+
+    /// An expression without variables, which can be printed and parsed back.
+    pub fn expression(&mut self) -> Expr {
+        let mut b = ExprBuilder::default();
+        let root = self.node(&mut b, self.depth);
+        b.finish(root)
+    }
+
+    fn node(&mut self, b: &mut ExprBuilder, depth: u32) -> NodeId {
+        // so that not every branch is equally deep
+        if depth == 0 || self.random.below(5) == 0 {
+            return self.literal(b);
+        }
+        match self.random.below(10) {
+            0..=5 => {
+                let op = OPERATORS[self.random.below(OPERATORS.len() as u64) as usize];
+                let (l, r) = (self.node(b, depth - 1), self.node(b, depth - 1));
+                b.op(op, l, r)
+            }
+            6 => {
+                // small integer exponents, so that the results stay reasonable
+                let base = self.node(b, depth - 1);
+                let exponent = b.float(self.random.below(4) as f64);
+                b.op(Operator::Pow, base, exponent)
+            }
+            7 => {
+                let a = self.node(b, depth - 1);
+                b.unary(UnaryOperator::Neg, a)
+            }
+            _ => {
+                let name = FUNCTIONS[self.random.below(FUNCTIONS.len() as u64) as usize];
+                let a = self.node(b, depth - 1);
+                b.call(name, vec![a])
+            }
+        }
+    }
+
+    /// An integer below 100, or sometimes a number with one or two decimals.
+    fn literal(&mut self, b: &mut ExprBuilder) -> NodeId {
+        let n = self.random.below(100) as f64;
+        let value = match self.random.below(4) {
+            0 => n / 10.0,
+            1 => n / 100.0,
+            _ => n,
+        };
+        b.float(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    fn depth(e: &Expr, id: NodeId) -> u32 {
+        match *e.node(id) {
+            Node::Unary(_, a) => 1 + depth(e, a),
+            Node::Op(Operator::Pow, a, _) => 1 + depth(e, a),
+            Node::Op(_, a, b) => 1 + depth(e, a).max(depth(e, b)),
+            Node::Call(_, ref args) => 1 + depth(e, args[0]),
+            _ => 0,
+        }
+    }
+
+    #[test]
+    fn valid_expressions() {
+        let mut g = Generator::new(42, 5);
+        for _ in 0..100 {
+            let e = g.expression();
+            assert!(depth(&e, e.root()) <= 5);
+            // negated literals are parsed as negative literals, so only the values are the same
+            let value = e.evaluate().unwrap();
+            let parsed = parse_line(&e.to_string()).unwrap().evaluate().unwrap();
+            assert!(parsed == value || (parsed.is_nan() && value.is_nan()), "{e}");
+        }
+    }
+
+    #[test]
+    fn seeds() {
+        let first = |seed| Generator::new(seed, 4).expression().to_string();
+        assert_eq!(first(1), first(1));
+        assert_ne!(first(1), first(2));
+    }
+}
@@ -0,0 +1,62 @@
+//! The history of the REPL, which is kept in a file between sessions.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// How many lines of history are kept.
+pub const HISTORY_LIMIT: usize = 1000;
+
+/// Where the history is stored by default, which is `~/.local/share/calc/history` unless
+/// `XDG_DATA_HOME` says otherwise.
+pub fn default_path() -> Option<PathBuf> {
+    let data = match std::env::var_os("XDG_DATA_HOME") {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".local/share"),
+    };
+    Some(data.join("calc/history"))
+}
+
+/// Reads the history from `path`, and shortens the file if it has grown past `limit` lines.
+/// A missing file is an empty history.
+pub fn load(path: &Path, limit: usize) -> Vec<String> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Vec::new();
+    };
+    let mut lines: Vec<String> = contents.lines().map(String::from).collect();
+    if lines.len() > limit {
+        lines.drain(..lines.len() - limit);
+        // a history that can not be shortened is still usable
+        let _ = fs::write(path, lines.iter().map(|l| format!("{l}\n")).collect::<String>());
+    }
+    lines
+}
+
+/// Adds a line to the end of the history in `path`, creating the file if needed.
+pub fn append(path: &Path, line: &str) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{line}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn load_and_append() {
+        let dir = std::env::temp_dir().join(format!("calculator-history-{}", std::process::id()));
+        let path = dir.join("calc/history");
+        assert_eq!(load(&path, 3), Vec::<String>::new());
+
+        for line in ["1", "2", "3", "4"] {
+            append(&path, line).unwrap();
+        }
+        assert_eq!(load(&path, 3), ["2", "3", "4"]);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2\n3\n4\n");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
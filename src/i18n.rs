@@ -0,0 +1,315 @@
+//! Translations of error messages and REPL text. Messages that can be translated are made from a
+//! [`Key`] and the texts to fill in, and are shown in the language of the user by
+//! [`Language::show`]. Any other text is shown as it is, in English.
+
+use std::fmt;
+use std::ops::Deref;
+
+use crate::parser::Grouping;
+
+/// A language that messages can be shown in.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    #[default]
+    English,
+    Swedish,
+    German,
+}
+
+impl Language {
+    pub const ALL: [Language; 3] = [Language::English, Language::Swedish, Language::German];
+
+    pub fn code(self) -> &'static str {
+        match self {
+            Language::English => "en",
+            Language::Swedish => "sv",
+            Language::German => "de",
+        }
+    }
+
+    /// Accepts both codes like `sv` and locales like `sv_SE.UTF-8`.
+    pub fn from_code(code: &str) -> Option<Language> {
+        let code = code.split(['_', '.', '-']).next().unwrap_or_default();
+        Language::ALL.into_iter().find(|l| l.code() == code)
+    }
+
+    /// How numbers are written with separators, for `--grouping`.
+    pub fn grouping(self) -> Grouping {
+        match self {
+            Language::English => Grouping::Commas,
+            Language::Swedish => Grouping::Spaces,
+            Language::German => Grouping::Dots,
+        }
+    }
+
+    /// The language of the locale, like `LANG` with `LC_ALL` and `LC_MESSAGES` taking precedence.
+    /// Unsupported languages fall back to English.
+    pub fn from_env() -> Language {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .into_iter()
+            .filter_map(|name| std::env::var(name).ok())
+            .find(|locale| !locale.is_empty())
+            .and_then(|locale| Language::from_code(&locale))
+            .unwrap_or_default()
+    }
+
+    /// The text of the message with `key`, for messages that have nothing to fill in.
+    pub fn text(self, key: Key) -> &'static str {
+        key.message().pattern(self)
+    }
+
+    /// The message in this language. The texts filled in, like the number of arguments in
+    /// `at least 1`, are translated too, and texts without a key are shown in English.
+    pub fn show(self, text: &Text) -> String {
+        match &text.key {
+            Some((key, args)) if self != Language::English => {
+                let args: Vec<String> = args.iter().map(|arg| self.show(arg)).collect();
+                fill(key.message().pattern(self), &args)
+            }
+            _ => text.english.clone(),
+        }
+    }
+}
+
+/// A message that can be shown in any language, made from a [`Key`] and the texts to fill in, or
+/// plain text that is always shown as it is. It reads as its English text, which is what
+/// messages are compared by.
+#[derive(Debug, Clone)]
+pub struct Text {
+    english: String,
+    key: Option<(Key, Vec<Text>)>,
+}
+
+impl Text {
+    /// The message `key` with `args` filled in for `{0}`, `{1}` and so on.
+    pub fn new(key: Key, args: Vec<Text>) -> Text {
+        let english: Vec<String> = args.iter().map(|arg| arg.english.clone()).collect();
+        Text {
+            english: fill(key.message().english, &english),
+            key: Some((key, args)),
+        }
+    }
+
+    /// The key of the message, or `None` for plain text.
+    pub fn key(&self) -> Option<Key> {
+        self.key.as_ref().map(|&(key, _)| key)
+    }
+}
+
+impl Deref for Text {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.english
+    }
+}
+
+impl fmt::Display for Text {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.english)
+    }
+}
+
+impl PartialEq for Text {
+    fn eq(&self, other: &Text) -> bool {
+        self.english == other.english
+    }
+}
+
+impl PartialEq<str> for Text {
+    fn eq(&self, other: &str) -> bool {
+        self.english == other
+    }
+}
+
+impl PartialEq<&str> for Text {
+    fn eq(&self, other: &&str) -> bool {
+        self.english == *other
+    }
+}
+
+impl From<String> for Text {
+    fn from(english: String) -> Text {
+        Text { english, key: None }
+    }
+}
+
+impl From<&String> for Text {
+    fn from(english: &String) -> Text {
+        english.clone().into()
+    }
+}
+
+impl From<&str> for Text {
+    fn from(english: &str) -> Text {
+        english.to_string().into()
+    }
+}
+
+impl From<&Text> for Text {
+    fn from(text: &Text) -> Text {
+        text.clone()
+    }
+}
+
+impl From<Text> for String {
+    fn from(text: Text) -> String {
+        text.english
+    }
+}
+
+/// `pattern` with `{0}`, `{1}` and so on replaced by `args`.
+fn fill(pattern: &str, args: &[String]) -> String {
+    let mut parts = pattern.split('{');
+    let mut res = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let (i, rest) = part.split_once('}').unwrap_or_default();
+        res.push_str(i.parse().ok().and_then(|i: usize| args.get(i)).map_or("", String::as_str));
+        res.push_str(rest);
+    }
+    res
+}
+
+/// One message in every language, where `{0}`, `{1}` and so on are the texts filled in.
+pub struct Message {
+    pub english: &'static str,
+    pub swedish: &'static str,
+    pub german: &'static str,
+}
+
+impl Message {
+    fn pattern(&self, language: Language) -> &'static str {
+        match language {
+            Language::English => self.english,
+            Language::Swedish => self.swedish,
+            Language::German => self.german,
+        }
+    }
+}
+
+/// The messages that are translated. A key stays the same when the wording changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    UnknownVariable,
+    UnknownFunction,
+    /// a function called with the wrong number of arguments
+    Arity,
+    /// the number of arguments in [`Key::Arity`] for functions with no upper limit
+    ArityAtLeast,
+    /// the number of arguments in [`Key::Arity`] for functions with optional arguments
+    ArityRange,
+    /// an error in the body of a user function
+    InFunction,
+    RecursionLimit,
+    ModuleNotLoaded,
+    UnclosedBracket,
+    UnexpectedBracket,
+    NoMemory,
+    UnloadCore,
+    UnknownModule,
+    UnknownMode,
+    UnknownLanguage,
+    UnknownCommand,
+    NothingToUndo,
+    NoProblems,
+    Warning,
+}
+
+impl Key {
+    pub fn message(self) -> Message {
+        let (english, swedish, german) = match self {
+            Key::UnknownVariable => ("unknown variable: {0}", "okänd variabel: {0}", "unbekannte Variable: {0}"),
+            Key::UnknownFunction => ("unknown function: {0}", "okänd funktion: {0}", "unbekannte Funktion: {0}"),
+            Key::Arity => (
+                "{0} takes {1} arguments, got {2}",
+                "{0} tar {1} argument, fick {2}",
+                "{0} erwartet {1} Argumente, erhielt {2}",
+            ),
+            Key::ArityAtLeast => ("at least {0}", "minst {0}", "mindestens {0}"),
+            Key::ArityRange => ("{0} to {1}", "{0} till {1}", "{0} bis {1}"),
+            Key::InFunction => ("in {0}: {1}", "i {0}: {1}", "in {0}: {1}"),
+            Key::RecursionLimit => (
+                "recursion limit exceeded in {0}",
+                "rekursionsgränsen överskreds i {0}",
+                "Rekursionsgrenze überschritten in {0}",
+            ),
+            Key::ModuleNotLoaded => (
+                "{0} is in the {1} module, which is not loaded",
+                "{0} finns i modulen {1}, som inte är laddad",
+                "{0} ist im Modul {1}, das nicht geladen ist",
+            ),
+            Key::UnclosedBracket => (
+                "unclosed {0} at column {1}",
+                "ostängd {0} i kolumn {1}",
+                "nicht geschlossene {0} in Spalte {1}",
+            ),
+            Key::UnexpectedBracket => (
+                "unexpected {0} at column {1}",
+                "oväntad {0} i kolumn {1}",
+                "unerwartete {0} in Spalte {1}",
+            ),
+            Key::NoMemory => (
+                "there is no result to add to the memory yet",
+                "det finns inget resultat att lägga till i minnet än",
+                "es gibt noch kein Ergebnis, das zum Speicher addiert werden kann",
+            ),
+            Key::UnloadCore => (
+                "the core module can not be unloaded",
+                "modulen core kan inte tas bort",
+                "das Modul core kann nicht entladen werden",
+            ),
+            Key::UnknownModule => ("unknown module: {0}", "okänd modul: {0}", "unbekanntes Modul: {0}"),
+            Key::UnknownMode => ("unknown mode: {0}", "okänt läge: {0}", "unbekannter Modus: {0}"),
+            Key::UnknownLanguage => ("unknown language: {0}", "okänt språk: {0}", "unbekannte Sprache: {0}"),
+            Key::UnknownCommand => ("unknown command: {0}", "okänt kommando: {0}", "unbekannter Befehl: {0}"),
+            Key::NothingToUndo => ("nothing to undo", "inget att ångra", "nichts rückgängig zu machen"),
+            Key::NoProblems => ("no problems found", "inga problem hittades", "keine Probleme gefunden"),
+            Key::Warning => ("warning: {0}", "varning: {0}", "Warnung: {0}"),
+        };
+        Message { english, swedish, german }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(key: Key, args: &[&str]) -> Text {
+        Text::new(key, args.iter().map(|&arg| arg.into()).collect())
+    }
+
+    #[test]
+    fn show() {
+        assert_eq!(Language::Swedish.show(&text(Key::UnknownVariable, &["x"])), "okänd variabel: x");
+        assert_eq!(
+            Language::German.show(&text(Key::Arity, &["sqrt", "1", "0"])),
+            "sqrt erwartet 1 Argumente, erhielt 0"
+        );
+        let at_least = text(Key::ArityAtLeast, &["1"]);
+        let arity = Text::new(Key::Arity, vec!["max".into(), at_least, "0".into()]);
+        assert_eq!(arity, "max takes at least 1 arguments, got 0");
+        assert_eq!(Language::Swedish.show(&arity), "max tar minst 1 argument, fick 0");
+        let inner = text(Key::UnknownVariable, &["y"]);
+        let error = Text::new(Key::InFunction, vec!["e".into(), inner]);
+        assert_eq!(Language::German.show(&error), "in e: unbekannte Variable: y");
+        assert_eq!(Language::Swedish.show(&text(Key::UnclosedBracket, &["[", "3"])), "ostängd [ i kolumn 3");
+        assert_eq!(Language::English.show(&text(Key::UnknownVariable, &["x"])), "unknown variable: x");
+        assert_eq!(Language::German.text(Key::NoProblems), "keine Probleme gefunden");
+    }
+
+    #[test]
+    fn plain_text_is_english() {
+        let plain = Text::from("cannot add a number to a duration");
+        assert_eq!(Language::Swedish.show(&plain), "cannot add a number to a duration");
+        assert_eq!(plain.key(), None);
+        // even when it reads like a message that has a translation
+        assert_eq!(Language::Swedish.show(&"unknown variable: x".into()), "unknown variable: x");
+    }
+
+    #[test]
+    fn codes() {
+        assert_eq!(Language::from_code("sv_SE.UTF-8"), Some(Language::Swedish));
+        assert_eq!(Language::from_code("de"), Some(Language::German));
+        assert_eq!(Language::from_code("C"), None);
+    }
+}
@@ -0,0 +1,58 @@
+//! Recognizes results that are simple multiples of well-known constants, like `π/2`.
+
+use std::f64::consts::{E, PI, SQRT_2};
+
+const CONSTANTS: [(f64, &str); 5] = [(1.0, ""), (PI, "π"), (E, "e"), (SQRT_2, "√2"), (1.732050807568877, "√3")];
+
+/// The largest denominator that is tried.
+const MAX_DENOMINATOR: i64 = 12;
+
+/// Finds a fraction or simple multiple of a constant that is `x`, within rounding errors.
+/// Integers are not identified, since they are already as simple as they get.
+pub fn identify(x: f64) -> Option<String> {
+    if !x.is_finite() || x.fract() == 0.0 {
+        return None;
+    }
+    for (c, name) in CONSTANTS {
+        let ratio = x / c;
+        for q in 1..=MAX_DENOMINATOR {
+            let p = (ratio * q as f64).round();
+            if p == 0.0 || p.abs() > 1000.0 || (ratio - p / q as f64).abs() > 1e-9 * ratio.abs() {
+                continue;
+            }
+            let p = p as i64;
+            let numerator = match (p, name) {
+                (1, n) if !n.is_empty() => String::new(),
+                (-1, n) if !n.is_empty() => "-".to_string(),
+                _ => p.to_string(),
+            };
+            let denominator = if q == 1 { String::new() } else { format!("/{q}") };
+            return Some(format!("{numerator}{name}{denominator}"));
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn constants() {
+        assert_eq!(identify(PI / 2.0), Some("π/2".into()));
+        assert_eq!(identify(-3.0 * PI / 4.0), Some("-3π/4".into()));
+        assert_eq!(identify(2.0 * E), Some("2e".into()));
+        assert_eq!(identify(2.0f64.sqrt() / 2.0), Some("√2/2".into()));
+        assert_eq!(identify(3.0f64.sqrt()), Some("√3".into()));
+        assert_eq!(identify(0.1 + 0.2), Some("3/10".into()));
+        assert_eq!(identify(-7.0 / 12.0), Some("-7/12".into()));
+    }
+
+    #[test]
+    fn unknown() {
+        assert_eq!(identify(3.0), None);
+        assert_eq!(identify(0.123456789), None);
+        assert_eq!(identify(f64::NAN), None);
+        assert_eq!(identify(1.0 / 13.0), None);
+    }
+}
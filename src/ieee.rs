@@ -0,0 +1,229 @@
+//! How `f64` numbers are stored in IEEE 754, for investigating rounding surprises like
+//! `0.1 + 0.2 != 0.3`.
+
+const MANTISSA_BITS: u32 = 52;
+const EXPONENT_BIAS: i32 = 1023;
+/// The power of 2 of the lowest bit of subnormal numbers.
+const MIN_POWER: i64 = -1074;
+
+/// The sign, exponent and mantissa fields of `x`, as rows of their name, their bits in binary
+/// and hex, and what they mean.
+pub fn fields(x: f64) -> Vec<Vec<String>> {
+    let bits = x.to_bits();
+    let sign = bits >> 63;
+    let exponent = (bits >> MANTISSA_BITS) & 0x7FF;
+    let mantissa = bits & ((1 << MANTISSA_BITS) - 1);
+    let scale = match exponent {
+        0 => format!("2^{} (subnormal)", 1 - EXPONENT_BIAS),
+        0x7FF if mantissa == 0 => "infinity".to_string(),
+        0x7FF => "NaN".to_string(),
+        _ => format!("2^{}", exponent as i32 - EXPONENT_BIAS),
+    };
+    // the implicit leading 1 of normal numbers
+    let leading = if exponent == 0 { 0.0 } else { 1.0 };
+    let fraction = f64::from_bits((EXPONENT_BIAS as u64) << MANTISSA_BITS | mantissa) - 1.0;
+    vec![
+        vec!["sign".into(), sign.to_string(), format!("0x{sign:X}"), if sign == 1 { "-" } else { "+" }.into()],
+        vec!["exponent".into(), format!("{exponent:011b}"), format!("0x{exponent:03X}"), scale],
+        vec![
+            "mantissa".into(),
+            format!("{mantissa:052b}"),
+            format!("0x{mantissa:013X}"),
+            exact(leading + fraction),
+        ],
+    ]
+}
+
+/// The fields of [`fields`] on one line, like
+/// `sign 0 (+), exponent 01111111011 = 0x3FB (2^-4), mantissa 1001…1010 = 0x999999999999A (1.6…)`.
+pub fn describe(x: f64) -> String {
+    let fields: Vec<String> = fields(x)
+        .into_iter()
+        .map(|field| match &field[..] {
+            [name, bits, _, value] if name == "sign" => format!("{name} {bits} ({value})"),
+            [name, bits, hex, value] => format!("{name} {bits} = {hex} ({value})"),
+            _ => unreachable!(),
+        })
+        .collect();
+    fields.join(", ")
+}
+
+/// All the decimals of `x`, like `0.1000000000000000055511151231257827021181583404541015625`
+/// for `0.1`. Every `f64` has a finite decimal expansion.
+pub fn exact(x: f64) -> String {
+    if !x.is_finite() {
+        return x.to_string();
+    }
+    // the smallest subnormal number is 2^-1074, which has as many decimals
+    let s = format!("{x:.1074}");
+    s.trim_end_matches('0').trim_end_matches('.').to_string()
+}
+
+/// The power of 2 that `x` is scaled by, so that `x / 2^exponent` is at least 1 and less than 2
+/// in magnitude. Subnormal numbers get exponents below -1022 too. Zero has no exponent.
+pub fn exponent(x: f64) -> Option<i32> {
+    if x == 0.0 || !x.is_finite() {
+        return None;
+    }
+    let bits = x.to_bits();
+    let exponent = ((bits >> MANTISSA_BITS) & 0x7FF) as i32;
+    if exponent == 0 {
+        let highest = 63 - (bits & ((1 << MANTISSA_BITS) - 1)).leading_zeros() as i32;
+        return Some(highest + 1 - EXPONENT_BIAS - MANTISSA_BITS as i32);
+    }
+    Some(exponent - EXPONENT_BIAS)
+}
+
+/// `x / 2^exponent(x)`, which keeps the sign of `x`, and is 0 for 0.
+pub fn mantissa(x: f64) -> Option<f64> {
+    match exponent(x) {
+        Some(exponent) => Some(ldexp(x, -exponent as i64)),
+        None => x.is_finite().then_some(x),
+    }
+}
+
+/// `m * 2^e`, rounded once, even when `2^e` itself is not an `f64`.
+pub fn ldexp(m: f64, e: i64) -> f64 {
+    if m == 0.0 || !m.is_finite() {
+        return m;
+    }
+    let bits = m.to_bits();
+    let exponent = ((bits >> MANTISSA_BITS) & 0x7FF) as i64;
+    let fraction = bits & ((1 << MANTISSA_BITS) - 1);
+    // m is the integer `significand * 2^power`
+    let (significand, power) = match exponent {
+        0 => (fraction, MIN_POWER),
+        _ => (fraction | 1 << MANTISSA_BITS, exponent + MIN_POWER - 1),
+    };
+    let power = power.saturating_add(e);
+    let sign = m.signum();
+    if power > EXPONENT_BIAS as i64 {
+        return sign * f64::INFINITY;
+    }
+    if power >= MIN_POWER {
+        return sign * significand as f64 * pow2(power);
+    }
+    // the bits below 2^-1074 are rounded off, to even on ties
+    let shift = MIN_POWER - power;
+    if shift > MANTISSA_BITS as i64 + 1 {
+        return sign * 0.0;
+    }
+    let (kept, rest, half) = (significand >> shift, significand & ((1 << shift) - 1), 1 << (shift - 1));
+    let rounded = if rest > half || rest == half && kept & 1 == 1 { kept + 1 } else { kept };
+    sign * rounded as f64 * pow2(MIN_POWER)
+}
+
+/// 2^n for n from -1074 to 1023.
+fn pow2(n: i64) -> f64 {
+    if n > -EXPONENT_BIAS as i64 {
+        f64::from_bits(((n + EXPONENT_BIAS as i64) as u64) << MANTISSA_BITS)
+    } else {
+        f64::from_bits(1 << (n - MIN_POWER))
+    }
+}
+
+/// The distance from `x` to the next `f64` away from zero, or toward zero for the largest one.
+pub fn ulp(x: f64) -> f64 {
+    let x = x.abs();
+    if x == f64::MAX {
+        x - x.next_down()
+    } else {
+        x.next_up() - x
+    }
+}
+
+/// The next `f64` after `x` in the direction of `toward`, like C's `nextafter`.
+pub fn next_after(x: f64, toward: f64) -> f64 {
+    if x.is_nan() || toward.is_nan() {
+        f64::NAN
+    } else if x == toward {
+        toward
+    } else if x < toward {
+        x.next_up()
+    } else {
+        x.next_down()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn field_bits() {
+        let one_tenth = fields(0.1);
+        assert_eq!(one_tenth[0], ["sign", "0", "0x0", "+"]);
+        assert_eq!(one_tenth[1], ["exponent", "01111111011", "0x3FB", "2^-4"]);
+        assert_eq!(one_tenth[2][1], "1001100110011001100110011001100110011001100110011010");
+        assert_eq!(one_tenth[2][2], "0x999999999999A");
+        assert_eq!(one_tenth[2][3], "1.600000000000000088817841970012523233890533447265625");
+        assert_eq!(fields(-2.0)[0][3], "-");
+        assert_eq!(fields(-2.0)[2][3], "1");
+        assert_eq!(fields(5e-324)[1][3], "2^-1022 (subnormal)");
+        assert_eq!(fields(5e-324)[2][2], "0x0000000000001");
+        assert_eq!(fields(f64::INFINITY)[1][3], "infinity");
+        assert_eq!(fields(f64::NAN)[1][3], "NaN");
+    }
+
+    #[test]
+    fn described() {
+        assert_eq!(
+            describe(-2.0),
+            "sign 1 (-), exponent 10000000000 = 0x400 (2^1), mantissa 0000000000000000000000000000000000000000000000000000 = 0x0000000000000 (1)"
+        );
+    }
+
+    #[test]
+    fn exact_decimals() {
+        assert_eq!(exact(0.1), "0.1000000000000000055511151231257827021181583404541015625");
+        assert_eq!(exact(-2.5), "-2.5");
+        assert_eq!(exact(1e20), "100000000000000000000");
+        assert_eq!(exact(5e-324).len(), 1076);
+        assert_eq!(exact(f64::NEG_INFINITY), "-inf");
+    }
+
+    #[test]
+    fn decompositions() {
+        assert_eq!((mantissa(0.1), exponent(0.1)), (Some(1.6), Some(-4)));
+        assert_eq!((mantissa(-12.0), exponent(-12.0)), (Some(-1.5), Some(3)));
+        assert_eq!((mantissa(5e-324), exponent(5e-324)), (Some(1.0), Some(-1074)));
+        assert_eq!(exponent(f64::MAX), Some(1023));
+        assert_eq!((mantissa(0.0), exponent(0.0)), (Some(0.0), None));
+        assert_eq!((mantissa(f64::INFINITY), exponent(f64::NAN)), (None, None));
+        for x in [0.1, -12.0, 5e-324, 3e-310, f64::MAX, f64::MIN_POSITIVE] {
+            assert_eq!(ldexp(mantissa(x).unwrap(), exponent(x).unwrap() as i64), x);
+        }
+    }
+
+    #[test]
+    fn scaling() {
+        assert_eq!(ldexp(1.6, -4), 0.1);
+        assert_eq!(ldexp(1.0, 1024), f64::INFINITY);
+        assert_eq!(ldexp(1.0, -1074), 5e-324);
+        assert_eq!(ldexp(1.0, -1075), 0.0);
+        assert_eq!(ldexp(1.5, -1074), 1e-323);
+        assert_eq!(ldexp(0.75, 1024), 0.75 * 2f64.powi(1023) * 2.0);
+        assert_eq!(ldexp(-3.0, -1075), -1e-323);
+        assert_eq!(ldexp(1e-320, 100), 1e-320 * 2f64.powi(100));
+        assert_eq!(ldexp(1.0, i64::MIN), 0.0);
+        assert_eq!(ldexp(0.0, i64::MAX), 0.0);
+    }
+
+    #[test]
+    fn units_in_the_last_place() {
+        assert_eq!(ulp(1.0), f64::EPSILON);
+        assert_eq!(ulp(-1.0), f64::EPSILON);
+        assert_eq!(ulp(0.0), 5e-324);
+        assert_eq!(ulp(f64::MAX), 2f64.powi(971));
+        assert!(ulp(f64::NAN).is_nan());
+    }
+
+    #[test]
+    fn next_floats() {
+        assert_eq!(next_after(1.0, 2.0), 1.0000000000000002);
+        assert_eq!(next_after(1.0, 0.0), 0.9999999999999999);
+        assert_eq!(next_after(0.0, 1.0), 5e-324);
+        assert_eq!(next_after(3.0, 3.0), 3.0);
+        assert!(next_after(f64::NAN, 1.0).is_nan());
+    }
+}
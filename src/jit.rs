@@ -0,0 +1,228 @@
+use crate::i18n::{Key, Text};
+use crate::repr::Expr;
+use crate::vm::Program;
+
+/// A [`Program`] prepared for fast repeated evaluation.
+///
+/// With the `jit` feature the program is compiled to native code using Cranelift. When the
+/// feature is off, or when native compilation fails for some reason, the VM is used instead.
+pub enum Executable {
+    Vm(Program),
+    // boxed, since the module that owns the code is large
+    #[cfg(feature = "jit")]
+    Native(Box<native::Function>),
+}
+
+impl Executable {
+    pub fn new(program: Program) -> Self {
+        #[cfg(feature = "jit")]
+        if let Ok(f) = native::Function::compile(&program) {
+            return Executable::Native(Box::new(f));
+        }
+        Executable::Vm(program)
+    }
+
+    // only checked by tests so far
+    #[allow(dead_code)]
+    pub fn is_native(&self) -> bool {
+        !matches!(self, Executable::Vm(_))
+    }
+
+    pub fn run(&self, args: &[f64]) -> Result<f64, String> {
+        match self {
+            Executable::Vm(p) => p.run(args),
+            #[cfg(feature = "jit")]
+            Executable::Native(f) => f.run(args),
+        }
+    }
+}
+
+/// An expression compiled for evaluating with rows of values, which have a value for each of
+/// its variables, one row at a time.
+pub struct Batch {
+    exe: Executable,
+    /// the position in a row of each variable slot
+    columns: Vec<usize>,
+}
+
+impl Batch {
+    pub fn run(&self, row: &[f64]) -> Result<f64, String> {
+        let args: Vec<f64> = self.columns.iter().map(|&i| row[i]).collect();
+        self.exe.run(&args)
+    }
+}
+
+impl Expr {
+    /// Compiles the expression for evaluating with rows of values, where each row has a value
+    /// for each of `vars` in the same order.
+    ///
+    /// The expression is compiled only once, and natively if possible, so this is much
+    /// faster than evaluating every row separately.
+    pub fn compile_batch(&self, vars: &[&str]) -> Result<Batch, String> {
+        let program = self.compile()?;
+        let columns = program
+            .vars()
+            .iter()
+            .map(|v| vars.iter().position(|w| w == v).ok_or_else(|| Text::new(Key::UnknownVariable, vec![v.into()])))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Batch {
+            exe: Executable::new(program),
+            columns,
+        })
+    }
+}
+
+#[cfg(feature = "jit")]
+mod native {
+    use cranelift_codegen::ir::{types, AbiParam, InstBuilder, MemFlags};
+    use cranelift_codegen::settings::{self, Configurable};
+    use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+    use cranelift_jit::{JITBuilder, JITModule};
+    use cranelift_module::{default_libcall_names, Linkage, Module};
+
+    use crate::repr::{Operator, UnaryOperator};
+    use crate::vm::{Instr, Program};
+
+    type Entry = unsafe extern "C" fn(*const f64) -> f64;
+
+    pub struct Function {
+        // owns the memory that `entry` points into
+        _module: JITModule,
+        entry: Entry,
+        num_vars: usize,
+    }
+
+    impl Function {
+        pub fn compile(program: &Program) -> Result<Self, String> {
+            let mut flags = settings::builder();
+            flags.set("use_colocated_libcalls", "false").map_err(|e| e.to_string())?;
+            flags.set("is_pic", "false").map_err(|e| e.to_string())?;
+            flags.set("opt_level", "speed").map_err(|e| e.to_string())?;
+            let isa = cranelift_native::builder()?
+                .finish(settings::Flags::new(flags))
+                .map_err(|e| e.to_string())?;
+            let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+
+            let mut ctx = module.make_context();
+            let ptr = module.target_config().pointer_type();
+            ctx.func.signature.params.push(AbiParam::new(ptr));
+            ctx.func.signature.returns.push(AbiParam::new(types::F64));
+
+            let mut fctx = FunctionBuilderContext::new();
+            let mut b = FunctionBuilder::new(&mut ctx.func, &mut fctx);
+            let block = b.create_block();
+            b.append_block_params_for_function_params(block);
+            b.switch_to_block(block);
+            b.seal_block(block);
+            let args = b.block_params(block)[0];
+
+            let mut stack = Vec::new();
+            for instr in program.instructions() {
+                let v = match *instr {
+                    Instr::Push(f) => b.ins().f64const(f),
+                    Instr::Load(slot) => b.ins().load(types::F64, MemFlags::trusted(), args, slot as i32 * 8),
+                    Instr::Unary(op) => {
+                        let x = stack.pop().ok_or("missing operand")?;
+                        match op {
+                            UnaryOperator::Neg => b.ins().fneg(x),
+                        }
+                    }
+                    Instr::Binary(op) => {
+                        let y = stack.pop().ok_or("missing operand")?;
+                        let x = stack.pop().ok_or("missing operand")?;
+                        match op {
+                            Operator::Add => b.ins().fadd(x, y),
+                            Operator::Sub => b.ins().fsub(x, y),
+                            Operator::Mul => b.ins().fmul(x, y),
+                            Operator::Div => b.ins().fdiv(x, y),
+                            // these are left to the VM for now
+                            _ => return Err(format!("unsupported operator: {op:?}")),
+                        }
+                    }
+                    Instr::Call(..) => return Err("function calls are not supported".into()),
+                    Instr::JumpIfZero(_) | Instr::Jump(_) => return Err("jumps are not supported".into()),
+                };
+                stack.push(v);
+            }
+            let res = stack.pop().ok_or("empty program")?;
+            b.ins().return_(&[res]);
+            b.finalize();
+
+            let id = module
+                .declare_function("expr", Linkage::Export, &ctx.func.signature)
+                .map_err(|e| e.to_string())?;
+            module.define_function(id, &mut ctx).map_err(|e| e.to_string())?;
+            module.clear_context(&mut ctx);
+            module.finalize_definitions().map_err(|e| e.to_string())?;
+
+            let code = module.get_finalized_function(id);
+            // SAFETY: the function was declared with exactly this signature above
+            let entry = unsafe { std::mem::transmute::<*const u8, Entry>(code) };
+            Ok(Function {
+                _module: module,
+                entry,
+                num_vars: program.vars().len(),
+            })
+        }
+
+        pub fn run(&self, args: &[f64]) -> Result<f64, String> {
+            if args.len() != self.num_vars {
+                return Err(format!("expected {} variable values, got {}", self.num_vars, args.len()));
+            }
+            // SAFETY: the generated code only reads `num_vars` values from the pointer
+            Ok(unsafe { (self.entry)(args.as_ptr()) })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    #[test]
+    fn same_results_as_vm() {
+        for input in ["1 + 2 * 3 - 4 / 5", "(x - y) / x", "x * x * x - 1 / y", "-(x - y)"] {
+            let p = parse_line(input).unwrap().compile().unwrap();
+            let e = Executable::new(p.clone());
+            assert_eq!(e.is_native(), cfg!(feature = "jit"));
+            for args in [[4.0, 1.0], [2.0, 0.0], [-1.5, 3.0]] {
+                let args = &args[..p.vars().len()];
+                assert_eq!(e.run(args), p.run(args), "input was {input:?}, args were {args:?}");
+            }
+            assert!(e.run(&[1.0, 2.0, 3.0]).is_err());
+        }
+    }
+
+    #[test]
+    fn falls_back_to_vm() {
+        let e = Executable::new(parse_line("sqrt(x) ^ 2").unwrap().compile().unwrap());
+        assert!(!e.is_native());
+        assert_eq!(e.run(&[4.0]), Ok(4.0));
+    }
+
+    #[test]
+    fn batch() {
+        let evaluate = |e: &Expr, vars: &[&str], rows: &[Vec<f64>]| {
+            let batch = e.compile_batch(vars)?;
+            Ok::<_, String>(rows.iter().map(|row| batch.run(row)).collect::<Vec<_>>())
+        };
+        let e = parse_line("x * x - 1").unwrap();
+        let rows = [0.0, 1.0, 2.0, -3.0].map(|x| vec![x]);
+        assert_eq!(evaluate(&e, &["x"], &rows), Ok(vec![Ok(-1.0), Ok(0.0), Ok(3.0), Ok(8.0)]));
+        assert_eq!(evaluate(&e, &["x"], &[]), Ok(vec![]));
+        assert_eq!(evaluate(&e, &["y"], &[vec![1.0]]), Err("unknown variable: x".into()));
+
+        let e = parse_line("2 / 4").unwrap();
+        assert_eq!(evaluate(&e, &["x"], &[vec![1.0], vec![2.0]]), Ok(vec![Ok(0.5), Ok(0.5)]));
+
+        let e = parse_line("y - max(x, 2) ^ 2").unwrap();
+        let rows = [vec![1.0, 10.0], vec![3.0, 0.0]];
+        assert_eq!(evaluate(&e, &["x", "y"], &rows), Ok(vec![Ok(6.0), Ok(-9.0)]));
+
+        let e = parse_line("if(x, 1, assert(0))").unwrap();
+        let rows = [vec![1.0], vec![0.0]];
+        assert_eq!(evaluate(&e, &["x"], &rows), Ok(vec![Ok(1.0), Err("assertion failed".into())]));
+    }
+}
@@ -0,0 +1,400 @@
+//! A small JSON reader and writer, used for exchanging expression trees with other tools.
+//!
+//! The tree format is:
+//! - a number literal is a JSON number, or one of the strings `"inf"`, `"-inf"` and `"NaN"`
+//! - a boolean literal is `true` or `false`
+//! - a variable is `{"var": "x"}`
+//! - a unary operation is `{"op": "neg", "arg": ...}`
+//! - a binary operation is `{"op": "add", "lhs": ..., "rhs": ...}`, where the operator
+//!   is one of `add`, `sub`, `mul`, `div`, `pow`, `lt`, `le`, `gt`, `ge`, `eq` and `ne`
+//! - a function call is `{"call": "max", "args": [...]}`
+
+use std::fmt::{self, Write};
+
+use crate::repr::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    pub fn get(&self, key: &str) -> Option<&Json> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+
+    pub fn parse(input: &str) -> Result<Json, String> {
+        let mut r = Reader(input);
+        let v = r.value()?;
+        r.spaces();
+        if !r.0.is_empty() {
+            return Err(format!("unexpected trailing characters: {:?}", r.0));
+        }
+        Ok(v)
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => f.write_str("null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(s, f),
+            Json::Array(items) => {
+                f.write_char('[')?;
+                for (i, v) in items.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write!(f, "{v}")?;
+                }
+                f.write_char(']')
+            }
+            Json::Object(fields) => {
+                f.write_char('{')?;
+                for (i, (k, v)) in fields.iter().enumerate() {
+                    if i != 0 {
+                        f.write_char(',')?;
+                    }
+                    write_string(k, f)?;
+                    write!(f, ":{v}")?;
+                }
+                f.write_char('}')
+            }
+        }
+    }
+}
+
+fn write_string(s: &str, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_char('"')?;
+    for c in s.chars() {
+        match c {
+            '"' => f.write_str("\\\"")?,
+            '\\' => f.write_str("\\\\")?,
+            '\n' => f.write_str("\\n")?,
+            '\r' => f.write_str("\\r")?,
+            '\t' => f.write_str("\\t")?,
+            c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+            c => f.write_char(c)?,
+        }
+    }
+    f.write_char('"')
+}
+
+struct Reader<'s>(&'s str);
+
+impl Reader<'_> {
+    fn spaces(&mut self) {
+        self.0 = self.0.trim_start_matches([' ', '\t', '\n', '\r']);
+    }
+
+    fn expect(&mut self, s: &str) -> Result<(), String> {
+        self.spaces();
+        match self.0.strip_prefix(s) {
+            Some(rest) => {
+                self.0 = rest;
+                Ok(())
+            }
+            None => Err(format!("expected {s:?} at {:?}", self.0)),
+        }
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        self.spaces();
+        match self.0.chars().next() {
+            Some('n') => self.expect("null").map(|_| Json::Null),
+            Some('t') => self.expect("true").map(|_| Json::Bool(true)),
+            Some('f') => self.expect("false").map(|_| Json::Bool(false)),
+            Some('"') => self.string().map(Json::String),
+            Some('[') => {
+                self.expect("[")?;
+                let mut items = Vec::new();
+                self.spaces();
+                if self.expect("]").is_err() {
+                    loop {
+                        items.push(self.value()?);
+                        if self.expect(",").is_err() {
+                            self.expect("]")?;
+                            break;
+                        }
+                    }
+                }
+                Ok(Json::Array(items))
+            }
+            Some('{') => {
+                self.expect("{")?;
+                let mut fields = Vec::new();
+                if self.expect("}").is_err() {
+                    loop {
+                        self.spaces();
+                        let k = self.string()?;
+                        self.expect(":")?;
+                        fields.push((k, self.value()?));
+                        if self.expect(",").is_err() {
+                            self.expect("}")?;
+                            break;
+                        }
+                    }
+                }
+                Ok(Json::Object(fields))
+            }
+            Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+            _ => Err(format!("expected a value at {:?}", self.0)),
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, String> {
+        let len = self
+            .0
+            .find(|c: char| !(c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E')))
+            .unwrap_or(self.0.len());
+        let (s, rest) = self.0.split_at(len);
+        let n = s.parse().map_err(|_| format!("invalid number: {s:?}"))?;
+        self.0 = rest;
+        Ok(Json::Number(n))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect("\"")?;
+        let mut res = String::new();
+        let mut chars = self.0.char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.0 = &self.0[i + 1..];
+                    return Ok(res);
+                }
+                '\\' => match chars.next().map(|(_, c)| c) {
+                    Some('"') => res.push('"'),
+                    Some('\\') => res.push('\\'),
+                    Some('/') => res.push('/'),
+                    Some('b') => res.push('\u{8}'),
+                    Some('f') => res.push('\u{c}'),
+                    Some('n') => res.push('\n'),
+                    Some('r') => res.push('\r'),
+                    Some('t') => res.push('\t'),
+                    Some('u') => {
+                        let hex: String = (0..4).filter_map(|_| chars.next().map(|(_, c)| c)).collect();
+                        let code = u32::from_str_radix(&hex, 16).map_err(|_| format!("invalid escape: \\u{hex}"))?;
+                        res.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    }
+                    _ => return Err("invalid escape in string".into()),
+                },
+                c => res.push(c),
+            }
+        }
+        Err("unterminated string".into())
+    }
+}
+
+fn operator_name(op: Operator) -> &'static str {
+    match op {
+        Operator::Add => "add",
+        Operator::Sub => "sub",
+        Operator::Mul => "mul",
+        Operator::Div => "div",
+        Operator::Pow => "pow",
+        Operator::Lt => "lt",
+        Operator::Le => "le",
+        Operator::Gt => "gt",
+        Operator::Ge => "ge",
+        Operator::Eq => "eq",
+        Operator::Ne => "ne",
+    }
+}
+
+impl Expr {
+    pub(crate) fn to_json(&self) -> Json {
+        self.node_to_json(self.root())
+    }
+
+    fn node_to_json(&self, id: NodeId) -> Json {
+        let object = |fields: Vec<(&str, Json)>| Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect());
+        match *self.node(id) {
+            Node::Float(f) if f.is_finite() => Json::Number(f),
+            Node::Float(f) => Json::String(f.to_string()),
+            Node::Bool(b) => Json::Bool(b),
+            Node::Var(ref name) => object(vec![("var", Json::String(name.clone()))]),
+            Node::Unary(UnaryOperator::Neg, a) => object(vec![
+                ("op", Json::String("neg".into())),
+                ("arg", self.node_to_json(a)),
+            ]),
+            Node::Op(op, a, b) => object(vec![
+                ("op", Json::String(operator_name(op).into())),
+                ("lhs", self.node_to_json(a)),
+                ("rhs", self.node_to_json(b)),
+            ]),
+            Node::Call(ref name, ref args) => object(vec![
+                ("call", Json::String(name.clone())),
+                ("args", Json::Array(args.iter().map(|&a| self.node_to_json(a)).collect())),
+            ]),
+            Node::Assign(ref name, a) => object(vec![
+                ("assign", Json::String(name.clone())),
+                ("value", self.node_to_json(a)),
+            ]),
+            Node::List(ref elements) => object(vec![(
+                "list",
+                Json::Array(elements.iter().map(|&e| self.node_to_json(e)).collect()),
+            )]),
+            Node::Range(a, b) => object(vec![("from", self.node_to_json(a)), ("to", self.node_to_json(b))]),
+            Node::Text(ref text) => object(vec![("text", Json::String(text.clone()))]),
+            Node::Lambda(ref params, body) => object(vec![
+                ("params", Json::Array(params.iter().map(|p| Json::String(p.clone())).collect())),
+                ("body", self.node_to_json(body)),
+            ]),
+        }
+    }
+
+    pub(crate) fn from_json(json: &Json) -> Result<Expr, String> {
+        let mut b = ExprBuilder::default();
+        let root = node_from_json(json, &mut b)?;
+        Ok(b.finish(root))
+    }
+}
+
+fn node_from_json(json: &Json, b: &mut ExprBuilder) -> Result<NodeId, String> {
+    let field = |key: &str| json.get(key).ok_or_else(|| format!("missing field {key:?} in {json}"));
+    match json {
+        Json::Number(f) => Ok(b.float(*f)),
+        Json::Bool(v) => Ok(b.bool(*v)),
+        Json::String(s) => match s.as_str() {
+            "inf" | "-inf" | "NaN" => Ok(b.float(s.parse().unwrap())),
+            _ => Err(format!("invalid number: {s:?}")),
+        },
+        Json::Object(_) if json.get("var").is_some() => match field("var")? {
+            Json::String(name) => Ok(b.var(name)),
+            v => Err(format!("invalid variable name: {v}")),
+        },
+        Json::Object(_) if json.get("call").is_some() => {
+            let (Json::String(name), Json::Array(args)) = (field("call")?, field("args")?) else {
+                return Err(format!("invalid function call: {json}"));
+            };
+            let args = args.iter().map(|a| node_from_json(a, b)).collect::<Result<_, _>>()?;
+            Ok(b.call(name, args))
+        }
+        Json::Object(_) if json.get("assign").is_some() => {
+            let Json::String(name) = field("assign")? else {
+                return Err(format!("invalid assignment: {json}"));
+            };
+            let value = node_from_json(field("value")?, b)?;
+            Ok(b.assign(name, value))
+        }
+        Json::Object(_) if json.get("list").is_some() => {
+            let Json::Array(elements) = field("list")? else {
+                return Err(format!("invalid list: {json}"));
+            };
+            let elements = elements.iter().map(|e| node_from_json(e, b)).collect::<Result<_, _>>()?;
+            Ok(b.list(elements))
+        }
+        Json::Object(_) if json.get("from").is_some() => {
+            let from = node_from_json(field("from")?, b)?;
+            let to = node_from_json(field("to")?, b)?;
+            Ok(b.range(from, to))
+        }
+        Json::Object(_) if json.get("text").is_some() => match field("text")? {
+            Json::String(text) => Ok(b.text(text)),
+            v => Err(format!("invalid text: {v}")),
+        },
+        Json::Object(_) if json.get("params").is_some() => {
+            let Json::Array(params) = field("params")? else {
+                return Err(format!("invalid function: {json}"));
+            };
+            let params = params
+                .iter()
+                .map(|p| match p {
+                    Json::String(name) => Ok(name.clone()),
+                    v => Err(format!("invalid parameter name: {v}")),
+                })
+                .collect::<Result<_, _>>()?;
+            let body = node_from_json(field("body")?, b)?;
+            Ok(b.lambda(params, body))
+        }
+        Json::Object(_) => {
+            let op = match field("op")? {
+                Json::String(op) => op.as_str(),
+                v => return Err(format!("invalid operator: {v}")),
+            };
+            if op == "neg" {
+                let a = node_from_json(field("arg")?, b)?;
+                return Ok(b.unary(UnaryOperator::Neg, a));
+            }
+            let op = Operator::ALL
+                .into_iter()
+                .find(|o| operator_name(*o) == op)
+                .ok_or_else(|| format!("unknown operator: {op:?}"))?;
+            let lhs = node_from_json(field("lhs")?, b)?;
+            let rhs = node_from_json(field("rhs")?, b)?;
+            Ok(b.op(op, lhs, rhs))
+        }
+        _ => Err(format!("not an expression: {json}")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    #[test]
+    fn parse_values() {
+        assert_eq!(
+            Json::parse(r#" {"a": [1, -2.5e1, true, null], "b\n": "x\"é"} "#),
+            Ok(Json::Object(vec![
+                (
+                    "a".into(),
+                    Json::Array(vec![Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Null])
+                ),
+                ("b\n".into(), Json::String("x\"é".into())),
+            ]))
+        );
+        assert_eq!(Json::parse("[]"), Ok(Json::Array(vec![])));
+        assert!(Json::parse("[1,]").is_err());
+        assert!(Json::parse("{} 1").is_err());
+        assert!(Json::parse("\"abc").is_err());
+    }
+
+    #[test]
+    fn write_values() {
+        let v = Json::parse(r#"{"a":[1,2.5,false],"b":"q\"\n"}"#).unwrap();
+        assert_eq!(v.to_string(), r#"{"a":[1,2.5,false],"b":"q\"\n"}"#);
+    }
+
+    #[test]
+    fn expr_round_trip() {
+        for input in [
+            "1 + 2 * 3",
+            "-(x - 1.5) / y",
+            "1 - -2",
+            "2 ^ x >= 1",
+            "max(pi(), sqrt(x), 3)", "true == (1 < 2)",
+            "reduce([1, 2], (a, b) -> a * b)",
+            "sum(1..x)",
+            "format(x, \"0.3f\")",
+        ] {
+            let e = parse_line(input).unwrap();
+            let json = Json::parse(&e.to_json().to_string()).unwrap();
+            assert_eq!(Expr::from_json(&json), Ok(e), "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn expr_from_json() {
+        let json = Json::parse(r#"{"op": "mul", "lhs": {"op": "neg", "arg": 2}, "rhs": {"var": "x"}}"#).unwrap();
+        assert_eq!(Expr::from_json(&json), parse_line("-(2) * x"));
+
+        let json = Json::parse(r#"{"op": "mod", "lhs": 1, "rhs": 2}"#).unwrap();
+        assert_eq!(Expr::from_json(&json), Err(r#"unknown operator: "mod""#.into()));
+        let json = Json::parse(r#"{"op": "add", "lhs": 1}"#).unwrap();
+        assert!(Expr::from_json(&json).is_err());
+    }
+}
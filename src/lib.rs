@@ -0,0 +1,80 @@
+//! Parsing and evaluating expressions like `2 * sqrt(x) + 1`, for programs that embed the
+//! calculator.
+//!
+//! ```
+//! use calculator::{parse_line, Expr, Operation, Operator};
+//!
+//! let e = parse_line("1 + 2 * 3").unwrap();
+//! assert_eq!(e.evaluate(), Ok(7.0));
+//!
+//! let e = Expr::from(Operation::new(Operator::Pow, [e, 2.0.into()]));
+//! assert_eq!(e.to_string(), "(1 + 2 * 3) ^ 2");
+//! assert_eq!(e.evaluate(), Ok(49.0));
+//! ```
+//!
+//! Errors point at the part of the input that failed:
+//!
+//! ```
+//! use calculator::{parse_line, Span};
+//!
+//! let error = parse_line("1 + 2 * x").unwrap().evaluate().unwrap_err();
+//! assert_eq!(error.message, "unknown variable: x");
+//! assert_eq!(error.span, Some(Span { start: 8, end: 9 }));
+//! ```
+
+pub use context::{Context, VariableResolver};
+pub use functions::Module;
+pub use generate::Generator;
+pub use i18n::{Language, Text};
+pub use number::{FixedWidth, IntType, Overflow};
+pub use parser::{parse_line, Syntax};
+pub use repr::{EvalError, Expr, Frame, Operation, Operator, Span};
+pub use stats::BINS_LIMIT;
+pub use theme::Theme;
+
+// the command line interface of the binary, with the files it reads
+pub mod cli;
+pub mod currency;
+pub mod history;
+
+mod cfrac;
+mod check;
+mod context;
+mod decimal;
+mod dot;
+mod equiv;
+mod eval;
+mod fft;
+mod format;
+mod functions;
+mod generate;
+mod i18n;
+mod identify;
+mod ieee;
+mod jit;
+mod json;
+mod lint;
+mod lsp;
+mod mathml;
+mod normalize;
+mod number;
+mod parser;
+mod poly;
+mod pretty;
+mod primes;
+mod profile;
+mod progress;
+mod random;
+mod rational;
+mod repr;
+mod rpn;
+mod server;
+mod solve;
+mod stats;
+mod stream;
+mod theme;
+mod tutorial;
+mod uncertainty;
+mod vm;
+mod warnings;
+mod websocket;
@@ -0,0 +1,212 @@
+//! Finds things in expressions that are probably not what was meant, or that could be
+//! simpler, like `x * 1`.
+
+use crate::repr::*;
+
+/// Something that could be written better, in the part of the input at `span`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Lint {
+    pub message: String,
+    pub span: Option<Span>,
+}
+
+impl Expr {
+    /// Lints the expression, which was parsed from `source`.
+    pub fn lint(&self, source: &str) -> Vec<Lint> {
+        // the parent of each node, and whether the node is its right operand
+        let mut parents = vec![None; self.nodes().len()];
+        for id in self.ids() {
+            match *self.node(id) {
+                Node::Unary(_, a) | Node::Assign(_, a) | Node::Lambda(_, a) => parents[a.index()] = Some((id, false)),
+                Node::Op(_, a, b) | Node::Range(a, b) => {
+                    parents[a.index()] = Some((id, false));
+                    parents[b.index()] = Some((id, true));
+                }
+                Node::Call(_, ref args) | Node::List(ref args) => {
+                    for &a in args {
+                        parents[a.index()] = Some((id, false));
+                    }
+                }
+                Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) => {}
+            }
+        }
+
+        let mut lints = Vec::new();
+        for id in self.ids() {
+            let node = self.node(id);
+            let mut lint = |message: &str| {
+                lints.push(Lint {
+                    message: message.into(),
+                    span: self.span(id),
+                })
+            };
+            let layers = self.span(id).map_or(0, |s| parentheses(&source[s.start..s.end]));
+            let needed = match parents[id.index()] {
+                Some((p, right)) => match *self.node(p) {
+                    Node::Op(op, _, _) => self.needs_parens(id, op, right),
+                    // `--x` looks like a mistake
+                    Node::Unary(..) => match *node {
+                        Node::Op(op, _, _) => op != Operator::Pow,
+                        _ => self.is_negated(id),
+                    },
+                    Node::Call(..)
+                    | Node::Assign(..)
+                    | Node::List(_)
+                    | Node::Range(..)
+                    | Node::Lambda(..)
+                    | Node::Float(_)
+                    | Node::Bool(_)
+                    | Node::Var(_)
+                    | Node::Text(_) => false,
+                },
+                None => false,
+            };
+            // only one layer is ever needed, like in `((1 + 2)) * 3`
+            if layers > needed as usize {
+                lint("these parentheses are not needed");
+            }
+            match *node {
+                Node::Unary(UnaryOperator::Neg, a) if self.is_negated(a) => lint("this negates twice"),
+                Node::Op(Operator::Mul, a, b) if self.is_literal(a, 1.0) || self.is_literal(b, 1.0) => {
+                    lint("multiplying by 1 does nothing")
+                }
+                Node::Op(Operator::Div, _, b) if self.is_literal(b, 0.0) => lint("this divides by 0"),
+                Node::Op(op, a, b) if op.precedence() == Operator::Eq.precedence() => {
+                    let always = if self.same(a, b) {
+                        Some(matches!(op, Operator::Eq | Operator::Le | Operator::Ge))
+                    } else if self.is_constant(a) && self.is_constant(b) {
+                        self.evaluate_at(id, &Default::default()).ok().map(|v| v != 0.0)
+                    } else {
+                        None
+                    };
+                    match always {
+                        Some(true) => lint("this comparison is always true"),
+                        Some(false) => lint("this comparison is always false"),
+                        None => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+        lints
+    }
+
+    fn is_negated(&self, id: NodeId) -> bool {
+        match *self.node(id) {
+            Node::Unary(UnaryOperator::Neg, _) => true,
+            Node::Float(v) => v.is_sign_negative(),
+            _ => false,
+        }
+    }
+
+    fn is_literal(&self, id: NodeId, value: f64) -> bool {
+        *self.node(id) == Node::Float(value)
+    }
+
+    /// Whether the subexpression has no variables or function calls.
+    fn is_constant(&self, id: NodeId) -> bool {
+        match *self.node(id) {
+            Node::Float(_) | Node::Bool(_) => true,
+            Node::Unary(_, a) => self.is_constant(a),
+            Node::Op(_, a, b) => self.is_constant(a) && self.is_constant(b),
+            Node::Var(_)
+            | Node::Call(..)
+            | Node::Assign(..)
+            | Node::List(_)
+            | Node::Range(..)
+            | Node::Text(_)
+            | Node::Lambda(..) => false,
+        }
+    }
+
+    /// Whether two subexpressions are written the same.
+    fn same(&self, a: NodeId, b: NodeId) -> bool {
+        match (self.node(a), self.node(b)) {
+            (Node::Unary(x, a), Node::Unary(y, b)) => x == y && self.same(*a, *b),
+            (Node::Op(x, a1, a2), Node::Op(y, b1, b2)) => x == y && self.same(*a1, *b1) && self.same(*a2, *b2),
+            (Node::Range(a1, a2), Node::Range(b1, b2)) => self.same(*a1, *b1) && self.same(*a2, *b2),
+            (Node::Lambda(x, a), Node::Lambda(y, b)) => x == y && self.same(*a, *b),
+            (Node::List(a), Node::List(b)) => a.len() == b.len() && a.iter().zip(b).all(|(&a, &b)| self.same(a, b)),
+            (Node::Assign(x, a), Node::Assign(y, b)) => x == y && self.same(*a, *b),
+            (Node::Call(f, a), Node::Call(g, b)) => {
+                f == g && a.len() == b.len() && a.iter().zip(b).all(|(&a, &b)| self.same(a, b))
+            }
+            (x, y) => x == y,
+        }
+    }
+}
+
+/// How many layers of parentheses that belong together `s` is in, like 2 for `((1 + 2))` and 0
+/// for `(1) + (2)`.
+fn parentheses(mut s: &str) -> usize {
+    let mut layers = 0;
+    while is_parenthesized(s) {
+        s = s[1..s.len() - 1].trim();
+        layers += 1;
+    }
+    layers
+}
+
+/// Whether `s` is in parentheses that belong together, unlike in `(1) + (2)`.
+fn is_parenthesized(s: &str) -> bool {
+    if !(s.starts_with('(') && s.ends_with(')')) {
+        return false;
+    }
+    let mut depth = 0;
+    for (i, c) in s.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            _ => {}
+        }
+        if depth == 0 {
+            return i == s.len() - 1;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    fn lint(input: &str) -> Vec<(String, String)> {
+        let e = parse_line(input).unwrap();
+        e.lint(input)
+            .into_iter()
+            .map(|l| (l.message, l.span.map_or(String::new(), |s| input[s.start..s.end].to_string())))
+            .collect()
+    }
+
+    fn found(message: &str, at: &str) -> Vec<(String, String)> {
+        vec![(message.into(), at.into())]
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(lint("1 + (2 * 3)"), found("these parentheses are not needed", "(2 * 3)"));
+        assert_eq!(lint("(x)"), found("these parentheses are not needed", "(x)"));
+        assert_eq!(lint("sqrt((x + 1))"), found("these parentheses are not needed", "(x + 1)"));
+        assert_eq!(lint("((1 + 2)) * 3"), found("these parentheses are not needed", "((1 + 2))"));
+        assert_eq!(lint("(( x ))"), found("these parentheses are not needed", "(( x ))"));
+        assert_eq!(lint("((x)) + ((1))").len(), 2);
+        assert!(lint("(1 + 2) * 3").is_empty());
+        assert_eq!(lint("(1) + (2) * x").len(), 2);
+        assert!(lint("-(x + 1) ^ 2").is_empty());
+        assert!(lint("(-2) ^ 2").is_empty());
+        assert!(lint("max(1, 2)").is_empty());
+    }
+
+    #[test]
+    fn mistakes() {
+        assert_eq!(lint("-(-x)"), found("this negates twice", "-(-x)"));
+        assert_eq!(lint("--2"), found("this negates twice", "--2"));
+        assert_eq!(lint("1 * x"), found("multiplying by 1 does nothing", "1 * x"));
+        assert_eq!(lint("x / 0"), found("this divides by 0", "x / 0"));
+        assert_eq!(lint("x + 1 <= x + 1"), found("this comparison is always true", "x + 1 <= x + 1"));
+        assert_eq!(lint("sin(x) != sin(x)"), found("this comparison is always false", "sin(x) != sin(x)"));
+        assert_eq!(lint("2 < 1"), found("this comparison is always false", "2 < 1"));
+        assert!(lint("x < x + 1").is_empty());
+        assert!(lint("x / 2 * 3").is_empty());
+    }
+}
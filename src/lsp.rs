@@ -0,0 +1,417 @@
+//! `lsp`, a language server for `.calc` scripts, which are run line by line like `run_script`
+//! does. It publishes errors and warnings as diagnostics, shows the type and value of the
+//! subexpression under the cursor on hover, and completes the names of functions and variables.
+//!
+//! Documents are synchronized in full on every change.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+
+use crate::check::Type;
+use crate::context::{self, Context};
+use crate::functions::{FUNCTIONS, METER};
+use crate::json::Json;
+use crate::number::F64;
+use crate::parser::{self, Syntax};
+use crate::repr::{Expr, NodeId, Span, Statement, Value};
+
+const ERROR: f64 = 1.0;
+const WARNING: f64 = 2.0;
+
+/// The kinds of completion items, as numbered by the protocol.
+const FUNCTION: f64 = 3.0;
+const VARIABLE: f64 = 6.0;
+
+pub struct Server {
+    documents: HashMap<String, String>,
+    /// what every document starts from, with the modules that are loaded
+    ctx: Context,
+}
+
+impl Server {
+    pub fn new(ctx: Context) -> Server {
+        Server {
+            documents: HashMap::new(),
+            ctx,
+        }
+    }
+
+    /// Handles one message of the client, and returns the messages to send back, or `None`
+    /// when the client asks the server to exit.
+    pub fn handle(&mut self, message: &Json) -> Option<Vec<Json>> {
+        let params = message.get("params").unwrap_or(&Json::Null);
+        let uri = || match params.get("textDocument").and_then(|d| d.get("uri")) {
+            Some(Json::String(uri)) => uri.clone(),
+            _ => String::new(),
+        };
+        let result = match message.get("method") {
+            Some(Json::String(method)) => match method.as_str() {
+                "initialize" => object([(
+                    "capabilities",
+                    object([
+                        ("textDocumentSync", Json::Number(1.0)),
+                        ("hoverProvider", Json::Bool(true)),
+                        ("completionProvider", object([])),
+                    ]),
+                )]),
+                "textDocument/didOpen" => {
+                    let text = params.get("textDocument").and_then(|d| d.get("text"));
+                    return Some(self.update(uri(), text));
+                }
+                "textDocument/didChange" => {
+                    let text = match params.get("contentChanges") {
+                        Some(Json::Array(changes)) => changes.last().and_then(|c| c.get("text")),
+                        _ => None,
+                    };
+                    return Some(self.update(uri(), text));
+                }
+                "textDocument/didClose" => {
+                    self.documents.remove(&uri());
+                    return Some(Vec::new());
+                }
+                "textDocument/hover" => {
+                    let (line, character) = position(params);
+                    match self.documents.get(&uri()) {
+                        Some(text) => self.hover(text, line, character),
+                        None => Json::Null,
+                    }
+                }
+                "textDocument/completion" => {
+                    let (line, _) = position(params);
+                    match self.documents.get(&uri()) {
+                        Some(text) => self.completion(text, line),
+                        None => Json::Array(Vec::new()),
+                    }
+                }
+                "shutdown" => Json::Null,
+                "exit" => return None,
+                // like `initialized`, or requests that the server does not support
+                _ if message.get("id").is_none() => return Some(Vec::new()),
+                _ => {
+                    let error = object([
+                        ("code", Json::Number(-32601.0)),
+                        ("message", Json::String(format!("unsupported method: {method}"))),
+                    ]);
+                    return Some(vec![response(message, "error", error)]);
+                }
+            },
+            // responses to requests of the server, which it does not make
+            _ => return Some(Vec::new()),
+        };
+        Some(vec![response(message, "result", result)])
+    }
+
+    /// Stores the new text of a document, and returns its diagnostics.
+    fn update(&mut self, uri: String, text: Option<&Json>) -> Vec<Json> {
+        let Some(Json::String(text)) = text else {
+            return Vec::new();
+        };
+        let diagnostics = self.diagnostics(text);
+        self.documents.insert(uri.clone(), text.clone());
+        vec![object([
+            ("jsonrpc", Json::String("2.0".into())),
+            ("method", Json::String("textDocument/publishDiagnostics".into())),
+            (
+                "params",
+                object([("uri", Json::String(uri)), ("diagnostics", Json::Array(diagnostics))]),
+            ),
+        ])]
+    }
+
+    fn diagnostics(&self, text: &str) -> Vec<Json> {
+        let mut ctx = self.ctx.clone();
+        let mut res = Vec::new();
+        for (n, line) in statements(text) {
+            let whole = Span { start: 0, end: line.len() };
+            let diagnostic = |span: Span, severity, message: String| {
+                object([
+                    ("range", range(n, line, span)),
+                    ("severity", Json::Number(severity)),
+                    ("source", Json::String("calculator".into())),
+                    ("message", Json::String(message)),
+                ])
+            };
+            let statement = match parser::parse_statement(line, Syntax::Native) {
+                Ok(statement) => statement,
+                Err(err) => {
+                    res.push(diagnostic(whole, ERROR, err.into()));
+                    continue;
+                }
+            };
+            for warning in statement.warnings(&ctx) {
+                res.push(diagnostic(whole, WARNING, warning));
+            }
+            if let Err(err) = ctx.run(&statement) {
+                res.push(diagnostic(err.span.unwrap_or(whole), ERROR, err.message.into()));
+            }
+        }
+        res
+    }
+
+    /// The context after running the lines before `line`.
+    fn context_at(&self, text: &str, line: usize) -> Context {
+        let mut ctx = self.ctx.clone();
+        for (_, line) in statements(text).take_while(|&(n, _)| n < line) {
+            if let Ok(statement) = parser::parse_statement(line, Syntax::Native) {
+                let _ = ctx.run(&statement);
+            }
+        }
+        ctx
+    }
+
+    fn hover(&self, text: &str, line: usize, character: usize) -> Json {
+        let Some(source) = text.lines().nth(line) else {
+            return Json::Null;
+        };
+        let e = match parser::parse_statement(source, Syntax::Native) {
+            Ok(Statement::Expr(e) | Statement::Assign(_, e)) => e,
+            _ => return Json::Null,
+        };
+        let ctx = self.context_at(text, line);
+        let offset = byte_offset(source, character);
+        // the smallest subexpression under the cursor
+        let Some((id, span)) = e
+            .ids()
+            .filter_map(|id| Some((id, e.span(id)?)))
+            .filter(|(_, span)| span.start <= offset && offset < span.end)
+            .min_by_key(|(_, span)| span.end - span.start)
+        else {
+            return Json::Null;
+        };
+        let value = match e.check_at(id, &ctx) {
+            // lists and text
+            Ok(t) if !t.is_scalar() => e
+                .evaluate_items(id, &ctx, &F64, Vec::new())
+                .map(|(v, _)| format!("{t} = {}", v.into_value(&F64))),
+            Ok(t) => e.evaluate_at(id, &ctx).map(|x| format!("{t} = {}", value(&e, id, t, x, &ctx.rates().base))),
+            Err(err) => Err(err),
+        };
+        let value = value.unwrap_or_else(|err| err.message.into());
+        object([
+            (
+                "contents",
+                object([
+                    ("kind", Json::String("plaintext".into())),
+                    ("value", Json::String(format!("{}: {value}", &source[span.start..span.end]))),
+                ]),
+            ),
+            ("range", range(line, source, span)),
+        ])
+    }
+
+    fn completion(&self, text: &str, line: usize) -> Json {
+        let ctx = self.context_at(text, line);
+        let item = |label: &str, kind, detail: String| {
+            object([
+                ("label", Json::String(label.into())),
+                ("kind", Json::Number(kind)),
+                ("detail", Json::String(detail)),
+            ])
+        };
+        let builtins = FUNCTIONS.iter().filter(|f| ctx.is_loaded(f.module));
+        let mut items: Vec<Json> = builtins.map(|f| item(f.name, FUNCTION, f.signature())).collect();
+        items.extend(ctx.funcs().map(|(name, f)| item(name, FUNCTION, format!("{name}({})", f.params.join(", ")))));
+        items.extend(ctx.vars().map(|(name, x)| item(name, VARIABLE, x.to_string())));
+        Json::Array(items)
+    }
+}
+
+/// The lines of a script that are statements, numbered from 0.
+fn statements(text: &str) -> impl Iterator<Item = (usize, &str)> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+}
+
+/// The value `x` of the subexpression `id` of `e`, which has the type `t`.
+fn value(e: &Expr, id: NodeId, t: Type, x: f64, currency: &str) -> Value {
+    match t {
+        Type::Number => Value::Number(x),
+        Type::Boolean => Value::Boolean(x != 0.0),
+        Type::Duration => Value::Duration(x),
+        Type::Length => context::length(e, id, x),
+        Type::Speed => Value::Speed(x / METER),
+        Type::Money => Value::Money(x, currency.into()),
+        Type::List | Type::Text | Type::ComplexList => unreachable!("{t} is not a number"),
+    }
+}
+
+fn object<const N: usize>(fields: [(&str, Json); N]) -> Json {
+    Json::Object(fields.into_iter().map(|(k, v)| (k.to_string(), v)).collect())
+}
+
+fn response(request: &Json, key: &str, value: Json) -> Json {
+    object([
+        ("jsonrpc", Json::String("2.0".into())),
+        ("id", request.get("id").cloned().unwrap_or(Json::Null)),
+        (key, value),
+    ])
+}
+
+fn position(params: &Json) -> (usize, usize) {
+    let number = |key| match params.get("position").and_then(|p| p.get(key)) {
+        Some(Json::Number(n)) => *n as usize,
+        _ => 0,
+    };
+    (number("line"), number("character"))
+}
+
+/// Positions in the protocol count UTF-16 code units, while spans count bytes.
+fn range(line: usize, source: &str, span: Span) -> Json {
+    let character = |offset: usize| Json::Number(source[..offset].encode_utf16().count() as f64);
+    let position = |offset| object([("line", Json::Number(line as f64)), ("character", character(offset))]);
+    object([("start", position(span.start)), ("end", position(span.end))])
+}
+
+fn byte_offset(source: &str, character: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in source.char_indices() {
+        if units >= character {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+fn read_message(input: &mut impl BufRead) -> io::Result<Option<Json>> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                length = value.trim().parse().ok();
+            }
+        }
+    }
+    let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+    let mut body = vec![0; length.ok_or_else(|| invalid("missing Content-Length".into()))?];
+    input.read_exact(&mut body)?;
+    let body = String::from_utf8(body).map_err(|_| invalid("the message is not UTF-8".into()))?;
+    Json::parse(&body).map(Some).map_err(invalid)
+}
+
+fn write_message(output: &mut impl Write, message: &Json) -> io::Result<()> {
+    let body = message.to_string();
+    write!(output, "Content-Length: {}\r\n\r\n{body}", body.len())?;
+    output.flush()
+}
+
+/// Serves one client on `input` and `output` until it exits or closes the input.
+pub fn run(input: &mut impl BufRead, output: &mut impl Write, ctx: Context) -> io::Result<()> {
+    let mut server = Server::new(ctx);
+    while let Some(message) = read_message(input)? {
+        let Some(replies) = server.handle(&message) else {
+            break;
+        };
+        for reply in replies {
+            write_message(output, &reply)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///tmp/a.calc";
+
+    fn request(method: &str, params: Json) -> Json {
+        object([("id", Json::Number(1.0)), ("method", Json::String(method.into())), ("params", params)])
+    }
+
+    fn open(server: &mut Server, text: &str) -> Json {
+        let document = object([("uri", Json::String(URI.into())), ("text", Json::String(text.into()))]);
+        let replies = server.handle(&request("textDocument/didOpen", object([("textDocument", document)])));
+        replies.unwrap().remove(0)
+    }
+
+    fn at(method: &str, line: usize, character: usize) -> Json {
+        let position = object([("line", Json::Number(line as f64)), ("character", Json::Number(character as f64))]);
+        request(method, object([("textDocument", object([("uri", Json::String(URI.into()))])), ("position", position)]))
+    }
+
+    fn result(server: &mut Server, message: &Json) -> Json {
+        server.handle(message).unwrap().remove(0).get("result").unwrap().clone()
+    }
+
+    #[test]
+    fn diagnostics() {
+        let mut server = Server::new(Context::default());
+        let published = open(&mut server, "# a script\nx = 2\ny + 1\n0.1 + 0.2 == 0.3\n1 +");
+        let Some(Json::Array(diagnostics)) = published.get("params").and_then(|p| p.get("diagnostics")) else {
+            panic!("no diagnostics in {published}");
+        };
+        let summary: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                let start = d.get("range").and_then(|r| r.get("start")).unwrap();
+                (start.to_string(), d.get("severity").unwrap().to_string())
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            [
+                (r#"{"line":2,"character":0}"#.into(), "1".into()),
+                (r#"{"line":3,"character":0}"#.into(), "2".into()),
+                (r#"{"line":4,"character":0}"#.into(), "1".into()),
+            ]
+        );
+        assert_eq!(diagnostics[0].get("message"), Some(&Json::String("unknown variable: y".into())));
+    }
+
+    #[test]
+    fn hover() {
+        let mut server = Server::new(Context::default());
+        open(&mut server, "x = 2\ny = 3 * (x + 1) < 10");
+        let hover = result(&mut server, &at("textDocument/hover", 1, 10));
+        let contents = hover.get("contents").and_then(|c| c.get("value"));
+        assert_eq!(contents, Some(&Json::String("(x + 1): number = 3".into())));
+        let hover = result(&mut server, &at("textDocument/hover", 1, 17));
+        let contents = hover.get("contents").and_then(|c| c.get("value"));
+        assert_eq!(contents, Some(&Json::String("3 * (x + 1) < 10: boolean = true".into())));
+        assert_eq!(result(&mut server, &at("textDocument/hover", 5, 0)), Json::Null);
+    }
+
+    #[test]
+    fn completion() {
+        let mut server = Server::new(Context::default());
+        open(&mut server, "rate = 0.05\ntotal(n) = n * (1 + rate)\n\nlater = 1");
+        let Json::Array(items) = result(&mut server, &at("textDocument/completion", 2, 0)) else {
+            panic!("no completion items");
+        };
+        let labels: Vec<_> = items.iter().filter_map(|i| i.get("label")).map(|l| l.to_string()).collect();
+        assert!(labels.contains(&r#""sqrt""#.to_string()));
+        assert!(labels.contains(&r#""rate""#.to_string()));
+        assert!(labels.contains(&r#""total""#.to_string()));
+        assert!(!labels.contains(&r#""later""#.to_string()));
+        assert!(!labels.contains(&r#""pmt""#.to_string()));
+    }
+
+    #[test]
+    fn session() {
+        let input = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{}}"#,
+            r#"{"jsonrpc":"2.0","method":"initialized","params":{}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","method":"exit"}"#,
+        ]
+        .map(|body| format!("Content-Length: {}\r\n\r\n{body}", body.len()))
+        .concat();
+        let mut output = Vec::new();
+        run(&mut input.as_bytes(), &mut output, Context::default()).unwrap();
+
+        let output = String::from_utf8(output).unwrap();
+        assert!(output.starts_with("Content-Length: "));
+        assert!(output.contains(r#""id":1,"result":{"capabilities":{"textDocumentSync":1,"#));
+        assert!(output.ends_with(r#"{"jsonrpc":"2.0","id":2,"result":null}"#));
+    }
+}
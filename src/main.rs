@@ -1,11 +1,265 @@
-use std::io::{stdin, stdout};
+use std::io::{stderr, stdin, stdout, IsTerminal};
+use std::path::PathBuf;
+use std::process::exit;
+use std::time::{SystemTime, UNIX_EPOCH};
 
-mod cli;
-mod parser;
-mod repr;
+use calculator::{cli, currency, history};
+use calculator::{FixedWidth, Generator, IntType, Language, Module, Overflow, Syntax, Theme, BINS_LIMIT};
+
+const USAGE: &str = "usage: calculator [--compat excel|bc|python] [--no-env] [--load MODULE]... [--bases] [--twos-complement BITS] [--identify] [--repeating] [--roundoff] [--numbering] [--int u8|u16|u32|i32|i64] [--cfrac-terms N] [--recursion-limit N] [--prompt STR] [-W on|off] [--lang en|sv|de] [--grouping] [--no-history] [--plain] [--json-errors] [--theme default|high-contrast|monochrome] [--rates FILE] \
+                     [--from-ast | --to-ast | --stream | --filter EXPR [--delimiter CHAR] | -e LINE... [--each] \
+                     | watch FILE | test FILE | fmt [EXPR] | lint [EXPR] | equiv EXPR EXPR \
+                     | stats | hist [--bins N] | convert QUANTITY UNIT \
+                     | gen [--depth N] [--seed N] [--count N] [--values] | serve --ws [--addr ADDR] | lsp | tutorial]";
+
+enum Mode {
+    Repl,
+    Lines,
+    FromAst,
+    ToAst,
+    /// evaluates the whole input as one expression
+    Stream,
+    Filter(String),
+    Watch(PathBuf),
+    /// runs a script of assertions
+    Test(PathBuf),
+    /// formats the expression, or each line of the input without one
+    Fmt(Option<String>),
+    /// lints the expression, or each line of the input without one
+    Lint(Option<String>),
+    /// summarizes the numbers of the input
+    Stats,
+    /// draws a histogram of the numbers of the input
+    Hist,
+    Equiv(String, String),
+    /// converts a quantity to a unit
+    Convert(String, String),
+    Generate,
+    /// evaluates for WebSocket clients
+    Serve,
+    Lsp,
+    /// walks through lessons with tasks to do
+    Tutorial,
+}
+
+fn usage() -> ! {
+    eprintln!("{USAGE}");
+    exit(2);
+}
 
 fn main() {
     let mut stdin = stdin().lock();
     let mut stdout = stdout().lock();
-    cli::run_cli(&mut stdin, &mut stdout).unwrap()
+    // not locked, since the spinner of slow computations writes to it from another thread
+    let mut stderr = stderr();
+
+    let mut options = cli::Options {
+        // nobody is typing the input, or reading the output
+        prompt: stdin.is_terminal() && stdout.is_terminal(),
+        color: stdout.is_terminal(),
+        language: Language::from_env(),
+        progress: stderr.is_terminal(),
+        bracketed_paste: stdin.is_terminal() && stdout.is_terminal(),
+        // only what someone typed is worth recalling
+        history_file: if stdin.is_terminal() { history::default_path() } else { None },
+        // the built-in rates are used without the file, or the other way around
+        rates_file: currency::default_path().filter(|path| path.exists()),
+        ..cli::Options::default()
+    };
+    if let Some(path) = &options.rates_file {
+        match currency::load(path) {
+            Ok(rates) => options.rates = rates,
+            Err(err) => eprintln!("warning: {err}"),
+        }
+    }
+    let mut mode = Mode::Repl;
+    let mut delimiter = None;
+    let mut lines = Vec::new();
+    let mut each = false;
+    // for random expressions
+    let (mut depth, mut seed, mut count, mut values) = (4, None, 1, false);
+    // for histograms
+    let mut bins = cli::HIST_BINS;
+    // for serving
+    let (mut ws, mut addr) = (false, "127.0.0.1:8080".to_string());
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--compat" => {
+                options.syntax = match args.next().as_deref() {
+                    Some("excel") => Syntax::Excel,
+                    Some("bc") => Syntax::Bc { scale: 0 },
+                    Some("python") => Syntax::Python,
+                    _ => usage(),
+                }
+            }
+            "--from-ast" => mode = Mode::FromAst,
+            "--to-ast" => mode = Mode::ToAst,
+            "--stream" => mode = Mode::Stream,
+            "--filter" => mode = Mode::Filter(args.next().unwrap_or_else(|| usage())),
+            "--delimiter" => {
+                let d = args.next().unwrap_or_else(|| usage());
+                let mut chars = d.chars();
+                delimiter = match (chars.next(), chars.next()) {
+                    (Some(c), None) => Some(c),
+                    _ => usage(),
+                };
+            }
+            "-e" => {
+                lines.push(args.next().unwrap_or_else(|| usage()));
+                mode = Mode::Lines;
+            }
+            "--each" => each = true,
+            "watch" => mode = Mode::Watch(args.next().unwrap_or_else(|| usage()).into()),
+            "test" => mode = Mode::Test(args.next().unwrap_or_else(|| usage()).into()),
+            "fmt" => mode = Mode::Fmt(args.next()),
+            "lint" => mode = Mode::Lint(args.next()),
+            "stats" => mode = Mode::Stats,
+            "hist" => mode = Mode::Hist,
+            "--bins" => {
+                let n = args.next().and_then(|n| n.parse().ok()).filter(|n| (1..=BINS_LIMIT).contains(n));
+                bins = n.unwrap_or_else(|| usage());
+            }
+            "gen" => mode = Mode::Generate,
+            "serve" => mode = Mode::Serve,
+            "lsp" => mode = Mode::Lsp,
+            "tutorial" => mode = Mode::Tutorial,
+            "--ws" => ws = true,
+            "--addr" => addr = args.next().unwrap_or_else(|| usage()),
+            "--depth" => depth = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage()),
+            "--seed" => seed = Some(args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage())),
+            "--count" => count = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage()),
+            "--values" => values = true,
+            "equiv" => match (args.next(), args.next()) {
+                (Some(left), Some(right)) => mode = Mode::Equiv(left, right),
+                _ => usage(),
+            },
+            "convert" => match (args.next(), args.next()) {
+                (Some(quantity), Some(unit)) => mode = Mode::Convert(quantity, unit),
+                _ => usage(),
+            },
+            "--no-env" => options.env = false,
+            "--no-history" => options.history_file = None,
+            "--plain" => options.make_plain(),
+            "--json-errors" => options.json_errors = true,
+            "--rates" => {
+                let path = PathBuf::from(args.next().unwrap_or_else(|| usage()));
+                options.rates = currency::load(&path).unwrap_or_else(|err| {
+                    eprintln!("error: {err}");
+                    exit(2)
+                });
+                options.rates_file = Some(path);
+            }
+            "--theme" => options.theme = args.next().and_then(|name| Theme::from_name(&name)).unwrap_or_else(|| usage()),
+            "--lang" => {
+                options.language = args.next().and_then(|code| Language::from_code(&code)).unwrap_or_else(|| usage())
+            }
+            "--grouping" => options.grouping = true,
+            "--bases" => options.bases = true,
+            "--twos-complement" => {
+                let bits = args.next().and_then(|n| n.parse().ok()).filter(|n| [8, 16, 32, 64].contains(n));
+                options.twos_complement = Some(bits.unwrap_or_else(|| usage()));
+                options.bases = true;
+            }
+            "--identify" => options.identify = true,
+            "--repeating" => options.repeating = true,
+            "--roundoff" => options.roundoff = true,
+            "--numbering" => options.numbering = true,
+            "-W" => {
+                options.warnings = match args.next().as_deref() {
+                    Some("on") => true,
+                    Some("off") => false,
+                    _ => usage(),
+                }
+            }
+            "--prompt" => options.prompt_format = args.next().unwrap_or_else(|| usage()),
+            "--int" => {
+                let int = args.next().and_then(|name| IntType::from_name(&name)).unwrap_or_else(|| usage());
+                options.int = Some(FixedWidth { int, overflow: Overflow::Wrap });
+            }
+            "--cfrac-terms" => {
+                options.cfrac_terms = args.next().and_then(|n| n.parse().ok()).unwrap_or_else(|| usage());
+            }
+            "--recursion-limit" => {
+                let limit = args.next().and_then(|n| n.parse().ok()).filter(|&n| n >= 1);
+                options.recursion_limit = limit.unwrap_or_else(|| usage());
+            }
+            "--load" => {
+                let module = args.next().and_then(|m| Module::from_name(&m));
+                options.modules.push(module.unwrap_or_else(|| usage()));
+            }
+            _ => usage(),
+        }
+    }
+
+    let res = match mode {
+        // only a script that is piped in fails, not a session where someone saw the errors
+        Mode::Repl => match cli::run_cli(&mut stdin, &mut stdout, &options) {
+            Ok(false) if !options.prompt => exit(1),
+            res => res.map(|_| ()),
+        },
+        Mode::Lines => match cli::run_lines(&lines, each, &options, &mut stdout, &mut stderr) {
+            Ok(false) => exit(1),
+            res => res.map(|_| ()),
+        },
+        Mode::FromAst => cli::run_from_ast(&mut stdin, &mut stdout, &mut stderr),
+        Mode::ToAst => cli::run_to_ast(&mut stdin, &mut stdout, &mut stderr),
+        Mode::Stream => cli::run_stream(&mut stdin, &options, &mut stdout, &mut stderr),
+        Mode::Filter(expression) => {
+            match cli::run_filter(&expression, delimiter, &options, &mut stdin, &mut stdout, &mut stderr) {
+                Ok(false) => exit(1),
+                res => res.map(|_| ()),
+            }
+        }
+        Mode::Watch(path) => cli::watch(&path, &options, &mut stdout, &mut stderr),
+        Mode::Test(path) => {
+            let passed = std::fs::read_to_string(path)
+                .and_then(|source| cli::run_test(&source, &options, &mut stdout, &mut stderr));
+            match passed {
+                Ok(false) => exit(1),
+                res => res.map(|_| ()),
+            }
+        }
+        Mode::Fmt(expression) => {
+            let formatted = match expression {
+                Some(expression) => cli::run_fmt(&mut expression.as_bytes(), &options, &mut stdout, &mut stderr),
+                None => cli::run_fmt(&mut stdin, &options, &mut stdout, &mut stderr),
+            };
+            match formatted {
+                Ok(false) => exit(1),
+                res => res.map(|_| ()),
+            }
+        }
+        Mode::Lint(expression) => {
+            let clean = match expression {
+                Some(expression) => cli::run_lint(&mut expression.as_bytes(), &options, &mut stdout, &mut stderr),
+                None => cli::run_lint(&mut stdin, &options, &mut stdout, &mut stderr),
+            };
+            match clean {
+                Ok(false) => exit(1),
+                res => res.map(|_| ()),
+            }
+        }
+        Mode::Stats => cli::run_stats(&mut stdin, &mut stdout, &mut stderr),
+        Mode::Hist => cli::run_hist(bins, &mut stdin, &mut stdout, &mut stderr),
+        Mode::Equiv(left, right) => cli::run_equiv(&left, &right, &options, &mut stdout, &mut stderr),
+        Mode::Convert(quantity, unit) => match cli::run_convert(&quantity, &unit, &options, &mut stdout, &mut stderr) {
+            Ok(false) => exit(1),
+            res => res.map(|_| ()),
+        },
+        Mode::Generate => {
+            // different expressions every time, unless a seed is given
+            let seed = seed.unwrap_or_else(|| {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH);
+                now.map_or(0, |d| d.as_nanos() as u64)
+            });
+            cli::run_generate(&mut Generator::new(seed, depth), count, values, &mut stdout)
+        }
+        // WebSocket is the only protocol so far, but it is named so that others can be added
+        Mode::Serve if !ws => usage(),
+        Mode::Serve => cli::run_serve(&addr, &options, &mut stderr),
+        Mode::Lsp => cli::run_lsp(&mut stdin, &mut stdout, &options),
+        Mode::Tutorial => cli::run_tutorial(&mut stdin, &mut stdout, &options),
+    };
+    res.unwrap()
 }
@@ -0,0 +1,235 @@
+use std::fmt::Write;
+
+use crate::repr::*;
+
+const MATH_OPEN: &str = r#"<math xmlns="http://www.w3.org/1998/Math/MathML">"#;
+
+impl Expr {
+    /// Renders the expression as presentation MathML.
+    pub fn to_mathml(&self) -> String {
+        let mut out = String::from(MATH_OPEN);
+        self.mathml_node(self.root(), &mut out);
+        out.push_str("</math>");
+        out
+    }
+
+    /// Renders `expr = result` as presentation MathML.
+    pub fn to_mathml_equation(&self, result: f64) -> String {
+        let mut out = String::from(MATH_OPEN);
+        self.mathml_node(self.root(), &mut out);
+        out.push_str("<mo>=</mo>");
+        mathml_number(result, &mut out);
+        out.push_str("</math>");
+        out
+    }
+
+    fn mathml_node(&self, id: NodeId, out: &mut String) {
+        match *self.node(id) {
+            Node::Float(f) => mathml_number(f, out),
+            Node::Bool(b) => write!(out, "<mi>{b}</mi>").unwrap(),
+            Node::Var(ref name) => write!(out, "<mi>{name}</mi>").unwrap(),
+            Node::Unary(UnaryOperator::Neg, a) => {
+                out.push_str("<mrow><mo>&#x2212;</mo>");
+                let parens = matches!(self.node(a), Node::Op(..) | Node::Assign(..) | Node::Range(..) | Node::Lambda(..));
+                self.mathml_operand(a, parens, out);
+                out.push_str("</mrow>");
+            }
+            Node::Op(Operator::Div, a, b) => {
+                // the fraction bar already groups both operands
+                out.push_str("<mfrac>");
+                self.mathml_node(a, out);
+                self.mathml_node(b, out);
+                out.push_str("</mfrac>");
+            }
+            Node::Op(Operator::Pow, a, b) => {
+                // the superscript binds tighter than anything but atoms
+                let parens = match *self.node(a) {
+                    Node::Float(f) => f.is_sign_negative(),
+                    Node::Bool(_) | Node::Var(_) | Node::Call(..) | Node::List(_) | Node::Text(_) => false,
+                    Node::Unary(..) | Node::Op(..) | Node::Assign(..) | Node::Range(..) | Node::Lambda(..) => true,
+                };
+                out.push_str("<msup>");
+                self.mathml_operand(a, parens, out);
+                self.mathml_node(b, out);
+                out.push_str("</msup>");
+            }
+            Node::Op(op, a, b) => {
+                let symbol = match op {
+                    Operator::Add => "+",
+                    Operator::Sub => "&#x2212;",
+                    Operator::Mul => "&#xD7;",
+                    Operator::Lt => "&lt;",
+                    Operator::Le => "&#x2264;",
+                    Operator::Gt => "&gt;",
+                    Operator::Ge => "&#x2265;",
+                    Operator::Eq => "=",
+                    Operator::Ne => "&#x2260;",
+                    Operator::Div | Operator::Pow => unreachable!(),
+                };
+                out.push_str("<mrow>");
+                self.mathml_operand(a, self.binds_looser(a, op, false), out);
+                write!(out, "<mo>{symbol}</mo>").unwrap();
+                self.mathml_operand(b, self.binds_looser(b, op, true), out);
+                out.push_str("</mrow>");
+            }
+            Node::Call(ref name, ref args) => {
+                write!(out, "<mrow><mi>{name}</mi><mo>&#x2061;</mo><mrow><mo>(</mo>").unwrap();
+                for (i, &a) in args.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str("<mo>,</mo>");
+                    }
+                    self.mathml_node(a, out);
+                }
+                out.push_str("<mo>)</mo></mrow></mrow>");
+            }
+            Node::Assign(ref name, a) => {
+                write!(out, "<mrow><mi>{name}</mi><mo>=</mo>").unwrap();
+                self.mathml_node(a, out);
+                out.push_str("</mrow>");
+            }
+            Node::List(ref elements) => {
+                out.push_str("<mrow><mo>[</mo>");
+                for (i, &e) in elements.iter().enumerate() {
+                    if i != 0 {
+                        out.push_str("<mo>,</mo>");
+                    }
+                    self.mathml_node(e, out);
+                }
+                out.push_str("<mo>]</mo></mrow>");
+            }
+            Node::Range(a, b) => {
+                out.push_str("<mrow>");
+                self.mathml_node(a, out);
+                out.push_str("<mo>..</mo>");
+                self.mathml_node(b, out);
+                out.push_str("</mrow>");
+            }
+            Node::Text(ref text) => write!(out, "<ms>{}</ms>", escape(text)).unwrap(),
+            Node::Lambda(ref params, body) => {
+                out.push_str("<mrow>");
+                match params.as_slice() {
+                    [param] => write!(out, "<mi>{param}</mi>").unwrap(),
+                    params => {
+                        out.push_str("<mrow><mo>(</mo>");
+                        for (i, param) in params.iter().enumerate() {
+                            if i != 0 {
+                                out.push_str("<mo>,</mo>");
+                            }
+                            write!(out, "<mi>{param}</mi>").unwrap();
+                        }
+                        out.push_str("<mo>)</mo></mrow>");
+                    }
+                }
+                out.push_str("<mo>&#x2192;</mo>");
+                self.mathml_node(body, out);
+                out.push_str("</mrow>");
+            }
+        }
+    }
+
+    fn mathml_operand(&self, id: NodeId, parens: bool, out: &mut String) {
+        if parens {
+            out.push_str("<mrow><mo>(</mo>");
+            self.mathml_node(id, out);
+            out.push_str("<mo>)</mo></mrow>");
+        } else {
+            self.mathml_node(id, out);
+        }
+    }
+
+    /// Whether the operand `id` of `parent` has to be parenthesized to keep its meaning.
+    fn binds_looser(&self, id: NodeId, parent: Operator, right: bool) -> bool {
+        match *self.node(id) {
+            // shown as a fraction, which needs no parentheses
+            Node::Op(Operator::Div, _, _) => false,
+            Node::Op(op, _, _) => {
+                // a - (b - c) but a + b - c
+                let associative = matches!(parent, Operator::Add | Operator::Mul);
+                op.precedence() > parent.precedence()
+                    || (right && !associative && op.precedence() == parent.precedence())
+            }
+            Node::Float(f) => right && f.is_sign_negative(),
+            Node::Unary(..) => right,
+            Node::Assign(..) | Node::Range(..) | Node::Lambda(..) => true,
+            Node::Bool(_) | Node::Var(_) | Node::Call(..) | Node::List(_) | Node::Text(_) => false,
+        }
+    }
+}
+
+/// `text` with the characters that mean something in XML escaped.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn mathml_number(f: f64, out: &mut String) {
+    if f.is_sign_negative() && !f.is_nan() {
+        write!(out, "<mrow><mo>&#x2212;</mo><mn>{}</mn></mrow>", -f).unwrap();
+    } else if f.is_infinite() {
+        out.push_str("<mi>&#x221E;</mi>");
+    } else {
+        write!(out, "<mn>{f}</mn>").unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    fn mathml(input: &str) -> String {
+        let s = parse_line(input).unwrap().to_mathml();
+        s.strip_prefix(MATH_OPEN).unwrap().strip_suffix("</math>").unwrap().to_string()
+    }
+
+    #[test]
+    fn basic() {
+        assert_eq!(mathml("x"), "<mi>x</mi>");
+        assert_eq!(mathml("1.5"), "<mn>1.5</mn>");
+        assert_eq!(mathml("1 + 2"), "<mrow><mn>1</mn><mo>+</mo><mn>2</mn></mrow>");
+        assert_eq!(mathml("1 / x"), "<mfrac><mn>1</mn><mi>x</mi></mfrac>");
+        assert_eq!(mathml("-x"), "<mrow><mo>&#x2212;</mo><mi>x</mi></mrow>");
+    }
+
+    #[test]
+    fn parentheses() {
+        assert_eq!(
+            mathml("(1 + 2) * 3"),
+            "<mrow><mrow><mo>(</mo><mrow><mn>1</mn><mo>+</mo><mn>2</mn></mrow><mo>)</mo></mrow><mo>&#xD7;</mo><mn>3</mn></mrow>"
+        );
+        assert_eq!(
+            mathml("1 - (2 - 3)"),
+            "<mrow><mn>1</mn><mo>&#x2212;</mo><mrow><mo>(</mo><mrow><mn>2</mn><mo>&#x2212;</mo><mn>3</mn></mrow><mo>)</mo></mrow></mrow>"
+        );
+        assert_eq!(
+            mathml("1 - -2"),
+            "<mrow><mn>1</mn><mo>&#x2212;</mo><mrow><mo>(</mo><mrow><mo>&#x2212;</mo><mn>2</mn></mrow><mo>)</mo></mrow></mrow>"
+        );
+        assert_eq!(
+            mathml("(1 + 2) / 3"),
+            "<mfrac><mrow><mn>1</mn><mo>+</mo><mn>2</mn></mrow><mn>3</mn></mfrac>"
+        );
+    }
+
+    #[test]
+    fn powers_and_calls() {
+        assert_eq!(mathml("x ^ 2"), "<msup><mi>x</mi><mn>2</mn></msup>");
+        assert_eq!(
+            mathml("(1 + x) ^ (1 / 2)"),
+            "<msup><mrow><mo>(</mo><mrow><mn>1</mn><mo>+</mo><mi>x</mi></mrow><mo>)</mo></mrow><mfrac><mn>1</mn><mn>2</mn></mfrac></msup>"
+        );
+        assert_eq!(
+            mathml("max(x, 1) <= 2"),
+            "<mrow><mrow><mi>max</mi><mo>&#x2061;</mo><mrow><mo>(</mo><mi>x</mi><mo>,</mo><mn>1</mn><mo>)</mo></mrow></mrow><mo>&#x2264;</mo><mn>2</mn></mrow>"
+        );
+    }
+
+    #[test]
+    fn equation() {
+        let e = parse_line("1 / 4").unwrap();
+        assert_eq!(
+            e.to_mathml_equation(e.evaluate().unwrap()),
+            format!("{MATH_OPEN}<mfrac><mn>1</mn><mn>4</mn></mfrac><mo>=</mo><mn>0.25</mn></math>")
+        );
+    }
+}
@@ -0,0 +1,71 @@
+use std::borrow::Cow;
+
+/// Maps look-alike characters commonly produced by chat apps, word processors and
+/// East Asian input methods onto their ASCII counterparts, so that pasted input
+/// like `１＋２` or `3 − 1` can be lexed.
+pub fn normalize(input: &str) -> Cow<'_, str> {
+    if input.is_ascii() {
+        return Cow::Borrowed(input);
+    }
+    Cow::Owned(input.chars().map(normalize_char).collect())
+}
+
+/// Maps every byte offset in `normalized` (including its end) to the corresponding offset in
+/// `original`. This works since normalization replaces each char with exactly one char.
+pub fn original_offsets(original: &str, normalized: &str) -> Vec<usize> {
+    let mut map = vec![original.len(); normalized.len() + 1];
+    for ((n, c), (o, _)) in normalized.char_indices().zip(original.char_indices()) {
+        map[n..n + c.len_utf8()].fill(o);
+    }
+    map
+}
+
+pub fn normalize_char(c: char) -> char {
+    match c {
+        // full-width forms of the printable ASCII range
+        '\u{FF01}'..='\u{FF5E}' => char::from_u32(c as u32 - 0xFEE0).unwrap_or(c),
+        // no-break, figure, thin, narrow no-break and ideographic spaces
+        '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}' => ' ',
+        // minus sign, and the dashes that tend to be auto-corrected from '-'
+        '\u{2212}' | '\u{2010}' | '\u{2011}' | '\u{2012}' | '\u{2013}' | '\u{FE63}' => '-',
+        '\u{2018}' | '\u{2019}' | '\u{201A}' | '\u{201B}' | '\u{2032}' => '\'',
+        '\u{201C}' | '\u{201D}' | '\u{201E}' | '\u{201F}' | '\u{2033}' => '"',
+        _ => c,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ascii_is_borrowed() {
+        assert!(matches!(normalize("1 + 2"), Cow::Borrowed("1 + 2")));
+    }
+
+    #[test]
+    fn normalize_chars() {
+        for (input, output) in [
+            ("１＋２", "1+2"),
+            ("（３．５）＊４", "(3.5)*4"),
+            ("3\u{2212}1", "3-1"),
+            ("3 \u{2013} 1", "3 - 1"),
+            ("1\u{00A0}+\u{202F}2", "1 + 2"),
+            ("１\u{3000}／\u{3000}２", "1 / 2"),
+            ("\u{2018}a\u{2019} \u{201C}b\u{201D}", "'a' \"b\""),
+            ("é", "é"),
+        ] {
+            assert_eq!(normalize(input), output, "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn offsets() {
+        let original = "１＋x";
+        let map = original_offsets(original, &normalize(original));
+        assert_eq!(map, [0, 3, 6, 7]);
+        let original = "é−1";
+        let map = original_offsets(original, &normalize(original));
+        assert_eq!(map, [0, 0, 2, 5, 6]);
+    }
+}
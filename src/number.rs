@@ -0,0 +1,705 @@
+//! The numeric types that expressions can be evaluated with. Variables are always stored as
+//! `f64`, and built-in functions that a backend does not implement itself are computed with
+//! `f64` too.
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+
+use crate::context::Context;
+use crate::decimal::{Decimal, Rounding};
+use crate::functions::{self, Eval};
+use crate::repr::*;
+
+/// How to do arithmetic with one kind of number.
+pub trait Backend {
+    type Num: Clone + fmt::Display;
+
+    /// The number closest to the decimal that is printed for `f`, so that `0.1` is as close
+    /// to a tenth as the backend can get.
+    fn number(&self, f: f64) -> Self::Num;
+
+    fn to_f64(&self, x: &Self::Num) -> f64;
+
+    fn unary(&self, op: UnaryOperator, a: &Self::Num) -> Self::Num;
+
+    /// Comparisons result in 1 when true and 0 when false.
+    fn op(&self, op: Operator, a: &Self::Num, b: &Self::Num) -> Self::Num;
+
+    /// Calls the built-in function `name`, or returns `None` to have it computed with `f64`.
+    fn call(&self, _name: &str, _args: &[Self::Num]) -> Option<Self::Num> {
+        None
+    }
+
+    /// What `memo` functions cache their results under, for backends whose numbers convert to
+    /// `f64` and back exactly, or `None` to not cache them.
+    fn memo_key(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// The variable `name` of `ctx`, for backends that keep more exact values of variables than
+    /// `f64`, or `None` to convert the `f64` one.
+    fn var(&self, _ctx: &Context, _name: &str) -> Option<Self::Num> {
+        None
+    }
+}
+
+/// The usual 64-bit floating point numbers.
+pub struct F64;
+
+impl Backend for F64 {
+    type Num = f64;
+
+    fn number(&self, f: f64) -> f64 {
+        f
+    }
+
+    fn to_f64(&self, x: &f64) -> f64 {
+        *x
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &f64) -> f64 {
+        op.apply(*a)
+    }
+
+    fn op(&self, op: Operator, a: &f64, b: &f64) -> f64 {
+        op.apply(*a, *b)
+    }
+
+    fn memo_key(&self) -> Option<&'static str> {
+        Some("f64")
+    }
+}
+
+/// 32-bit floating point numbers, for targets where `f64` is slow or missing.
+#[cfg_attr(not(feature = "f32"), allow(dead_code))]
+pub struct F32;
+
+impl Backend for F32 {
+    type Num = f32;
+
+    fn number(&self, f: f64) -> f32 {
+        f as f32
+    }
+
+    fn to_f64(&self, x: &f32) -> f64 {
+        *x as f64
+    }
+
+    fn memo_key(&self) -> Option<&'static str> {
+        Some("f32")
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &f32) -> f32 {
+        op.apply(*a as f64) as f32
+    }
+
+    /// Computed with `f64`, which rounds to the same `f32` as arithmetic on `f32` does.
+    fn op(&self, op: Operator, a: &f32, b: &f32) -> f32 {
+        op.apply(*a as f64, *b as f64) as f32
+    }
+}
+
+/// The backend of [`Expr::evaluate_in`], which is `f32` with the `f32` feature.
+#[cfg(not(feature = "f32"))]
+pub const NATIVE: F64 = F64;
+#[cfg(feature = "f32")]
+pub const NATIVE: F32 = F32;
+
+/// `f` rounded to a number of [`NATIVE`], like the results of evaluating are.
+#[cfg(test)]
+pub fn native(f: f64) -> f64 {
+    NATIVE.to_f64(&NATIVE.number(f))
+}
+
+/// Decimal floating point numbers, which represent decimal fractions like `0.1` exactly.
+pub struct Decimal128;
+
+impl Backend for Decimal128 {
+    type Num = Decimal;
+
+    fn number(&self, f: f64) -> Decimal {
+        Decimal::from_f64(f)
+    }
+
+    fn to_f64(&self, x: &Decimal) -> f64 {
+        x.to_f64()
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &Decimal) -> Decimal {
+        match op {
+            UnaryOperator::Neg => a.neg(),
+        }
+    }
+
+    fn op(&self, op: Operator, a: &Decimal, b: &Decimal) -> Decimal {
+        let (a, b) = (*a, *b);
+        let ordering = a.partial_cmp(&b);
+        match op {
+            Operator::Add => a.add(b),
+            Operator::Sub => a.add(b.neg()),
+            Operator::Mul => a.mul(b),
+            Operator::Div => a.div(b),
+            Operator::Pow => a.powi(b).unwrap_or_else(|| Decimal::from_f64(a.to_f64().powf(b.to_f64()))),
+            Operator::Lt => Decimal::from_bool(ordering == Some(Ordering::Less)),
+            Operator::Le => Decimal::from_bool(matches!(ordering, Some(Ordering::Less | Ordering::Equal))),
+            Operator::Gt => Decimal::from_bool(ordering == Some(Ordering::Greater)),
+            Operator::Ge => Decimal::from_bool(matches!(ordering, Some(Ordering::Greater | Ordering::Equal))),
+            Operator::Eq => Decimal::from_bool(ordering == Some(Ordering::Equal)),
+            Operator::Ne => Decimal::from_bool(ordering != Some(Ordering::Equal)),
+        }
+    }
+
+    fn call(&self, name: &str, args: &[Decimal]) -> Option<Decimal> {
+        let places = match args {
+            [_] => 0,
+            [_, places] => places.to_f64() as i32,
+            _ => return None,
+        };
+        let rounding = match name {
+            "abs" => return Some(args[0].abs()),
+            "floor" => Rounding::Floor,
+            "ceil" => Rounding::Ceil,
+            "trunc" => Rounding::Trunc,
+            "round" => Rounding::HalfAwayFromZero,
+            _ => return None,
+        };
+        Some(args[0].to_places(places, rounding))
+    }
+
+    fn var(&self, ctx: &Context, name: &str) -> Option<Decimal> {
+        ctx.decimal_var(name)
+    }
+}
+
+/// `f64` with a bound of the rounding errors that have piled up in it, for showing how far
+/// off a result may be, like after cancellation in `(1 + 0.000000000000001) - 1`.
+pub struct ErrorBound;
+
+/// The unit roundoff of `f64`, the largest relative error of rounding to it.
+const ROUNDOFF: f64 = f64::EPSILON / 2.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounded {
+    pub value: f64,
+    /// how far from `value` the exact result may be
+    pub error: f64,
+}
+
+impl Bounded {
+    /// The result of an operation whose operands have the errors `error`, after it is rounded too.
+    fn rounded(value: f64, error: f64) -> Bounded {
+        Bounded {
+            value,
+            error: error + value.abs() * ROUNDOFF,
+        }
+    }
+
+    /// Like `1.2e-16` of `value`, or `None` for a result of 0 with an error.
+    pub fn relative_error(&self) -> Option<f64> {
+        match (self.value, self.error) {
+            (_, 0.0) => Some(0.0),
+            (0.0, _) => None,
+            (value, error) => Some(error / value.abs()),
+        }
+    }
+}
+
+impl fmt::Display for Bounded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.value)
+    }
+}
+
+impl Backend for ErrorBound {
+    type Num = Bounded;
+
+    /// Integers are exact, and everything else was rounded when it was parsed.
+    fn number(&self, f: f64) -> Bounded {
+        if f.fract() == 0.0 && f.abs() <= 2f64.powi(53) {
+            Bounded { value: f, error: 0.0 }
+        } else {
+            Bounded::rounded(f, 0.0)
+        }
+    }
+
+    fn to_f64(&self, x: &Bounded) -> f64 {
+        x.value
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &Bounded) -> Bounded {
+        Bounded {
+            value: op.apply(a.value),
+            error: a.error,
+        }
+    }
+
+    fn op(&self, op: Operator, a: &Bounded, b: &Bounded) -> Bounded {
+        let value = op.apply(a.value, b.value);
+        let (x, y) = (a.value.abs(), b.value.abs());
+        let error = match op {
+            Operator::Add | Operator::Sub => a.error + b.error,
+            Operator::Mul => x * b.error + y * a.error + a.error * b.error,
+            Operator::Div if y > b.error => (a.error + value.abs() * b.error) / (y - b.error),
+            Operator::Div => f64::INFINITY,
+            Operator::Pow => {
+                let base = if a.error == 0.0 { 0.0 } else { y * a.error / x };
+                let exponent = if b.error == 0.0 { 0.0 } else { x.ln().abs() * b.error };
+                value.abs() * (base + exponent)
+            }
+            // the outcome may be wrong near equality, but there is no error to speak of
+            _ => return Bounded { value, error: 0.0 },
+        };
+        // only integers have no error, and operations on them are exact while the result is too
+        if error == 0.0 && value.fract() == 0.0 && value.abs() <= 2f64.powi(53) {
+            Bounded { value, error }
+        } else {
+            Bounded::rounded(value, error)
+        }
+    }
+
+    /// Functions are assumed to be accurate, and the errors of their arguments to be small
+    /// enough that the function is about linear within them.
+    fn call(&self, name: &str, args: &[Bounded]) -> Option<Bounded> {
+        let Eval::Numbers(eval) = functions::lookup(name)?.eval else {
+            return None;
+        };
+        let values: Vec<f64> = args.iter().map(|a| a.value).collect();
+        let value = eval(&values).ok()?;
+        let mut error = 0.0;
+        for (i, a) in args.iter().enumerate().filter(|(_, a)| a.error != 0.0) {
+            let mut shifted = values.clone();
+            shifted[i] = a.value + a.error;
+            let above = eval(&shifted).ok();
+            shifted[i] = a.value - a.error;
+            let below = eval(&shifted).ok();
+            // like integer arguments, which can not be off
+            if let (Some(above), Some(below)) = (above, below) {
+                error += (above - value).abs().max((below - value).abs());
+            }
+        }
+        Some(Bounded::rounded(value, error))
+    }
+}
+
+/// The integer types that functions like `u8(x)` convert to, and that `:int` keeps results in.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum IntType {
+    U8,
+    U16,
+    U32,
+    I32,
+    I64,
+}
+
+impl IntType {
+    pub const ALL: [IntType; 5] = [IntType::U8, IntType::U16, IntType::U32, IntType::I32, IntType::I64];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            IntType::U8 => "u8",
+            IntType::U16 => "u16",
+            IntType::U32 => "u32",
+            IntType::I32 => "i32",
+            IntType::I64 => "i64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<IntType> {
+        IntType::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    fn bits(self) -> u32 {
+        match self {
+            IntType::U8 => 8,
+            IntType::U16 => 16,
+            IntType::U32 | IntType::I32 => 32,
+            IntType::I64 => 64,
+        }
+    }
+
+    fn min(self) -> i128 {
+        match self {
+            IntType::I32 | IntType::I64 => -(1 << (self.bits() - 1)),
+            _ => 0,
+        }
+    }
+
+    fn max(self) -> i128 {
+        self.min() + (1 << self.bits()) - 1
+    }
+
+    /// Wraps `x` around into the range of the type, like two's complement arithmetic does.
+    pub fn wrap(self, x: i128) -> i128 {
+        (x - self.min()).rem_euclid(1 << self.bits()) + self.min()
+    }
+}
+
+/// What happens to results that do not fit in an [`IntType`].
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Overflow {
+    Wrap,
+    Saturate,
+}
+
+impl Overflow {
+    pub fn name(self) -> &'static str {
+        match self {
+            Overflow::Wrap => "wrap",
+            Overflow::Saturate => "saturate",
+        }
+    }
+}
+
+/// An integer of a [`FixedWidth`], or `None` after dividing by zero.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Int(pub Option<i128>);
+
+impl fmt::Display for Int {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.0 {
+            Some(n) => write!(f, "{n}"),
+            None => f.write_str("undefined"),
+        }
+    }
+}
+
+/// Integers of a fixed width that overflow like they do in programs. Fractions are truncated
+/// toward zero.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct FixedWidth {
+    pub int: IntType,
+    pub overflow: Overflow,
+}
+
+impl FixedWidth {
+    fn fit(&self, x: i128) -> i128 {
+        match self.overflow {
+            Overflow::Wrap => self.int.wrap(x),
+            Overflow::Saturate => x.clamp(self.int.min(), self.int.max()),
+        }
+    }
+
+    fn pow(&self, a: i128, b: i128) -> Option<i128> {
+        Some(match (a, b) {
+            (0, ..0) => return None,
+            (1, _) => 1,
+            (-1, _) => 1 - 2 * b.rem_euclid(2),
+            (_, ..0) => 0,
+            _ => {
+                let b = u32::try_from(b).unwrap_or(u32::MAX);
+                match (self.overflow, a.checked_pow(b)) {
+                    (Overflow::Wrap, _) => self.int.wrap(a.wrapping_pow(b)),
+                    (Overflow::Saturate, Some(p)) => self.fit(p),
+                    (Overflow::Saturate, None) if a < 0 && b % 2 == 1 => self.int.min(),
+                    (Overflow::Saturate, None) => self.int.max(),
+                }
+            }
+        })
+    }
+}
+
+impl Backend for FixedWidth {
+    type Num = Int;
+
+    fn number(&self, f: f64) -> Int {
+        Int((!f.is_nan()).then(|| self.fit(f as i128)))
+    }
+
+    fn to_f64(&self, x: &Int) -> f64 {
+        x.0.map_or(f64::NAN, |x| x as f64)
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &Int) -> Int {
+        match op {
+            UnaryOperator::Neg => Int(a.0.map(|a| self.fit(-a))),
+        }
+    }
+
+    fn op(&self, op: Operator, a: &Int, b: &Int) -> Int {
+        let (Int(Some(a)), Int(Some(b))) = (*a, *b) else {
+            return Int(None);
+        };
+        // the operands are at most 64 bits, so the results fit before they are wrapped
+        Int(Some(match op {
+            Operator::Add => self.fit(a + b),
+            Operator::Sub => self.fit(a - b),
+            Operator::Mul => self.fit(a * b),
+            Operator::Div => match a.checked_div(b) {
+                Some(q) => self.fit(q),
+                None => return Int(None),
+            },
+            Operator::Pow => return Int(self.pow(a, b)),
+            Operator::Lt => (a < b) as i128,
+            Operator::Le => (a <= b) as i128,
+            Operator::Gt => (a > b) as i128,
+            Operator::Ge => (a >= b) as i128,
+            Operator::Eq => (a == b) as i128,
+            Operator::Ne => (a != b) as i128,
+        }))
+    }
+
+    fn call(&self, name: &str, args: &[Int]) -> Option<Int> {
+        let (Some(int), [x]) = (IntType::from_name(name), args) else {
+            return None;
+        };
+        Some(Int(x.0.map(|x| int.wrap(x))))
+    }
+}
+
+/// Floating point numbers with any number of bits of precision, using GNU MPFR.
+#[cfg(feature = "mpfr")]
+pub struct Mpfr {
+    pub bits: u32,
+}
+
+#[cfg(feature = "mpfr")]
+impl Backend for Mpfr {
+    type Num = rug::Float;
+
+    fn number(&self, f: f64) -> rug::Float {
+        match rug::Float::parse(f.to_string()) {
+            Ok(parsed) => rug::Float::with_val(self.bits, parsed),
+            // like inf and NaN
+            Err(_) => rug::Float::with_val(self.bits, f),
+        }
+    }
+
+    fn to_f64(&self, x: &rug::Float) -> f64 {
+        x.to_f64()
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &rug::Float) -> rug::Float {
+        match op {
+            UnaryOperator::Neg => rug::Float::with_val(self.bits, -a),
+        }
+    }
+
+    fn op(&self, op: Operator, a: &rug::Float, b: &rug::Float) -> rug::Float {
+        use rug::ops::Pow;
+        let bits = self.bits;
+        let truth = |t: bool| rug::Float::with_val(bits, t as u8);
+        match op {
+            Operator::Add => rug::Float::with_val(bits, a + b),
+            Operator::Sub => rug::Float::with_val(bits, a - b),
+            Operator::Mul => rug::Float::with_val(bits, a * b),
+            Operator::Div => rug::Float::with_val(bits, a / b),
+            Operator::Pow => rug::Float::with_val(bits, a.pow(b)),
+            Operator::Lt => truth(a < b),
+            Operator::Le => truth(a <= b),
+            Operator::Gt => truth(a > b),
+            Operator::Ge => truth(a >= b),
+            Operator::Eq => truth(a == b),
+            Operator::Ne => truth(a != b),
+        }
+    }
+
+    fn call(&self, name: &str, args: &[rug::Float]) -> Option<rug::Float> {
+        let [x] = args else {
+            return None;
+        };
+        let mut y = x.clone();
+        match name {
+            "abs" => y.abs_mut(),
+            "sqrt" => y.sqrt_mut(),
+            "exp" => y.exp_mut(),
+            "ln" => y.ln_mut(),
+            "log10" => y.log10_mut(),
+            "sin" => y.sin_mut(),
+            "cos" => y.cos_mut(),
+            "tan" => y.tan_mut(),
+            "asin" => y.asin_mut(),
+            "acos" => y.acos_mut(),
+            "atan" => y.atan_mut(),
+            "floor" => y.floor_mut(),
+            "ceil" => y.ceil_mut(),
+            _ => return None,
+        }
+        Some(y)
+    }
+}
+
+pub const PRECISION_UNAVAILABLE: &str = "more precision needs the mpfr feature";
+
+/// Evaluates `e` with `bits` bits of precision, and formats the result with all its digits.
+#[cfg(feature = "mpfr")]
+pub fn evaluate_precise(e: &Expr, ctx: &Context, bits: u32) -> Result<String, EvalError> {
+    e.evaluate_with(&Mpfr { bits }, ctx).map(|x| x.to_string())
+}
+
+#[cfg(not(feature = "mpfr"))]
+pub fn evaluate_precise(_e: &Expr, _ctx: &Context, _bits: u32) -> Result<String, EvalError> {
+    Err(EvalError {
+        message: PRECISION_UNAVAILABLE.into(),
+        span: None,
+        backtrace: Vec::new(),
+    })
+}
+
+/// The constants that `:digits` computes.
+pub const DIGIT_CONSTANTS: [&str; 4] = ["pi", "e", "sqrt2", "ln2"];
+
+pub const DIGITS_LIMIT: usize = 1_000_000;
+
+/// The digits that `:digits` computes with decimal128 when there is no MPFR.
+pub const DECIMAL_DIGITS_LIMIT: usize = 30;
+
+/// Writes one of [`DIGIT_CONSTANTS`] with `decimals` digits after the point, truncated. MPFR
+/// computes the constant, and the digits are written nine at a time as they are extracted.
+#[cfg(feature = "mpfr")]
+pub fn write_digits(name: &str, decimals: usize, out: &mut impl io::Write) -> io::Result<()> {
+    use rug::float::Constant;
+    // every extracted digit makes the error 10 times larger compared to what is left
+    let bits = (decimals as f64 * std::f64::consts::LOG2_10).ceil() as u32 + 64;
+    let mut x = match name {
+        "pi" => rug::Float::with_val(bits, Constant::Pi),
+        "e" => rug::Float::with_val(bits, 1).exp(),
+        "sqrt2" => rug::Float::with_val(bits, 2).sqrt(),
+        "ln2" => rug::Float::with_val(bits, Constant::Log2),
+        _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("unknown constant {name}"))),
+    };
+    let mut whole = rug::Float::with_val(bits, x.floor_ref());
+    x -= &whole;
+    write!(out, "{}.", whole.to_f64())?;
+    let mut left = decimals;
+    while left > 0 {
+        let n = left.min(9);
+        x *= 10u32.pow(n as u32);
+        whole = rug::Float::with_val(bits, x.floor_ref());
+        x -= &whole;
+        write!(out, "{:0n$}", whole.to_f64() as u32)?;
+        out.flush()?;
+        left -= n;
+    }
+    writeln!(out)
+}
+
+/// Writes one of [`DIGIT_CONSTANTS`] with up to [`DECIMAL_DIGITS_LIMIT`] digits after the
+/// point, computed with decimal128.
+#[cfg(not(feature = "mpfr"))]
+pub fn write_digits(name: &str, decimals: usize, out: &mut impl io::Write) -> io::Result<()> {
+    if decimals > DECIMAL_DIGITS_LIMIT {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, PRECISION_UNAVAILABLE));
+    }
+    let x = Decimal::constant(name)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, format!("unknown constant {name}")))?;
+    writeln!(out, "{}", x.to_fixed(decimals))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::functions::Module;
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    #[test]
+    fn f64_backend() {
+        let e = parse_line("-2 ^ 2 + sqrt(16) * (1 < 2)").unwrap();
+        assert_eq!(e.evaluate_with(&F64, &Context::default()), Ok(0.0));
+    }
+
+    #[test]
+    fn f32_backend() {
+        let single = |input: &str| parse_line(input).unwrap().evaluate_with(&F32, &Context::default());
+        assert_eq!(single("0.1 + 0.2"), Ok(0.3));
+        assert_eq!(single("2 ^ 24 + 1"), Ok(16777216.0));
+        assert_eq!(single("sqrt(2) * 2 < 3"), Ok(1.0));
+    }
+
+    #[test]
+    fn decimal_backend() {
+        let decimal = |input: &str| {
+            let e = parse_line(input).unwrap();
+            e.evaluate_with(&Decimal128, &Context::default()).map(|x| x.to_string())
+        };
+        assert_eq!(decimal("0.1 + 0.2 == 0.3"), Ok("1".into()));
+        assert_eq!(decimal("0.1 * 3"), Ok("0.3".into()));
+        assert_eq!(decimal("round(2.675, 2) + floor(-0.5)"), Ok("1.68".into()));
+        assert_eq!(decimal("1.05 ^ 2"), Ok("1.1025".into()));
+        assert_eq!(decimal("4 ^ 0.5"), Ok("2".into()));
+        assert_eq!(decimal("max(0.1, 0.2)"), Ok("0.2".into()));
+    }
+
+    #[test]
+    fn error_bounds() {
+        let bounded = |input: &str| {
+            let e = parse_line(input).unwrap();
+            e.evaluate_with(&ErrorBound, &Context::default()).unwrap()
+        };
+        assert_eq!(bounded("2 ^ 10 - 3 * 4"), Bounded { value: 1012.0, error: 0.0 });
+        let sum = bounded("0.1 + 0.2");
+        assert!(sum.relative_error().unwrap() < 1e-15, "{sum:?}");
+        // most digits cancel out
+        let difference = bounded("(1 + 0.000000000000001) - 1");
+        assert_eq!(difference.value, 1.1102230246251565e-15);
+        assert!(difference.relative_error().unwrap() > 0.1, "{difference:?}");
+        let root = bounded("sqrt(2 + 0.0000000001) - sqrt(2)");
+        assert!(root.relative_error().unwrap() > 1e-6, "{root:?}");
+        assert_eq!(bounded("0.1 - 0.1").relative_error(), None);
+        assert_eq!(bounded("round(2.5) < 3"), Bounded { value: 0.0, error: 0.0 });
+    }
+
+    #[test]
+    fn fixed_width() {
+        let int = |input: &str, int: IntType, overflow: Overflow| {
+            let e = parse_line(input).unwrap();
+            let mut ctx = Context::default();
+            ctx.load(Module::Bits);
+            e.evaluate_with(&FixedWidth { int, overflow }, &ctx).unwrap().0
+        };
+        let wrapping = |input: &str, t| int(input, t, Overflow::Wrap);
+        let saturating = |input: &str, t| int(input, t, Overflow::Saturate);
+        assert_eq!(wrapping("200 + 100", IntType::U8), Some(44));
+        assert_eq!(saturating("200 + 100", IntType::U8), Some(255));
+        assert_eq!(wrapping("0 - 1", IntType::U16), Some(65535));
+        assert_eq!(saturating("0 - 1", IntType::U32), Some(0));
+        assert_eq!(wrapping("2147483647 + 1", IntType::I32), Some(-2147483648));
+        assert_eq!(saturating("-2147483647 - 10", IntType::I32), Some(-2147483648));
+        assert_eq!(wrapping("-(1 - 2 ^ 63 - 1)", IntType::I64), Some(i64::MIN as i128));
+        assert_eq!(wrapping("3 ^ 41", IntType::I64), Some(3i64.wrapping_pow(41) as i128));
+        assert_eq!(saturating("(-3) ^ 41", IntType::I64), Some(i64::MIN as i128));
+        assert_eq!(wrapping("7.9 / 2 + -7 / 2", IntType::I32), Some(0));
+        assert_eq!(wrapping("2 ^ -1 + (-1) ^ 3", IntType::I32), Some(-1));
+        assert_eq!(wrapping("1 / (1 - 1)", IntType::I32), None);
+        assert_eq!(wrapping("u8(257) + u16(-1) < 0", IntType::I64), Some(0));
+    }
+
+    #[cfg(feature = "mpfr")]
+    #[test]
+    fn mpfr_backend() {
+        let precise = |input: &str| {
+            let e = parse_line(input).unwrap();
+            e.evaluate_with(&Mpfr { bits: 200 }, &Context::default())
+        };
+        assert_eq!(precise("0.1 + 0.2 == 0.3").map(|x| x.to_f64()), Ok(1.0));
+        assert!(precise("sqrt(2)").unwrap().to_string().starts_with("1.41421356237309504880168872420969807"));
+        assert_eq!(precise("1 / 0").map(|x| x.to_f64()), Ok(f64::INFINITY));
+    }
+
+    #[test]
+    fn constant_digits() {
+        let digits = |name: &str, decimals: usize| {
+            let mut out = Vec::new();
+            write_digits(name, decimals, &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(digits("pi", 30), "3.141592653589793238462643383279\n");
+        assert_eq!(digits("e", 20), "2.71828182845904523536\n");
+        assert_eq!(digits("sqrt2", 5), "1.41421\n");
+        assert_eq!(digits("ln2", 25), "0.6931471805599453094172321\n");
+        if cfg!(feature = "mpfr") {
+            assert_eq!(digits("pi", 1000).len(), 1003);
+        } else {
+            assert!(write_digits("pi", DECIMAL_DIGITS_LIMIT + 1, &mut Vec::new()).is_err());
+        }
+    }
+
+    #[cfg(not(feature = "mpfr"))]
+    #[test]
+    fn mpfr_unavailable() {
+        let e = parse_line("1 + 2").unwrap();
+        let err = evaluate_precise(&e, &Context::default(), 100).unwrap_err();
+        assert_eq!(err.message, PRECISION_UNAVAILABLE);
+    }
+}
@@ -1,11 +1,180 @@
+use crate::currency;
+use crate::functions;
+use crate::i18n::{Key, Text};
+use crate::normalize::{normalize, original_offsets};
 use crate::repr::*;
+use crate::uncertainty::PM;
 use regex::Regex;
+use std::borrow::Cow;
 use std::sync::LazyLock;
 
+/// The dialect of the input.
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub enum Syntax {
+    #[default]
+    Native,
+    /// Spreadsheet formulas like `=ROUND(SUM(1,2;3)/7, 2)`. Function names are case
+    /// insensitive, `=` and `<>` compare, and `&` is dropped, which joins numbers.
+    Excel,
+    /// GNU bc expressions. Quotients are truncated to `scale` decimal digits, `-` binds
+    /// tighter than `^`, and the math library functions `s`, `c`, `a`, `l` and `e` exist.
+    Bc { scale: u32 },
+    /// Python expressions with `**`, `//`, `%` and functions and constants of the `math`
+    /// module like `math.sqrt(2)` and `math.pi`.
+    Python,
+}
+
+impl Syntax {
+    /// Operator tokens, with longer tokens before their prefixes.
+    fn operators(self) -> &'static [(&'static str, Operator)] {
+        use Operator::*;
+        match self {
+            Syntax::Native | Syntax::Bc { .. } => &[
+                ("<=", Le),
+                (">=", Ge),
+                ("==", Eq),
+                ("!=", Ne),
+                ("<", Lt),
+                (">", Gt),
+                ("+", Add),
+                ("-", Sub),
+                ("*", Mul),
+                ("/", Div),
+                ("^", Pow),
+            ],
+            Syntax::Excel => &[
+                ("<=", Le),
+                (">=", Ge),
+                ("<>", Ne),
+                ("=", Eq),
+                ("<", Lt),
+                (">", Gt),
+                ("+", Add),
+                ("-", Sub),
+                ("*", Mul),
+                ("/", Div),
+                ("^", Pow),
+            ],
+            // `//` and `%` are rewritten into calls by `Parser::expr`
+            Syntax::Python => &[
+                ("<=", Le),
+                (">=", Ge),
+                ("==", Eq),
+                ("!=", Ne),
+                ("<", Lt),
+                (">", Gt),
+                ("+", Add),
+                ("-", Sub),
+                ("**", Pow),
+                ("*", Mul),
+                ("//", Div),
+                ("/", Div),
+                ("%", Div),
+            ],
+        }
+    }
+
+    fn function_name(self, name: &str, args: usize) -> Cow<'_, str> {
+        match self {
+            Syntax::Native => Cow::Borrowed(name),
+            Syntax::Bc { .. } => Cow::Borrowed(match name {
+                "s" => "sin",
+                "c" => "cos",
+                "a" => "atan",
+                "l" => "ln",
+                "e" => "exp",
+                _ => name,
+            }),
+            Syntax::Excel => {
+                let name = name.to_ascii_lowercase();
+                Cow::Owned(match name.as_str() {
+                    "average" => "avg".to_string(),
+                    "int" => "floor".to_string(),
+                    "power" => "pow".to_string(),
+                    _ => name,
+                })
+            }
+            Syntax::Python => {
+                let name = name.strip_prefix("math.").unwrap_or(name);
+                Cow::Borrowed(match name {
+                    "fabs" => "abs",
+                    // the base defaults to e instead of 10
+                    "log" if args == 1 => "ln",
+                    _ => name,
+                })
+            }
+        }
+    }
+
+    fn constant(self, name: &str) -> Option<f64> {
+        match (self, name) {
+            (Syntax::Excel, _) if name.eq_ignore_ascii_case("true") => Some(1.0),
+            (Syntax::Excel, _) if name.eq_ignore_ascii_case("false") => Some(0.0),
+            (Syntax::Bc { scale }, "scale") => Some(scale as f64),
+            (_, "π") | (Syntax::Native, "pi") => Some(std::f64::consts::PI),
+            (Syntax::Native, "e") => Some(std::f64::consts::E),
+            (Syntax::Python, "True") => Some(1.0),
+            (Syntax::Python, "False") => Some(0.0),
+            (Syntax::Python, "math.pi") => Some(std::f64::consts::PI),
+            (Syntax::Python, "math.e") => Some(std::f64::consts::E),
+            (Syntax::Python, "math.tau") => Some(std::f64::consts::TAU),
+            (Syntax::Python, "math.inf") => Some(f64::INFINITY),
+            _ => None,
+        }
+    }
+
+    /// Whether `-2^2` means `(-2)^2`.
+    fn negation_binds_tightest(self) -> bool {
+        matches!(self, Syntax::Excel | Syntax::Bc { .. })
+    }
+}
+
+const NAME: &str = r"[\p{XID_Start}_]\p{XID_Continue}*";
+
+/// Whether a name can start with `c`. Names follow the Unicode identifier rules like Rust's
+/// do, so `α` and `Δx` are names too.
+pub fn is_name_start(c: char) -> bool {
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^[\p{XID_Start}_]$").unwrap());
+    c.is_ascii_alphabetic() || c == '_' || !c.is_ascii() && RE.is_match(c.encode_utf8(&mut [0; 4]))
+}
+
+/// Whether `c` can follow the first char of a name.
+pub fn is_name_continue(c: char) -> bool {
+    static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^\p{XID_Continue}$").unwrap());
+    c.is_ascii_alphanumeric() || c == '_' || !c.is_ascii() && RE.is_match(c.encode_utf8(&mut [0; 4]))
+}
+
+/// The keyword arguments of a call, like `digits = 2`.
+type Keywords<'s> = Vec<(&'s str, NodeId)>;
+
 #[derive(Clone)]
-struct Parser<'s>(&'s str);
+struct Parser<'s> {
+    input: &'s str,
+    rest: &'s str,
+    syntax: Syntax,
+    /// whether `x = 1` may be used as an expression, which only statements can
+    assignments: bool,
+    /// why a call was rejected, like a keyword argument naming no parameter, which says more
+    /// than where parsing stopped
+    error: Option<String>,
+}
 
 impl<'s> Parser<'s> {
+    fn new(input: &'s str) -> Self {
+        Parser {
+            input,
+            rest: input,
+            syntax: Syntax::Native,
+            assignments: false,
+            error: None,
+        }
+    }
+
+    /// byte offset of the remaining input
+    fn pos(&self) -> usize {
+        self.input.len() - self.rest.len()
+    }
+
     fn attempt<T>(&mut self, f: impl FnOnce(&mut Parser<'s>) -> Option<T>) -> Option<T> {
         let mut p = self.clone();
         let res = f(&mut p);
@@ -16,8 +185,8 @@ impl<'s> Parser<'s> {
     }
 
     fn consume(&mut self, p: char) -> Option<()> {
-        if let Some(rest) = self.0.strip_prefix(p) {
-            self.0 = rest;
+        if let Some(rest) = self.rest.strip_prefix(p) {
+            self.rest = rest;
             Some(())
         } else {
             None
@@ -25,9 +194,9 @@ impl<'s> Parser<'s> {
     }
 
     fn next(&mut self) -> Option<char> {
-        let c = self.0.chars().next();
-        if c.is_some() {
-            self.0 = &self.0[1..];
+        let c = self.rest.chars().next();
+        if let Some(c) = c {
+            self.rest = &self.rest[c.len_utf8()..];
         }
         c
     }
@@ -38,73 +207,894 @@ impl<'s> Parser<'s> {
 
     fn float(&mut self) -> Option<f64> {
         static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^-?\d+(\.\d+)?").unwrap());
-        let s = &RE.captures(self.0)?.get(0)?.as_str();
+        let s = &RE.captures(self.rest)?.get(0)?.as_str();
         let f = s.parse::<f64>().ok();
         if f.is_some() {
-            self.0 = &self.0[s.len()..];
+            self.rest = &self.rest[s.len()..];
         }
         f
     }
 
-    fn term(&mut self) -> Option<Expr> {
-        match self.clone().next()? {
+    fn ident(&mut self) -> Option<&'s str> {
+        static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(&format!("^{NAME}")).unwrap());
+        let s = RE.find(self.rest)?.as_str();
+        self.rest = &self.rest[s.len()..];
+        Some(s)
+    }
+
+    /// A name like `area`, or one qualified with namespaces like `geom.area`.
+    fn qualified_name(&mut self) -> Option<&'s str> {
+        static RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(&format!(r"^{NAME}(\.{NAME})*")).unwrap());
+        let s = RE.find(self.rest)?.as_str();
+        self.rest = &self.rest[s.len()..];
+        Some(s)
+    }
+
+    /// `message` about the keyword arguments `named`, which says how to assign in an argument
+    /// instead where assignments are allowed, since `name = value` in a call is a keyword argument.
+    fn keyword_error(&self, message: String, named: &Keywords<'s>) -> String {
+        match named.first() {
+            Some((name, _)) if self.assignments => {
+                format!("{message}; to assign {name} in an argument, put it in parentheses, like ({name} = 1)")
+            }
+            _ => message,
+        }
+    }
+
+    /// The arguments of a call after the `(`, and then the keyword arguments like `digits = 2`
+    /// that follow them.
+    fn args(&mut self, b: &mut ExprBuilder) -> Option<(Vec<NodeId>, Keywords<'s>)> {
+        let mut args = Vec::new();
+        let mut named = Vec::new();
+        self.spaces();
+        if self.consume(')').is_some() {
+            return Some((args, named));
+        }
+        loop {
+            // `=` compares in spreadsheets
+            let name = (self.syntax != Syntax::Excel).then(|| {
+                self.attempt(|p| {
+                    p.spaces();
+                    let name = p.ident()?;
+                    p.spaces();
+                    p.consume('=')?;
+                    (!p.rest.starts_with('=')).then_some(name)
+                })
+            });
+            let arg = self.value(b).ok()?;
+            match name.flatten() {
+                Some(name) => named.push((name, arg)),
+                None if !named.is_empty() => {
+                    let message = "positional arguments cannot follow keyword arguments".to_string();
+                    self.error = Some(self.keyword_error(message, &named));
+                    return None;
+                }
+                None => args.push(arg),
+            }
+            self.spaces();
+            match self.next()? {
+                ')' => return Some((args, named)),
+                ',' => {}
+                ';' if self.syntax == Syntax::Excel => {}
+                _ => return None,
+            }
+        }
+    }
+
+    /// The parameter list of a function definition, after the `(`.
+    fn params(&mut self) -> Option<Vec<String>> {
+        let mut params = Vec::new();
+        self.spaces();
+        if self.consume(')').is_some() {
+            return Some(params);
+        }
+        loop {
+            self.spaces();
+            params.push(self.ident()?.to_string());
+            self.spaces();
+            match self.next()? {
+                ')' => return Some(params),
+                ',' => {}
+                _ => return None,
+            }
+        }
+    }
+
+    fn term(&mut self, b: &mut ExprBuilder) -> Option<NodeId> {
+        let start = self.pos();
+        let native = self.syntax == Syntax::Native;
+        let id = match self.clone().next()? {
+            _ if native && self.clone().lambda_params().is_some() => self.lambda(b),
+            '[' if native => {
+                self.consume('[')?;
+                self.list(b).map_err(|err| self.error = Some(err)).ok()
+            }
+            '"' if native => {
+                self.consume('"')?;
+                let Some((text, rest)) = self.rest.split_once('"') else {
+                    self.error = Some(format!("expected \" at the end of the text, got {:?}", self.rest));
+                    return None;
+                };
+                self.rest = rest;
+                Some(b.text(text))
+            }
             '(' => {
                 self.consume('(')?;
-                let e = self.expr(100).ok()?;
+                let e = self.assignment(b).ok()?;
+                self.spaces();
                 self.consume(')')?;
                 Some(e)
             }
+            '-' => {
+                let literal = self.attempt(|p| {
+                    let f = p.float()?;
+                    let f = f + f.signum() * p.fraction_part(f).unwrap_or(0.0);
+                    // -2^2 means -(2^2) like -x^2 does, except in spreadsheets and bc
+                    let mut q = p.clone();
+                    q.spaces();
+                    let pow_follows = q.operator(100).map(|(_, op)| op) == Some(Operator::Pow);
+                    // -1:30 is a negated duration
+                    (!p.rest.starts_with(':') && (p.syntax.negation_binds_tightest() || !pow_follows)).then_some(f)
+                });
+                match literal {
+                    Some(f) => Some(b.float(f)),
+                    None => {
+                        self.consume('-')?;
+                        let a = if self.syntax.negation_binds_tightest() {
+                            self.term(b)?
+                        } else {
+                            self.expr(b, Operator::Pow.precedence() + 1).ok()?
+                        };
+                        Some(b.unary(UnaryOperator::Neg, a))
+                    }
+                }
+            }
+            '$' => {
+                self.consume('$')?;
+                Some(b.var(&format!("${}", self.ident()?)))
+            }
+            c if is_name_start(c) => {
+                let name = self.qualified_name()?;
+                // a numbered result like `out[3]`, which is looked up like a variable
+                let output = (name == "out")
+                    .then(|| {
+                        self.attempt(|p| {
+                            p.consume('[')?;
+                            let digits = p.rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(p.rest.len());
+                            let n: usize = p.rest[..digits].parse().ok()?;
+                            p.rest = &p.rest[digits..];
+                            p.consume(']')?;
+                            Some(n)
+                        })
+                    })
+                    .flatten();
+                if let Some(n) = output {
+                    Some(b.var(&format!("out[{n}]")))
+                } else if self.consume('(').is_some() {
+                    // the equations of `nsolve([x + y = 3, x * y = 2], [x, y], [0, 3])` are a function
+                    let equations = (native && name == "nsolve").then(|| self.attempt(|p| p.equations(b))).flatten();
+                    let (mut args, named) = self.args(b)?;
+                    args.splice(0..0, equations);
+                    // `roots(x ^ 2 - 2, x)` is `roots(x -> x ^ 2 - 2)`
+                    if let (true, "roots", &[p, x]) = (native, name, &args[..]) {
+                        if let Node::Var(var) = b.node(x) {
+                            let var = var.clone();
+                            args = vec![b.lambda(vec![var], p)];
+                        }
+                    }
+                    let name = self.syntax.function_name(name, args.len() + named.len());
+                    let args = match named.is_empty() {
+                        true => args,
+                        false => {
+                            let first = named[..1].to_vec();
+                            let f = functions::lookup(&name);
+                            let f = f.ok_or_else(|| format!("only built-in functions take keyword arguments, not {name}"));
+                            match f.and_then(|f| f.bind(args, named, |x| b.float(x))) {
+                                Ok(args) => args,
+                                Err(err) => {
+                                    self.error = Some(self.keyword_error(err, &first));
+                                    return None;
+                                }
+                            }
+                        }
+                    };
+                    Some(b.call(&name, args))
+                } else if let (Syntax::Native, "true" | "false") = (self.syntax, name) {
+                    Some(b.bool(name == "true"))
+                } else if let Some(f) = self.syntax.constant(name) {
+                    Some(b.float(f))
+                } else {
+                    Some(b.var(name))
+                }
+            }
             _ => {
-                self.float().map(|f| f.into())
+                let f = self.float()?;
+                if let Some(fraction) = self.fraction_part(f) {
+                    Some(b.float(f + fraction))
+                } else if let Some(rest) = self.clock(b) {
+                    let hours = b.float(f);
+                    Some(b.call("hms", [hours].into_iter().chain(rest).collect()))
+                } else {
+                    Some(b.float(f))
+                }
             },
+        }?;
+        b.set_span(id, Span { start, end: self.pos() });
+        let id = match self.unit_suffix() {
+            Some(unit) => {
+                let mut id = unit.quantity(b, id);
+                b.set_span(id, Span { start, end: self.pos() });
+                // like 5' 10" or 6 ft 2 in
+                let inches = (unit == Unit::of("feet"))
+                    .then(|| {
+                        self.attempt(|p| {
+                            p.spaces();
+                            let f = p.float()?;
+                            let f = f + p.fraction_part(f).unwrap_or(0.0);
+                            (p.unit_suffix()? == Unit::of("inches")).then_some(f)
+                        })
+                    })
+                    .flatten();
+                if let Some(inches) = inches {
+                    let n = b.float(inches);
+                    let inches = b.call("inches", vec![n]);
+                    id = b.op(Operator::Add, id, inches);
+                    b.set_span(id, Span { start, end: self.pos() });
+                }
+                id
+            }
+            None => id,
+        };
+        // a measurement with its uncertainty, like `1.50±0.02`
+        let error = self.attempt(|p| {
+            p.spaces();
+            p.consume('±')?;
+            p.spaces();
+            p.term(b)
+        });
+        if let Some(error) = error {
+            let id = b.call(PM, vec![id, error]);
+            b.set_span(id, Span { start, end: self.pos() });
+            return Some(id);
+        }
+        Some(id)
+    }
+
+    /// The unit after a quantity, like the `min` of `90 min`, the `'` of `5'`, the `km/h` of
+    /// `50 km/h` or the `USD` of `100 USD`.
+    fn unit_suffix(&mut self) -> Option<Unit<'s>> {
+        if self.syntax != Syntax::Native {
+            return None;
+        }
+        if self.consume('\'').is_some() {
+            return Some(Unit::of("feet"));
+        }
+        if self.consume('"').is_some() {
+            return Some(Unit::of("inches"));
         }
+        self.attempt(|p| {
+            p.spaces();
+            p.unit_name()
+        })
+    }
+
+    /// A unit or currency that a quantity can be converted to, like the `EUR` of `to EUR` or
+    /// the `m/s` of `to m/s`.
+    fn unit_name(&mut self) -> Option<Unit<'s>> {
+        let word = self.ident()?;
+        let name = unit(word).or(currency::is_code(word).then_some(word))?;
+        // a unit per time, like km/h, but not a division like 90 min/hours(1)
+        let per = self.attempt(|p| {
+            p.consume('/')?;
+            let per = unit(p.ident()?)?;
+            (!p.rest.starts_with('(')).then_some(per)
+        });
+        Some(Unit { name, per })
     }
 
-    fn expr(&mut self, max_precedence: u8) -> Result<Expr, String> {
+    /// The fraction of a mixed number like `1 1/2`, after its whole part `whole`.
+    fn fraction_part(&mut self, whole: f64) -> Option<f64> {
+        static RE: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"^ +(\d+)/(\d+)").unwrap());
+        if self.syntax != Syntax::Native || whole.fract() != 0.0 {
+            return None;
+        }
+        let captures = RE.captures(self.rest)?;
+        let numerator: f64 = captures[1].parse().ok()?;
+        let denominator: f64 = captures[2].parse().ok()?;
+        // `1 3/2` is most likely a typo
+        if numerator >= denominator {
+            return None;
+        }
+        self.rest = &self.rest[captures[0].len()..];
+        Some(numerator / denominator)
+    }
+
+    /// The minutes and optional seconds of a clock time like `1:30` or `1:30:15`.
+    fn clock(&mut self, b: &mut ExprBuilder) -> Option<Vec<NodeId>> {
+        static RE: LazyLock<Regex> =
+            LazyLock::new(|| Regex::new(r"^:(\d\d)(:(\d\d(\.\d+)?))?").unwrap());
+        if self.syntax != Syntax::Native {
+            return None;
+        }
+        let captures = RE.captures(self.rest)?;
+        self.rest = &self.rest[captures.get(0)?.len()..];
+        let parts = [captures.get(1), captures.get(3)];
+        Some(parts.into_iter().flatten().map(|m| b.float(m.as_str().parse().unwrap())).collect())
+    }
+
+    fn operator(&mut self, max_precedence: u8) -> Option<(&'static str, Operator)> {
+        let &(token, op) = self.syntax.operators().iter().find(|(t, _)| self.rest.starts_with(t))?;
+        if op.precedence() >= max_precedence {
+            return None;
+        }
+        self.rest = &self.rest[token.len()..];
+        Some((token, op))
+    }
+
+    fn expr(&mut self, b: &mut ExprBuilder, max_precedence: u8) -> Result<NodeId, String> {
         self.spaces();
-        let mut a = self.term().ok_or_else(|| format!("invalid term: {:?}", self.0))?;
+        let start = self.pos();
+        let mut a = self.term(b).ok_or_else(|| match &self.error {
+            Some(err) => err.clone(),
+            None => format!("invalid term: {:?}", self.rest),
+        })?;
 
+        // trailing spaces are left alone so that spans end at the last term
+        while let Some((token, op)) = self.attempt(|p| {
+            p.spaces();
+            p.operator(max_precedence)
+        }) {
+            self.spaces();
+            let c = if op.is_right_associative() {
+                self.expr(b, op.precedence() + 1)?
+            } else {
+                self.expr(b, op.precedence())?
+            };
+
+            a = match (self.syntax, token) {
+                (Syntax::Python, "%") => b.call("mod", vec![a, c]),
+                (Syntax::Python, "//") => {
+                    let q = b.op(op, a, c);
+                    b.set_span(q, Span { start, end: self.pos() });
+                    b.call("floor", vec![q])
+                }
+                _ => b.op(op, a, c),
+            };
+            b.set_span(a, Span { start, end: self.pos() });
+            if let (Syntax::Bc { scale }, Operator::Div) = (self.syntax, op) {
+                let digits = b.float(scale as f64);
+                a = b.call("trunc", vec![a, digits]);
+                b.set_span(a, Span { start, end: self.pos() });
+            }
+        }
+
+        Ok(a)
+    }
+
+    /// An expression, or a range like `1..10` of the numbers from one to the other, which binds
+    /// looser than every operator.
+    fn value(&mut self, b: &mut ExprBuilder) -> Result<NodeId, String> {
+        self.spaces();
+        let start = self.pos();
+        let from = self.expr(b, 100)?;
+        let dots = (self.syntax == Syntax::Native).then(|| {
+            self.attempt(|p| {
+                p.spaces();
+                p.consume('.')?;
+                p.consume('.')
+            })
+        });
+        if dots.flatten().is_none() {
+            return Ok(from);
+        }
+        let to = self.expr(b, 100)?;
+        let id = b.range(from, to);
+        b.set_span(id, Span { start, end: self.pos() });
+        Ok(id)
+    }
+
+    /// Consumes `c` after any spaces, or explains that it was expected after `what`.
+    fn expect(&mut self, c: char, what: &str) -> Result<(), String> {
+        self.spaces();
+        self.consume(c).ok_or_else(|| format!("expected {c} after {what}, got {:?}", self.rest))
+    }
+
+    fn keyword(&mut self, word: &str) -> Option<()> {
+        self.attempt(|p| {
+            p.spaces();
+            p.ident().filter(|&w| w == word).map(|_| ())
+        })
+    }
+
+    /// A list after its `[`, like `1, 2]`, or a list comprehension like
+    /// `x ^ 2 for x in 1..10 if x > 2]`.
+    fn list(&mut self, b: &mut ExprBuilder) -> Result<NodeId, String> {
+        let start = self.pos() - 1;
+        let mut elements = Vec::new();
+        self.spaces();
+        if self.consume(']').is_some() {
+            return Ok(b.list(elements));
+        }
+        elements.push(self.value(b)?);
+        if self.keyword("for").is_some() {
+            return self.comprehension(b, start, elements[0]);
+        }
         loop {
             self.spaces();
-            if self.0.is_empty() {
-                break;
-            }
-            let Some(op) = self.attempt(|p| {
-                let op = match p.next()? {
-                    '+' => Operator::Add,
-                    '-' => Operator::Sub,
-                    '*' => Operator::Mul,
-                    '/' => Operator::Div,
-                    _ => return None,
-                };
-                if op.precedence() >= max_precedence {
-                    return None;
+            match self.clone().next() {
+                Some(']') => {
+                    self.next();
+                    return Ok(b.list(elements));
+                }
+                Some(',') => {
+                    self.next();
+                    elements.push(self.value(b)?);
                 }
-                Some(op)
-            }) else {
-                break;
+                _ => return Err(format!("expected , or ] in the list, got {:?}", self.rest)),
+            }
+        }
+    }
+
+    /// The rest of a list comprehension like `[body for x in source if condition]` after
+    /// `for`, which is `map(filter(source, x -> condition), x -> body)`.
+    fn comprehension(&mut self, b: &mut ExprBuilder, start: usize, body: NodeId) -> Result<NodeId, String> {
+        self.spaces();
+        let var = self.ident().ok_or_else(|| format!("expected a name after for, got {:?}", self.rest))?;
+        self.keyword("in").ok_or_else(|| format!("expected in after for {var}, got {:?}", self.rest))?;
+        let mut source = self.value(b)?;
+        if self.keyword("if").is_some() {
+            let condition = self.value(b)?;
+            let f = b.lambda(vec![var.to_string()], condition);
+            source = b.call("filter", vec![source, f]);
+        }
+        self.expect(']', "the list")?;
+        b.set_span(source, Span { start, end: self.pos() });
+        let f = b.lambda(vec![var.to_string()], body);
+        Ok(b.call("map", vec![source, f]))
+    }
+
+    /// The parameters of a function without a name and its `->`, like the `x ->` of
+    /// `x -> x ^ 2` or the `(a, b) ->` of `(a, b) -> a + b`.
+    fn lambda_params(&mut self) -> Option<Vec<String>> {
+        self.attempt(|p| {
+            let params = match p.consume('(') {
+                Some(()) => p.params()?,
+                None => vec![p.ident()?.to_string()],
             };
+            p.spaces();
+            p.consume('-')?;
+            p.consume('>')?;
+            Some(params)
+        })
+    }
+
+    /// A function without a name, like `x -> x ^ 2`, which can be passed to built-in functions
+    /// like `map`.
+    fn lambda(&mut self, b: &mut ExprBuilder) -> Option<NodeId> {
+        let params = self.lambda_params()?;
+        // `x -> (y = x)` assigns nothing
+        let assignments = std::mem::replace(&mut self.assignments, false);
+        let body = self.value(b);
+        self.assignments = assignments;
+        match body {
+            Ok(body) => Some(b.lambda(params, body)),
+            Err(err) => {
+                self.error = Some(err);
+                None
+            }
+        }
+    }
+
+    /// The equations and variables of `nsolve([x + y = 3, x * y = 2], [x, y], ...` after its
+    /// `(`, and the `,` after them, as the function `(x, y) -> [x + y - 3, x * y - 2]`, which is
+    /// what `nsolve` takes.
+    fn equations(&mut self, b: &mut ExprBuilder) -> Option<NodeId> {
+        self.spaces();
+        self.consume('[')?;
+        let mut sides = Vec::new();
+        loop {
+            self.spaces();
+            let start = self.pos();
+            let lhs = self.value(b).ok()?;
             self.spaces();
-            let b = self.expr(op.precedence())?;
+            sides.push(match self.consume('=') {
+                Some(()) => {
+                    let rhs = self.value(b).ok()?;
+                    let difference = b.op(Operator::Sub, lhs, rhs);
+                    b.set_span(difference, Span { start, end: self.pos() });
+                    difference
+                }
+                None => lhs,
+            });
+            self.spaces();
+            match self.next()? {
+                ']' => break,
+                ',' => {}
+                _ => return None,
+            }
+        }
+        self.spaces();
+        self.consume(',')?;
+        self.spaces();
+        self.consume('[')?;
+        let mut vars = Vec::new();
+        loop {
+            self.spaces();
+            vars.push(self.ident()?.to_string());
+            self.spaces();
+            match self.next()? {
+                ']' => break,
+                ',' => {}
+                _ => return None,
+            }
+        }
+        self.spaces();
+        self.consume(',')?;
+        let sides = b.list(sides);
+        Some(b.lambda(vars, sides))
+    }
 
-            a = Operation::new(op, [a, b]).into();
+    /// An expression, or an assignment like `x = 1 + 2` whose value is the assigned value.
+    /// `=` binds looser than everything and from the right, so `a = b = 5` sets both.
+    fn assignment(&mut self, b: &mut ExprBuilder) -> Result<NodeId, String> {
+        let target = self.assignments.then(|| {
+            self.attempt(|p| {
+                p.spaces();
+                let start = p.pos();
+                let name = p.qualified_name()?;
+                p.spaces();
+                p.consume('=')?;
+                (!p.rest.starts_with('=')).then_some((start, name))
+            })
+        });
+        let Some((start, name)) = target.flatten() else {
+            return self.value(b);
+        };
+        let value = self.assignment(b)?;
+        let id = b.assign(name, value);
+        b.set_span(id, Span { start, end: self.pos() });
+        Ok(id)
+    }
+}
+
+/// Whether `word` is a unit or currency that quantities can be converted to with `to`, like
+/// `h`, `EUR` or `m/s`.
+pub fn is_unit(word: &str) -> bool {
+    match word.split_once('/') {
+        Some((word, per)) => is_unit(word) && unit(per).is_some(),
+        None => unit(word).is_some() || currency::is_code(word),
+    }
+}
+
+/// The function that makes a quantity from a number of the given unit.
+fn unit(word: &str) -> Option<&'static str> {
+    Some(match word {
+        "h" | "hr" | "hour" | "hours" => "hours",
+        "min" | "minute" | "minutes" => "minutes",
+        "s" | "sec" | "second" | "seconds" => "seconds",
+        "ft" | "foot" | "feet" => "feet",
+        "in" | "inch" | "inches" => "inches",
+        "mi" | "mile" | "miles" => "miles",
+        "m" | "meter" | "meters" | "metre" | "metres" => "meters",
+        "km" | "kilometer" | "kilometers" | "kilometre" | "kilometres" => "kilometers",
+        "cm" | "centimeter" | "centimeters" | "centimetre" | "centimetres" => "centimeters",
+        "mm" | "millimeter" | "millimeters" | "millimetre" | "millimetres" => "millimeters",
+        _ => return None,
+    })
+}
+
+/// A unit of quantities, as the function that makes a quantity from a number of the unit, and
+/// for units like km/h the function of the time unit that it is per.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Unit<'s> {
+    name: &'s str,
+    per: Option<&'static str>,
+}
+
+impl<'s> Unit<'s> {
+    fn of(name: &'s str) -> Self {
+        Unit { name, per: None }
+    }
+
+    /// The quantity of `n` of the unit, like `kilometers(n) / hours(1)` for km/h.
+    fn quantity(self, b: &mut ExprBuilder, n: NodeId) -> NodeId {
+        let id = b.call(self.name, vec![n]);
+        match self.per {
+            Some(per) => {
+                let one = b.float(1.0);
+                let per = b.call(per, vec![one]);
+                b.op(Operator::Div, id, per)
+            }
+            None => id,
         }
+    }
+}
 
-        Ok(a)
+/// How numbers are written with thousands separators and a decimal separator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Grouping {
+    /// like `1,234.56`
+    Commas,
+    /// like `1 234,56`
+    Spaces,
+    /// like `1.234,56`
+    Dots,
+}
+
+impl Grouping {
+    /// The thousands separator and the decimal separator.
+    fn separators(self) -> (u8, u8) {
+        match self {
+            Grouping::Commas => (b',', b'.'),
+            Grouping::Spaces => (b' ', b','),
+            Grouping::Dots => (b'.', b','),
+        }
+    }
+}
+
+/// Rewrites numbers like `1,234.56` written with `grouping` to `1234.56`. Commas in calls and
+/// lists separate arguments and items, so `max(1,234)` is left alone, and numbers that are not
+/// grouped by threes, like `1.5` with dots, too. The numbers are padded with spaces in front to
+/// keep the columns of everything else for errors.
+pub fn ungroup(line: &str, grouping: Grouping) -> Cow<'_, str> {
+    let (thousands, decimal) = grouping.separators();
+    let bytes = line.as_bytes();
+    let mut res = String::new();
+    let mut copied = None;
+    // whether each open bracket separates things with commas
+    let mut lists = Vec::new();
+    let mut quoted = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i];
+        let after_word = line[..i].ends_with(|c: char| c.is_alphanumeric() || c == '_' || c == '.');
+        match c {
+            b'"' => quoted = !quoted,
+            _ if quoted => {}
+            b'(' => lists.push(line[..i].trim_end().ends_with(|c: char| c.is_alphanumeric() || c == '_')),
+            b'[' => lists.push(true),
+            b')' | b']' => _ = lists.pop(),
+            b'0'..=b'9' if !after_word => {
+                let in_list = lists.last() == Some(&true);
+                if let Some((len, number)) = grouped_number(&line[i..], thousands, decimal, in_list) {
+                    res.push_str(&line[copied.unwrap_or(0)..i]);
+                    res.extend(std::iter::repeat_n(' ', len - number.len()));
+                    res.push_str(&number);
+                    copied = Some(i + len);
+                    i += len;
+                    continue;
+                }
+                i += bytes[i..].iter().take_while(|c| c.is_ascii_digit()).count();
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    match copied {
+        Some(end) => Cow::Owned(res + &line[end..]),
+        None => Cow::Borrowed(line),
     }
 }
 
+/// The length of the grouped number at the start of `s` and the number without separators, or
+/// `None` if it has no separators to remove. Commas in lists separate items instead.
+fn grouped_number(s: &str, thousands: u8, decimal: u8, in_list: bool) -> Option<(usize, String)> {
+    let bytes = s.as_bytes();
+    let digits = |from: usize| bytes.get(from..).map_or(0, |b| b.iter().take_while(|c| c.is_ascii_digit()).count());
+    let separated = |i: usize, separator: u8| {
+        bytes.get(i) == Some(&separator) && digits(i + 1) > 0 && !(separator == b',' && in_list)
+    };
+    let lead = digits(0);
+    let mut number = s[..lead].to_string();
+    let mut i = lead;
+    let mut grouped = false;
+    if lead <= 3 {
+        while separated(i, thousands) && digits(i + 1) == 3 {
+            number += &s[i + 1..i + 4];
+            (i, grouped) = (i + 4, true);
+        }
+    }
+    if (grouped || decimal == b',') && separated(i, decimal) {
+        let n = digits(i + 1);
+        number = number + "." + &s[i + 1..i + 1 + n];
+        (i, grouped) = (i + 1 + n, true);
+    }
+    // like `1,2345` or `1 2`, which are not numbers to guess about
+    if !grouped || separated(i, thousands) || separated(i, decimal) {
+        return None;
+    }
+    Some((i, number))
+}
+
+/// Removes every `c` from `s`. Also returns the offset in `s` of each byte offset of the result.
+fn remove_char(s: &str, c: char) -> (String, Vec<usize>) {
+    let mut res = String::with_capacity(s.len());
+    let mut offsets = Vec::with_capacity(s.len() + 1);
+    for (i, ch) in s.char_indices() {
+        if ch != c {
+            res.push(ch);
+            offsets.extend(i..i + ch.len_utf8());
+        }
+    }
+    offsets.push(s.len());
+    (res, offsets)
+}
+
+/// The brackets of `s` that are still open at its end with their columns, innermost last, or
+/// an error for one that closes nothing. Brackets in quoted text do not count, but a `"` right
+/// after a number is inches, like in `5' 6"`.
+fn open_brackets(s: &str) -> Result<Vec<(char, usize)>, Text> {
+    let mut open = Vec::new();
+    let mut quoted = false;
+    let mut prev = None;
+    for (column, c) in s.chars().enumerate().map(|(i, c)| (i + 1, c)) {
+        let after_number = prev.is_some_and(|p: char| p.is_ascii_digit());
+        prev = Some(c);
+        match c {
+            '"' if quoted || !after_number => quoted = !quoted,
+            _ if quoted => {}
+            '(' | '[' => open.push((c, column)),
+            ')' | ']' => match open.pop() {
+                Some(('(', _)) if c == ')' => {}
+                Some(('[', _)) if c == ']' => {}
+                _ => {
+                    let args = vec![c.to_string().into(), column.to_string().into()];
+                    return Err(Text::new(Key::UnexpectedBracket, args));
+                }
+            },
+            _ => {}
+        }
+    }
+    Ok(open)
+}
+
+/// Finds a bracket that is never closed or one that closes nothing, and reports its column.
+fn check_brackets(s: &str) -> Result<(), Text> {
+    match open_brackets(s)?.pop() {
+        Some((c, column)) => Err(unclosed(c, column)),
+        None => Ok(()),
+    }
+}
+
+/// The error for the bracket `c` at `column`, which is never closed.
+pub(crate) fn unclosed(c: char, column: usize) -> Text {
+    Text::new(Key::UnclosedBracket, vec![c.to_string().into(), column.to_string().into()])
+}
+
+/// Whether `line` has brackets that may be closed on the lines after it. Lines with a bracket
+/// that closes nothing are complete, since more lines can not fix them.
+pub fn is_unclosed(line: &str) -> bool {
+    open_brackets(&normalize(line)).is_ok_and(|open| !open.is_empty())
+}
+
+/// Parses a bc statement like `scale=10`, returning the new scale.
+pub fn parse_scale_assignment(line: &str) -> Option<u32> {
+    let value = line.trim().strip_prefix("scale")?.trim_start().strip_prefix('=')?;
+    value.trim().parse().ok()
+}
+
 pub fn parse_line(line: &str) -> Result<Expr, String> {
-    let mut p = Parser(line);
-    let res = p.expr(100)?;
+    parse_line_with(line, Syntax::Native)
+}
+
+pub fn parse_line_with(line: &str, syntax: Syntax) -> Result<Expr, String> {
+    match parse(line, syntax, false)? {
+        Statement::Expr(e) => Ok(e),
+        _ => unreachable!(),
+    }
+}
+
+/// Parses an expression, an assignment like `x = 1 + 2`, a function definition like
+/// `f(x) = x ^ 2`, or a memory key like `M+`.
+pub fn parse_statement(line: &str, syntax: Syntax) -> Result<Statement, Text> {
+    let key = match normalize(line).trim() {
+        "M+" => MemoryKey::Add,
+        "M-" => MemoryKey::Sub,
+        "MR" => MemoryKey::Recall,
+        "MC" => MemoryKey::Clear,
+        _ => return parse(line, syntax, true),
+    };
+    Ok(Statement::Memory(key))
+}
+
+fn parse(line: &str, syntax: Syntax, statement: bool) -> Result<Statement, Text> {
+    let normalized = normalize(line);
+    // normalization keeps every char in its column
+    check_brackets(&normalized)?;
+    // byte offsets in the original line of each byte offset in the parsed string
+    let mut offsets = match normalized {
+        Cow::Owned(ref n) => Some(original_offsets(line, n)),
+        Cow::Borrowed(_) => None,
+    };
+    let source = match syntax {
+        Syntax::Excel if normalized.contains('&') => {
+            let (stripped, stripped_offsets) = remove_char(&normalized, '&');
+            offsets = Some(match offsets {
+                Some(o) => stripped_offsets.into_iter().map(|i| o[i]).collect(),
+                None => stripped_offsets,
+            });
+            Cow::Owned(stripped)
+        }
+        _ => normalized,
+    };
+
+    // spreadsheets have no variables, and `=` compares there
+    let assignments = statement && syntax != Syntax::Excel;
+    let mut p = Parser {
+        syntax,
+        assignments,
+        ..Parser::new(&source)
+    };
+    if syntax == Syntax::Excel {
+        p.spaces();
+        p.consume('=');
+    }
+    let target = assignments
+        .then(|| {
+            p.attempt(|p| {
+                p.spaces();
+                // not a variable named memo
+                let memo = p.rest.strip_prefix("memo ").is_some_and(|r| r.trim_start().starts_with(char::is_alphabetic))
+                    && p.keyword("memo").is_some();
+                p.spaces();
+                let name = p.qualified_name()?.to_string();
+                let params = match p.consume('(') {
+                    Some(()) => Some(p.params()?),
+                    None => None,
+                };
+                p.spaces();
+                p.consume('=')?;
+                (!p.rest.starts_with('=')).then_some((name, params, memo))
+            })
+        })
+        .flatten();
+    let mut b = ExprBuilder::default();
+    let mut root = p.assignment(&mut b)?;
+    // a conversion like `90 min to hours`, which divides by one of the unit
+    let unit = p.attempt(|p| {
+        p.spaces();
+        p.ident().filter(|&w| w == "to" && syntax == Syntax::Native)?;
+        p.spaces();
+        p.unit_name()
+    });
+    if let Some(unit) = unit {
+        let end = p.pos();
+        let one = b.float(1.0);
+        let divisor = unit.quantity(&mut b, one);
+        root = b.op(Operator::Div, root, divisor);
+        b.set_span(root, Span { start: 0, end });
+    }
+    if let Some(map) = offsets {
+        b.map_spans(|s| Span {
+            start: map[s.start],
+            end: map[s.end],
+        });
+    }
     p.spaces();
-    if !p.0.is_empty() {
+    if !p.rest.is_empty() {
         Err(format!(
-            "could not parse the end of the imput, namely: {:?}",
-            p.0
-        ))
+            "could not parse the end of the input, namely: {:?}",
+            p.rest
+        )
+        .into())
     } else {
-        Ok(res)
+        let e = b.finish(root);
+        Ok(match target {
+            Some((name, Some(_), _)) if e.nodes().iter().any(|n| matches!(n, Node::Assign(..))) => {
+                return Err(format!("a function cannot assign to variables: {name}").into());
+            }
+            Some((name, Some(params), memo)) => Statement::Define(name, UserFunction { params, body: e, memo }),
+            Some((name, None, true)) => {
+                return Err(format!("only functions can be memo, like memo {name}(n) = ...").into());
+            }
+            Some((name, None, false)) if syntax.constant(&name).is_some() => {
+                return Err(format!("cannot assign to the constant {name}").into());
+            }
+            Some((name, None, false)) => Statement::Assign(name, e),
+            None => Statement::Expr(e),
+        })
     }
 }
 
@@ -119,9 +1109,9 @@ mod tests {
             (" abc", "abc"),
             ("  abc", "abc"), //
         ] {
-            let mut p = Parser(input);
+            let mut p = Parser::new(input);
             p.spaces();
-            assert_eq!(p.0, output, "input was {input:?}",);
+            assert_eq!(p.rest, output, "input was {input:?}",);
         }
     }
 
@@ -137,141 +1127,525 @@ mod tests {
             ("-1.abc", Some((-1.0, ".abc"))),
             ("+1.2", None),
         ] {
-            let mut p = Parser(input);
+            let mut p = Parser::new(input);
 
             let res = p.float();
             if let Some((output, rest)) = expected {
-                assert_eq!((res, p.0), (Some(output), rest), "parsing failed for {input:?}");
+                assert_eq!((res, p.rest), (Some(output), rest), "parsing failed for {input:?}");
             } else {
                 assert_eq!(res, None, "parsing did not fail for {input:?}");
             }
         }
     }
 
+    #[test]
+    fn parse_mixed_number() {
+        for (input, expected) in [
+            ("1 1/2", Some(1.5)),
+            ("3  3/4 + 1", Some(3.75 + 1.0)),
+            ("-1 1/2", Some(-1.5)),
+            ("2 1/2 * 2", Some(5.0)),
+            ("1 3/2", None),
+            ("1.5 1/2", None),
+            ("1 1 / 2", None),
+        ] {
+            let res = parse_line(input).ok().map(|e| e.evaluate().unwrap());
+            assert_eq!(res, expected, "input was {input:?}");
+        }
+        assert!(parse_line_with("1 1/2", Syntax::Python).is_err());
+    }
+
+    #[test]
+    fn brackets() {
+        assert_eq!(parse_line("(1 + (2 * 3)").map(|_| ()), Err("unclosed ( at column 1".into()));
+        assert_eq!(parse_line("max(1, (2)").map(|_| ()), Err("unclosed ( at column 4".into()));
+        assert_eq!(parse_line("1 + 2) * 3").map(|_| ()), Err("unexpected ) at column 6".into()));
+        assert_eq!(parse_line("（1 + 2").map(|_| ()), Err("unclosed ( at column 1".into()));
+        assert_eq!(parse_line("[1, 2").map(|_| ()), Err("unclosed [ at column 1".into()));
+        assert_eq!(parse_line("sum([1, 2)]").map(|_| ()), Err("unexpected ) at column 10".into()));
+        assert!(parse_line("format(1, \"(\")").is_ok());
+        assert!(parse_line("(5' 6\" + 1\") * 2").is_ok());
+        assert_eq!(parse_line("format((1, \")\")").map(|_| ()), Err("unclosed ( at column 7".into()));
+        assert!(parse_line("(1 + (2)) * 3").is_ok());
+    }
+
+    #[test]
+    fn unclosed() {
+        assert!(is_unclosed("max(1,"));
+        assert!(is_unclosed("[1, (2"));
+        assert!(!is_unclosed("format(x, \"(\")"));
+        assert!(!is_unclosed("(5' 6\")"));
+        assert!(!is_unclosed("1) + (2"));
+    }
+
+    #[test]
+    fn parse_ident() {
+        for (input, expected) in [
+            ("x", Some(("x", ""))),
+            ("x1+2", Some(("x1", "+2"))),
+            ("_tmp ", Some(("_tmp", " "))),
+            ("1x", None),
+            ("Δx²", Some(("Δx", "²"))),
+            ("θ_1 ", Some(("θ_1", " "))),
+        ] {
+            let mut p = Parser::new(input);
+            let res = p.ident();
+            if let Some((output, rest)) = expected {
+                assert_eq!((res, p.rest), (Some(output), rest), "parsing failed for {input:?}");
+            } else {
+                assert_eq!(res, None, "parsing did not fail for {input:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn parse_qualified_name() {
+        for (input, expected) in [
+            ("geom.area(2)", Some(("geom.area", "(2)"))),
+            ("a.b.c+1", Some(("a.b.c", "+1"))),
+            ("x.", Some(("x", "."))),
+            ("x.1", Some(("x", ".1"))),
+            (".x", None),
+        ] {
+            let mut p = Parser::new(input);
+            let res = p.qualified_name();
+            if let Some((output, rest)) = expected {
+                assert_eq!((res, p.rest), (Some(output), rest), "parsing failed for {input:?}");
+            } else {
+                assert_eq!(res, None, "parsing did not fail for {input:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn grouped_numbers() {
+        assert_eq!(ungroup("1,234.56 * 2", Grouping::Commas), " 1234.56 * 2");
+        assert_eq!(ungroup("-1,234,567", Grouping::Commas), "-  1234567");
+        assert_eq!(ungroup("max(1,234) + 1,000", Grouping::Commas), "max(1,234) +  1000");
+        assert_eq!(ungroup("2 * (1,000 + 1)", Grouping::Commas), "2 * ( 1000 + 1)");
+        assert_eq!(ungroup("1 234,56 + 1,5", Grouping::Spaces), " 1234.56 + 1.5");
+        assert_eq!(ungroup("max(1 000,5)", Grouping::Spaces), "max( 1000,5)");
+        assert_eq!(ungroup("1.234,5 + 1.5 + 2.500", Grouping::Dots), " 1234.5 + 1.5 +  2500");
+        // not grouped by threes, or parts of names and strings
+        for line in ["1,2345", "12,345,67", "1234,567", "x1,234", "format(1, \"1,000\")", "1..10"] {
+            assert_eq!(ungroup(line, Grouping::Commas), line);
+        }
+        assert_eq!(ungroup("1 2", Grouping::Spaces), "1 2");
+    }
+
     mod expr {
+        use crate::number::native;
+
         use super::*;
 
         #[test]
         fn add_2() {
-            for (input, (a, b)) in [("1+2", (1.0, 2.0)), ("1 + 2", (1.0, 2.0))] {
-                assert_eq!(
-                    parse_line(input),
-                    Ok(Operation::new(Operator::Add, [a.into(), b.into()]).into()),
-                    "failed to parse {input:?}"
-                );
+            for input in ["1+2", "1 + 2", "(1)+2", "1+(2)", "(1)+(2)"] {
+                assert_eq!(parse_line(input), Ok(expr!(1 + 2)), "failed to parse {input:?}");
+            }
+            assert_eq!(parse_line("(-1.5)+(-2.5)"), Ok(expr!(-1.5 + -2.5)));
+        }
+
+        #[test]
+        fn add_3() {
+            for input in ["1+2+3", "1 + 2 + 3", "(1+2)+3"] {
+                assert_eq!(parse_line(input), Ok(expr!((1 + 2) + 3)), "failed to parse {input:?}");
+            }
+            assert_eq!(parse_line("1+(2+3)"), Ok(expr!(1 + (2 + 3))));
+        }
+
+        #[test]
+        fn add_4() {
+            for input in ["1+2+3+4", "1 + 2 + 3 + 4"] {
+                // a + b + c + d = (((a + b) + c) + d)
+                assert_eq!(parse_line(input), Ok(expr!(((1 + 2) + 3) + 4)), "failed to parse {input:?}");
+            }
+        }
+
+        #[test]
+        fn unicode_input() {
+            assert_eq!(parse_line("１＋２"), Ok(expr!(1 + 2)));
+            assert_eq!(parse_line("3\u{00A0}\u{2212}\u{00A0}\u{2212}1"), Ok(expr!(3 - -1)));
+            assert_eq!(parse_line("1 + é"), Ok(expr!(1 + é)));
+            assert!(parse_line("1 + ¿").is_err());
+            assert!(parse_line("1 é").is_err());
+        }
+
+        #[test]
+        fn variables() {
+            assert_eq!(parse_line("x * 2"), Ok(expr!(x * 2)));
+            assert_eq!(parse_line("out[12] * 2"), Ok(Expr::var("out[12]") * 2.0));
+            assert!(parse_line("out[x]").is_err());
+            assert_eq!(parse_line("α * Δx + θ_0"), Ok(Expr::var("α") * Expr::var("Δx") + Expr::var("θ_0")));
+            assert_eq!(parse_line("2 * π"), Ok(2.0 * Expr::from(std::f64::consts::PI)));
+            assert_eq!(parse_line("pi * e"), Ok(Expr::from(std::f64::consts::PI) * std::f64::consts::E));
+            assert_eq!(
+                parse_line("x * 2").unwrap().evaluate().map_err(|e| e.message),
+                Err("unknown variable: x".into())
+            );
+        }
+
+        #[test]
+        fn negation() {
+            assert_eq!(parse_line("-x * -2"), Ok(expr!(-x * -2)));
+            assert_eq!(parse_line("--(1 + 2)"), Ok(expr!(- -(1 + 2))));
+        }
+
+        #[test]
+        fn spans() {
+            let e = parse_line(" (1 + x) * 2 ").unwrap();
+            let Node::Op(_, lhs, rhs) = *e.node(e.root()) else { panic!() };
+            let Node::Op(_, _, x) = *e.node(lhs) else { panic!() };
+
+            assert_eq!(e.span(e.root()), Some(Span { start: 1, end: 12 }));
+            assert_eq!(e.span(lhs), Some(Span { start: 1, end: 8 }));
+            assert_eq!(e.span(rhs), Some(Span { start: 11, end: 12 }));
+            assert_eq!(e.span(x), Some(Span { start: 6, end: 7 }));
+        }
+
+        #[test]
+        fn spans_in_original_input() {
+            let e = parse_line("１＋x").unwrap();
+            assert_eq!(
+                e.evaluate(),
+                Err(EvalError {
+                    message: "unknown variable: x".into(),
+                    span: Some(Span { start: 6, end: 7 }),
+                    backtrace: vec![
+                        Frame {
+                            expr: "x".into(),
+                            function: None,
+                            span: Some(Span { start: 6, end: 7 }),
+                        },
+                        Frame {
+                            expr: "1 + x".into(),
+                            function: None,
+                            span: Some(Span { start: 0, end: 7 }),
+                        },
+                    ],
+                })
+            );
+        }
+
+        #[test]
+        fn pow_order() {
+            // 2 * 3 ^ 2 ^ 2 = 2 * (3 ^ (2 ^ 2))
+            assert_eq!(parse_line("2 * 3 ^ 2 ^ 2"), Ok(expr!(2 * (3 ^ (2 ^ 2)))));
+            assert_eq!(parse_line("-2 ^ 2").unwrap().evaluate(), Ok(-4.0));
+            assert_eq!(parse_line("(-2) ^ 2").unwrap().evaluate(), Ok(4.0));
+            assert_eq!(parse_line("2 ^ -1").unwrap().evaluate(), Ok(0.5));
+        }
+
+        #[test]
+        fn comparisons() {
+            assert_eq!(
+                parse_line("1 + 1 <= 2"),
+                Ok(Operation::new(
+                    Operator::Le,
+                    [Operation::new(Operator::Add, [1.0.into(), 1.0.into()]).into(), 2.0.into()]
+                )
+                .into())
+            );
+            for (input, output) in [("1 < 2", 1.0), ("1 > 2", 0.0), ("2 == 2", 1.0), ("2 != 2", 0.0), ("3 >= 2", 1.0)] {
+                assert_eq!(parse_line(input).unwrap().evaluate(), Ok(output), "input was {input:?}");
             }
         }
 
         #[test]
-        fn add_2_paren() {
-            for (input, (a, b)) in [
-                ("(1)+2", (1.0, 2.0)),
-                ("1+(2)", (1.0, 2.0)),
-                ("(1)+(2)", (1.0, 2.0)),
-                ("(-1.5)+(-2.5)", (-1.5, -2.5)),
+        fn calls() {
+            assert_eq!(parse_line("max(1 + x, pi( ))"), Ok(expr!(max(1 + x, pi()))));
+
+            assert_eq!(parse_line("sqrt(16) + round(2.5)").unwrap().evaluate(), Ok(7.0));
+            assert!(parse_line("max(1,)").is_err());
+            assert!(parse_line("max(1").is_err());
+        }
+
+        #[test]
+        fn keyword_arguments() {
+            assert_eq!(parse_line("round(x, digits = 2)"), Ok(expr!(round(x, 2))));
+            assert_eq!(parse_line("log(base=2, x=8)").unwrap().to_string(), "log(8, 2)");
+            assert_eq!(parse_line("log(base=2, x=8)"), parse_line("log(8, 2)"));
+            assert_eq!(parse_line("hms(1, seconds = 30)"), Err("hms is missing minutes".into()));
+            assert_eq!(parse_line("1 + sqrt(round(x, y = 1))"), Err("round has no parameter named y".into()));
+            assert_eq!(
+                parse_line("round(digits = 1, 2.25)"),
+                Err("positional arguments cannot follow keyword arguments".into())
+            );
+            assert_eq!(parse_line("f(x = 1)"), Err("only built-in functions take keyword arguments, not f".into()));
+            assert_eq!(parse_line_with("math.log(8, base = 2)", Syntax::Python).unwrap().evaluate(), Ok(3.0));
+            assert_eq!(parse_line("round(1 == 1)").unwrap().evaluate(), Ok(1.0));
+        }
+
+        #[test]
+        fn excel() {
+            for (input, output) in [
+                ("=SUM(1,2,3)", 6.0),
+                ("=sum(1;2;3)", 6.0),
+                ("= 2^3", 8.0),
+                ("=-2^2", 4.0),
+                ("=ROUND(2/3, 2)", 0.67),
+                ("=IF(1+1=2, 10, 20)", 10.0),
+                ("=IF(1<>1, 10, 20)", 20.0),
+                ("=AVERAGE(1, 2, 6)", 3.0),
+                ("=INT(-2.5)", -3.0),
+                ("=IF(TRUE, 1, 0)", 1.0),
+                ("=1&2 + 1", 13.0),
             ] {
                 assert_eq!(
-                    parse_line(input),
-                    Ok(Operation::new(Operator::Add, [a.into(), b.into()]).into()),
-                    "failed to parse {input:?}"
+                    parse_line_with(input, Syntax::Excel).unwrap().evaluate(),
+                    Ok(native(output)),
+                    "input was {input:?}"
                 );
             }
+            assert!(parse_line("1 = 1").is_err());
+            assert!(parse_line("SUM(1)").unwrap().evaluate().is_err());
         }
 
         #[test]
-        fn add_3() {
-            for (input, (a, b, c)) in [("1+2+3", (1.0, 2.0, 3.0)), ("1 + 2 + 3", (1.0, 2.0, 3.0))] {
+        fn excel_spans() {
+            let e = parse_line_with("=1&2 + x", Syntax::Excel).unwrap();
+            assert_eq!(
+                e.evaluate().map_err(|e| e.span),
+                Err(Some(Span { start: 7, end: 8 }))
+            );
+            assert_eq!(e.span(e.root()), Some(Span { start: 1, end: 8 }));
+        }
+
+        #[test]
+        fn bc() {
+            for (input, scale, output) in [
+                ("7 / 2", 0, 3.0),
+                ("-7 / 2", 0, -3.0),
+                ("1 / 3", 3, 0.333),
+                ("1 / 4 * 4", 1, 0.8),
+                ("-2^2", 0, 4.0),
+                ("2^3^2", 0, 512.0),
+                ("s(0) + e(0)", 0, 1.0),
+                ("scale + 1", 4, 5.0),
+            ] {
                 assert_eq!(
-                    parse_line(input),
-                    Ok(Operation::new(
-                        Operator::Add,
-                        [
-                            Operation::new(Operator::Add, [a.into(), b.into()]).into(),
-                            c.into(),
-                        ]
-                    )
-                    .into()),
-                    "failed to parse {input:?}"
+                    parse_line_with(input, Syntax::Bc { scale }).unwrap().evaluate(),
+                    Ok(native(output)),
+                    "input was {input:?}"
                 );
             }
+            assert_eq!(parse_line("7 / 2").unwrap().evaluate(), Ok(3.5));
         }
 
+        // like `math.log(math.e)`, which is off by a bit in `f32`
+        #[cfg(not(feature = "f32"))]
         #[test]
-        fn add_4() {
-            for (input, (a, b, c, d)) in [
-                ("1+2+3+4", (1.0, 2.0, 3.0, 4.0)),
-                ("1 + 2 + 3 + 4", (1.0, 2.0, 3.0, 4.0)),
+        fn python() {
+            for (input, output) in [
+                ("2 ** 3 ** 2", 512.0),
+                ("-2 ** 2", -4.0),
+                ("2 ** -1", 0.5),
+                ("7 // 2", 3.0),
+                ("-7 // 2", -4.0),
+                ("-7 % 3", 2.0),
+                ("1 + 7 % 3 * 2", 3.0),
+                ("math.sqrt(16) * math.pi", 4.0 * std::f64::consts::PI),
+                ("math.log(math.e)", 1.0),
+                ("math.log(8, 2)", 3.0),
+                ("abs(-1) + True", 2.0),
             ] {
-                // a + b + c + d = (((a + b) + c) + d)
-                let _a = a.into();
-                let _b = b.into();
-                let _c = c.into();
-                let _d = d.into();
-                let _ab = Operation::new(Operator::Add, [_a, _b]).into();
-                let _abc = Operation::new(Operator::Add, [_ab, _c]).into();
-                let _abcd = Operation::new(Operator::Add, [_abc, _d]).into();
-                assert_eq!(parse_line(input), Ok(_abcd), "failed to parse {input:?}");
+                assert_eq!(
+                    parse_line_with(input, Syntax::Python).unwrap().evaluate(),
+                    Ok(output),
+                    "input was {input:?}"
+                );
             }
+            assert!(parse_line("2 ** 3").is_err());
+            assert!(parse_line("math.pi").unwrap().evaluate().is_err());
         }
 
         #[test]
-        fn add_3_paren() {
+        fn scale_assignment() {
+            assert_eq!(parse_scale_assignment("scale=10"), Some(10));
+            assert_eq!(parse_scale_assignment(" scale = 2 "), Some(2));
+            assert_eq!(parse_scale_assignment("scale==2"), None);
+            assert_eq!(parse_scale_assignment("scale"), None);
+            assert_eq!(parse_scale_assignment("x=2"), None);
+        }
+
+        #[test]
+        fn environment_variables() {
+            let mut b = ExprBuilder::default();
+            let width = b.var("$WIDTH");
+            let one = b.float(1.0);
+            let root = b.op(Operator::Sub, width, one);
+            assert_eq!(parse_line("$WIDTH - 1"), Ok(b.finish(root)));
+            assert!(parse_line("$ WIDTH").is_err());
+            assert!(parse_line("x = $1").is_err());
+        }
+
+        #[test]
+        fn statements() {
             assert_eq!(
-                parse_line("(1+2)+3"),
-                Ok(Operation::new(
-                    Operator::Add,
-                    [
-                        Operation::new(Operator::Add, [1.0.into(), 2.0.into()]).into(),
-                        3.0.into(),
-                    ]
-                )
-                .into()),
-                "failed to parse {:?}",
-                "(1+2)+3"
+                parse_statement("x = 1 + 2", Syntax::Native),
+                Ok(Statement::Assign("x".into(), parse_line("1 + 2").unwrap()))
             );
             assert_eq!(
-                parse_line("1+(2+3)"),
-                Ok(Operation::new(
-                    Operator::Add,
-                    [
-                        1.0.into(),
-                        Operation::new(Operator::Add, [2.0.into(), 3.0.into()]).into()
-                    ]
-                )
-                .into()),
-                "failed to parse {:?}",
-                "1+(2+3)"
+                parse_statement("x=-1", Syntax::Bc { scale: 0 }),
+                Ok(Statement::Assign("x".into(), (-1.0).into()))
+            );
+            let expr = parse_line("x == 1").map(Statement::Expr).map_err(Text::from);
+            assert_eq!(parse_statement("x == 1", Syntax::Native), expr);
+            assert_eq!(
+                parse_statement("A = 1", Syntax::Excel),
+                parse_line_with("A = 1", Syntax::Excel).map(Statement::Expr).map_err(Text::from)
+            );
+            assert!(parse_statement("x = ", Syntax::Native).is_err());
+            assert_eq!(
+                parse_statement("f(x, y) = x * y", Syntax::Native),
+                Ok(Statement::Define(
+                    "f".into(),
+                    UserFunction {
+                        params: vec!["x".into(), "y".into()],
+                        body: parse_line("x * y").unwrap(),
+                        memo: false,
+                    }
+                ))
+            );
+            assert!(matches!(
+                parse_statement("memo fib(n) = n", Syntax::Native),
+                Ok(Statement::Define(name, f)) if name == "fib" && f.memo
+            ));
+            assert!(matches!(parse_statement("memo = 2", Syntax::Native), Ok(Statement::Assign(..))));
+            assert_eq!(parse_statement("pi = 3", Syntax::Native), Err("cannot assign to the constant pi".into()));
+            assert!(matches!(parse_statement("memo(n) = n", Syntax::Native), Ok(Statement::Define(_, f)) if !f.memo));
+            assert_eq!(
+                parse_statement("memo x = 2", Syntax::Native),
+                Err("only functions can be memo, like memo x(n) = ...".into())
             );
+            assert!(matches!(parse_statement("pi() = 3", Syntax::Native), Ok(Statement::Define(..))));
+            assert!(matches!(parse_statement("f(1) == 3", Syntax::Native), Ok(Statement::Expr(_))));
+            assert!(parse_statement("f(1) = 3", Syntax::Native).is_err());
+            assert_eq!(parse_statement(" M+ ", Syntax::Native), Ok(Statement::Memory(MemoryKey::Add)));
+            assert_eq!(parse_statement("ＭＲ", Syntax::Native), Ok(Statement::Memory(MemoryKey::Recall)));
+            assert!(matches!(parse_statement("M + 1", Syntax::Native), Ok(Statement::Expr(_))));
+            assert!(parse_line("x = 1").is_err());
+
+            let Ok(Statement::Assign(_, e)) = parse_statement("ab = 1 + foo", Syntax::Native) else {
+                panic!();
+            };
+            assert_eq!(e.span(e.root()), Some(Span { start: 5, end: 12 }));
         }
 
         #[test]
-        fn add_mul_order() {
+        fn lists() {
+            let formatted = |input: &str| match parse_statement(input, Syntax::Native) {
+                Ok(Statement::Expr(e)) => Ok(e.to_string()),
+                Ok(Statement::Assign(name, e)) => Ok(format!("{name} = {e}")),
+                Ok(_) => panic!("{input} is not an expression"),
+                Err(err) => Err(err),
+            };
+            for input in [
+                "[]",
+                "[1, -2, x ^ 2]",
+                "1..n + 1",
+                "range(0, 1, 0.1)",
+                "sum(1..10)",
+                "avg([x, 2])",
+                "map(v, x -> x ^ 2)",
+                "filter(1..10, x -> x > 0)",
+                "reduce(map(v, x -> -x), 0, (a, b) -> a + b)",
+                "s = sort(reverse(v))",
+                "len(unique(cumsum(1..3))) + 1",
+                "v = odesolve((x, y) -> x * y, 1, range(0, 1, 0.1))",
+                "ifft(map(v, x -> x / 2))",
+                "roots([1, 0, -2])",
+                "sum(roots(v))",
+                "len(factor(2 ^ 10 - 1))",
+                "f(sort(x)) * 2",
+                "(x -> x) + 1",
+                "-(1..2)",
+            ] {
+                assert_eq!(formatted(input), Ok(input.to_string()));
+            }
+            // comprehensions and equations are written with the functions that they are
+            for (input, output) in [
+                ("[x ^ 2 for x in 1..10]", "map(1..10, x -> x ^ 2)"),
+                ("v = [x for x in [1, 2] if x > 1]", "v = map(filter([1, 2], x -> x > 1), x -> x)"),
+                ("[x  for x in 1 ..2 + 3 if x>1 ]", "map(filter(1..2 + 3, x -> x > 1), x -> x)"),
+                ("nsolve([x + y = 3, x * y - 2], [x, y], [1, 1])", "nsolve((x, y) -> [x + y - 3, x * y - 2], [1, 1])"),
+                ("nsolve([cos(t) = t], [t], v)", "nsolve(t -> [cos(t) - t], v)"),
+                ("r = roots((x - a) ^ 2 - 2, x)", "r = roots(x -> (x - a) ^ 2 - 2)"),
+            ] {
+                assert_eq!(formatted(input), Ok(output.to_string()), "input was {input:?}");
+            }
+            for (input, error) in [
+                ("[1, 2", "unclosed [ at column 1"),
+                ("[x for 1 in 1..2]", "expected a name after for, got \"1 in 1..2]\""),
+                ("[x for x of 1..2]", "expected in after for x, got \" of 1..2]\""),
+                ("[x for x in v if]", "invalid term: \"]\""),
+                ("[1; 2]", "expected , or ] in the list, got \"; 2]\""),
+                ("x ->", "invalid term: \"\""),
+            ] {
+                assert_eq!(formatted(input), Err(error.into()), "input was {input:?}");
+            }
+        }
+
+        #[test]
+        fn formats() {
+            let Ok(Statement::Expr(e)) = parse_statement("format(x / 3, \".2e\")", Syntax::Native) else {
+                panic!();
+            };
+            let format = e.nodes().iter().find_map(|n| match n {
+                Node::Text(text) => Some(text.as_str()),
+                _ => None,
+            });
+            assert_eq!(format, Some(".2e"));
+            assert_eq!(parse_line("\"0.3f"), Err("expected \" at the end of the text, got \"0.3f\"".into()));
+        }
+
+        #[test]
+        fn assignment_expressions() {
+            let Ok(Statement::Expr(e)) = parse_statement("(x = 3) * 2", Syntax::Native) else {
+                panic!();
+            };
+            let Node::Op(Operator::Mul, a, _) = *e.node(e.root()) else { panic!() };
+            assert!(matches!(e.node(a), Node::Assign(name, _) if name == "x"));
+            assert_eq!(e.span(a), Some(Span { start: 0, end: 7 }));
+
+            let Ok(Statement::Assign(a, e)) = parse_statement("a = b = 5", Syntax::Native) else {
+                panic!();
+            };
+            assert_eq!(a, "a");
+            assert!(matches!(e.node(e.root()), Node::Assign(name, _) if name == "b"));
+            assert_eq!(e.span(e.root()), Some(Span { start: 4, end: 9 }));
+
+            // `=` binds looser than everything else
+            assert!(parse_statement("1 + x = 3", Syntax::Native).is_err());
+            assert!(parse_statement("(x == 3) * 2", Syntax::Native).is_ok());
+            assert!(parse_line("(x = 3) * 2").is_err());
             assert_eq!(
-                parse_line("1*2+3"),
-                Ok(Operation::new(
-                    Operator::Add,
-                    [
-                        Operation::new(Operator::Mul, [1.0.into(), 2.0.into()]).into(),
-                        3.0.into(),
-                    ]
-                )
-                .into()),
-                "failed to parse {:?}",
-                "1*2+3"
+                parse_statement("f(x) = (y = x)", Syntax::Native),
+                Err("a function cannot assign to variables: f".into())
             );
+
+            // `=` in a call is a keyword argument, so assigning there needs parentheses
             assert_eq!(
-                parse_line("1+2*3"),
-                Ok(Operation::new(
-                    Operator::Add,
-                    [
-                        1.0.into(),
-                        Operation::new(Operator::Mul, [2.0.into(), 3.0.into()]).into(),
-                    ]
-                )
-                .into()),
-                "failed to parse {:?}",
-                "1+2*3"
+                parse_statement("if(0, x = 5, 1)", Syntax::Native),
+                Err("positional arguments cannot follow keyword arguments; to assign x in an argument, put it in \
+                     parentheses, like (x = 1)"
+                    .into())
+            );
+            assert_eq!(
+                parse_statement("round(1, y = 2)", Syntax::Native),
+                Err("round has no parameter named y; to assign y in an argument, put it in parentheses, like (y = 1)".into())
             );
+            let Ok(Statement::Expr(e)) = parse_statement("if(0, (x = 5), 1)", Syntax::Native) else {
+                panic!();
+            };
+            let Node::Call(_, ref args) = *e.node(e.root()) else { panic!() };
+            assert!(matches!(e.node(args[1]), Node::Assign(name, _) if name == "x"));
+        }
+
+        #[test]
+        fn add_mul_order() {
+            assert_eq!(parse_line("1*2+3"), Ok(expr!((1 * 2) + 3)));
+            assert_eq!(parse_line("1+2*3"), Ok(expr!(1 + (2 * 3))));
         }
     }
 }
@@ -0,0 +1,250 @@
+//! Polynomials, for `roots`.
+
+use crate::fft::Complex;
+use crate::i18n::Text;
+use crate::repr::{EvalError, Expr, Node, NodeId, Operator, UnaryOperator};
+
+/// The highest degree of polynomials, so that `x ^ 1000000` does not make a million coefficients.
+const DEGREE_LIMIT: usize = 1000;
+
+/// How many rounds the Durand-Kerner method takes at most.
+const ITERATIONS: usize = 1000;
+
+/// The coefficients of the subexpression `id` of `e` as a polynomial in `var`, lowest degree
+/// first. The parts without `var` are evaluated by `constant`, so they can use variables.
+pub fn coefficients(
+    e: &Expr,
+    id: NodeId,
+    var: &str,
+    constant: &mut dyn FnMut(NodeId) -> Result<f64, EvalError>,
+) -> Result<Vec<f64>, EvalError> {
+    // children come before their parents, so one pass finds where var is used
+    let mut uses = Vec::with_capacity(e.nodes().len());
+    for node in e.nodes() {
+        let used = match node {
+            Node::Var(name) => name == var,
+            Node::Float(_) | Node::Bool(_) | Node::Text(_) => false,
+            &Node::Unary(_, a) | &Node::Assign(_, a) | &Node::Lambda(_, a) => uses[a.index()],
+            &Node::Op(_, a, b) | &Node::Range(a, b) => uses[a.index()] || uses[b.index()],
+            Node::Call(_, args) | Node::List(args) => args.iter().any(|a| uses[a.index()]),
+        };
+        uses.push(used);
+    }
+    polynomial(e, id, var, &uses, constant)
+}
+
+fn polynomial(
+    e: &Expr,
+    id: NodeId,
+    var: &str,
+    uses: &[bool],
+    constant: &mut dyn FnMut(NodeId) -> Result<f64, EvalError>,
+) -> Result<Vec<f64>, EvalError> {
+    if !uses[id.index()] {
+        return Ok(vec![constant(id)?]);
+    }
+    let error = |message: Text| EvalError {
+        message,
+        span: e.span(id),
+        backtrace: Vec::new(),
+    };
+    let not_polynomial = || {
+        error(format!("roots needs a polynomial in {var}, with only +, -, *, / by numbers and whole powers").into())
+    };
+    let mut sub = |id| polynomial(e, id, var, uses, constant);
+    let res = match e.node(id) {
+        Node::Var(_) => vec![0.0, 1.0],
+        &Node::Unary(UnaryOperator::Neg, a) => sub(a)?.into_iter().map(|c| -c).collect(),
+        &Node::Op(Operator::Add, a, b) => add(&sub(a)?, &sub(b)?, 1.0),
+        &Node::Op(Operator::Sub, a, b) => add(&sub(a)?, &sub(b)?, -1.0),
+        &Node::Op(Operator::Mul, a, b) => mul(&sub(a)?, &sub(b)?),
+        &Node::Op(Operator::Div, a, b) if !uses[b.index()] => {
+            // without var, b is a constant
+            let d = sub(b)?[0];
+            sub(a)?.into_iter().map(|c| c / d).collect()
+        }
+        &Node::Op(Operator::Pow, a, b) if !uses[b.index()] => {
+            let n = sub(b)?[0];
+            if n < 0.0 || n.fract() != 0.0 {
+                return Err(not_polynomial());
+            }
+            let base = sub(a)?;
+            let degree = (base.len() - 1) as f64 * n;
+            if degree > DEGREE_LIMIT as f64 {
+                return Err(error(format!("roots takes polynomials up to degree {DEGREE_LIMIT}, got {degree}").into()));
+            }
+            (0..n as usize).fold(vec![1.0], |p, _| mul(&p, &base))
+        }
+        _ => return Err(not_polynomial()),
+    };
+    if res.len() > DEGREE_LIMIT + 1 {
+        return Err(error(format!("roots takes polynomials up to degree {DEGREE_LIMIT}, got {}", res.len() - 1).into()));
+    }
+    Ok(res)
+}
+
+/// `a + sign * b`
+fn add(a: &[f64], b: &[f64], sign: f64) -> Vec<f64> {
+    let mut res = vec![0.0; a.len().max(b.len())];
+    for (i, c) in a.iter().enumerate() {
+        res[i] += c;
+    }
+    for (i, c) in b.iter().enumerate() {
+        res[i] += sign * c;
+    }
+    res
+}
+
+fn mul(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut res = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            res[i + j] += x * y;
+        }
+    }
+    res
+}
+
+/// The value of the polynomial at `x`, by Horner's method with the rounding errors of each step
+/// added back, which is as accurate as doubling the precision. Near a root, where the terms
+/// cancel, plain Horner cannot tell which of two neighbouring numbers is closer to it.
+fn eval(coefficients: &[f64], x: f64) -> f64 {
+    let (mut sum, mut error) = (0.0f64, 0.0);
+    for &c in coefficients.iter().rev() {
+        let product = sum * x;
+        let product_error = sum.mul_add(x, -product);
+        let next = product + c;
+        let z = next - product;
+        let sum_error = (product - (next - z)) + (c - z);
+        (sum, error) = (next, error * x + product_error + sum_error);
+    }
+    sum + error
+}
+
+/// The real roots of the polynomial with `coefficients`, lowest degree first, from smallest to
+/// largest and repeated as many times as they are roots. Complex roots are left out, since
+/// results cannot be complex.
+pub fn roots(coefficients: &[f64]) -> Result<Vec<f64>, String> {
+    if let Some(c) = coefficients.iter().find(|c| !c.is_finite()) {
+        return Err(format!("roots needs finite coefficients, got {c}"));
+    }
+    let Some(degree) = coefficients.iter().rposition(|&c| c != 0.0) else {
+        return Err("every number is a root of 0".into());
+    };
+    let p = &coefficients[..=degree];
+    // x = 0 is a root as many times as x divides the polynomial
+    let zeros = p.iter().position(|&c| c != 0.0).unwrap_or(0);
+    let mut res = vec![0.0; zeros];
+    let p = &p[zeros..];
+    match p.len() - 1 {
+        0 => {}
+        1 => res.push(-p[0] / p[1]),
+        _ => {
+            for z in durand_kerner(p) {
+                // repeated roots are only found to about half the digits, also in their
+                // imaginary parts
+                if z.im.abs() <= 1e-6 * z.abs().max(1.0) {
+                    res.push(polish(p, z.re));
+                }
+            }
+        }
+    }
+    res.sort_by(f64::total_cmp);
+    Ok(res)
+}
+
+/// All the complex roots of the polynomial, which are improved together until they stop moving.
+fn durand_kerner(p: &[f64]) -> Vec<Complex> {
+    let n = p.len() - 1;
+    let monic: Vec<Complex> = p.iter().map(|c| Complex::new(c / p[n], 0.0)).collect();
+    // every root is within this radius, and starting them on a circle that is not symmetric
+    // around the real axis keeps conjugates apart
+    let radius = 1.0 + monic[..n].iter().map(|c| c.abs()).fold(0.0, f64::max);
+    let mut z: Vec<Complex> = (0..n)
+        .map(|k| {
+            let angle = 2.0 * std::f64::consts::PI * k as f64 / n as f64 + 0.4;
+            Complex::new(radius * angle.cos(), radius * angle.sin())
+        })
+        .collect();
+    for _ in 0..ITERATIONS {
+        let mut moved = 0.0f64;
+        for k in 0..n {
+            let value = monic.iter().rev().fold(Complex::new(0.0, 0.0), |acc, &c| acc.mul(z[k]).add(c));
+            let others = (0..n).filter(|&j| j != k).fold(Complex::new(1.0, 0.0), |acc, j| acc.mul(z[k].sub(z[j])));
+            let step = value.div(others);
+            if step.re.is_finite() && step.im.is_finite() {
+                z[k] = z[k].sub(step);
+                moved = moved.max(step.abs() / z[k].abs().max(1.0));
+            }
+        }
+        if moved <= f64::EPSILON {
+            break;
+        }
+    }
+    z
+}
+
+/// Improves a real root with Newton's method, as long as the polynomial does not get further
+/// from 0.
+fn polish(p: &[f64], mut x: f64) -> f64 {
+    let derivative: Vec<f64> = p.iter().enumerate().skip(1).map(|(i, c)| i as f64 * c).collect();
+    for _ in 0..10 {
+        let next = x - eval(p, x) / eval(&derivative, x);
+        if next == x || !next.is_finite() || eval(p, next).abs() > eval(p, x).abs() {
+            break;
+        }
+        x = next;
+    }
+    x
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::Context;
+    use crate::parser::{parse_statement, Syntax};
+    use crate::repr::Statement;
+
+    fn close(xs: &[f64], expected: &[f64]) -> bool {
+        xs.len() == expected.len() && xs.iter().zip(expected).all(|(x, e)| (x - e).abs() < 1e-6)
+    }
+
+    #[test]
+    fn real_roots() {
+        assert_eq!(roots(&[-2.0, 0.0, 1.0]), Ok(vec![-2f64.sqrt(), 2f64.sqrt()]));
+        assert_eq!(roots(&[6.0, -5.0, 1.0]), Ok(vec![2.0, 3.0]));
+        assert_eq!(roots(&[1.0, 0.0, 1.0]), Ok(vec![]));
+        assert_eq!(roots(&[0.0, 0.0, 2.0, 0.0]), Ok(vec![0.0, 0.0]));
+        assert_eq!(roots(&[3.0, 2.0]), Ok(vec![-1.5]));
+        assert_eq!(roots(&[5.0]), Ok(vec![]));
+        assert_eq!(roots(&[0.0, 0.0]), Err("every number is a root of 0".into()));
+        assert!(roots(&[f64::NAN, 1.0]).is_err());
+        // (x - 1)^2 (x + 2) (x^2 + 1)
+        let p = mul(&mul(&mul(&[-1.0, 1.0], &[-1.0, 1.0]), &[2.0, 1.0]), &[1.0, 0.0, 1.0]);
+        let res = roots(&p).unwrap();
+        assert!(close(&res, &[-2.0, 1.0, 1.0]), "{res:?}");
+        // the Wilkinson-like (x - 1)(x - 2)...(x - 8)
+        let p = (1..=8).fold(vec![1.0], |p, k| mul(&p, &[-k as f64, 1.0]));
+        let res = roots(&p).unwrap();
+        assert!(close(&res, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0]), "{res:?}");
+    }
+
+    #[test]
+    fn expressions() {
+        let coefficients = |input: &str| {
+            let Ok(Statement::Expr(e)) = parse_statement(input, Syntax::Native) else { panic!("{input}") };
+            let ctx = Context::default();
+            coefficients(&e, e.root(), "x", &mut |id| e.evaluate_at(id, &ctx)).map_err(|e| e.message)
+        };
+        assert_eq!(coefficients("x ^ 2 - 2"), Ok(vec![-2.0, 0.0, 1.0]));
+        assert_eq!(coefficients("(x + 1) ^ 3 / 2"), Ok(vec![0.5, 1.5, 1.5, 0.5]));
+        assert_eq!(coefficients("-(2 * x) * (x - 3)"), Ok(vec![0.0, 6.0, -2.0]));
+        assert_eq!(coefficients("sqrt(4)"), Ok(vec![2.0]));
+        let message = "roots needs a polynomial in x, with only +, -, *, / by numbers and whole powers";
+        assert_eq!(coefficients("sin(x)"), Err(message.into()));
+        assert_eq!(coefficients("1 / x"), Err(message.into()));
+        assert_eq!(coefficients("x ^ 0.5"), Err(message.into()));
+        assert_eq!(coefficients("x ^ 1001"), Err("roots takes polynomials up to degree 1000, got 1001".into()));
+        assert_eq!(coefficients("y * x"), Err("unknown variable: y".into()));
+    }
+}
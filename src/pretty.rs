@@ -0,0 +1,236 @@
+use std::fmt;
+
+use crate::repr::*;
+
+/// Prints the expression in the native syntax, with only the parentheses that are needed
+/// to parse it back into the same tree.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.pretty_node(self.root(), f)
+    }
+}
+
+impl Expr {
+    /// The subexpression at `id` in the native syntax, like `sqrt(x - 2)`.
+    pub(crate) fn subexpression(&self, id: NodeId) -> String {
+        At(self, id, Expr::pretty_node).to_string()
+    }
+
+    /// The expression with parentheses around every operation, like `(1 + (2 * (3 ^ 2)))`,
+    /// which shows how it was parsed.
+    pub fn parenthesized(&self) -> String {
+        At(self, self.root(), Expr::parenthesized_node).to_string()
+    }
+
+    fn parenthesized_node(&self, id: NodeId, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.node(id) {
+            Node::Unary(UnaryOperator::Neg, a) => {
+                f.write_str("(-")?;
+                self.parenthesized_node(a, f)?;
+                f.write_str(")")
+            }
+            Node::Op(op, a, b) => {
+                f.write_str("(")?;
+                self.parenthesized_node(a, f)?;
+                write!(f, " {} ", op.symbol())?;
+                self.parenthesized_node(b, f)?;
+                f.write_str(")")
+            }
+            Node::Call(ref name, ref args) => {
+                write!(f, "{name}(")?;
+                for (i, &a) in args.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.parenthesized_node(a, f)?;
+                }
+                f.write_str(")")
+            }
+            Node::Assign(ref name, a) => {
+                write!(f, "({name} = ")?;
+                self.parenthesized_node(a, f)?;
+                f.write_str(")")
+            }
+            Node::List(ref elements) => {
+                f.write_str("[")?;
+                for (i, &e) in elements.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.parenthesized_node(e, f)?;
+                }
+                f.write_str("]")
+            }
+            Node::Range(a, b) => {
+                self.parenthesized_node(a, f)?;
+                f.write_str("..")?;
+                self.parenthesized_node(b, f)
+            }
+            Node::Lambda(ref params, body) => {
+                write_params(params, f)?;
+                self.parenthesized_node(body, f)
+            }
+            Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) => self.pretty_node(id, f),
+        }
+    }
+
+    fn pretty_node(&self, id: NodeId, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.node(id) {
+            Node::Float(v) => write!(f, "{v}"),
+            Node::Bool(b) => write!(f, "{b}"),
+            Node::Var(ref name) => f.write_str(name),
+            Node::Unary(UnaryOperator::Neg, a) => {
+                f.write_str("-")?;
+                // -x^2 already means -(x^2)
+                let parens = match *self.node(a) {
+                    Node::Op(op, _, _) => op != Operator::Pow,
+                    Node::Assign(..) | Node::Range(..) | Node::Lambda(..) => true,
+                    _ => false,
+                };
+                self.pretty_operand(a, parens, f)
+            }
+            Node::Op(op, a, b) => {
+                self.pretty_operand(a, self.needs_parens(a, op, false), f)?;
+                write!(f, " {} ", op.symbol())?;
+                self.pretty_operand(b, self.needs_parens(b, op, true), f)
+            }
+            Node::Call(ref name, ref args) => {
+                write!(f, "{name}(")?;
+                for (i, &a) in args.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.pretty_operand(a, matches!(self.node(a), Node::Assign(..)), f)?;
+                }
+                f.write_str(")")
+            }
+            Node::Assign(ref name, a) => {
+                write!(f, "{name} = ")?;
+                self.pretty_node(a, f)
+            }
+            Node::List(ref elements) => {
+                f.write_str("[")?;
+                for (i, &e) in elements.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    self.pretty_operand(e, matches!(self.node(e), Node::Assign(..)), f)?;
+                }
+                f.write_str("]")
+            }
+            Node::Range(a, b) => {
+                self.pretty_operand(a, matches!(self.node(a), Node::Assign(..) | Node::Lambda(..)), f)?;
+                f.write_str("..")?;
+                self.pretty_operand(b, matches!(self.node(b), Node::Assign(..)), f)
+            }
+            Node::Text(ref text) => write!(f, "\"{text}\""),
+            Node::Lambda(ref params, body) => {
+                write_params(params, f)?;
+                self.pretty_node(body, f)
+            }
+        }
+    }
+
+    fn pretty_operand(&self, id: NodeId, parens: bool, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if parens {
+            f.write_str("(")?;
+            self.pretty_node(id, f)?;
+            f.write_str(")")
+        } else {
+            self.pretty_node(id, f)
+        }
+    }
+
+    /// Whether the operand `id` of `parent` would be parsed differently without parentheses.
+    pub(crate) fn needs_parens(&self, id: NodeId, parent: Operator, right: bool) -> bool {
+        match *self.node(id) {
+            Node::Op(op, _, _) => {
+                op.precedence() > parent.precedence()
+                    || (op.precedence() == parent.precedence() && right != parent.is_right_associative())
+            }
+            // -2^2 and -x^2 are negated powers
+            Node::Float(v) => parent == Operator::Pow && !right && v.is_sign_negative(),
+            Node::Unary(..) => parent == Operator::Pow && !right,
+            // `=`, `..` and `->` bind looser than everything
+            Node::Assign(..) | Node::Range(..) | Node::Lambda(..) => true,
+            Node::Bool(_) | Node::Var(_) | Node::Call(..) | Node::List(_) | Node::Text(_) => false,
+        }
+    }
+}
+
+/// Writes the parameters of a function without a name and its `->`, like `x -> ` or `(a, b) -> `.
+fn write_params(params: &[String], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match params {
+        [param] => write!(f, "{param} -> "),
+        params => write!(f, "({}) -> ", params.join(", ")),
+    }
+}
+
+/// Displays the subexpression at an id with one of the ways of printing nodes.
+struct At<'e>(&'e Expr, NodeId, fn(&Expr, NodeId, &mut fmt::Formatter<'_>) -> fmt::Result);
+
+impl fmt::Display for At<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.2(self.0, self.1, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    #[test]
+    fn minimal_parentheses() {
+        for (input, output) in [
+            ("1+2 *(3 )", "1 + 2 * 3"),
+            ("(1 + 2) * 3", "(1 + 2) * 3"),
+            ("1 - (2 - 3)", "1 - (2 - 3)"),
+            ("(1 - 2) - 3", "1 - 2 - 3"),
+            ("1 + (2 + 3)", "1 + (2 + 3)"),
+            ("(2 ^ 3) ^ 2", "(2 ^ 3) ^ 2"),
+            ("2 ^ (3 ^ 2)", "2 ^ 3 ^ 2"),
+            ("(-2) ^ 2", "(-2) ^ 2"),
+            ("-(2 ^ 2)", "-2 ^ 2"),
+            ("-(x + 1) * -y", "-(x + 1) * -y"),
+            ("max(1, (2), x < 3)", "max(1, 2, x < 3)"),
+            ("(true) == (x < 3)", "true == (x < 3)"),
+            ("map([1,2, 3], x->x^2)", "map([1, 2, 3], x -> x ^ 2)"),
+            ("reduce(1 .. 4, (a,b) -> a*b)", "reduce(1..4, (a, b) -> a * b)"),
+            ("format(x, \"0.3f\")", "format(x, \"0.3f\")"),
+        ] {
+            let e = parse_line(input).unwrap();
+            assert_eq!(e.to_string(), output, "input was {input:?}");
+            assert_eq!(parse_line(output), Ok(e), "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn all_parentheses() {
+        for (input, output) in [
+            ("1+2*3^2", "(1 + (2 * (3 ^ 2)))"),
+            ("1 - 2 - 3", "((1 - 2) - 3)"),
+            ("2 ^ 3 ^ 2", "(2 ^ (3 ^ 2))"),
+            ("-x^2 + -2", "((-(x ^ 2)) + -2)"),
+            ("max(1 + 2, x) < 3", "(max((1 + 2), x) < 3)"),
+            ("x", "x"),
+        ] {
+            let e = parse_line(input).unwrap();
+            assert_eq!(e.parenthesized(), output, "input was {input:?}");
+            assert_eq!(parse_line(output), Ok(e), "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn assignments() {
+        use crate::parser::{parse_statement, Syntax};
+        use crate::repr::Statement;
+
+        for (input, output) in [("(x = 3) * 2", "(x = 3) * 2"), ("max((a = (b = 1)), 2)", "max((a = b = 1), 2)")] {
+            let Ok(Statement::Expr(e)) = parse_statement(input, Syntax::Native) else {
+                panic!("input was {input:?}");
+            };
+            assert_eq!(e.to_string(), output, "input was {input:?}");
+            assert_eq!(parse_statement(output, Syntax::Native), Ok(Statement::Expr(e)), "input was {input:?}");
+        }
+    }
+}
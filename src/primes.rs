@@ -0,0 +1,199 @@
+//! Prime factorization, for `factor`, and modular arithmetic, for `powmod` and `invmod`.
+
+use std::time::{Duration, Instant};
+
+/// How long `factor` tries before it gives up with the factors it has found.
+pub const TIME_LIMIT: Duration = Duration::from_secs(5);
+
+/// The primes that are divided out before Pollard's rho, which is slow for small factors.
+const SMALL_PRIMES: [u64; 12] = [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37];
+
+/// How far trial division goes.
+const TRIAL_LIMIT: u64 = 1000;
+
+/// A factorization that ran out of time.
+#[derive(Debug, PartialEq)]
+pub struct Partial {
+    /// the prime factors that were found
+    pub found: Vec<u64>,
+    /// what is left to factor, which is composite
+    pub left: u64,
+}
+
+/// `a * b mod m` without overflowing.
+pub fn mul_mod(a: u64, b: u64, m: u64) -> u64 {
+    (a as u128 * b as u128 % m as u128) as u64
+}
+
+/// `base ^ exponent mod m` by repeated squaring.
+pub fn pow_mod(mut base: u64, mut exponent: u64, m: u64) -> u64 {
+    let mut res = 1 % m;
+    base %= m;
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            res = mul_mod(res, base, m);
+        }
+        base = mul_mod(base, base, m);
+        exponent >>= 1;
+    }
+    res
+}
+
+/// The `x` where `a * x mod m` is 1, by the extended Euclidean algorithm, or `None` if `a` and
+/// `m` have a common factor.
+pub fn inv_mod(a: u64, m: u64) -> Option<u64> {
+    // r = s * a mod m holds for both rows
+    let (mut r0, mut r1) = (m as i128, (a % m) as i128);
+    let (mut s0, mut s1) = (0i128, 1i128);
+    while r1 != 0 {
+        let q = r0 / r1;
+        (r0, r1) = (r1, r0 - q * r1);
+        (s0, s1) = (s1, s0 - q * s1);
+    }
+    (r0 == 1).then(|| s0.rem_euclid(m as i128) as u64)
+}
+
+pub fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Whether `n` is prime, by the Miller-Rabin test with the first 12 primes as bases, which
+/// has no false positives below 2^64.
+pub fn is_prime(n: u64) -> bool {
+    if n < 2 {
+        return false;
+    }
+    if let Some(&p) = SMALL_PRIMES.iter().find(|&&p| n.is_multiple_of(p)) {
+        return n == p;
+    }
+    let s = (n - 1).trailing_zeros();
+    let d = (n - 1) >> s;
+    SMALL_PRIMES.iter().all(|&a| {
+        let mut x = pow_mod(a, d, n);
+        if x == 1 || x == n - 1 {
+            return true;
+        }
+        for _ in 1..s {
+            x = mul_mod(x, x, n);
+            if x == n - 1 {
+                return true;
+            }
+        }
+        false
+    })
+}
+
+/// The prime factors of `n` from smallest to largest, repeated as many times as they divide
+/// it, or the ones found before `deadline`. 1 has no prime factors.
+pub fn factor(mut n: u64, deadline: Instant) -> Result<Vec<u64>, Partial> {
+    let mut found = Vec::new();
+    let mut p = 2;
+    while p < TRIAL_LIMIT && p * p <= n {
+        while n.is_multiple_of(p) {
+            found.push(p);
+            n /= p;
+        }
+        p += if p == 2 { 1 } else { 2 };
+    }
+    let mut composites = Vec::new();
+    match n {
+        1 => {}
+        n if is_prime(n) => found.push(n),
+        n => composites.push(n),
+    }
+    while let Some(n) = composites.pop() {
+        let mut c = 1;
+        let d = loop {
+            if Instant::now() >= deadline {
+                found.sort_unstable();
+                let left = composites.iter().product::<u64>() * n;
+                return Err(Partial { found, left });
+            }
+            match rho(n, c, deadline) {
+                Some(d) => break d,
+                None => c += 1,
+            }
+        };
+        for part in [d, n / d] {
+            if is_prime(part) {
+                found.push(part);
+            } else {
+                composites.push(part);
+            }
+        }
+    }
+    found.sort_unstable();
+    Ok(found)
+}
+
+/// A nontrivial factor of the composite `n` by Pollard's rho with `x^2 + c`, or `None` if this
+/// `c` finds none or the deadline passes.
+fn rho(n: u64, c: u64, deadline: Instant) -> Option<u64> {
+    let f = |x| (mul_mod(x, x, n) + c) % n;
+    let (mut x, mut y, mut d) = (2, 2, 1);
+    let mut steps = 0u64;
+    while d == 1 {
+        x = f(x);
+        y = f(f(y));
+        d = gcd(x.abs_diff(y), n);
+        steps += 1;
+        if steps.is_multiple_of(1024) && Instant::now() >= deadline {
+            return None;
+        }
+    }
+    (d != n).then_some(d)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn factor(n: u64) -> Result<Vec<u64>, Partial> {
+        super::factor(n, Instant::now() + TIME_LIMIT)
+    }
+
+    #[test]
+    fn primes() {
+        let small: Vec<u64> = (0..50).filter(|&n| is_prime(n)).collect();
+        assert_eq!(small, [2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47]);
+        assert!(is_prime(9007199254740881));
+        assert!(!is_prime(9007199254740881 * 3));
+        // a strong pseudoprime to the bases 2, 3, 5 and 7
+        assert!(!is_prime(3215031751));
+        assert!(is_prime(u64::MAX - 58));
+    }
+
+    #[test]
+    fn factors() {
+        assert_eq!(factor(1), Ok(vec![]));
+        assert_eq!(factor(360), Ok(vec![2, 2, 2, 3, 3, 5]));
+        assert_eq!(factor(600851475143), Ok(vec![71, 839, 1471, 6857]));
+        assert_eq!(factor((1 << 53) - 1), Ok(vec![6361, 69431, 20394401]));
+        assert_eq!(factor(67108879 * 67108913), Ok(vec![67108879, 67108913]));
+        assert_eq!(factor(1009 * 1009 * 1013), Ok(vec![1009, 1009, 1013]));
+        assert_eq!(factor(9007199254740881), Ok(vec![9007199254740881]));
+    }
+
+    #[test]
+    fn out_of_time() {
+        let res = super::factor(4 * 67108879 * 67108913, Instant::now());
+        assert_eq!(res, Err(Partial { found: vec![2, 2], left: 67108879 * 67108913 }));
+    }
+
+    #[test]
+    fn modular_arithmetic() {
+        assert_eq!(pow_mod(3, 200, 1000003), (0..200).fold(1, |x, _| x * 3 % 1000003));
+        assert_eq!(pow_mod(2, 0, 1), 0);
+        assert_eq!(mul_mod(u64::MAX, u64::MAX, u64::MAX - 1), 1);
+        assert_eq!(inv_mod(3, 7), Some(5));
+        assert_eq!(inv_mod(10, 7), Some(5));
+        assert_eq!(inv_mod(4, 6), None);
+        assert_eq!(inv_mod(5, 1), Some(0));
+        assert_eq!(inv_mod(2, (1 << 61) - 1), Some(1 << 60));
+        assert_eq!(gcd(12, 18), 6);
+        assert_eq!(gcd(0, 5), 5);
+    }
+}
@@ -0,0 +1,190 @@
+//! Timing the evaluation of each subexpression, for `:profile`.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use crate::context::Context;
+use crate::repr::{Expr, Node, NodeId};
+
+/// What the time outside user functions is shown as.
+const EXPRESSION: &str = "(expression)";
+
+/// How often a node was evaluated and how long it took.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timing {
+    pub count: usize,
+    /// the time with the nodes below it, without the calls that were inside another call of the
+    /// same function, which are counted already
+    pub total: Duration,
+    /// the time without the nodes below it
+    pub own: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Profile {
+    /// the timing of each node, by the user function whose body it is in, or `None` for the
+    /// expression that is profiled
+    pub timings: HashMap<(Option<String>, NodeId), Timing>,
+    /// the time of the finished children of each node that is being evaluated, innermost last
+    children: Vec<Duration>,
+}
+
+impl Profile {
+    pub fn enter(&mut self) {
+        self.children.push(Duration::ZERO);
+    }
+
+    /// Records that evaluating a node took `elapsed`. `nested` is whether its function was called
+    /// inside another call of it.
+    pub fn leave(&mut self, function: Option<String>, id: NodeId, elapsed: Duration, nested: bool) {
+        let children = self.children.pop().unwrap_or_default();
+        if let Some(parent) = self.children.last_mut() {
+            *parent += elapsed;
+        }
+        let timing = self.timings.entry((function, id)).or_default();
+        timing.count += 1;
+        timing.own += elapsed.saturating_sub(children);
+        if !nested {
+            timing.total += elapsed;
+        }
+    }
+
+    fn timing(&self, function: Option<&str>, id: NodeId) -> Timing {
+        let key = (function.map(str::to_string), id);
+        self.timings.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Rows of the time spent in each built-in function, and in the operators and variables of
+    /// each user function and of `e`, most first.
+    pub fn flat(&self, e: &Expr, ctx: &Context) -> Vec<Vec<String>> {
+        let total = self.timing(None, e.root()).total;
+        let mut rows: HashMap<String, (usize, Duration)> = HashMap::new();
+        for ((function, id), timing) in &self.timings {
+            let Some(body) = body(e, ctx, function.as_deref()) else { continue };
+            let outer = function.clone().unwrap_or(EXPRESSION.into());
+            // each call of a user function evaluates the root of its body once
+            if *id == body.root() {
+                rows.entry(outer.clone()).or_default().0 += timing.count;
+            }
+            let row = match body.node(*id) {
+                Node::Call(name, _) if ctx.func(name).is_none() => {
+                    let row = rows.entry(name.clone()).or_default();
+                    row.0 += timing.count;
+                    row
+                }
+                _ => rows.entry(outer).or_default(),
+            };
+            row.1 += timing.own;
+        }
+        let mut rows: Vec<_> = rows.into_iter().collect();
+        rows.sort_by(|a, b| b.1 .1.cmp(&a.1 .1).then_with(|| a.0.cmp(&b.0)));
+        rows.into_iter()
+            .map(|(name, (calls, own))| vec![name, calls.to_string(), duration(own), percent(own, total)])
+            .collect()
+    }
+
+    /// Rows of the time spent in each subexpression of `e` with those below it, indented by how
+    /// deep they are. The bodies of user functions are below their first call, and numbers and
+    /// variables are left out.
+    pub fn tree(&self, e: &Expr, ctx: &Context) -> Vec<Vec<String>> {
+        let mut tree = Tree {
+            profile: self,
+            ctx,
+            total: self.timing(None, e.root()).total,
+            expanded: Vec::new(),
+            rows: Vec::new(),
+        };
+        tree.add(e, None, e.root(), 0);
+        tree.rows
+    }
+}
+
+/// The rows of [`Profile::tree`] so far.
+struct Tree<'a> {
+    profile: &'a Profile,
+    ctx: &'a Context,
+    total: Duration,
+    /// the user functions whose bodies are shown already
+    expanded: Vec<String>,
+    rows: Vec<Vec<String>>,
+}
+
+impl Tree<'_> {
+    fn add(&mut self, e: &Expr, function: Option<&str>, id: NodeId, depth: usize) {
+        let timing = self.profile.timing(function, id);
+        let children = match e.node(id) {
+            Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) => return,
+            // like the branch of `if` that is not taken
+            _ if timing.count == 0 => return,
+            &Node::Unary(_, a) | &Node::Assign(_, a) | &Node::Lambda(_, a) => vec![a],
+            &Node::Op(_, a, b) | &Node::Range(a, b) => vec![a, b],
+            Node::Call(_, args) | Node::List(args) => args.clone(),
+        };
+        // the root of a body is labeled with its function
+        let label = match function {
+            Some(name) if id == e.root() => format!("{name}: {}", e.subexpression(id)),
+            _ => e.subexpression(id),
+        };
+        let (total, count) = (duration(timing.total), timing.count.to_string());
+        self.rows.push(vec![total, percent(timing.total, self.total), count, "  ".repeat(depth) + &label]);
+        for child in children {
+            self.add(e, function, child, depth + 1);
+        }
+        let ctx = self.ctx;
+        if let Node::Call(name, _) = e.node(id) {
+            if let Some(f) = ctx.func(name).filter(|_| !self.expanded.contains(name)) {
+                self.expanded.push(name.clone());
+                self.add(&f.body, Some(name), f.body.root(), depth + 1);
+            }
+        }
+    }
+}
+
+/// The expression that the nodes of `function` are in.
+fn body<'a>(e: &'a Expr, ctx: &'a Context, function: Option<&str>) -> Option<&'a Expr> {
+    match function {
+        Some(name) => ctx.func(name).map(|f| &f.body),
+        None => Some(e),
+    }
+}
+
+/// Like `1.250 ms`.
+fn duration(d: Duration) -> String {
+    format!("{:.3} ms", d.as_secs_f64() * 1e3)
+}
+
+fn percent(part: Duration, total: Duration) -> String {
+    if total.is_zero() {
+        return "-".into();
+    }
+    format!("{:.1}%", part.as_secs_f64() / total.as_secs_f64() * 100.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::parse_line;
+
+    #[test]
+    fn views() {
+        let e = parse_line("sqrt(4) + 1").unwrap();
+        let ids: Vec<NodeId> = e.ids().collect();
+        let [four, sqrt, one, sum] = ids[..] else { panic!("{ids:?}") };
+        let ms = Duration::from_millis;
+        let mut profile = Profile::default();
+        profile.enter();
+        profile.enter();
+        profile.enter();
+        profile.leave(None, four, ms(1), false);
+        profile.leave(None, sqrt, ms(3), false);
+        profile.enter();
+        profile.leave(None, one, ms(1), false);
+        profile.leave(None, sum, ms(5), false);
+
+        let ctx = Context::default();
+        let flat = [["(expression)", "1", "3.000 ms", "60.0%"], ["sqrt", "1", "2.000 ms", "40.0%"]];
+        assert_eq!(profile.flat(&e, &ctx), flat);
+        let tree = [["5.000 ms", "100.0%", "1", "sqrt(4) + 1"], ["3.000 ms", "60.0%", "1", "  sqrt(4)"]];
+        assert_eq!(profile.tree(&e, &ctx), tree);
+    }
+}
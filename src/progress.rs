@@ -0,0 +1,92 @@
+//! A spinner that shows that a slow computation is still going.
+
+use std::io::Write;
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+/// How long a computation can take before the spinner is shown.
+pub const DELAY: Duration = Duration::from_millis(200);
+
+const INTERVAL: Duration = Duration::from_millis(100);
+
+const FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// Shows a spinner on `out` after a delay, until it is dropped. The spinner is erased when it
+/// is dropped, so that the result can be printed where it was.
+pub struct Spinner {
+    stop: Option<Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Spinner {
+    pub fn start(delay: Duration, mut out: impl Write + Send + 'static) -> Spinner {
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = thread::spawn(move || {
+            if stopped.recv_timeout(delay) != Err(RecvTimeoutError::Timeout) {
+                return;
+            }
+            for frame in FRAMES.iter().cycle() {
+                // the spinner is not worth failing the computation for
+                let _ = write!(out, "\r{frame} computing").and_then(|_| out.flush());
+                if stopped.recv_timeout(INTERVAL) != Err(RecvTimeoutError::Timeout) {
+                    break;
+                }
+            }
+            let _ = write!(out, "\r\x1b[K").and_then(|_| out.flush());
+        });
+        Spinner {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        // disconnecting the channel stops the thread
+        self.stop.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn fast_computations_show_nothing() {
+        let out = Shared::default();
+        drop(Spinner::start(Duration::from_secs(10), out.clone()));
+        assert!(out.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn slow_computations_show_a_spinner() {
+        let out = Shared::default();
+        let spinner = Spinner::start(Duration::ZERO, out.clone());
+        thread::sleep(Duration::from_millis(150));
+        drop(spinner);
+
+        let out = String::from_utf8(out.0.lock().unwrap().clone()).unwrap();
+        assert!(out.starts_with("\r| computing"), "{out:?}");
+        assert!(out.ends_with("\r\x1b[K"), "{out:?}");
+    }
+}
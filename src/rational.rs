@@ -0,0 +1,189 @@
+//! Exact rational arithmetic, used to show results like `1/6` as `0.1(6)`.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::context::Context;
+use crate::repr::*;
+
+/// The longest repeating part that is shown.
+const MAX_PERIOD: usize = 100;
+
+/// A fraction in lowest terms, with a positive denominator.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Rational {
+    num: i128,
+    den: i128,
+}
+
+fn gcd(a: i128, b: i128) -> i128 {
+    if b == 0 { a.abs() } else { gcd(b, a % b) }
+}
+
+impl Rational {
+    pub fn new(num: i128, den: i128) -> Option<Self> {
+        if den == 0 {
+            return None;
+        }
+        let g = gcd(num, den) * den.signum();
+        Some(Rational {
+            num: num / g,
+            den: den / g,
+        })
+    }
+
+    /// The numerator and denominator.
+    pub fn parts(self) -> (i128, i128) {
+        (self.num, self.den)
+    }
+
+    /// The exact value of the decimal number that is printed for `f`, so `0.1` is `1/10`.
+    pub fn from_f64(f: f64) -> Option<Self> {
+        let s = f.to_string();
+        if s.contains(['e', 'i', 'N']) {
+            return None;
+        }
+        let (whole, fraction) = s.split_once('.').unwrap_or((&s, ""));
+        let den = 10i128.checked_pow(fraction.len() as u32)?;
+        let num: i128 = format!("{whole}{fraction}").parse().ok()?;
+        Rational::new(num, den)
+    }
+
+    fn add(self, o: Self) -> Option<Self> {
+        let num = self.num.checked_mul(o.den)?.checked_add(o.num.checked_mul(self.den)?)?;
+        Rational::new(num, self.den.checked_mul(o.den)?)
+    }
+
+    fn mul(self, o: Self) -> Option<Self> {
+        Rational::new(self.num.checked_mul(o.num)?, self.den.checked_mul(o.den)?)
+    }
+
+    fn neg(self) -> Self {
+        Rational {
+            num: -self.num,
+            den: self.den,
+        }
+    }
+
+    fn recip(self) -> Option<Self> {
+        Rational::new(self.den, self.num)
+    }
+
+    fn pow(self, exp: Self) -> Option<Self> {
+        if exp.den != 1 {
+            return None;
+        }
+        let base = if exp.num < 0 { self.recip()? } else { self };
+        let exp = u32::try_from(exp.num.unsigned_abs()).ok()?;
+        Rational::new(base.num.checked_pow(exp)?, base.den.checked_pow(exp)?)
+    }
+
+    /// Whether the decimal expansion goes on forever.
+    pub fn is_repeating(self) -> bool {
+        let mut den = self.den;
+        for p in [2, 5] {
+            while den % p == 0 {
+                den /= p;
+            }
+        }
+        den != 1
+    }
+}
+
+/// Writes the decimal expansion, with the repeating part in parentheses like `0.1(6)`.
+/// Falls back to the floating point value when the repeating part is very long.
+impl fmt::Display for Rational {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.num < 0 { "-" } else { "" };
+        let (whole, mut rem) = (self.num.abs() / self.den, self.num.abs() % self.den);
+        let mut digits = String::new();
+        // where the digits for each remainder started
+        let mut seen = HashMap::new();
+        while rem != 0 {
+            if let Some(&start) = seen.get(&rem) {
+                return write!(f, "{sign}{whole}.{}({})", &digits[..start], &digits[start..]);
+            }
+            if digits.len() > MAX_PERIOD {
+                return write!(f, "{}", self.num as f64 / self.den as f64);
+            }
+            seen.insert(rem, digits.len());
+            rem *= 10;
+            digits.push(char::from(b'0' + (rem / self.den) as u8));
+            rem %= self.den;
+        }
+        if digits.is_empty() {
+            write!(f, "{sign}{whole}")
+        } else {
+            write!(f, "{sign}{whole}.{digits}")
+        }
+    }
+}
+
+impl Expr {
+    /// Evaluates the expression without rounding errors. Only works for arithmetic on
+    /// numbers and variables, with integer powers.
+    pub(crate) fn evaluate_exact(&self, ctx: &Context) -> Option<Rational> {
+        self.exact_node(self.root(), ctx)
+    }
+
+    fn exact_node(&self, id: NodeId, ctx: &Context) -> Option<Rational> {
+        match *self.node(id) {
+            Node::Float(f) => Rational::from_f64(f),
+            Node::Var(ref name) => Rational::from_f64(ctx.var(name)?),
+            // the variables that it assigns would be read as they were before
+            Node::Assign(..) => None,
+            Node::Unary(UnaryOperator::Neg, a) => Some(self.exact_node(a, ctx)?.neg()),
+            Node::Op(op, a, b) => {
+                let (a, b) = (self.exact_node(a, ctx)?, self.exact_node(b, ctx)?);
+                match op {
+                    Operator::Add => a.add(b),
+                    Operator::Sub => a.add(b.neg()),
+                    Operator::Mul => a.mul(b),
+                    Operator::Div => a.mul(b.recip()?),
+                    Operator::Pow => a.pow(b),
+                    _ => None,
+                }
+            }
+            Node::Bool(_) | Node::Call(..) | Node::List(_) | Node::Range(..) | Node::Text(_) | Node::Lambda(..) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    fn exact(input: &str) -> Option<String> {
+        let e = parse_line(input).unwrap();
+        e.evaluate_exact(&Context::default()).map(|r| r.to_string())
+    }
+
+    #[test]
+    fn decimals() {
+        assert_eq!(exact("1 / 3"), Some("0.(3)".into()));
+        assert_eq!(exact("1 / 6"), Some("0.1(6)".into()));
+        assert_eq!(exact("-22 / 7"), Some("-3.(142857)".into()));
+        assert_eq!(exact("0.1 + 0.2"), Some("0.3".into()));
+        assert_eq!(exact("(2 / 3) ^ -2"), Some("2.25".into()));
+        assert_eq!(exact("4 / 2"), Some("2".into()));
+        // the period of 1/109 has 108 digits
+        assert_eq!(exact("1 / 109"), Some((1.0 / 109.0).to_string()));
+    }
+
+    #[test]
+    fn not_exact() {
+        assert_eq!(exact("2 ^ 0.5"), None);
+        assert_eq!(exact("sqrt(4)"), None);
+        assert_eq!(exact("1 / 0"), None);
+        assert_eq!(exact("x"), None);
+        assert_eq!(exact("10 ^ 100"), None);
+    }
+
+    #[test]
+    fn repeating() {
+        assert!(Rational::new(1, 3).unwrap().is_repeating());
+        assert!(!Rational::new(3, 40).unwrap().is_repeating());
+    }
+}
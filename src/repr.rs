@@ -1,28 +1,653 @@
+use std::fmt;
+use std::ops;
+
+use crate::context::Context;
+use crate::eval::Assignments;
+use crate::fft::Complex;
+use crate::format::mark_rounded;
+use crate::i18n::{Key, Text};
+use crate::number::{Backend, F64, NATIVE};
+use crate::rational::Rational;
+use crate::uncertainty::Measurement;
+
+/// Index of a node within the arena of an [`Expr`].
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct NodeId(u32);
+
+impl NodeId {
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+}
+
+/// Byte range of a node in the parsed input.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
 
 #[derive(Debug, PartialEq, Clone)]
-pub enum Expr {
+pub struct EvalError {
+    pub message: Text,
+    /// the subexpression that failed, if the expression was parsed from some input
+    pub span: Option<Span>,
+    /// the subexpressions that were being evaluated, from the one that failed out to the
+    /// whole expression
+    pub backtrace: Vec<Frame>,
+}
+
+/// A subexpression that was being evaluated when an error happened.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Frame {
+    /// the subexpression in the native syntax
+    pub expr: String,
+    /// the user function whose body it is in, or `None` for the evaluated expression
+    pub function: Option<String>,
+    /// where it is in the input, but only for the evaluated expression
+    pub span: Option<Span>,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// Stack for each level of nesting when checking, which is generous since frames are large
+/// in debug builds.
+pub const STACK_PER_LEVEL: usize = 16 << 10;
+
+/// The message of an error in the body of the user function `name`, which says so unless an
+/// outer call of the function will, or the message names the call already.
+pub fn in_function(name: &str, message: Text, recursive: bool) -> Text {
+    if recursive || message.key() == Some(Key::RecursionLimit) {
+        message
+    } else {
+        Text::new(Key::InFunction, vec![name.into(), message])
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node {
     Float(f64),
-    Op(Operation),
+    Bool(bool),
+    Var(String),
+    Unary(UnaryOperator, NodeId),
+    Op(Operator, NodeId, NodeId),
+    Call(String, Vec<NodeId>),
+    /// `name = value` inside an expression, which has the assigned value
+    Assign(String, NodeId),
+    /// a list like `[1, 2, 3]`
+    List(Vec<NodeId>),
+    /// `from..to`, which counts up from `from` by 1 to at most `to`, so `1..3` is `[1, 2, 3]`
+    Range(NodeId, NodeId),
+    /// text in double quotes, like the `"0.3f"` of `format(x, "0.3f")`
+    Text(String),
+    /// a function without a name, like `x -> x ^ 2` or `(a, b) -> a + b`, which only built-in
+    /// functions like `map` take
+    Lambda(Vec<String>, NodeId),
 }
 
-impl Expr {
-    pub fn evaluate(&self) -> Result<f64, String> {
+/// One line of input.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Statement {
+    Expr(Expr),
+    /// `name = expr`, where the expression may be a list
+    Assign(String, Expr),
+    /// `name(params) = body`
+    Define(String, UserFunction),
+    Memory(MemoryKey),
+}
+
+/// The result of running a [`Statement`].
+#[derive(Debug, PartialEq, Clone)]
+pub enum Value {
+    Number(f64),
+    Boolean(bool),
+    Measurement(Measurement),
+    /// in seconds
+    Duration(f64),
+    /// in the metric unit that the symbol names, like `m`, or in inches without one, which are
+    /// shown as feet and inches
+    Length(f64, Option<&'static str>),
+    /// in meters per second
+    Speed(f64),
+    /// an amount of the currency named by the string
+    Money(f64, String),
+    /// an exact number, which is shown with its repeating decimals like `0.(3)`
+    Rational(Rational),
+    /// a number computed with another backend than `f64`, already formatted
+    Precise(String),
+    List(Vec<f64>),
+    /// text, like a number formatted by `format`
+    Text(String),
+    /// the result of `fft`, which can only be shown
+    ComplexList(Vec<Complex>),
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            Expr::Float(f) => Ok(*f),
-            Expr::Op(n) => n.evaluate(),
+            // without the digits that only come from widening to `f64`
+            Value::Number(n) if cfg!(feature = "f32") => write!(f, "{}", *n as f32),
+            Value::Number(n) => write!(f, "{n}"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Measurement(m) => write!(f, "{m}"),
+            Value::Rational(r) => write!(f, "{r}"),
+            Value::Precise(digits) | Value::Text(digits) => f.write_str(digits),
+            Value::List(values) => {
+                f.write_str("[")?;
+                for (i, &x) in values.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{separator}{}", Value::Number(x))?;
+                }
+                f.write_str("]")
+            }
+            Value::ComplexList(values) => {
+                f.write_str("[")?;
+                for (i, c) in values.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{separator}{c}")?;
+                }
+                f.write_str("]")
+            }
+            Value::Money(amount, currency) => write!(f, "{} {currency}", mark_rounded(format!("{amount:.2}"), *amount)),
+            // like 1:30 or 0:00:05.5
+            &Value::Duration(seconds) => {
+                let sign = if seconds < 0.0 { "-" } else { "" };
+                let hours = (seconds.abs() / 3600.0).floor();
+                let minutes = ((seconds.abs() - hours * 3600.0) / 60.0).floor();
+                let seconds = seconds.abs() - hours * 3600.0 - minutes * 60.0;
+                write!(f, "{sign}{hours}:{minutes:02}")?;
+                match seconds {
+                    0.0 => Ok(()),
+                    s if s < 10.0 => write!(f, ":0{s}"),
+                    s => write!(f, ":{s}"),
+                }
+            }
+            Value::Speed(v) => write!(f, "{} m/s", Value::Number(*v)),
+            // like 5' 10 1/2", to the nearest 1/16 inch
+            &Value::Length(length, Some(symbol)) => write!(f, "{} {symbol}", Value::Number(length)),
+            &Value::Length(inches, None) => {
+                let sign = if inches < 0.0 { "-" } else { "" };
+                let sixteenths = (inches.abs() * 16.0).round() as u64;
+                let (feet, whole, mut n) = (sixteenths / 192, sixteenths % 192 / 16, sixteenths % 16);
+                let mut d = 16;
+                while n != 0 && n % 2 == 0 {
+                    (n, d) = (n / 2, d / 2);
+                }
+                match (whole, n) {
+                    (_, 0) => write!(f, "{sign}{feet}' {whole}\""),
+                    (0, _) => write!(f, "{sign}{feet}' {n}/{d}\""),
+                    _ => write!(f, "{sign}{feet}' {whole} {n}/{d}\""),
+                }
+            }
+        }
+    }
+}
+
+/// A function defined in a session, like `f(x, y) = x * y`.
+#[derive(Debug, PartialEq, Clone)]
+pub struct UserFunction {
+    pub params: Vec<String>,
+    pub body: Expr,
+    /// whether results are cached by their arguments, for `memo f(n) = ...`
+    pub memo: bool,
+}
+
+/// The memory keys of a desk calculator, which act on the most recent result.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum MemoryKey {
+    /// `M+`
+    Add,
+    /// `M-`
+    Sub,
+    /// `MR`
+    Recall,
+    /// `MC`
+    Clear,
+}
+
+/// An expression tree stored as a flat arena of nodes.
+///
+/// The operands of a node always come before it and the last node is the root. Otherwise the
+/// order depends on how the tree was built, like for keyword arguments, which are put in the
+/// order of the parameters after they are parsed. So trees are compared by their structure and
+/// not by their buffers.
+///
+/// Spans are not taken into account when comparing trees.
+#[derive(Clone)]
+pub struct Expr {
+    nodes: Vec<Node>,
+    spans: Vec<Option<Span>>,
+}
+
+impl PartialEq for Expr {
+    fn eq(&self, other: &Self) -> bool {
+        let mut pending = vec![(self.root(), other.root())];
+        while let Some((a, b)) = pending.pop() {
+            match (self.node(a), other.node(b)) {
+                (&Node::Unary(x, a), &Node::Unary(y, b)) if x == y => pending.push((a, b)),
+                (&Node::Op(x, a1, a2), &Node::Op(y, b1, b2)) if x == y => pending.extend([(a1, b1), (a2, b2)]),
+                (Node::Assign(x, a), Node::Assign(y, b)) if x == y => pending.push((*a, *b)),
+                (Node::Call(f, a), Node::Call(g, b)) if f == g && a.len() == b.len() => {
+                    pending.extend(a.iter().copied().zip(b.iter().copied()))
+                }
+                (Node::List(a), Node::List(b)) if a.len() == b.len() => {
+                    pending.extend(a.iter().copied().zip(b.iter().copied()))
+                }
+                (&Node::Range(a1, a2), &Node::Range(b1, b2)) => pending.extend([(a1, b1), (a2, b2)]),
+                (Node::Lambda(x, a), Node::Lambda(y, b)) if x == y => pending.push((*a, *b)),
+                (x, y) if x != y => return false,
+                _ => {}
+            }
+        }
+        true
+    }
+}
+
+impl Expr {
+    pub(crate) fn root(&self) -> NodeId {
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    pub(crate) fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    pub(crate) fn span(&self, id: NodeId) -> Option<Span> {
+        self.spans[id.0 as usize]
+    }
+
+    /// All nodes, operands before the nodes using them.
+    pub(crate) fn nodes(&self) -> &[Node] {
+        &self.nodes
+    }
+
+    /// The number of nodes on the longest path from the root to a leaf.
+    pub fn depth(&self) -> usize {
+        let mut depths = Vec::with_capacity(self.nodes.len());
+        for node in &self.nodes {
+            let children = match node {
+                Node::Unary(_, a) | Node::Assign(_, a) | Node::Lambda(_, a) => vec![*a],
+                Node::Op(_, a, b) | Node::Range(a, b) => vec![*a, *b],
+                Node::Call(_, args) | Node::List(args) => args.clone(),
+                Node::Float(_) | Node::Bool(_) | Node::Var(_) | Node::Text(_) => vec![],
+            };
+            depths.push(1 + children.iter().map(|id| depths[id.index()]).max().unwrap_or(0));
+        }
+        depths.last().copied().unwrap_or(0)
+    }
+
+    /// The ids of all nodes, children before their parents.
+    pub(crate) fn ids(&self) -> impl Iterator<Item = NodeId> {
+        (0..self.nodes.len() as u32).map(NodeId)
+    }
+
+    pub fn evaluate(&self) -> Result<f64, EvalError> {
+        self.evaluate_in(&Context::default())
+    }
+
+    /// Evaluates the expression with the variables and functions of `ctx`.
+    pub(crate) fn evaluate_in(&self, ctx: &Context) -> Result<f64, EvalError> {
+        self.evaluate_with(&NATIVE, ctx).map(|x| NATIVE.to_f64(&x))
+    }
+
+    /// Evaluates the subexpression at `id`.
+    pub(crate) fn evaluate_at(&self, id: NodeId, ctx: &Context) -> Result<f64, EvalError> {
+        self.evaluate_node(id, ctx, &F64, &[])
+    }
+
+    /// Like [`Expr::evaluate_in`], but with the numbers of `backend`.
+    pub(crate) fn evaluate_with<B: Backend>(&self, backend: &B, ctx: &Context) -> Result<B::Num, EvalError> {
+        self.evaluate_node(self.root(), ctx, backend, &[])
+    }
+
+    /// Like [`Expr::evaluate_with`], but also returns the variables that the expression assigns,
+    /// in the order it assigns them, for the caller to assign if it succeeds.
+    pub(crate) fn evaluate_assigning_with<B: Backend>(
+        &self,
+        backend: &B,
+        ctx: &Context,
+    ) -> Result<(B::Num, Assignments<B::Num>), EvalError> {
+        self.evaluate_assigning(self.root(), ctx, backend, &[])
+    }
+
+    fn fmt_node(&self, id: NodeId, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self.node(id) {
+            Node::Float(v) => write!(f, "Float({v:?})"),
+            Node::Bool(b) => write!(f, "Bool({b})"),
+            Node::Var(ref name) => write!(f, "Var({name:?})"),
+            Node::Unary(op, a) => {
+                write!(f, "Unary({op:?}, ")?;
+                self.fmt_node(a, f)?;
+                write!(f, ")")
+            }
+            Node::Op(op, a, b) => {
+                write!(f, "Op({op:?}, ")?;
+                self.fmt_node(a, f)?;
+                write!(f, ", ")?;
+                self.fmt_node(b, f)?;
+                write!(f, ")")
+            }
+            Node::Assign(ref name, a) => {
+                write!(f, "Assign({name:?}, ")?;
+                self.fmt_node(a, f)?;
+                write!(f, ")")
+            }
+            Node::Call(ref name, ref args) => {
+                write!(f, "Call({name:?}, ")?;
+                self.fmt_nodes(args, f)?;
+                write!(f, ")")
+            }
+            Node::List(ref elements) => {
+                write!(f, "List(")?;
+                self.fmt_nodes(elements, f)?;
+                write!(f, ")")
+            }
+            Node::Range(a, b) => {
+                write!(f, "Range(")?;
+                self.fmt_node(a, f)?;
+                write!(f, ", ")?;
+                self.fmt_node(b, f)?;
+                write!(f, ")")
+            }
+            Node::Text(ref text) => write!(f, "Text({text:?})"),
+            Node::Lambda(ref params, body) => {
+                write!(f, "Lambda({params:?}, ")?;
+                self.fmt_node(body, f)?;
+                write!(f, ")")
+            }
         }
     }
+
+    fn fmt_nodes(&self, ids: &[NodeId], f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[")?;
+        for (i, &a) in ids.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            self.fmt_node(a, f)?;
+        }
+        write!(f, "]")
+    }
+}
+
+impl fmt::Debug for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.fmt_node(self.root(), f)
+    }
 }
 
 impl From<f64> for Expr {
     fn from(val: f64) -> Self {
-        Expr::Float(val)
+        let mut b = ExprBuilder::default();
+        let root = b.float(val);
+        b.finish(root)
     }
 }
 
 impl From<Operation> for Expr {
     fn from(val: Operation) -> Self {
-        Expr::Op(val)
+        // reuse the buffers of the left operand, since it already sits in the right place
+        let mut b = ExprBuilder {
+            nodes: val.lhs.nodes,
+            spans: val.lhs.spans,
+        };
+        let lhs = NodeId(b.nodes.len() as u32 - 1);
+        let rhs = b.append(val.rhs);
+        let root = b.op(val.op, lhs, rhs);
+        b.finish(root)
+    }
+}
+
+// for building trees by hand, like programs using the library do
+impl Expr {
+    pub fn var(name: &str) -> Expr {
+        let mut b = ExprBuilder::default();
+        let root = b.var(name);
+        b.finish(root)
+    }
+
+    pub fn call(name: &str, args: Vec<Expr>) -> Expr {
+        let mut b = ExprBuilder::default();
+        let args = args.into_iter().map(|a| b.append(a)).collect();
+        let root = b.call(name, args);
+        b.finish(root)
+    }
+
+    pub fn pow(self, exponent: impl Into<Expr>) -> Expr {
+        Operation::new(Operator::Pow, [self, exponent.into()]).into()
+    }
+}
+
+/// Builds the tree of an expression like `expr!(1 + 2 * x ^ 2 - max(x, 3))`, with the same
+/// shape as the parser gives. Only numbers, variables, calls, parentheses, `-` and the
+/// arithmetic operators are supported, and `^` only between numbers, variables, calls and
+/// parentheses, like `x ^ -2` or `(1 + x) ^ y ^ 2`.
+// only used in tests so far
+#[allow(unused_macros)]
+macro_rules! expr {
+    // `@operand`: waiting for an operand, after any number of `-`. A `-` would be taken as the
+    // start of a negative literal, so it is never left in front of a `literal` fragment.
+    (@operand [$($out:tt)*] - - $($rest:tt)*) => {
+        $crate::repr::expr!(@operand [$($out)* -] - $($rest)*)
+    };
+    (@operand [$($out:tt)*] - $lit:literal ^ $($rest:tt)*) => {
+        $crate::repr::expr!(@power [$($out)* -] [] $lit ^ $($rest)*)
+    };
+    (@operand [$($out:tt)*] - $lit:literal $($rest:tt)*) => {
+        $crate::repr::expr!(@operator [$($out)* ($crate::repr::Expr::from(-($lit as f64)))] $($rest)*)
+    };
+    (@operand [$($out:tt)*] - $($rest:tt)*) => {
+        $crate::repr::expr!(@operand [$($out)* -] $($rest)*)
+    };
+    (@operand [$($out:tt)*] $($rest:tt)*) => {
+        $crate::repr::expr!(@power [$($out)*] [] $($rest)*)
+    };
+
+    // `@power`: the bases so far of a power like `x ^ y ^ 2`, which is applied from the right
+    (@power [$($out:tt)*] [$($bases:tt)*] - $lit:literal $($rest:tt)*) => {
+        $crate::repr::expr!(@base [$($out)*] [$($bases)*] ($crate::repr::Expr::from(-($lit as f64))) $($rest)*)
+    };
+    (@power [$($out:tt)*] [$($bases:tt)*] $lit:literal $($rest:tt)*) => {
+        $crate::repr::expr!(@base [$($out)*] [$($bases)*] ($crate::repr::Expr::from($lit as f64)) $($rest)*)
+    };
+    (@power [$($out:tt)*] [$($bases:tt)*] $name:ident ($($args:tt)*) $($rest:tt)*) => {
+        $crate::repr::expr!(
+            @base [$($out)*] [$($bases)*]
+            ($crate::repr::Expr::call(stringify!($name), $crate::repr::expr!(@args [] [] $($args)*)))
+            $($rest)*
+        )
+    };
+    (@power [$($out:tt)*] [$($bases:tt)*] $name:ident $($rest:tt)*) => {
+        $crate::repr::expr!(@base [$($out)*] [$($bases)*] ($crate::repr::Expr::var(stringify!($name))) $($rest)*)
+    };
+    (@power [$($out:tt)*] [$($bases:tt)*] ($($inner:tt)*) $($rest:tt)*) => {
+        $crate::repr::expr!(@base [$($out)*] [$($bases)*] ($crate::repr::expr!($($inner)*)) $($rest)*)
+    };
+    (@base [$($out:tt)*] [$($bases:tt)*] $term:tt ^ $($rest:tt)*) => {
+        $crate::repr::expr!(@power [$($out)*] [$($bases)* $term] $($rest)*)
+    };
+    (@base [$($out:tt)*] [$($bases:tt)*] $term:tt $($rest:tt)*) => {
+        $crate::repr::expr!(@operator [$($out)* ($crate::repr::expr!(@fold $($bases)* $term))] $($rest)*)
+    };
+    (@fold $term:tt) => { $term };
+    (@fold $base:tt $($rest:tt)+) => { $base.pow($crate::repr::expr!(@fold $($rest)+)) };
+
+    // `@operator`: after an operand, where Rust applies the operators of `Expr` in the right order
+    (@operator [$($out:tt)*]) => { $($out)* };
+    (@operator [$($out:tt)*] $op:tt $($rest:tt)*) => {
+        $crate::repr::expr!(@operand [$($out)* $op] $($rest)*)
+    };
+
+    // `@args`: the finished arguments of a call, and the tokens of the current one
+    (@args [$($done:tt)*] []) => { vec![$($done)*] };
+    (@args [$($done:tt)*] [$($arg:tt)+]) => { vec![$($done)* $crate::repr::expr!($($arg)+)] };
+    (@args [$($done:tt)*] [$($arg:tt)*] , $($rest:tt)*) => {
+        $crate::repr::expr!(@args [$($done)* $crate::repr::expr!($($arg)*),] [] $($rest)*)
+    };
+    (@args [$($done:tt)*] [$($arg:tt)*] $t:tt $($rest:tt)*) => {
+        $crate::repr::expr!(@args [$($done)*] [$($arg)* $t] $($rest)*)
+    };
+
+    ($($t:tt)+) => { $crate::repr::expr!(@operand [] $($t)+) };
+}
+
+#[allow(unused_imports)]
+pub(crate) use expr;
+
+/// Implements an operator for trees, so that `Expr::var("x") * 2.0 + 1.0` builds `x * 2 + 1`.
+macro_rules! expr_op {
+    ($trait:ident, $method:ident, $op:expr) => {
+        impl<T: Into<Expr>> ops::$trait<T> for Expr {
+            type Output = Expr;
+
+            fn $method(self, rhs: T) -> Expr {
+                Operation::new($op, [self, rhs.into()]).into()
+            }
+        }
+
+        impl ops::$trait<Expr> for f64 {
+            type Output = Expr;
+
+            fn $method(self, rhs: Expr) -> Expr {
+                Operation::new($op, [self.into(), rhs]).into()
+            }
+        }
+    };
+}
+
+expr_op!(Add, add, Operator::Add);
+expr_op!(Sub, sub, Operator::Sub);
+expr_op!(Mul, mul, Operator::Mul);
+expr_op!(Div, div, Operator::Div);
+
+impl ops::Neg for Expr {
+    type Output = Expr;
+
+    fn neg(self) -> Expr {
+        let mut b = ExprBuilder {
+            nodes: self.nodes,
+            spans: self.spans,
+        };
+        let a = NodeId(b.nodes.len() as u32 - 1);
+        let root = b.unary(UnaryOperator::Neg, a);
+        b.finish(root)
+    }
+}
+
+/// Appends nodes to a new [`Expr`] arena, operands first.
+#[derive(Default)]
+pub struct ExprBuilder {
+    nodes: Vec<Node>,
+    spans: Vec<Option<Span>>,
+}
+
+impl ExprBuilder {
+    fn push(&mut self, node: Node) -> NodeId {
+        self.nodes.push(node);
+        self.spans.push(None);
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    pub(crate) fn node(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0 as usize]
+    }
+
+    pub(crate) fn set_span(&mut self, id: NodeId, span: Span) {
+        self.spans[id.0 as usize] = Some(span);
+    }
+
+    pub fn float(&mut self, val: f64) -> NodeId {
+        self.push(Node::Float(val))
+    }
+
+    pub fn bool(&mut self, val: bool) -> NodeId {
+        self.push(Node::Bool(val))
+    }
+
+    pub fn var(&mut self, name: &str) -> NodeId {
+        self.push(Node::Var(name.to_string()))
+    }
+
+    pub fn unary(&mut self, op: UnaryOperator, a: NodeId) -> NodeId {
+        self.push(Node::Unary(op, a))
+    }
+
+    pub fn op(&mut self, op: Operator, a: NodeId, b: NodeId) -> NodeId {
+        self.push(Node::Op(op, a, b))
+    }
+
+    pub fn call(&mut self, name: &str, args: Vec<NodeId>) -> NodeId {
+        self.push(Node::Call(name.to_string(), args))
+    }
+
+    pub fn assign(&mut self, name: &str, value: NodeId) -> NodeId {
+        self.push(Node::Assign(name.to_string(), value))
+    }
+
+    pub fn list(&mut self, elements: Vec<NodeId>) -> NodeId {
+        self.push(Node::List(elements))
+    }
+
+    pub fn range(&mut self, from: NodeId, to: NodeId) -> NodeId {
+        self.push(Node::Range(from, to))
+    }
+
+    pub fn text(&mut self, text: &str) -> NodeId {
+        self.push(Node::Text(text.to_string()))
+    }
+
+    pub fn lambda(&mut self, params: Vec<String>, body: NodeId) -> NodeId {
+        self.push(Node::Lambda(params, body))
+    }
+
+    /// Copies all nodes of `expr` into this arena and returns the id of its root.
+    pub fn append(&mut self, expr: Expr) -> NodeId {
+        let offset = self.nodes.len() as u32;
+        let moved = |NodeId(id)| NodeId(id + offset);
+        self.nodes.extend(expr.nodes.into_iter().map(|n| match n {
+            Node::Unary(op, a) => Node::Unary(op, moved(a)),
+            Node::Op(op, a, b) => Node::Op(op, moved(a), moved(b)),
+            Node::Call(name, args) => Node::Call(name, args.into_iter().map(moved).collect()),
+            Node::Assign(name, a) => Node::Assign(name, moved(a)),
+            Node::List(elements) => Node::List(elements.into_iter().map(moved).collect()),
+            Node::Range(a, b) => Node::Range(moved(a), moved(b)),
+            Node::Lambda(params, body) => Node::Lambda(params, moved(body)),
+            n => n,
+        }));
+        self.spans.extend(expr.spans);
+        NodeId(self.nodes.len() as u32 - 1)
+    }
+
+    pub fn map_spans(&mut self, mut f: impl FnMut(Span) -> Span) {
+        for span in self.spans.iter_mut().flatten() {
+            *span = f(*span);
+        }
+    }
+
+    /// `root` has to be the most recently added node.
+    pub fn finish(self, root: NodeId) -> Expr {
+        debug_assert_eq!(root.0 as usize + 1, self.nodes.len());
+        Expr {
+            nodes: self.nodes,
+            spans: self.spans,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum UnaryOperator {
+    Neg,
+}
+
+impl UnaryOperator {
+    pub fn apply(self, a: f64) -> f64 {
+        match self {
+            UnaryOperator::Neg => -a,
+        }
     }
 }
 
@@ -32,68 +657,92 @@ pub enum Operator {
     Sub,
     Mul,
     Div,
+    Pow,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
 }
 
 impl Operator {
+    pub const ALL: [Operator; 11] = [
+        Operator::Add,
+        Operator::Sub,
+        Operator::Mul,
+        Operator::Div,
+        Operator::Pow,
+        Operator::Lt,
+        Operator::Le,
+        Operator::Gt,
+        Operator::Ge,
+        Operator::Eq,
+        Operator::Ne,
+    ];
+
     /// lower value means operator is applied sooner
     pub fn precedence(self) -> u8 {
         match self {
+            Operator::Pow => 0,
             Operator::Add => 2,
             Operator::Sub => 2,
             Operator::Mul => 1,
             Operator::Div => 1,
+            Operator::Lt | Operator::Le | Operator::Gt | Operator::Ge | Operator::Eq | Operator::Ne => 3,
+        }
+    }
+
+    pub fn is_right_associative(self) -> bool {
+        self == Operator::Pow
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            Operator::Add => "+",
+            Operator::Sub => "-",
+            Operator::Mul => "*",
+            Operator::Div => "/",
+            Operator::Pow => "^",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+        }
+    }
+
+    /// Comparisons result in 1 when true and 0 when false.
+    pub fn apply(self, a: f64, b: f64) -> f64 {
+        match self {
+            Operator::Add => a + b,
+            Operator::Sub => a - b,
+            Operator::Mul => a * b,
+            Operator::Div => a / b,
+            Operator::Pow => a.powf(b),
+            Operator::Lt => (a < b) as u8 as f64,
+            Operator::Le => (a <= b) as u8 as f64,
+            Operator::Gt => (a > b) as u8 as f64,
+            Operator::Ge => (a >= b) as u8 as f64,
+            Operator::Eq => (a == b) as u8 as f64,
+            Operator::Ne => (a != b) as u8 as f64,
         }
     }
 }
 
+/// Describes a binary operation whose operands are separate trees. Converting it into an
+/// [`Expr`] merges everything into a single arena.
 #[derive(Debug, PartialEq, Clone)]
 pub struct Operation {
     op: Operator,
-    params: Vec<Expr>,
-}
-
-impl Operation {
-    pub fn new(op: Operator, params: impl IntoIterator<Item = Expr>) -> Self {
-        Self {
-            op,
-            params: params.into_iter().collect(),
-        }
-    }
+    lhs: Expr,
+    rhs: Expr,
 }
 
 impl Operation {
-    pub fn evaluate(&self) -> Result<f64, String> {
-        let res = match self.op {
-            Operator::Add => self
-                .evaluate_params()?
-                .into_iter()
-                .reduce(|a, b| a + b)
-                .unwrap(),
-            Operator::Sub => self
-                .evaluate_params()?
-                .into_iter()
-                .reduce(|a, b| a - b)
-                .unwrap(),
-            Operator::Mul => self
-                .evaluate_params()?
-                .into_iter()
-                .reduce(|a, b| a * b)
-                .unwrap(),
-            Operator::Div => self
-                .evaluate_params()?
-                .into_iter()
-                .reduce(|a, b| a / b)
-                .unwrap(),
-        };
-        Ok(res)
-    }
-
-    fn evaluate_params(&self) -> Result<Vec<f64>, String> {
-        let mut res = Vec::with_capacity(self.params.len());
-        for p in &self.params {
-            res.push(p.evaluate()?);
-        }
-        Ok(res)
+    pub fn new(op: Operator, [lhs, rhs]: [Expr; 2]) -> Self {
+        Self { op, lhs, rhs }
     }
 }
 
@@ -103,30 +752,246 @@ mod tests {
 
     use super::*;
 
+    /// Like `assert_f64_near`, but as near as `f32` gets with the `f32` feature.
+    macro_rules! assert_near {
+        ($a:expr, $b:expr) => {
+            #[cfg(not(feature = "f32"))]
+            assert_f64_near!($a, $b);
+            #[cfg(feature = "f32")]
+            {
+                let (a, b): (f64, f64) = ($a, $b);
+                assert_float_eq::assert_f32_near!(a as f32, b as f32);
+            }
+        };
+    }
+
+    fn evaluate(op: Operation) -> Result<f64, EvalError> {
+        Expr::from(op).evaluate()
+    }
+
     #[test]
     fn add_basic() {
-        assert_f64_near!(Operation::new(Operator::Add, [2.3.into(), 4.1.into()]).evaluate().unwrap(), 6.4);
+        assert_near!(evaluate(Operation::new(Operator::Add, [2.3.into(), 4.1.into()])).unwrap(), 6.4);
     }
 
     #[test]
     fn sub_basic() {
-        assert_f64_near!(Operation::new(Operator::Sub, [2.3.into(), 4.1.into()]).evaluate().unwrap(), -1.8);
+        assert_near!(evaluate(Operation::new(Operator::Sub, [2.3.into(), 4.1.into()])).unwrap(), -1.8);
     }
 
     #[test]
     fn mul_basic() {
-        assert_f64_near!(Operation::new(Operator::Mul, [2.3.into(), 4.1.into()]).evaluate().unwrap(), 9.43);
+        assert_near!(evaluate(Operation::new(Operator::Mul, [2.3.into(), 4.1.into()])).unwrap(), 9.43);
     }
-    
+
     #[test]
     fn div_basic() {
-        assert_f64_near!(Operation::new(Operator::Div, [2.3.into(), 4.1.into()]).evaluate().unwrap(), 0.560975609756098);
+        assert_near!(evaluate(Operation::new(Operator::Div, [2.3.into(), 4.1.into()])).unwrap(), 0.560975609756098);
     }
-    
+
     #[test]
     fn div_zero() {
         // TODO: should there be an error instead?
-        assert_f64_near!(Operation::new(Operator::Div, [2.3.into(), 0.0.into()]).evaluate().unwrap(), f64::INFINITY);
-        assert_f64_near!(Operation::new(Operator::Div, [2.3.into(), (-0.0).into()]).evaluate().unwrap(), -f64::INFINITY);
+        assert_f64_near!(evaluate(Operation::new(Operator::Div, [2.3.into(), 0.0.into()])).unwrap(), f64::INFINITY);
+        assert_f64_near!(evaluate(Operation::new(Operator::Div, [2.3.into(), (-0.0).into()])).unwrap(), -f64::INFINITY);
+    }
+
+    #[test]
+    fn pow_basic() {
+        assert_near!(evaluate(Operation::new(Operator::Pow, [2.0.into(), 0.5.into()])).unwrap(), std::f64::consts::SQRT_2);
+    }
+
+    #[test]
+    fn compare_basic() {
+        assert_eq!(evaluate(Operation::new(Operator::Lt, [1.0.into(), 2.0.into()])), Ok(1.0));
+        assert_eq!(evaluate(Operation::new(Operator::Ge, [1.0.into(), 2.0.into()])), Ok(0.0));
+        assert_eq!(evaluate(Operation::new(Operator::Ne, [1.0.into(), 2.0.into()])), Ok(1.0));
+    }
+
+    #[test]
+    fn operators() {
+        let x = || Expr::var("x");
+        let e = x() * 2.0 + 1.0;
+        let expected = Operation::new(
+            Operator::Add,
+            [Operation::new(Operator::Mul, [x(), 2.0.into()]).into(), 1.0.into()],
+        );
+        assert_eq!(e, expected.into());
+        assert_eq!((1.0 - -x() / x()).to_string(), "1 - -x / x");
+        let e = 10.0 / (x() - 3.0) * 2.0;
+        assert_eq!(e.to_string(), "10 / (x - 3) * 2");
+    }
+
+    #[test]
+    fn expr_macro() {
+        let x = || Expr::var("x");
+        assert_eq!(expr!(x * 2 + 1), x() * 2.0 + 1.0);
+        assert_eq!(expr!(-x * -2.5), -x() * -2.5);
+        assert_eq!(expr!(2 * 3 ^ x ^ 2), 2.0 * Expr::from(3.0).pow(x().pow(2.0)));
+        assert_eq!(expr!(-2 ^ 2), -Expr::from(2.0).pow(2.0));
+        assert_eq!(expr!((1 + x) ^ -1), (1.0 + x()).pow(-1.0));
+        assert_eq!(expr!(max(1 + x, pi()) / 2), Expr::call("max", vec![1.0 + x(), Expr::call("pi", vec![])]) / 2.0);
+
+        let parse = |input| crate::parser::parse_line(input).unwrap();
+        assert_eq!(expr!(1 - (2 - 3)), parse("1 - (2 - 3)"));
+        assert_eq!(expr!(-(x + 1) * max(x, 2, -3)), parse("-(x + 1) * max(x, 2, -3)"));
+        assert_eq!(expr!(2 ^ 3 ^ x / 4), parse("2 ^ 3 ^ x / 4"));
+        assert_eq!(expr!(sqrt(x) ^ 2 - -1), parse("sqrt(x) ^ 2 - -1"));
+    }
+
+    #[test]
+    fn call_basic() {
+        let mut b = ExprBuilder::default();
+        let args = vec![b.float(1.0), b.float(5.0), b.float(3.0)];
+        let root = b.call("max", args);
+        assert_eq!(b.finish(root).evaluate(), Ok(5.0));
+
+        let mut b = ExprBuilder::default();
+        let root = b.call("nope", vec![]);
+        assert_eq!(b.finish(root).evaluate().map_err(|e| e.message), Err("unknown function: nope".into()));
+    }
+
+    #[test]
+    fn if_is_lazy() {
+        let mut b = ExprBuilder::default();
+        let args = vec![b.float(1.0), b.float(2.0), b.var("undefined")];
+        let root = b.call("if", args);
+        assert_eq!(b.finish(root).evaluate(), Ok(2.0));
+    }
+
+    #[test]
+    fn neg_basic() {
+        let mut b = ExprBuilder::default();
+        let x = b.float(2.5);
+        let root = b.unary(UnaryOperator::Neg, x);
+        assert_eq!(b.finish(root).evaluate(), Ok(-2.5));
+    }
+
+    #[test]
+    fn nested_layout() {
+        // (1 - 2) * (3 + 4)
+        let e: Expr = Operation::new(
+            Operator::Mul,
+            [
+                Operation::new(Operator::Sub, [1.0.into(), 2.0.into()]).into(),
+                Operation::new(Operator::Add, [3.0.into(), 4.0.into()]).into(),
+            ],
+        )
+        .into();
+
+        let mut b = ExprBuilder::default();
+        let (one, two) = (b.float(1.0), b.float(2.0));
+        let sub = b.op(Operator::Sub, one, two);
+        let (three, four) = (b.float(3.0), b.float(4.0));
+        let add = b.op(Operator::Add, three, four);
+        let root = b.op(Operator::Mul, sub, add);
+
+        assert_eq!(e, b.finish(root));
+
+        // the same tree with its nodes in another order
+        let mut b = ExprBuilder::default();
+        let (three, four) = (b.float(3.0), b.float(4.0));
+        let add = b.op(Operator::Add, three, four);
+        let (one, two) = (b.float(1.0), b.float(2.0));
+        let sub = b.op(Operator::Sub, one, two);
+        let root = b.op(Operator::Mul, sub, add);
+        assert_eq!(e, b.finish(root));
+        let swapped: Expr = Operation::new(
+            Operator::Mul,
+            [
+                Operation::new(Operator::Add, [3.0.into(), 4.0.into()]).into(),
+                Operation::new(Operator::Sub, [1.0.into(), 2.0.into()]).into(),
+            ],
+        )
+        .into();
+        assert_ne!(e, swapped);
+
+        assert_eq!(e.evaluate(), Ok(-7.0));
+        assert_eq!(
+            format!("{e:?}"),
+            "Op(Mul, Op(Sub, Float(1.0), Float(2.0)), Op(Add, Float(3.0), Float(4.0)))"
+        );
+    }
+
+    /// Compares the arena against the previous boxed representation. Run with
+    /// `cargo test --release bench_ -- --ignored --nocapture`.
+    mod bench {
+        use std::hint::black_box;
+        use std::time::{Duration, Instant};
+
+        use super::*;
+
+        enum TreeExpr {
+            Float(f64),
+            Op(Operator, Vec<TreeExpr>),
+        }
+
+        impl TreeExpr {
+            fn evaluate(&self) -> f64 {
+                match self {
+                    TreeExpr::Float(f) => *f,
+                    TreeExpr::Op(op, params) => {
+                        params.iter().map(|p| p.evaluate()).reduce(|a, b| op.apply(a, b)).unwrap()
+                    }
+                }
+            }
+        }
+
+        const OPS: [Operator; 4] = [Operator::Add, Operator::Mul, Operator::Sub, Operator::Div];
+
+        /// A balanced tree with `2^depth` leaves.
+        fn tree(depth: u32, i: &mut usize) -> TreeExpr {
+            *i += 1;
+            if depth == 0 {
+                return TreeExpr::Float(*i as f64);
+            }
+            let a = tree(depth - 1, i);
+            let b = tree(depth - 1, i);
+            TreeExpr::Op(OPS[*i % 4], vec![a, b])
+        }
+
+        fn arena(depth: u32, i: &mut usize, b: &mut ExprBuilder) -> NodeId {
+            *i += 1;
+            if depth == 0 {
+                return b.float(*i as f64);
+            }
+            let x = arena(depth - 1, i, b);
+            let y = arena(depth - 1, i, b);
+            b.op(OPS[*i % 4], x, y)
+        }
+
+        fn time<T>(f: impl Fn() -> T) -> Duration {
+            let start = Instant::now();
+            for _ in 0..10 {
+                black_box(f());
+            }
+            start.elapsed() / 10
+        }
+
+        #[test]
+        #[ignore]
+        fn bench_large_expression() {
+            const DEPTH: u32 = 20;
+
+            let build_tree = time(|| tree(DEPTH, &mut 0));
+            let build_arena = time(|| {
+                let mut b = ExprBuilder::default();
+                let root = arena(DEPTH, &mut 0, &mut b);
+                b.finish(root)
+            });
+
+            let t = tree(DEPTH, &mut 0);
+            let mut b = ExprBuilder::default();
+            let root = arena(DEPTH, &mut 0, &mut b);
+            let e = b.finish(root);
+            assert_eq!(t.evaluate().to_bits(), e.evaluate().unwrap().to_bits());
+
+            let eval_tree = time(|| t.evaluate());
+            let eval_arena = time(|| e.evaluate());
+
+            // only printed, since timings depend on the machine
+            println!("build: boxed {build_tree:?}, arena {build_arena:?}");
+            println!("evaluate: boxed {eval_tree:?}, arena {eval_arena:?}");
+        }
     }
 }
@@ -0,0 +1,111 @@
+//! An HP-style stack calculator, used by the REPL in `:mode rpn`.
+
+use crate::context::Context;
+use crate::functions::{self, Eval};
+use crate::repr::{Operator, UnaryOperator};
+
+/// Numbers and variables are pushed, while operators and functions pop their operands and
+/// push the result.
+#[derive(Debug, Default, Clone)]
+pub struct Stack {
+    values: Vec<f64>,
+}
+
+impl Stack {
+    /// Bottom first.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    fn pop(&mut self, n: usize, word: &str) -> Result<Vec<f64>, String> {
+        if self.values.len() < n {
+            return Err(format!("{word} needs {n} values, but the stack has {}", self.values.len()));
+        }
+        Ok(self.values.split_off(self.values.len() - n))
+    }
+
+    /// Runs the whitespace separated words of `line` in order. Nothing is changed when one
+    /// of them fails.
+    pub fn run(&mut self, line: &str, ctx: &Context) -> Result<(), String> {
+        let mut s = self.clone();
+        for word in line.split_whitespace() {
+            s.word(word, ctx)?;
+        }
+        *self = s;
+        Ok(())
+    }
+
+    fn word(&mut self, word: &str, ctx: &Context) -> Result<(), String> {
+        match word {
+            "drop" => {
+                self.pop(1, word)?;
+            }
+            "dup" => {
+                let a = self.pop(1, word)?[0];
+                self.values.extend([a, a]);
+            }
+            "swap" => {
+                let a = self.pop(2, word)?;
+                self.values.extend([a[1], a[0]]);
+            }
+            "clear" => self.values.clear(),
+            "neg" => {
+                let a = self.pop(1, word)?[0];
+                self.values.push(UnaryOperator::Neg.apply(a));
+            }
+            _ => {
+                if let Some(op) = Operator::ALL.into_iter().find(|op| op.symbol() == word) {
+                    let a = self.pop(2, word)?;
+                    self.values.push(op.apply(a[0], a[1]));
+                } else if let Ok(v) = word.parse() {
+                    self.values.push(v);
+                } else if let Some(f) = functions::lookup(word).filter(|f| ctx.is_loaded(f.module)) {
+                    // variadic functions would not know how many values to take
+                    if f.min_args != f.max_args {
+                        return Err(format!("{word} takes a variable number of arguments"));
+                    }
+                    let Eval::Numbers(eval) = f.eval else {
+                        return Err(format!("{word} does not take numbers from the stack"));
+                    };
+                    let args = self.pop(f.min_args, word)?;
+                    self.values.push(eval(&args)?);
+                } else {
+                    self.values.push(ctx.lookup(word).map_err(|_| format!("unknown word: {word}"))?);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn arithmetic() {
+        let ctx = Context::default();
+        let mut s = Stack::default();
+        assert_eq!(s.run("1 2 3 * +", &ctx), Ok(()));
+        assert_eq!(s.values(), [7.0]);
+        assert_eq!(s.run("2 / 4 swap -", &ctx), Ok(()));
+        assert_eq!(s.values(), [0.5]);
+        assert_eq!(s.run("dup * sqrt neg 10 max", &ctx), Err("max takes a variable number of arguments".into()));
+        assert_eq!(s.run("dup * sqrt neg 10", &ctx), Ok(()));
+        assert_eq!(s.values(), [-0.5, 10.0]);
+        assert_eq!(s.run("drop 3 <", &ctx), Ok(()));
+        assert_eq!(s.values(), [1.0]);
+        assert_eq!(s.run("clear", &ctx), Ok(()));
+        assert_eq!(s.values(), []);
+    }
+
+    #[test]
+    fn errors_leave_the_stack_unchanged() {
+        let ctx = Context::default();
+        let mut s = Stack::default();
+        s.run("1 2", &ctx).unwrap();
+        assert_eq!(s.run("+ +", &ctx), Err("+ needs 2 values, but the stack has 1".into()));
+        assert_eq!(s.run("x", &ctx), Err("unknown word: x".into()));
+        assert_eq!(s.values(), [1.0, 2.0]);
+    }
+}
@@ -0,0 +1,201 @@
+//! `serve --ws`, which evaluates for clients like a web page over WebSocket. Each connection is
+//! a session with its own variables and functions.
+//!
+//! Clients send JSON objects with one of these fields, and an optional `id` which is sent back
+//! with the response:
+//! - `{"evaluate": "1 + x"}` responds with `{"result": 4}` or `{"error": "..."}`
+//! - `{"define": "x = 3"}` runs an assignment or function definition and responds with
+//!   `{"ok": true}`
+//! - `{"subscribe": "x"}` responds with `{"ok": true}`, and then sends
+//!   `{"variable": "x", "value": 3}` now and whenever `x` changes, where the value is `null`
+//!   while `x` is undefined
+
+use std::io::{self, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+
+use crate::context::Context;
+use crate::json::Json;
+use crate::parser::{self, Syntax};
+use crate::repr::{Statement, Value};
+use crate::websocket;
+
+pub struct Session {
+    ctx: Context,
+    subscriptions: Vec<String>,
+}
+
+impl Session {
+    pub fn new(ctx: Context) -> Session {
+        Session {
+            ctx,
+            subscriptions: Vec::new(),
+        }
+    }
+
+    /// Handles one message of the client, and returns the messages to send back.
+    pub fn handle(&mut self, message: &str) -> Vec<Json> {
+        let request = match Json::parse(message) {
+            Ok(request) => request,
+            Err(err) => return vec![Json::Object(vec![("error".into(), Json::String(err))])],
+        };
+        let mut response = Vec::new();
+        if let Some(id) = request.get("id") {
+            response.push(("id".into(), id.clone()));
+        }
+        let before = self.watched();
+        let mut updates = Vec::new();
+        match self.request(&request) {
+            Ok(Some((key, value))) => response.push((key.into(), value)),
+            Ok(None) => response.push(("ok".into(), Json::Bool(true))),
+            Err(err) => response.push(("error".into(), Json::String(err))),
+        }
+        if let Some(Json::String(name)) = request.get("subscribe") {
+            updates.push(update(name, self.ctx.var(name)));
+        }
+        for ((name, old), (_, new)) in before.iter().zip(self.watched()) {
+            if *old != new {
+                updates.push(update(name, new));
+            }
+        }
+        let mut res = vec![Json::Object(response)];
+        res.extend(updates);
+        res
+    }
+
+    fn request(&mut self, request: &Json) -> Result<Option<(&'static str, Json)>, String> {
+        let source = |key| match request.get(key) {
+            Some(Json::String(s)) => Ok(s.clone()),
+            _ => Err(format!("{key} takes a string")),
+        };
+        if request.get("evaluate").is_some() {
+            let e = parser::parse_line(&source("evaluate")?)?;
+            let value = self.ctx.run(&Statement::Expr(e)).map_err(|e| e.message)?;
+            Ok(value.map(|v| ("result", value_json(&v))))
+        } else if request.get("define").is_some() {
+            match parser::parse_statement(&source("define")?, Syntax::Native)? {
+                statement @ (Statement::Assign(..) | Statement::Define(..)) => {
+                    self.ctx.run(&statement).map_err(|e| e.message)?;
+                    Ok(None)
+                }
+                _ => Err("expected a definition like x = 1 or f(x) = 2 * x".into()),
+            }
+        } else if request.get("subscribe").is_some() {
+            let name = source("subscribe")?;
+            if !self.subscriptions.contains(&name) {
+                self.subscriptions.push(name);
+            }
+            Ok(None)
+        } else {
+            Err("expected evaluate, define or subscribe".into())
+        }
+    }
+
+    /// The subscribed variables with their values.
+    fn watched(&self) -> Vec<(String, Option<f64>)> {
+        self.subscriptions.iter().map(|name| (name.clone(), self.ctx.var(name))).collect()
+    }
+}
+
+fn update(name: &str, value: Option<f64>) -> Json {
+    Json::Object(vec![
+        ("variable".into(), Json::String(name.into())),
+        ("value".into(), value.map_or(Json::Null, Json::Number)),
+    ])
+}
+
+fn value_json(value: &Value) -> Json {
+    match value {
+        Value::Number(x) if x.is_finite() => Json::Number(*x),
+        Value::Boolean(b) => Json::Bool(*b),
+        value => Json::String(value.to_string()),
+    }
+}
+
+/// Accepts WebSocket connections on `addr` until the process is stopped, with a session that
+/// starts from `ctx` for each.
+pub fn serve(addr: &str, ctx: &Context, stderr: &mut impl Write) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    writeln!(stderr, "listening on ws://{}", listener.local_addr()?)?;
+    for stream in listener.incoming() {
+        let stream = stream?;
+        let ctx = ctx.clone();
+        thread::spawn(move || {
+            // a client that goes away is not a problem of the server
+            let _ = connection(stream, ctx);
+        });
+    }
+    Ok(())
+}
+
+fn connection(stream: TcpStream, ctx: Context) -> io::Result<()> {
+    let mut input = BufReader::new(stream.try_clone()?);
+    let mut output = stream;
+    websocket::handshake(&mut input, &mut output)?;
+    let mut session = Session::new(ctx);
+    while let Some(message) = websocket::read_message(&mut input, &mut output)? {
+        for response in session.handle(&message) {
+            websocket::write_message(&mut output, &response.to_string())?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handle(session: &mut Session, message: &str) -> Vec<String> {
+        session.handle(message).iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn session() {
+        let mut session = Session::new(Context::default());
+        assert_eq!(handle(&mut session, r#"{"id": 1, "evaluate": "1 + 2"}"#), [r#"{"id":1,"result":3}"#]);
+        assert_eq!(handle(&mut session, r#"{"evaluate": "1 < 2"}"#), [r#"{"result":true}"#]);
+        assert_eq!(
+            handle(&mut session, r#"{"id": 2, "evaluate": "x"}"#),
+            [r#"{"id":2,"error":"unknown variable: x"}"#]
+        );
+        assert_eq!(
+            handle(&mut session, r#"{"subscribe": "x"}"#),
+            [r#"{"ok":true}"#, r#"{"variable":"x","value":null}"#]
+        );
+        assert_eq!(
+            handle(&mut session, r#"{"define": "x = 3"}"#),
+            [r#"{"ok":true}"#, r#"{"variable":"x","value":3}"#]
+        );
+        assert_eq!(handle(&mut session, r#"{"define": "f(y) = x * y"}"#), [r#"{"ok":true}"#]);
+        assert_eq!(handle(&mut session, r#"{"evaluate": "f(2)"}"#), [r#"{"result":6}"#]);
+        assert_eq!(
+            handle(&mut session, r#"{"define": "2 + 2"}"#),
+            [r#"{"error":"expected a definition like x = 1 or f(x) = 2 * x"}"#]
+        );
+        assert_eq!(handle(&mut session, r#"{"nope": 1}"#), [r#"{"error":"expected evaluate, define or subscribe"}"#]);
+        assert_eq!(handle(&mut session, "{").len(), 1);
+    }
+
+    #[test]
+    fn connection_over_tcp() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        thread::spawn(move || connection(listener.accept().unwrap().0, Context::default()));
+
+        let mut client = TcpStream::connect(addr).unwrap();
+        write!(client, "GET / HTTP/1.1\r\nSec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n").unwrap();
+        let mut input = BufReader::new(client.try_clone().unwrap());
+        let mut status = String::new();
+        while !status.ends_with("\r\n\r\n") {
+            let mut line = String::new();
+            io::BufRead::read_line(&mut input, &mut line).unwrap();
+            status += &line;
+        }
+        assert!(status.starts_with("HTTP/1.1 101 "));
+
+        // clients may send unmasked frames to this server
+        websocket::write_message(&mut client, r#"{"evaluate": "2 ^ 10"}"#).unwrap();
+        let response = websocket::read_message(&mut input, &mut Vec::new()).unwrap();
+        assert_eq!(response.as_deref(), Some(r#"{"result":1024}"#));
+    }
+}
@@ -0,0 +1,192 @@
+//! Numerical methods for `nsolve` and `odesolve`.
+
+/// How many steps Newton's method takes before giving up.
+const ITERATIONS: usize = 100;
+
+/// How many Runge-Kutta steps are taken between each of the given points.
+const SUBSTEPS: usize = 16;
+
+/// Why a system of equations was not solved.
+#[derive(Debug, PartialEq)]
+pub enum Failure<E> {
+    /// evaluating the equations failed
+    Eval(E),
+    /// the Jacobian is singular at these values, so there is no Newton step
+    Singular(Vec<f64>),
+    /// the values that were reached, and how far off the worst equation still is there
+    NoConvergence(Vec<f64>, f64),
+}
+
+impl<E> From<E> for Failure<E> {
+    fn from(e: E) -> Self {
+        Failure::Eval(e)
+    }
+}
+
+/// Finds where the sides of the equations are equal, starting from `guess`. `sides` gives the
+/// left and right side of each equation at some values, and there are as many equations as
+/// values.
+pub fn newton<E>(
+    sides: impl Fn(&[f64]) -> Result<Vec<(f64, f64)>, E>,
+    guess: Vec<f64>,
+) -> Result<Vec<f64>, Failure<E>> {
+    let residuals = |x: &[f64]| -> Result<Vec<f64>, E> { Ok(sides(x)?.into_iter().map(|(l, r)| l - r).collect()) };
+    let mut x = guess;
+    let mut at = sides(&x)?;
+    for _ in 0..ITERATIONS {
+        if worst(&at) == 0.0 {
+            return Ok(x);
+        }
+        let r: Vec<f64> = at.iter().map(|(l, r)| l - r).collect();
+        // central differences, which are exact for quadratic equations
+        let mut jacobian = vec![vec![0.0; x.len()]; r.len()];
+        for j in 0..x.len() {
+            let h = f64::EPSILON.cbrt() * x[j].abs().max(1.0);
+            let (mut above, mut below) = (x.clone(), x.clone());
+            above[j] += h;
+            below[j] -= h;
+            let (above, below) = (residuals(&above)?, residuals(&below)?);
+            for i in 0..r.len() {
+                jacobian[i][j] = (above[i] - below[i]) / (2.0 * h);
+            }
+        }
+        let Some(step) = gauss(jacobian, r.iter().map(|v| -v).collect()) else {
+            return if converged(&at) { Ok(x) } else { Err(Failure::Singular(x)) };
+        };
+        // halves the step until the equations are closer to holding, which stops working at
+        // the rounding errors around a solution
+        let mut t = 1.0;
+        loop {
+            let next: Vec<f64> = x.iter().zip(&step).map(|(x, d)| x + t * d).collect();
+            let next_at = sides(&next)?;
+            let off = worst(&next_at);
+            if off.is_finite() && off < worst(&at) {
+                (x, at) = (next, next_at);
+                break;
+            }
+            if converged(&at) {
+                return Ok(x);
+            }
+            if t < 1e-6 {
+                return Err(Failure::NoConvergence(x, worst(&at)));
+            }
+            t /= 2.0;
+        }
+    }
+    if converged(&at) {
+        Ok(x)
+    } else {
+        Err(Failure::NoConvergence(x, worst(&at)))
+    }
+}
+
+/// The solution of `dy/dx = slope(x, y)` at each of `xs`, where `y` is `y0` at the first x, using
+/// the classic fourth order Runge-Kutta method.
+pub fn rk4<E>(slope: impl Fn(f64, f64) -> Result<f64, E>, y0: f64, xs: &[f64]) -> Result<Vec<f64>, E> {
+    let Some((&first, rest)) = xs.split_first() else {
+        return Ok(Vec::new());
+    };
+    let (mut x, mut y) = (first, y0);
+    let mut ys = vec![y];
+    for &to in rest {
+        let h = (to - x) / SUBSTEPS as f64;
+        for i in 0..SUBSTEPS {
+            let at = x + i as f64 * h;
+            let k1 = slope(at, y)?;
+            let k2 = slope(at + h / 2.0, y + h / 2.0 * k1)?;
+            let k3 = slope(at + h / 2.0, y + h / 2.0 * k2)?;
+            let k4 = slope(at + h, y + h * k3)?;
+            y += h / 6.0 * (k1 + 2.0 * k2 + 2.0 * k3 + k4);
+        }
+        x = to;
+        ys.push(y);
+    }
+    Ok(ys)
+}
+
+/// Whether each equation holds up to rounding errors of its sides.
+fn converged(sides: &[(f64, f64)]) -> bool {
+    sides.iter().all(|&(l, r)| (l - r).abs() <= 1e-9 * l.abs().max(r.abs()).max(1.0))
+}
+
+/// How far off the worst equation is.
+fn worst(sides: &[(f64, f64)]) -> f64 {
+    sides.iter().map(|(l, r)| (l - r).abs()).fold(0.0, f64::max)
+}
+
+/// Solves `a x = b` by Gaussian elimination with partial pivoting, or `None` if `a` is singular.
+fn gauss(mut a: Vec<Vec<f64>>, mut b: Vec<f64>) -> Option<Vec<f64>> {
+    let n = b.len();
+    let scale = a.iter().flatten().fold(0.0, |m: f64, v| m.max(v.abs()));
+    for col in 0..n {
+        let pivot = (col..n).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+        let largest = a[pivot][col].abs();
+        if largest.is_nan() || largest <= 1e-9 * scale {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..n {
+            let factor = a[row][col] / a[col][col];
+            let (above, below) = a.split_at_mut(row);
+            for (v, pivot) in below[0][col..].iter_mut().zip(&above[col][col..]) {
+                *v -= factor * pivot;
+            }
+            b[row] -= factor * b[col];
+        }
+    }
+    let mut x = vec![0.0; n];
+    for row in (0..n).rev() {
+        let known: f64 = (row + 1..n).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - known) / a[row][row];
+    }
+    Some(x)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn solve(guess: Vec<f64>) -> Result<Vec<f64>, Failure<()>> {
+        // x + y = 3 and x * y = 2
+        newton(|v| Ok(vec![(v[0] + v[1], 3.0), (v[0] * v[1], 2.0)]), guess)
+    }
+
+    #[test]
+    fn systems() {
+        let x = solve(vec![0.0, 3.0]).unwrap();
+        assert!((x[0] - 1.0).abs() < 1e-12 && (x[1] - 2.0).abs() < 1e-12, "{x:?}");
+        let x = solve(vec![5.0, -1.0]).unwrap();
+        assert!((x[0] - 2.0).abs() < 1e-12 && (x[1] - 1.0).abs() < 1e-12, "{x:?}");
+        // symmetric guesses stay symmetric, where the Jacobian is singular
+        assert_eq!(solve(vec![1.0, 1.0]), Err(Failure::Singular(vec![1.0, 1.0])));
+        let x = newton(|v| Ok::<_, ()>(vec![(v[0].exp(), 2.0)]), vec![0.0]).unwrap();
+        assert!((x[0] - 2f64.ln()).abs() < 1e-12);
+        assert_eq!(newton(|_| Err("no"), vec![0.0]), Err(Failure::Eval("no")));
+    }
+
+    #[test]
+    fn no_solutions() {
+        let res = newton(|v| Ok::<_, ()>(vec![(v[0] * v[0], -1.0)]), vec![0.5]);
+        let Err(Failure::NoConvergence(_, off)) = res else { panic!("{res:?}") };
+        assert!(off >= 1.0);
+    }
+
+    #[test]
+    fn differential_equations() {
+        let xs = [0.0, 0.25, 0.5, -0.25];
+        let ys = rk4(|_, y| Ok::<_, ()>(y), 1.0, &xs).unwrap();
+        for (y, x) in ys.into_iter().zip(xs) {
+            assert!((y - f64::exp(x)).abs() < 1e-7, "{y} at {x}");
+        }
+        assert_eq!(rk4(|x, _| Ok::<_, ()>(x), 1.0, &[]), Ok(vec![]));
+        assert_eq!(rk4(|_, _| Err("no"), 1.0, &[0.0, 1.0]), Err("no"));
+    }
+
+    #[test]
+    fn elimination() {
+        let x = gauss(vec![vec![0.0, 2.0], vec![1.0, 1.0]], vec![4.0, 3.0]);
+        assert_eq!(x, Some(vec![1.0, 2.0]));
+        assert_eq!(gauss(vec![vec![1.0, 2.0], vec![2.0, 4.0]], vec![1.0, 2.0]), None);
+    }
+}
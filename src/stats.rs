@@ -0,0 +1,172 @@
+//! Summaries of data sets, for `calculator stats` and histograms.
+
+/// The percentiles that are shown besides the median.
+pub const PERCENTILES: [u32; 6] = [5, 25, 75, 90, 95, 99];
+
+/// How many `#` the bar of the largest bin of a histogram has.
+const BAR_WIDTH: usize = 40;
+
+/// The most bins a histogram can have.
+pub const BINS_LIMIT: usize = 1000;
+
+/// The numbers from `from` up to `to`, or up to and including it for the last bin.
+#[derive(Debug, PartialEq)]
+pub struct Bin {
+    pub from: f64,
+    pub to: f64,
+    pub count: usize,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Summary {
+    pub count: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    pub median: f64,
+    /// the sample standard deviation, like `stdev`, which is 0 for one number
+    pub stddev: f64,
+    /// the [`PERCENTILES`] with their values
+    pub percentiles: Vec<(u32, f64)>,
+}
+
+impl Summary {
+    /// The summary of `values`, or `None` if there are none.
+    pub fn new(values: &[f64]) -> Option<Summary> {
+        if values.is_empty() {
+            return None;
+        }
+        let mut sorted = values.to_vec();
+        sorted.sort_by(f64::total_cmp);
+        let n = values.len();
+        let mean = values.iter().sum::<f64>() / n as f64;
+        let squares: f64 = values.iter().map(|x| (x - mean).powi(2)).sum();
+        Some(Summary {
+            count: n,
+            min: sorted[0],
+            max: sorted[n - 1],
+            mean,
+            median: percentile(&sorted, 50.0),
+            stddev: if n > 1 { (squares / (n - 1) as f64).sqrt() } else { 0.0 },
+            percentiles: PERCENTILES.iter().map(|&p| (p, percentile(&sorted, p as f64))).collect(),
+        })
+    }
+
+    /// The rows of the summary, as names and values.
+    pub fn rows(&self) -> Vec<(String, f64)> {
+        let mut rows = vec![
+            ("count".to_string(), self.count as f64),
+            ("min".into(), self.min),
+            ("max".into(), self.max),
+            ("mean".into(), self.mean),
+            ("median".into(), self.median),
+            ("stddev".into(), self.stddev),
+        ];
+        rows.extend(self.percentiles.iter().map(|&(p, x)| (format!("p{p}"), x)));
+        rows
+    }
+}
+
+/// The `p`th percentile of the sorted values, interpolating linearly between the two closest
+/// ones, like spreadsheets' `PERCENTILE` does.
+pub fn percentile(sorted: &[f64], p: f64) -> f64 {
+    let rank = p / 100.0 * (sorted.len() - 1) as f64;
+    let (below, above) = (rank.floor() as usize, rank.ceil() as usize);
+    sorted[below] + (sorted[above] - sorted[below]) * (rank - below as f64)
+}
+
+/// Splits the range of `values` into `n` bins of equal width and counts the values in each.
+/// All values go in one bin if they are equal, and no values have no bins.
+pub fn bins(values: &[f64], n: usize) -> Vec<Bin> {
+    if values.is_empty() {
+        return Vec::new();
+    }
+    let (min, max) = values.iter().fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &x| (lo.min(x), hi.max(x)));
+    if min == max {
+        return vec![Bin { from: min, to: max, count: values.len() }];
+    }
+    // dividing last rounds less, so 1..2 in 10 bins has edges like 1.7 and not 1.7000000000000002
+    let edge = |i: usize| min + (max - min) * i as f64 / n as f64;
+    let mut bins: Vec<Bin> = (0..n).map(|i| Bin { from: edge(i), to: edge(i + 1), count: 0 }).collect();
+    for &x in values {
+        let i = ((n as f64 * (x - min) / (max - min)) as usize).min(n - 1);
+        bins[i].count += 1;
+    }
+    bins
+}
+
+/// Draws the bins as rows of their range, a bar as long as their count relative to the
+/// largest one, and their count.
+pub fn histogram(bins: &[Bin]) -> String {
+    let last = bins.len().saturating_sub(1);
+    let ranges: Vec<String> = bins
+        .iter()
+        .enumerate()
+        .map(|(i, bin)| format!("[{}, {}{}", bin.from, bin.to, if i == last { "]" } else { ")" }))
+        .collect();
+    let width = ranges.iter().map(|r| r.chars().count()).max().unwrap_or(0);
+    let largest = bins.iter().map(|bin| bin.count).max().unwrap_or(0).max(1);
+    let mut res = String::new();
+    for (range, bin) in ranges.iter().zip(bins) {
+        // rounded up, so that every value shows
+        let bar = "#".repeat((bin.count * BAR_WIDTH).div_ceil(largest));
+        res += &format!("{range:<width$}  {bar:<BAR_WIDTH$}  {}\n", bin.count);
+    }
+    res
+}
+
+/// The numbers in `text`, separated by whitespace, or the first word that is not a number.
+pub fn parse(text: &str) -> Result<Vec<f64>, String> {
+    text.split_whitespace()
+        .map(|word| word.parse().map_err(|_| format!("not a number: {word}")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn summaries() {
+        let summary = Summary::new(&[2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0]).unwrap();
+        assert_eq!((summary.count, summary.min, summary.max), (8, 2.0, 9.0));
+        assert_eq!((summary.mean, summary.median), (5.0, 4.5));
+        assert_eq!(summary.stddev, 2.138089935299395);
+        assert_eq!(summary.percentiles[1], (25, 4.0));
+        assert_eq!(summary.percentiles[3], (90, 7.6));
+        assert_eq!(Summary::new(&[3.0]).unwrap().stddev, 0.0);
+        assert_eq!(Summary::new(&[]), None);
+    }
+
+    #[test]
+    fn percentiles() {
+        let sorted = [1.0, 2.0, 3.0, 4.0];
+        assert_eq!(percentile(&sorted, 0.0), 1.0);
+        assert_eq!(percentile(&sorted, 50.0), 2.5);
+        assert_eq!(percentile(&sorted, 100.0), 4.0);
+        assert_eq!(percentile(&[7.0], 99.0), 7.0);
+    }
+
+    #[test]
+    fn binning() {
+        let values = [1.0, 2.0, 2.5, 4.0, 5.0];
+        let counts: Vec<_> = bins(&values, 4).iter().map(|bin| (bin.from, bin.to, bin.count)).collect();
+        assert_eq!(counts, [(1.0, 2.0, 1), (2.0, 3.0, 2), (3.0, 4.0, 0), (4.0, 5.0, 2)]);
+        assert_eq!(bins(&[3.0, 3.0], 10), [Bin { from: 3.0, to: 3.0, count: 2 }]);
+        assert_eq!(bins(&[], 10), []);
+        let expected = [
+            "[1, 2)  ####################                      1\n",
+            "[2, 3)  ########################################  2\n",
+            "[3, 4)                                            0\n",
+            "[4, 5]  ########################################  2\n",
+        ];
+        assert_eq!(histogram(&bins(&values, 4)), expected.concat());
+    }
+
+    #[test]
+    fn parsing() {
+        assert_eq!(parse("1 2.5\n-3\t1e3\n"), Ok(vec![1.0, 2.5, -3.0, 1000.0]));
+        assert_eq!(parse(""), Ok(vec![]));
+        assert_eq!(parse("1 two"), Err("not a number: two".into()));
+    }
+}
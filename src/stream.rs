@@ -0,0 +1,487 @@
+//! Parses one expression from a reader as it arrives, for generated expressions that are too
+//! large to comfortably hold as a string. Only the part of the native syntax that generators
+//! use is supported: numbers, variables, calls, parentheses, `-` and the binary operators.
+//! Line breaks count as spaces.
+//!
+//! Unlike [`crate::parser`] this parses with explicit stacks instead of recursion, so deeply
+//! nested parentheses are fine, and it never looks more than two tokens ahead.
+
+use std::io::BufRead;
+
+use crate::normalize::normalize_char;
+use crate::parser::{self, is_name_continue, is_name_start};
+use crate::repr::{Expr, ExprBuilder, NodeId, Operator, Span, UnaryOperator};
+
+#[derive(Debug, PartialEq, Clone)]
+enum Kind {
+    Number(f64),
+    Name(String),
+    /// like `$HOME`
+    Env(String),
+    Op(Operator),
+    Open,
+    Close,
+    Comma,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: Kind,
+    span: Span,
+    /// of the first char, counted from 1
+    column: usize,
+}
+
+struct Lexer<R> {
+    input: R,
+    /// the next char, with its byte offset
+    peeked: Option<(char, usize)>,
+    pos: usize,
+    column: usize,
+    tokens: Vec<Token>,
+}
+
+impl<R: BufRead> Lexer<R> {
+    fn new(input: R) -> Self {
+        Lexer {
+            input,
+            peeked: None,
+            pos: 0,
+            column: 0,
+            tokens: Vec::new(),
+        }
+    }
+
+    fn byte(&mut self) -> Result<Option<u8>, String> {
+        let buf = self.input.fill_buf().map_err(|err| err.to_string())?;
+        let Some(&b) = buf.first() else {
+            return Ok(None);
+        };
+        self.input.consume(1);
+        self.pos += 1;
+        Ok(Some(b))
+    }
+
+    fn read_char(&mut self) -> Result<Option<(char, usize)>, String> {
+        let start = self.pos;
+        let Some(first) = self.byte()? else {
+            return Ok(None);
+        };
+        let len = match first {
+            0x00..=0x7F => 1,
+            0xC0..=0xDF => 2,
+            0xE0..=0xEF => 3,
+            _ => 4,
+        };
+        let mut bytes = vec![first];
+        for _ in 1..len {
+            bytes.extend(self.byte()?);
+        }
+        let s = std::str::from_utf8(&bytes).map_err(|_| format!("the input is not UTF-8 at byte {start}"))?;
+        Ok(s.chars().next().map(|c| (normalize_char(c), start)))
+    }
+
+    fn peek(&mut self) -> Result<Option<char>, String> {
+        if self.peeked.is_none() {
+            self.peeked = self.read_char()?;
+        }
+        Ok(self.peeked.map(|(c, _)| c))
+    }
+
+    /// The byte offset of the next char.
+    fn offset(&mut self) -> Result<usize, String> {
+        self.peek()?;
+        Ok(self.peeked.map_or(self.pos, |(_, offset)| offset))
+    }
+
+    fn next_char(&mut self) -> Result<Option<char>, String> {
+        let c = self.peek()?;
+        self.peeked = None;
+        if c.is_some() {
+            self.column += 1;
+        }
+        Ok(c)
+    }
+
+    /// Appends chars to `s` while they match `f`.
+    fn take_while(&mut self, s: &mut String, f: impl Fn(char) -> bool) -> Result<(), String> {
+        while let Some(c) = self.peek()?.filter(|&c| f(c)) {
+            s.push(c);
+            self.next_char()?;
+        }
+        Ok(())
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token>, String> {
+        while self.peek()?.is_some_and(|c| c.is_whitespace()) {
+            self.next_char()?;
+        }
+        let start = self.offset()?;
+        let column = self.column + 1;
+        let Some(c) = self.next_char()? else {
+            return Ok(None);
+        };
+        let unexpected = |c: char| format!("unexpected {c:?} at column {column}");
+        let kind = match c {
+            '(' => Kind::Open,
+            ')' => Kind::Close,
+            ',' => Kind::Comma,
+            '0'..='9' => {
+                let mut s = c.to_string();
+                self.take_while(&mut s, |c| c.is_ascii_digit())?;
+                if self.peek()? == Some('.') {
+                    self.next_char()?;
+                    s.push('.');
+                    let before = s.len();
+                    self.take_while(&mut s, |c| c.is_ascii_digit())?;
+                    if s.len() == before {
+                        return Err(format!("expected digits after the . at column {}", self.column));
+                    }
+                }
+                Kind::Number(s.parse().map_err(|_| unexpected(c))?)
+            }
+            c if is_name_start(c) => Kind::Name(self.name(c)?),
+            '$' => match self.next_char()? {
+                Some(c) if is_name_start(c) => Kind::Env(format!("${}", self.name(c)?)),
+                _ => return Err(unexpected('$')),
+            },
+            c => {
+                let second = self.peek()?;
+                let (op, two) = match (c, second) {
+                    ('<', Some('=')) => (Operator::Le, true),
+                    ('>', Some('=')) => (Operator::Ge, true),
+                    ('=', Some('=')) => (Operator::Eq, true),
+                    ('!', Some('=')) => (Operator::Ne, true),
+                    ('<', _) => (Operator::Lt, false),
+                    ('>', _) => (Operator::Gt, false),
+                    ('+', _) => (Operator::Add, false),
+                    ('-', _) => (Operator::Sub, false),
+                    ('*', _) => (Operator::Mul, false),
+                    ('/', _) => (Operator::Div, false),
+                    ('^', _) => (Operator::Pow, false),
+                    _ => return Err(unexpected(c)),
+                };
+                if two {
+                    self.next_char()?;
+                }
+                Kind::Op(op)
+            }
+        };
+        let end = self.offset()?;
+        Ok(Some(Token {
+            kind,
+            span: Span { start, end },
+            column,
+        }))
+    }
+
+    /// A name like `area` or `geom.area` that starts with `first`.
+    fn name(&mut self, first: char) -> Result<String, String> {
+        let mut name = first.to_string();
+        loop {
+            self.take_while(&mut name, is_name_continue)?;
+            if self.peek()? != Some('.') {
+                return Ok(name);
+            }
+            self.next_char()?;
+            name.push('.');
+            match self.next_char()? {
+                Some(c) if is_name_start(c) => name.push(c),
+                _ => return Err(format!("expected a name after the . at column {}", self.column)),
+            }
+        }
+    }
+
+    /// The token `n` tokens ahead, where 0 is the next one.
+    fn peek_token(&mut self, n: usize) -> Result<Option<&Token>, String> {
+        while self.tokens.len() <= n {
+            match self.read_token()? {
+                Some(token) => self.tokens.push(token),
+                None => return Ok(None),
+            }
+        }
+        Ok(self.tokens.get(n))
+    }
+
+    fn next_token(&mut self) -> Result<Option<Token>, String> {
+        self.peek_token(0)?;
+        Ok((!self.tokens.is_empty()).then(|| self.tokens.remove(0)))
+    }
+}
+
+/// What an operand or a closing parenthesis is waiting for.
+enum Pending {
+    Op(Operator),
+    /// at the byte offset of the `-`
+    Neg(usize),
+    Paren(usize, usize),
+    /// a call of `name` that has gotten `args` arguments so far
+    Call {
+        name: String,
+        args: usize,
+        start: usize,
+        column: usize,
+    },
+}
+
+struct Parser {
+    b: ExprBuilder,
+    values: Vec<(NodeId, Span)>,
+    pending: Vec<Pending>,
+}
+
+impl Parser {
+    fn value(&mut self, id: NodeId, span: Span) {
+        self.b.set_span(id, span);
+        self.values.push((id, span));
+    }
+
+    /// Applies the operators on top of the stack that bind tighter than `op`, or all of them
+    /// until a parenthesis for `None`.
+    fn reduce(&mut self, op: Option<Operator>) {
+        loop {
+            let (id, span) = match (self.pending.last(), op) {
+                (Some(&Pending::Op(top)), _)
+                    if op.is_none_or(|op| {
+                        top.precedence() < op.precedence()
+                            || top.precedence() == op.precedence() && !op.is_right_associative()
+                    }) =>
+                {
+                    let (b, b_span) = self.values.pop().unwrap();
+                    let (a, a_span) = self.values.pop().unwrap();
+                    let id = self.b.op(top, a, b);
+                    (id, Span { start: a_span.start, end: b_span.end })
+                }
+                // the operand of `-` is only a power, so `-x^2` means `-(x^2)`
+                (Some(&Pending::Neg(start)), _)
+                    if op.is_none_or(|op| op.precedence() > Operator::Pow.precedence()) =>
+                {
+                    let (a, a_span) = self.values.pop().unwrap();
+                    let id = self.b.unary(UnaryOperator::Neg, a);
+                    (id, Span { start, end: a_span.end })
+                }
+                _ => return,
+            };
+            self.pending.pop();
+            self.value(id, span);
+        }
+    }
+}
+
+/// Parses the whole of `input` as one expression.
+pub fn parse_reader(input: impl BufRead) -> Result<Expr, String> {
+    let mut lexer = Lexer::new(input);
+    let mut p = Parser {
+        b: ExprBuilder::default(),
+        values: Vec::new(),
+        pending: Vec::new(),
+    };
+    loop {
+        // an operand, after any number of `(` and `-`
+        let token = lexer.next_token()?.ok_or("the input ended where a term was expected")?;
+        let unexpected = format!("unexpected {} at column {}", describe(&token.kind), token.column);
+        match token.kind {
+            Kind::Open => {
+                p.pending.push(Pending::Paren(token.span.start, token.column));
+                continue;
+            }
+            Kind::Op(Operator::Sub) => {
+                let literal = match lexer.peek_token(0)? {
+                    Some(&Token {
+                        kind: Kind::Number(f),
+                        span,
+                        ..
+                    }) if span.start == token.span.end => Some((f, span)),
+                    _ => None,
+                };
+                // -2^2 means -(2^2) like -x^2 does
+                let pow_follows = literal.is_some()
+                    && lexer.peek_token(1)?.is_some_and(|t| t.kind == Kind::Op(Operator::Pow));
+                match literal {
+                    Some((f, span)) if !pow_follows => {
+                        lexer.next_token()?;
+                        let id = p.b.float(-f);
+                        p.value(id, Span { start: token.span.start, end: span.end });
+                    }
+                    _ => {
+                        p.pending.push(Pending::Neg(token.span.start));
+                        continue;
+                    }
+                }
+            }
+            Kind::Number(f) => {
+                let id = p.b.float(f);
+                p.value(id, token.span);
+            }
+            Kind::Env(name) => {
+                let id = p.b.var(&name);
+                p.value(id, token.span);
+            }
+            Kind::Name(name) => {
+                if lexer.peek_token(0)?.is_some_and(|t| t.kind == Kind::Open) {
+                    lexer.next_token()?;
+                    let call = Pending::Call {
+                        name,
+                        args: 0,
+                        start: token.span.start,
+                        column: token.column,
+                    };
+                    if lexer.peek_token(0)?.is_some_and(|t| t.kind == Kind::Close) {
+                        p.pending.push(call);
+                        let close = lexer.next_token()?.unwrap();
+                        close_call(&mut p, close.span.end);
+                    } else {
+                        p.pending.push(call);
+                        continue;
+                    }
+                } else if name == "true" || name == "false" {
+                    let id = p.b.bool(name == "true");
+                    p.value(id, token.span);
+                } else if name == "π" {
+                    let id = p.b.float(std::f64::consts::PI);
+                    p.value(id, token.span);
+                } else {
+                    let id = p.b.var(&name);
+                    p.value(id, token.span);
+                }
+            }
+            Kind::Close | Kind::Comma | Kind::Op(_) => return Err(unexpected),
+        }
+
+        // then any number of `)` and `,`, and an operator or the end
+        loop {
+            let Some(token) = lexer.next_token()? else {
+                p.reduce(None);
+                return match p.pending.pop() {
+                    Some(Pending::Paren(_, column) | Pending::Call { column, .. }) => {
+                        Err(parser::unclosed('(', column).into())
+                    }
+                    _ => {
+                        let (root, _) = p.values.pop().unwrap();
+                        Ok(p.b.finish(root))
+                    }
+                };
+            };
+            match token.kind {
+                Kind::Op(op) => {
+                    p.reduce(Some(op));
+                    p.pending.push(Pending::Op(op));
+                    break;
+                }
+                Kind::Close => {
+                    p.reduce(None);
+                    match p.pending.last() {
+                        Some(&Pending::Paren(start, _)) => {
+                            p.pending.pop();
+                            let (id, _) = p.values.pop().unwrap();
+                            p.value(id, Span { start, end: token.span.end });
+                        }
+                        Some(Pending::Call { .. }) => {
+                            if let Some(Pending::Call { args, .. }) = p.pending.last_mut() {
+                                *args += 1;
+                            }
+                            close_call(&mut p, token.span.end);
+                        }
+                        _ => return Err(format!("unexpected ) at column {}", token.column)),
+                    }
+                }
+                Kind::Comma => {
+                    p.reduce(None);
+                    match p.pending.last_mut() {
+                        Some(Pending::Call { args, .. }) => *args += 1,
+                        _ => return Err(format!("unexpected , at column {}", token.column)),
+                    }
+                    break;
+                }
+                kind => return Err(format!("unexpected {} at column {}", describe(&kind), token.column)),
+            }
+        }
+    }
+}
+
+/// Makes the call on top of the stack from its arguments, which are the latest values.
+fn close_call(p: &mut Parser, end: usize) {
+    let Some(Pending::Call { name, args, start, .. }) = p.pending.pop() else {
+        unreachable!()
+    };
+    let args = p.values.split_off(p.values.len() - args).into_iter().map(|(id, _)| id).collect();
+    let id = p.b.call(&name, args);
+    p.value(id, Span { start, end });
+}
+
+fn describe(kind: &Kind) -> String {
+    match kind {
+        Kind::Number(f) => f.to_string(),
+        Kind::Name(name) | Kind::Env(name) => name.clone(),
+        Kind::Op(op) => op.symbol().to_string(),
+        Kind::Open => "(".into(),
+        Kind::Close => ")".into(),
+        Kind::Comma => ",".into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::BufReader;
+
+    use super::*;
+    use crate::parser::parse_line;
+
+    #[test]
+    fn same_as_the_parser() {
+        for input in [
+            "1 + 2 * 3",
+            "2 ^ 3 ^ 2",
+            "(1 + 2) * -3",
+            "-2^2 + -x^2 - - 4",
+            "2^-3 * 4 - 1 - 1",
+            "max(1, min(2, 3), pi()) / geom.area(2)",
+            "1 < 2 == true",
+            "  $HOME + 1.5 ",
+            "((x))",
+            "－１ ＋ ２",
+            "2 * π * Δx + θ",
+        ] {
+            let expected = parse_line(input).unwrap();
+            let e = parse_reader(input.as_bytes()).unwrap();
+            assert_eq!(e, expected, "input was {input:?}");
+            let spans = |e: &Expr| e.ids().map(|id| e.span(id)).collect::<Vec<_>>();
+            assert_eq!(spans(&e), spans(&expected), "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn small_reads() {
+        // chars are split between reads
+        let input = "１\n+ ２ * (3 −\n1)";
+        let e = parse_reader(BufReader::with_capacity(1, input.as_bytes())).unwrap();
+        assert_eq!(e.evaluate().unwrap(), 5.0);
+    }
+
+    #[test]
+    fn large_inputs() {
+        let sum = "1 + ".repeat(100_000) + "1";
+        assert_eq!(parse_reader(sum.as_bytes()).unwrap().nodes().len(), 200_001);
+        // far deeper than the recursive parser can go
+        let nested = "(".repeat(100_000) + "1" + &")".repeat(100_000);
+        assert_eq!(parse_reader(nested.as_bytes()).unwrap().nodes().len(), 1);
+    }
+
+    #[test]
+    fn errors() {
+        for (input, error) in [
+            ("", "the input ended where a term was expected"),
+            ("1 +", "the input ended where a term was expected"),
+            ("(1 + 2", "unclosed ( at column 1"),
+            ("f(1, 2", "unclosed ( at column 1"),
+            ("1 + 2)", "unexpected ) at column 6"),
+            ("1 2", "unexpected 2 at column 3"),
+            ("1 * / 2", "unexpected / at column 5"),
+            ("x = 1", "unexpected '=' at column 3"),
+            ("1.x", "expected digits after the . at column 2"),
+            ("(1, 2)", "unexpected , at column 3"),
+        ] {
+            assert_eq!(parse_reader(input.as_bytes()).map(|_| ()), Err(error.into()), "input was {input:?}");
+        }
+    }
+}
@@ -0,0 +1,162 @@
+//! The colors of the REPL output, which are chosen with `--theme` or `:theme`.
+
+use colored::{Color, ColoredString, Colorize};
+
+/// What a piece of output is, which decides its color.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Role {
+    Result,
+    Boolean,
+    Error,
+    Warning,
+    Prompt,
+    /// numbers in echoed input
+    Number,
+    /// variable and function names in echoed input
+    Name,
+    /// operators in echoed input
+    Operator,
+}
+
+/// A color for each [`Role`], where `None` leaves the text as it is.
+#[derive(Debug, PartialEq)]
+pub struct Theme {
+    pub name: &'static str,
+    pub result: Option<Color>,
+    pub boolean: Option<Color>,
+    pub error: Option<Color>,
+    pub warning: Option<Color>,
+    pub prompt: Option<Color>,
+    pub numbers: Option<Color>,
+    pub names: Option<Color>,
+    pub operators: Option<Color>,
+    pub bold: bool,
+}
+
+impl Theme {
+    pub fn from_name(name: &str) -> Option<&'static Theme> {
+        THEMES.iter().find(|t| t.name == name)
+    }
+
+    pub fn color(&self, role: Role) -> Option<Color> {
+        match role {
+            Role::Result => self.result,
+            Role::Boolean => self.boolean,
+            Role::Error => self.error,
+            Role::Warning => self.warning,
+            Role::Prompt => self.prompt,
+            Role::Number => self.numbers,
+            Role::Name => self.names,
+            Role::Operator => self.operators,
+        }
+    }
+
+    pub fn paint(&self, s: &str, role: Role) -> ColoredString {
+        let painted = match self.color(role) {
+            Some(color) => s.color(color),
+            None => s.normal(),
+        };
+        if self.bold {
+            painted.bold()
+        } else {
+            painted
+        }
+    }
+
+    /// Colors the numbers, names and operators of an expression.
+    pub fn highlight(&self, input: &str) -> String {
+        let mut res = String::new();
+        let mut chars = input.char_indices().peekable();
+        while let Some((start, c)) = chars.next() {
+            let (role, same): (_, fn(char) -> bool) = match c {
+                '0'..='9' | '.' => (Some(Role::Number), |c| c.is_ascii_digit() || c == '.'),
+                c if c.is_alphabetic() || c == '_' => (Some(Role::Name), |c| c.is_alphanumeric() || c == '_'),
+                '+' | '-' | '*' | '/' | '^' | '<' | '>' | '=' | '!' => (Some(Role::Operator), |_| false),
+                _ => (None, |_| false),
+            };
+            let mut end = start + c.len_utf8();
+            while let Some(&(i, c)) = chars.peek().filter(|&&(_, c)| same(c)) {
+                end = i + c.len_utf8();
+                chars.next();
+            }
+            match role.filter(|&role| self.color(role).is_some()) {
+                Some(role) => res += &self.paint(&input[start..end], role).to_string(),
+                None => res += &input[start..end],
+            }
+        }
+        res
+    }
+}
+
+pub static THEMES: &[Theme] = &[
+    Theme {
+        name: "default",
+        result: Some(Color::Green),
+        boolean: Some(Color::Cyan),
+        error: Some(Color::Red),
+        warning: Some(Color::Yellow),
+        prompt: None,
+        numbers: None,
+        names: None,
+        operators: None,
+        bold: false,
+    },
+    // bright colors on anything, for terminals with low contrast or for poor eyesight
+    Theme {
+        name: "high-contrast",
+        result: Some(Color::BrightGreen),
+        boolean: Some(Color::BrightCyan),
+        error: Some(Color::BrightRed),
+        warning: Some(Color::BrightYellow),
+        prompt: Some(Color::BrightWhite),
+        numbers: Some(Color::BrightMagenta),
+        names: Some(Color::BrightBlue),
+        operators: Some(Color::BrightWhite),
+        bold: true,
+    },
+    Theme {
+        name: "monochrome",
+        result: None,
+        boolean: None,
+        error: None,
+        warning: None,
+        prompt: None,
+        numbers: None,
+        names: None,
+        operators: None,
+        bold: false,
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_are_unique() {
+        for (i, t) in THEMES.iter().enumerate() {
+            assert!(THEMES[..i].iter().all(|u| u.name != t.name), "{} is defined twice", t.name);
+        }
+    }
+
+    #[test]
+    fn highlight() {
+        let theme = Theme::from_name("high-contrast").unwrap();
+        assert_eq!(
+            theme.highlight("max(x1, 2.5) * 3"),
+            [
+                &theme.paint("max", Role::Name).to_string(),
+                "(",
+                &theme.paint("x1", Role::Name).to_string(),
+                ", ",
+                &theme.paint("2.5", Role::Number).to_string(),
+                ") ",
+                &theme.paint("*", Role::Operator).to_string(),
+                " ",
+                &theme.paint("3", Role::Number).to_string(),
+            ]
+            .concat()
+        );
+        assert_eq!(Theme::from_name("default").unwrap().highlight("1 + x"), "1 + x");
+    }
+}
@@ -0,0 +1,154 @@
+//! The lessons of `calculator tutorial`, which walk through operators, variables and functions
+//! with tasks that are checked by what the input evaluates to.
+
+use crate::context::Context;
+use crate::parser;
+use crate::repr::Value;
+
+/// How far a result may be from the expected one, relative to it, since `0.1 + 0.2` is not
+/// exactly `0.3`.
+const TOLERANCE: f64 = 1e-9;
+
+pub struct Lesson {
+    pub title: &'static str,
+    pub tasks: &'static [Task],
+}
+
+pub struct Task {
+    /// what to do, like "compute the hypotenuse of 3 and 4"
+    pub text: &'static str,
+    /// an expression that is evaluated after each input instead of looking at its result, like
+    /// `f(2)` for a task to define `f`
+    pub probe: Option<&'static str>,
+    pub expected: f64,
+    /// an input that does the task, shown with `:hint`
+    pub hint: &'static str,
+}
+
+impl Task {
+    /// Whether the task is done, given the result of the input that was just run.
+    pub fn is_done(&self, res: Option<&Value>, ctx: &Context) -> bool {
+        let value = match self.probe {
+            Some(probe) => parser::parse_line(probe).ok().and_then(|e| e.evaluate_in(ctx).ok()),
+            None => match res {
+                Some(&Value::Number(x)) => Some(x),
+                _ => None,
+            },
+        };
+        value.is_some_and(|x| (x - self.expected).abs() <= TOLERANCE * self.expected.abs().max(1.0))
+    }
+}
+
+pub const LESSONS: &[Lesson] = &[
+    Lesson {
+        title: "Operators",
+        tasks: &[
+            Task {
+                text: "Compute 7 plus 5 times 3.",
+                probe: None,
+                expected: 22.0,
+                hint: "7 + 5 * 3",
+            },
+            Task {
+                text: "Now add 7 and 5 first, and then multiply by 3.",
+                probe: None,
+                expected: 36.0,
+                hint: "(7 + 5) * 3",
+            },
+            Task {
+                text: "Raise 2 to the power of 10.",
+                probe: None,
+                expected: 1024.0,
+                hint: "2 ^ 10",
+            },
+            Task {
+                text: "Divide 1 by 8.",
+                probe: None,
+                expected: 0.125,
+                hint: "1 / 8",
+            },
+        ],
+    },
+    Lesson {
+        title: "Variables",
+        tasks: &[
+            Task {
+                text: "Store 12 in a variable named width.",
+                probe: Some("width"),
+                expected: 12.0,
+                hint: "width = 12",
+            },
+            Task {
+                text: "Store 5 in a variable named height.",
+                probe: Some("height"),
+                expected: 5.0,
+                hint: "height = 5",
+            },
+            Task {
+                text: "Compute the area of a rectangle that is width wide and height high.",
+                probe: None,
+                expected: 60.0,
+                hint: "width * height",
+            },
+            Task {
+                text: "The previous result is called ans. Halve it.",
+                probe: None,
+                expected: 30.0,
+                hint: "ans / 2",
+            },
+        ],
+    },
+    Lesson {
+        title: "Functions",
+        tasks: &[
+            Task {
+                text: "Compute the square root of 144.",
+                probe: None,
+                expected: 12.0,
+                hint: "sqrt(144)",
+            },
+            Task {
+                text: "Compute the hypotenuse of 3 and 4.",
+                probe: None,
+                expected: 5.0,
+                hint: "sqrt(3^2 + 4^2)",
+            },
+            Task {
+                text: "Define a function hyp(a, b) that computes the hypotenuse of a and b.",
+                probe: Some("hyp(5, 12)"),
+                expected: 13.0,
+                hint: "hyp(a, b) = sqrt(a^2 + b^2)",
+            },
+            Task {
+                text: "Use hyp to compute the hypotenuse of 8 and 15.",
+                probe: None,
+                expected: 17.0,
+                hint: "hyp(8, 15)",
+            },
+        ],
+    },
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hints_do_the_tasks() {
+        let mut ctx = Context::default();
+        for task in LESSONS.iter().flat_map(|lesson| lesson.tasks) {
+            let statement = parser::parse_statement(task.hint, Default::default()).unwrap();
+            let res = ctx.run(&statement).unwrap();
+            assert!(task.is_done(res.as_ref(), &ctx), "{}", task.text);
+        }
+    }
+
+    #[test]
+    fn close_results() {
+        let task = &LESSONS[0].tasks[0];
+        let ctx = Context::default();
+        assert!(task.is_done(Some(&Value::Number(22.000000000001)), &ctx));
+        assert!(!task.is_done(Some(&Value::Number(22.1)), &ctx));
+        assert!(!task.is_done(None, &ctx));
+    }
+}
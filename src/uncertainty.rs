@@ -0,0 +1,161 @@
+//! Measurements with uncertainties, like `1.50±0.02`, which are parsed as calls of `pm`.
+//!
+//! Uncertainties are assumed to be independent, so they are added in quadrature.
+
+use std::fmt;
+
+use crate::functions::{self, Eval, Function};
+use crate::number::Backend;
+use crate::repr::*;
+
+/// The name of the function that `a±b` is parsed as.
+pub const PM: &str = "pm";
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct Measurement {
+    pub value: f64,
+    /// the standard uncertainty, which is never negative
+    pub error: f64,
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ± {}", self.value, self.error)
+    }
+}
+
+impl Measurement {
+    pub fn exact(value: f64) -> Self {
+        Measurement { value, error: 0.0 }
+    }
+
+    fn unary(op: UnaryOperator, a: Measurement) -> Self {
+        Measurement {
+            value: op.apply(a.value),
+            error: a.error,
+        }
+    }
+
+    fn binary(op: Operator, a: Measurement, b: Measurement) -> Self {
+        let value = op.apply(a.value, b.value);
+        let error = match op {
+            Operator::Add | Operator::Sub => a.error.hypot(b.error),
+            Operator::Mul => (a.error * b.value).hypot(b.error * a.value),
+            Operator::Div => (a.error / b.value).hypot(b.error * a.value / (b.value * b.value)),
+            Operator::Pow => {
+                // skipped when exact, since the derivatives may not exist then
+                let base = if a.error == 0.0 { 0.0 } else { b.value * a.value.powf(b.value - 1.0) * a.error };
+                let exponent = if b.error == 0.0 { 0.0 } else { value * a.value.ln() * b.error };
+                base.hypot(exponent)
+            }
+            // comparisons are exact, true or false
+            _ => 0.0,
+        };
+        Measurement { value, error }
+    }
+
+    /// Propagates the uncertainties of the arguments using numerical partial derivatives.
+    fn call(f: &Function, args: &[Measurement]) -> Result<Self, String> {
+        let Eval::Numbers(eval) = f.eval else {
+            return Err(format!("{} does not take uncertain numbers", f.name));
+        };
+        let values: Vec<f64> = args.iter().map(|a| a.value).collect();
+        let value = eval(&values)?;
+        let mut error = 0.0f64;
+        for (i, a) in args.iter().enumerate().filter(|(_, a)| a.error != 0.0) {
+            let h = a.value.abs().max(1.0) * 1e-6;
+            let mut shifted = values.clone();
+            shifted[i] = a.value + h;
+            let above = eval(&shifted)?;
+            shifted[i] = a.value - h;
+            let below = eval(&shifted)?;
+            error = error.hypot((above - below) / (2.0 * h) * a.error);
+        }
+        Ok(Measurement { value, error })
+    }
+}
+
+impl Expr {
+    /// Whether the expression contains a measurement like `1.50±0.02`.
+    pub fn has_uncertainty(&self) -> bool {
+        self.nodes().iter().any(|n| matches!(n, Node::Call(name, _) if name == PM))
+    }
+}
+
+/// Measurements as numbers, which only [`PM`] makes uncertain.
+pub struct Uncertain;
+
+impl Backend for Uncertain {
+    type Num = Measurement;
+
+    fn number(&self, f: f64) -> Measurement {
+        Measurement::exact(f)
+    }
+
+    fn to_f64(&self, x: &Measurement) -> f64 {
+        x.value
+    }
+
+    fn unary(&self, op: UnaryOperator, a: &Measurement) -> Measurement {
+        Measurement::unary(op, *a)
+    }
+
+    fn op(&self, op: Operator, a: &Measurement, b: &Measurement) -> Measurement {
+        Measurement::binary(op, *a, *b)
+    }
+
+    /// Errors are left to the `f64` evaluation, which fails the same way for the values.
+    fn call(&self, name: &str, args: &[Measurement]) -> Option<Measurement> {
+        if name == PM {
+            let [m, e] = args else { return None };
+            return Some(Measurement {
+                value: m.value,
+                error: m.error.hypot(e.value),
+            });
+        }
+        Measurement::call(functions::lookup(name)?, args).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::Context;
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    fn measure(input: &str) -> Measurement {
+        let m = parse_line(input).unwrap().evaluate_with(&Uncertain, &Context::default()).unwrap();
+        // hide rounding errors
+        let round = |x: f64| (x * 1e9).round() / 1e9;
+        Measurement {
+            value: round(m.value),
+            error: round(m.error),
+        }
+    }
+
+    #[test]
+    fn propagation() {
+        assert_eq!(measure("1.50±0.02"), Measurement { value: 1.5, error: 0.02 });
+        assert_eq!(measure("(1±0.3) + (2±0.4)"), Measurement { value: 3.0, error: 0.5 });
+        assert_eq!(measure("(1±0.3) - 2"), Measurement { value: -1.0, error: 0.3 });
+        // relative uncertainties of 3% and 4% make 5%
+        assert_eq!(measure("(10±0.3) * 20±0.8"), Measurement { value: 200.0, error: 10.0 });
+        assert_eq!(measure("(10±0.3) / 20±0.8"), Measurement { value: 0.5, error: 0.025 });
+        assert_eq!(measure("-(3±0.1) ^ 2"), Measurement { value: -9.0, error: 0.6 });
+        assert_eq!(measure("2 * 1±0.1"), Measurement { value: 2.0, error: 0.2 });
+        assert_eq!(measure("sqrt(4±0.4)"), Measurement { value: 2.0, error: 0.1 });
+        assert_eq!(measure("max(1±0.1, 3)"), Measurement { value: 3.0, error: 0.0 });
+        assert_eq!(measure("1±0.1 < 2"), Measurement { value: 1.0, error: 0.0 });
+    }
+
+    #[test]
+    fn detection() {
+        assert!(parse_line("2 * (1 ± 0.1)").unwrap().has_uncertainty());
+        assert!(!parse_line("2 * 1").unwrap().has_uncertainty());
+        assert_eq!(
+            parse_line("1±0.1").unwrap().evaluate().map_err(|e| e.message),
+            Err("uncertainties like 1±0.1 are not supported here".into())
+        );
+    }
+}
@@ -0,0 +1,256 @@
+use crate::functions::{self, Eval, FUNCTIONS};
+use crate::i18n::{Key, Text};
+use crate::repr::*;
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum Instr {
+    Push(f64),
+    /// pushes the value of the variable in the given slot
+    Load(u32),
+    /// pops one operand and pushes the result
+    Unary(UnaryOperator),
+    /// pops two operands and pushes the result
+    Binary(Operator),
+    /// pops the arguments for the built-in function with the given index and pushes the result
+    Call(u32, u32),
+    /// pops the condition and continues at the given instruction if it is 0
+    JumpIfZero(u32),
+    /// continues at the given instruction
+    Jump(u32),
+}
+
+/// A flat stack-machine program compiled from an [`Expr`].
+///
+/// Variables are not resolved when compiling; instead every distinct variable gets a slot,
+/// and its value is passed to [`Program::run`]. This makes the program cheap to evaluate
+/// many times with different inputs.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Program {
+    code: Vec<Instr>,
+    vars: Vec<String>,
+    max_stack: usize,
+}
+
+impl Program {
+    /// The variables of the program, in slot order.
+    pub fn vars(&self) -> &[String] {
+        &self.vars
+    }
+
+    #[cfg_attr(not(feature = "jit"), allow(dead_code))]
+    pub fn instructions(&self) -> &[Instr] {
+        &self.code
+    }
+
+    pub fn slot(&self, name: &str) -> Option<usize> {
+        self.vars.iter().position(|v| v == name)
+    }
+
+    pub fn run(&self, args: &[f64]) -> Result<f64, String> {
+        if args.len() != self.vars.len() {
+            return Err(format!(
+                "expected {} variable values, got {}",
+                self.vars.len(),
+                args.len()
+            ));
+        }
+        let mut stack = Vec::with_capacity(self.max_stack);
+        let mut pc = 0;
+        while let Some(instr) = self.code.get(pc) {
+            pc += 1;
+            match *instr {
+                Instr::Push(v) => stack.push(v),
+                Instr::Load(slot) => stack.push(args[slot as usize]),
+                Instr::Unary(op) => {
+                    let a = stack.pop().unwrap();
+                    stack.push(op.apply(a));
+                }
+                Instr::Binary(op) => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    stack.push(op.apply(a, b));
+                }
+                Instr::Call(f, n) => {
+                    let at = stack.len() - n as usize;
+                    let Eval::Numbers(eval) = FUNCTIONS[f as usize].eval else {
+                        unreachable!("only functions of numbers are compiled")
+                    };
+                    let v = eval(&stack[at..])?;
+                    stack.truncate(at);
+                    stack.push(v);
+                }
+                Instr::JumpIfZero(target) => {
+                    if stack.pop().unwrap() == 0.0 {
+                        pc = target as usize;
+                    }
+                }
+                Instr::Jump(target) => pc = target as usize,
+            }
+        }
+        Ok(stack.pop().unwrap())
+    }
+}
+
+impl Expr {
+    /// Fails for calls of unknown functions, or with the wrong number of arguments.
+    pub fn compile(&self) -> Result<Program, String> {
+        let mut c = Compiler {
+            program: Program {
+                code: Vec::new(),
+                vars: Vec::new(),
+                max_stack: 0,
+            },
+            depth: 0,
+        };
+        c.compile(self, self.root())?;
+        Ok(c.program)
+    }
+}
+
+struct Compiler {
+    program: Program,
+    depth: usize,
+}
+
+impl Compiler {
+    fn emit(&mut self, instr: Instr, pops: usize) {
+        self.depth = self.depth - pops + 1;
+        self.program.max_stack = self.program.max_stack.max(self.depth);
+        self.program.code.push(instr);
+    }
+
+    /// Emits a jump whose target is set by [`Compiler::land`], and returns where it is.
+    fn jump(&mut self, instr: Instr, pops: usize) -> usize {
+        self.depth -= pops;
+        self.program.code.push(instr);
+        self.program.code.len() - 1
+    }
+
+    /// Makes the jump at `at` continue at the next instruction.
+    fn land(&mut self, at: usize) {
+        let target = self.program.code.len() as u32;
+        match &mut self.program.code[at] {
+            Instr::JumpIfZero(t) | Instr::Jump(t) => *t = target,
+            _ => unreachable!(),
+        }
+    }
+
+    fn compile(&mut self, expr: &Expr, id: NodeId) -> Result<(), String> {
+        match *expr.node(id) {
+            Node::Float(f) => self.emit(Instr::Push(f), 0),
+            Node::Bool(b) => self.emit(Instr::Push(b as u8 as f64), 0),
+            Node::Var(ref name) => {
+                let slot = match self.program.slot(name) {
+                    Some(slot) => slot,
+                    None => {
+                        self.program.vars.push(name.clone());
+                        self.program.vars.len() - 1
+                    }
+                };
+                self.emit(Instr::Load(slot as u32), 0);
+            }
+            Node::Unary(op, a) => {
+                self.compile(expr, a)?;
+                self.emit(Instr::Unary(op), 1);
+            }
+            Node::Op(op, a, b) => {
+                self.compile(expr, a)?;
+                self.compile(expr, b)?;
+                self.emit(Instr::Binary(op), 2);
+            }
+            Node::Assign(ref name, _) => return Err(format!("cannot compile the assignment to {name}")),
+            Node::List(_) | Node::Range(..) => return Err("cannot compile lists".to_string()),
+            Node::Text(_) => return Err("cannot compile text".to_string()),
+            Node::Lambda(..) => return Err("cannot compile functions without a name".to_string()),
+            // like the tree evaluator, only the branch that is taken is evaluated
+            Node::Call(ref name, ref args) if name == "if" && args.len() == 3 => {
+                self.compile(expr, args[0])?;
+                let to_else = self.jump(Instr::JumpIfZero(0), 1);
+                self.compile(expr, args[1])?;
+                let to_end = self.jump(Instr::Jump(0), 0);
+                // the then branch is not on the stack in the else branch
+                self.depth -= 1;
+                self.land(to_else);
+                self.compile(expr, args[2])?;
+                self.land(to_end);
+            }
+            Node::Call(ref name, ref args) => {
+                let f = functions::lookup(name).ok_or_else(|| Text::new(Key::UnknownFunction, vec![name.into()]))?;
+                if let Eval::Values { .. } = f.eval {
+                    return Err(format!("cannot compile {name}, which takes or gives more than numbers"));
+                }
+                f.check_arity(args.len())?;
+                for &a in args {
+                    self.compile(expr, a)?;
+                }
+                let index = FUNCTIONS.iter().position(|g| std::ptr::eq(f, g)).unwrap();
+                self.emit(Instr::Call(index as u32, args.len() as u32), args.len());
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::number::native;
+    use crate::parser::parse_line;
+
+    use super::*;
+
+    #[test]
+    fn compile_simple() {
+        let p = parse_line("1 + x * 2").unwrap().compile().unwrap();
+        assert_eq!(
+            p.code,
+            [
+                Instr::Push(1.0),
+                Instr::Load(0),
+                Instr::Push(2.0),
+                Instr::Binary(Operator::Mul),
+                Instr::Binary(Operator::Add),
+            ]
+        );
+        assert_eq!(p.vars(), ["x"]);
+        assert_eq!(p.max_stack, 3);
+    }
+
+    #[test]
+    fn run_with_vars() {
+        let p = parse_line("(x - y) / x").unwrap().compile().unwrap();
+        assert_eq!(p.vars(), ["x", "y"]);
+        assert_eq!(p.run(&[4.0, 1.0]), Ok(0.75));
+        assert_eq!(p.run(&[2.0, 2.0]), Ok(0.0));
+        assert!(p.run(&[2.0]).is_err());
+    }
+
+    #[test]
+    fn matches_tree_evaluation() {
+        for input in [
+            "1",
+            "1 + 2 * 3 - 4 / 5",
+            "(1 - 2) * (3 + 4) / -2",
+            "-(1 - 2)",
+            "1 / 0",
+            "2 ^ 3 ^ 2 < 1000",
+            "max(1, sqrt(2) * 3, if(1, 4, 5))",
+        ] {
+            let e = parse_line(input).unwrap();
+            assert_eq!(e.compile().unwrap().run(&[]).ok().map(native), e.evaluate().ok(), "input was {input:?}");
+        }
+    }
+
+    #[test]
+    fn if_takes_one_branch() {
+        let p = parse_line("if(x, 1, assert(0)) + if(x - 1, assert(0), 2)").unwrap().compile().unwrap();
+        assert_eq!(p.run(&[1.0]), Ok(3.0));
+        assert_eq!(p.run(&[0.0]), Err("assertion failed".into()));
+        assert_eq!(p.max_stack, 3);
+    }
+
+    #[test]
+    fn compile_errors() {
+        assert_eq!(parse_line("foo(1)").unwrap().compile(), Err("unknown function: foo".into()));
+        assert_eq!(parse_line("sqrt()").unwrap().compile(), Err("sqrt takes 1 arguments, got 0".into()));
+    }
+}
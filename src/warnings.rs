@@ -0,0 +1,147 @@
+//! Mistakes that are not fatal, like comparing fractional numbers with `==`, which are
+//! reported next to the result.
+
+use crate::context::Context;
+use crate::currency;
+use crate::repr::*;
+
+/// Results with fewer significant digits than this fraction of the operands are suspicious.
+const CANCELLATION: f64 = 1e-8;
+
+/// Larger integers can not all be represented exactly.
+const MAX_EXACT: f64 = 9007199254740992.0;
+
+impl Statement {
+    /// Warnings about the statement, each reported once.
+    pub fn warnings(&self, ctx: &Context) -> Vec<String> {
+        let mut warnings = Vec::new();
+        match self {
+            Statement::Expr(e) | Statement::Assign(_, e) => e.warnings(ctx, &mut warnings),
+            Statement::Define(name, f) => {
+                for param in &f.params {
+                    let used = f
+                        .body
+                        .nodes()
+                        .iter()
+                        .any(|n| matches!(n, Node::Var(v) if v == param));
+                    if !used {
+                        warnings.push(format!("the parameter {param} of {name} is never used"));
+                    }
+                }
+            }
+            Statement::Memory(_) => {}
+        }
+        warnings
+    }
+}
+
+impl Expr {
+    fn warnings(&self, ctx: &Context, warnings: &mut Vec<String>) {
+        let money = |n: &Node| matches!(n, Node::Call(name, _) if currency::is_code(name) && ctx.func(name).is_none());
+        if self.nodes().iter().any(money) {
+            warnings.extend(ctx.rates().stale(currency::today()));
+        }
+        for node in self.nodes() {
+            let &Node::Op(op, a, b) = node else {
+                continue;
+            };
+            let (Ok(a), Ok(b)) = (self.evaluate_at(a, ctx), self.evaluate_at(b, ctx)) else {
+                continue;
+            };
+            let res = op.apply(a, b);
+            let integers = a.fract() == 0.0 && b.fract() == 0.0;
+            let warning = match op {
+                Operator::Add | Operator::Sub
+                    if !integers && res != 0.0 && res.abs() < a.abs().max(b.abs()) * CANCELLATION =>
+                {
+                    "the operands nearly cancel out, so few digits of the result are accurate".to_string()
+                }
+                Operator::Eq | Operator::Ne if !integers => format!(
+                    "comparing fractional numbers with {} is unreliable because of rounding errors",
+                    op.symbol()
+                ),
+                Operator::Add | Operator::Sub | Operator::Mul | Operator::Pow
+                    if integers && res.abs() > MAX_EXACT && !is_exact(op, a, b, res) =>
+                {
+                    "the result is too large to be an exact integer".to_string()
+                }
+                _ => continue,
+            };
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+    }
+}
+
+/// Whether `res` is exactly `a op b`, for integers `a` and `b`, like for `2 ^ 60` but not for
+/// `2 ^ 60 + 1`.
+fn is_exact(op: Operator, a: f64, b: f64, res: f64) -> bool {
+    match op {
+        // the rounding error of the sum, which is computed exactly
+        Operator::Add | Operator::Sub => {
+            let b = if op == Operator::Sub { -b } else { b };
+            let rounded = res - a;
+            (a - (res - rounded)) + (b - rounded) == 0.0
+        }
+        Operator::Mul => a.mul_add(b, -res) == 0.0,
+        // the powers of 2 are in the exponent, so only the odd part has to fit
+        Operator::Pow => {
+            let mut odd = a.abs();
+            while odd != 0.0 && odd % 2.0 == 0.0 {
+                odd /= 2.0;
+            }
+            res.is_finite() && odd.powf(b) <= MAX_EXACT
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::{parse_statement, Syntax};
+
+    use super::*;
+
+    fn warnings(input: &str) -> Vec<String> {
+        parse_statement(input, Syntax::Native).unwrap().warnings(&Context::default())
+    }
+
+    #[test]
+    fn expressions() {
+        assert_eq!(
+            warnings("(1 + 0.0000000001) - 1"),
+            ["the operands nearly cancel out, so few digits of the result are accurate"]
+        );
+        assert_eq!(
+            warnings("0.1 + 0.2 == 0.3"),
+            ["comparing fractional numbers with == is unreliable because of rounding errors"]
+        );
+        assert_eq!(warnings("2 ^ 60 + 1"), ["the result is too large to be an exact integer"]);
+        assert_eq!(warnings("3 ^ 40"), ["the result is too large to be an exact integer"]);
+        assert_eq!(warnings("3 ^ 20 * 3 ^ 20"), ["the result is too large to be an exact integer"]);
+        assert_eq!(
+            warnings("x = 1.5 != 2"),
+            ["comparing fractional numbers with != is unreliable because of rounding errors"]
+        );
+    }
+
+    #[test]
+    fn no_warnings() {
+        assert!(warnings("1 + 2 == 3").is_empty());
+        assert!(warnings("0.3 - 0.1").is_empty());
+        assert!(warnings("(10 ^ 15 + 1) - 10 ^ 15").is_empty());
+        assert!(warnings("2 ^ 53").is_empty());
+        // powers of two and their small multiples are exact
+        assert!(warnings("2 ^ 60").is_empty());
+        assert!(warnings("2 ^ 60 * 3 - 2 ^ 59").is_empty());
+        assert!(warnings("10 ^ 20").is_empty());
+        assert!(warnings("y + 1").is_empty());
+    }
+
+    #[test]
+    fn definitions() {
+        assert_eq!(warnings("f(x, y) = x * 2"), ["the parameter y of f is never used"]);
+        assert!(warnings("f(x) = x * 2").is_empty());
+    }
+}
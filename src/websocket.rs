@@ -0,0 +1,230 @@
+//! Just enough of WebSocket (RFC 6455) for `serve --ws`: the opening handshake, and text
+//! messages in frames.
+
+use std::io::{self, BufRead, Read, Write};
+
+/// Appended to the key of the client to make the accept key of the handshake.
+const GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Messages longer than this are refused, since they are surely not expressions.
+const MAX_MESSAGE: usize = 1 << 20;
+
+const CONTINUATION: u8 = 0x0;
+const TEXT: u8 = 0x1;
+const CLOSE: u8 = 0x8;
+const PING: u8 = 0x9;
+const PONG: u8 = 0xA;
+
+/// Reads the HTTP upgrade request and answers it, so that frames can be sent afterwards.
+pub fn handshake(input: &mut impl BufRead, output: &mut impl Write) -> io::Result<()> {
+    let mut key = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line)? == 0 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "the request ended early"));
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("sec-websocket-key") {
+                key = Some(value.trim().to_string());
+            }
+        }
+    }
+    let Some(key) = key else {
+        write!(output, "HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")?;
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not a WebSocket request"));
+    };
+    write!(
+        output,
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Accept: {}\r\n\r\n",
+        accept_key(&key)
+    )?;
+    output.flush()
+}
+
+fn accept_key(key: &str) -> String {
+    base64(&sha1(format!("{key}{GUID}").as_bytes()))
+}
+
+/// Reads the next text message, answering pings on the way. Returns `None` when the client
+/// closes the connection.
+pub fn read_message(input: &mut impl Read, output: &mut impl Write) -> io::Result<Option<String>> {
+    let mut message = Vec::new();
+    loop {
+        let mut header = [0; 2];
+        input.read_exact(&mut header)?;
+        let (fin, opcode, masked) = (header[0] & 0x80 != 0, header[0] & 0x0F, header[1] & 0x80 != 0);
+        let len = match header[1] & 0x7F {
+            126 => {
+                let mut len = [0; 2];
+                input.read_exact(&mut len)?;
+                u16::from_be_bytes(len) as u64
+            }
+            127 => {
+                let mut len = [0; 8];
+                input.read_exact(&mut len)?;
+                u64::from_be_bytes(len)
+            }
+            len => len as u64,
+        };
+        if len as usize > MAX_MESSAGE || message.len() + len as usize > MAX_MESSAGE {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "the message is too long"));
+        }
+        let mut mask = [0; 4];
+        if masked {
+            input.read_exact(&mut mask)?;
+        }
+        let mut payload = vec![0; len as usize];
+        input.read_exact(&mut payload)?;
+        if masked {
+            for (i, b) in payload.iter_mut().enumerate() {
+                *b ^= mask[i % 4];
+            }
+        }
+        match opcode {
+            TEXT | CONTINUATION => {
+                message.extend(payload);
+                if fin {
+                    let text = String::from_utf8(message)
+                        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "the message is not UTF-8"))?;
+                    return Ok(Some(text));
+                }
+            }
+            PING => write_frame(output, PONG, &payload)?,
+            PONG => {}
+            CLOSE => {
+                write_frame(output, CLOSE, &[])?;
+                return Ok(None);
+            }
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidData, "only text messages are supported")),
+        }
+    }
+}
+
+pub fn write_message(output: &mut impl Write, text: &str) -> io::Result<()> {
+    write_frame(output, TEXT, text.as_bytes())
+}
+
+/// Writes a whole unmasked frame, which is how servers send them.
+fn write_frame(output: &mut impl Write, opcode: u8, payload: &[u8]) -> io::Result<()> {
+    let mut frame = vec![0x80 | opcode];
+    match payload.len() {
+        len @ 0..=125 => frame.push(len as u8),
+        len @ 126..=0xFFFF => {
+            frame.push(126);
+            frame.extend((len as u16).to_be_bytes());
+        }
+        len => {
+            frame.push(127);
+            frame.extend((len as u64).to_be_bytes());
+        }
+    }
+    frame.extend(payload);
+    output.write_all(&frame)?;
+    output.flush()
+}
+
+fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+    let mut padded = data.to_vec();
+    padded.push(0x80);
+    while padded.len() % 64 != 56 {
+        padded.push(0);
+    }
+    padded.extend((data.len() as u64 * 8).to_be_bytes());
+    for chunk in padded.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes(chunk[i * 4..i * 4 + 4].try_into().unwrap());
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, &w) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let t = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(w);
+            (e, d, c, b, a) = (d, c, b.rotate_left(30), a, t);
+        }
+        for (h, x) in h.iter_mut().zip([a, b, c, d, e]) {
+            *h = h.wrapping_add(x);
+        }
+    }
+    let mut res = [0; 20];
+    for (i, h) in h.iter().enumerate() {
+        res[i * 4..i * 4 + 4].copy_from_slice(&h.to_be_bytes());
+    }
+    res
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut res = String::new();
+    for chunk in data.chunks(3) {
+        let n = chunk.iter().enumerate().fold(0u32, |n, (i, &b)| n | (b as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            if i <= chunk.len() {
+                res.push(ALPHABET[(n >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                res.push('=');
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn accept() {
+        // the example of RFC 6455
+        assert_eq!(accept_key("dGhlIHNhbXBsZSBub25jZQ=="), "s3pPLMBiTxaQ9kYGzzhZRbK+xOo=");
+        assert_eq!(base64(b"ab"), "YWI=");
+        assert_eq!(base64(b"a"), "YQ==");
+    }
+
+    #[test]
+    fn handshake_request() {
+        let request = "GET / HTTP/1.1\r\nHost: localhost\r\nUpgrade: websocket\r\n\
+                       Sec-WebSocket-Key: dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let mut response = Vec::new();
+        handshake(&mut request.as_bytes(), &mut response).unwrap();
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 101 "));
+        assert!(response.contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+
+        let mut response = Vec::new();
+        assert!(handshake(&mut "GET / HTTP/1.1\r\n\r\n".as_bytes(), &mut response).is_err());
+        assert!(response.starts_with(b"HTTP/1.1 400 "));
+    }
+
+    #[test]
+    fn frames() {
+        // a masked "Hel" and "lo" in two frames from the RFC, then a ping and a close
+        let mut input: &[u8] = &[
+            0x01, 0x83, 0x37, 0xfa, 0x21, 0x3d, 0x7f, 0x9f, 0x4d, 0x80, 0x82, 0x37, 0xfa, 0x21, 0x3d, 0x5b, 0x95, 0x89,
+            0x00, 0x88, 0x00,
+        ];
+        let mut output = Vec::new();
+        assert_eq!(read_message(&mut input, &mut output).unwrap(), Some("Hello".into()));
+        assert_eq!(read_message(&mut input, &mut output).unwrap(), None);
+        assert_eq!(output, [0x8A, 0x00, 0x88, 0x00]);
+
+        let mut output = Vec::new();
+        write_message(&mut output, &"x".repeat(200)).unwrap();
+        assert_eq!(output[..4], [0x81, 126, 0, 200]);
+        let mut input = &output[..];
+        assert_eq!(read_message(&mut input, &mut Vec::new()).unwrap(), Some("x".repeat(200)));
+    }
+}